@@ -16,6 +16,8 @@ pub enum TwammError {
     InvalidTokenAmount,
     #[msg("Invalid token pair price")]
     InvalidTokenPairPrice,
+    #[msg("Invalid max price deviation")]
+    InvalidPriceDeviation,
     #[msg("Deposits are not allowed at this time")]
     DepositsNotAllowed,
     #[msg("Withdrawals are not allowed at this time")]
@@ -26,8 +28,12 @@ pub enum TwammError {
     SettlementsNotAllowed,
     #[msg("Instruction is not allowed in production")]
     InvalidEnvironment,
+    #[msg("Account is not authorized to manage this order")]
+    OrderNotAuthorized,
     #[msg("Order side mismatch")]
     OrderSideMismatch,
+    #[msg("Order type mismatch")]
+    OrderTypeMismatch,
     #[msg("Time in force mismatch")]
     TimeInForceMismatch,
     #[msg("Invalid time in force")]
@@ -40,6 +46,36 @@ pub enum TwammError {
     ExpiredPool,
     #[msg("Invalid pool state")]
     InvalidPoolState,
+    #[msg("Invalid order start time")]
+    InvalidStartTime,
+    #[msg("Invalid order expiration time")]
+    InvalidExpirationTime,
+    #[msg("Only exact-input orders in the pool without price conditions can be tokenized")]
+    OrderNotTokenizable,
+    #[msg("Order can't be rolled into the next pool")]
+    OrderNotRollable,
+    #[msg("Subscription has no order due at this time")]
+    SubscriptionNotDue,
+    #[msg("Order id is reserved for subscription orders")]
+    ReservedOrderId,
+    #[msg("Invalid link between orders of different token pairs")]
+    InvalidOrderLink,
+    #[msg("Invalid order intent signature")]
+    InvalidIntentSignature,
+    #[msg("Order intent has expired")]
+    IntentExpired,
+    #[msg("Order intent has not expired yet")]
+    IntentNotExpired,
+    #[msg("Unsupported account layout version")]
+    UnsupportedAccountVersion,
+    #[msg("Order has no pending trigger")]
+    OrderNotTriggerable,
+    #[msg("Trigger price has not been reached")]
+    TriggerPriceNotReached,
+    #[msg("Token mint has unsupported extensions")]
+    UnsupportedMintExtension,
+    #[msg("Invalid token account")]
+    InvalidTokenAccount,
     #[msg("Overflow in arithmetic operation")]
     MathOverflow,
     #[msg("Unsupported price oracle")]
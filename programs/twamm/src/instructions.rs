@@ -1,41 +1,93 @@
 pub mod cancel_order;
+pub mod cancel_stop_order;
+pub mod cancel_subscription;
+pub mod cancel_virtual_order;
+pub mod close_intent_nonce;
 pub mod crank;
+pub mod create_subscription;
 pub mod delete_test_pair;
 pub mod delete_test_pool;
+pub mod execute_subscription;
+pub mod forward_proceeds;
 pub mod get_outstanding_amount;
 pub mod init;
+pub mod init_observations;
 pub mod init_token_pair;
+pub mod init_virtual_pool;
+pub mod link_order;
+pub mod migrate_account;
 pub mod place_order;
+pub mod place_stop_order;
+pub mod place_virtual_order;
+pub mod redeem_position;
+pub mod refresh_order;
+pub mod roll_order;
 pub mod set_admin_signers;
 pub mod set_crank_authority;
 pub mod set_fees;
 pub mod set_limits;
 pub mod set_oracle_config;
+pub mod set_order_beneficiary;
+pub mod set_order_delegate;
 pub mod set_permissions;
+pub mod set_pool_slots;
+pub mod set_schedule;
 pub mod set_test_oracle_price;
 pub mod set_test_time;
 pub mod set_time_in_force;
 pub mod settle;
+pub mod submit_order_intent;
 pub mod test_init;
+pub mod tokenize_order;
+pub mod transfer_order;
+pub mod trigger_order;
+pub mod update_order;
 pub mod withdraw_fees;
+pub mod withdraw_proceeds;
 
 pub use cancel_order::*;
+pub use cancel_stop_order::*;
+pub use cancel_subscription::*;
+pub use cancel_virtual_order::*;
+pub use close_intent_nonce::*;
 pub use crank::*;
+pub use create_subscription::*;
 pub use delete_test_pair::*;
 pub use delete_test_pool::*;
+pub use execute_subscription::*;
+pub use forward_proceeds::*;
 pub use get_outstanding_amount::*;
 pub use init::*;
+pub use init_observations::*;
 pub use init_token_pair::*;
+pub use init_virtual_pool::*;
+pub use link_order::*;
+pub use migrate_account::*;
 pub use place_order::*;
+pub use place_stop_order::*;
+pub use place_virtual_order::*;
+pub use redeem_position::*;
+pub use refresh_order::*;
+pub use roll_order::*;
 pub use set_admin_signers::*;
 pub use set_crank_authority::*;
 pub use set_fees::*;
 pub use set_limits::*;
 pub use set_oracle_config::*;
+pub use set_order_beneficiary::*;
+pub use set_order_delegate::*;
 pub use set_permissions::*;
+pub use set_pool_slots::*;
+pub use set_schedule::*;
 pub use set_test_oracle_price::*;
 pub use set_test_time::*;
 pub use set_time_in_force::*;
 pub use settle::*;
+pub use submit_order_intent::*;
 pub use test_init::*;
+pub use tokenize_order::*;
+pub use transfer_order::*;
+pub use trigger_order::*;
+pub use update_order::*;
 pub use withdraw_fees::*;
+pub use withdraw_proceeds::*;
@@ -13,67 +13,74 @@ use {
     anchor_lang::{prelude::*, AccountsClose},
     anchor_spl::{
         associated_token::get_associated_token_address,
-        token::{Token, TokenAccount},
+        token::{self, Burn, Token},
+        token_interface::{Mint, TokenAccount, TokenInterface},
     },
 };
 
 #[derive(Accounts)]
 pub struct CancelOrder<'info> {
-    #[account()]
+    #[account(mut)]
     pub payer: Signer<'info>,
 
     /// CHECK: user's wallet
-    #[account()]
+    #[account(mut)]
     pub owner: AccountInfo<'info>,
 
+    // user token accounts can be omitted for the native mint to receive lamports
     #[account(
         mut,
-        constraint = user_account_token_a.mint == custody_token_a.mint,
-        has_one = owner
+        constraint = user_account_token_a.mint == custody_token_a.mint
     )]
-    pub user_account_token_a: Box<Account<'info, TokenAccount>>,
+    pub user_account_token_a: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
 
     #[account(
         mut,
-        constraint = user_account_token_b.mint == custody_token_b.mint,
-        has_one = owner
+        constraint = user_account_token_b.mint == custody_token_b.mint
     )]
-    pub user_account_token_b: Box<Account<'info, TokenAccount>>,
+    pub user_account_token_b: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
 
     #[account(
         mut,
         seeds = [b"token_pair",
-                 token_pair.config_a.mint.as_ref(),
-                 token_pair.config_b.mint.as_ref()],
-        bump = token_pair.token_pair_bump
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
     )]
-    pub token_pair: Box<Account<'info, TokenPair>>,
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    #[account(constraint = mint_token_a.key() == token_pair.load()?.config_a.mint)]
+    pub mint_token_a: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(constraint = mint_token_b.key() == token_pair.load()?.config_b.mint)]
+    pub mint_token_b: Box<InterfaceAccount<'info, Mint>>,
 
     /// CHECK: empty PDA, authority for token accounts
     #[account(
         mut,
         seeds = [b"transfer_authority"],
-        bump = token_pair.transfer_authority_bump
+        bump = token_pair.load()?.transfer_authority_bump
     )]
     pub transfer_authority: AccountInfo<'info>,
 
     #[account(
         mut,
-        constraint = custody_token_a.key() == token_pair.config_a.custody
+        constraint = custody_token_a.key() == token_pair.load()?.config_a.custody
     )]
-    pub custody_token_a: Box<Account<'info, TokenAccount>>,
+    pub custody_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
-        constraint = custody_token_b.key() == token_pair.config_b.custody
+        constraint = custody_token_b.key() == token_pair.load()?.config_b.custody
     )]
-    pub custody_token_b: Box<Account<'info, TokenAccount>>,
+    pub custody_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
         seeds = [b"order",
                  owner.key().as_ref(),
-                 pool.key().as_ref()],
+                 pool.key().as_ref(),
+                 Order::get_id_seed(order.id).as_slice()],
         bump = order.bump
     )]
     pub order: Box<Account<'info, Order>>,
@@ -83,70 +90,108 @@ pub struct CancelOrder<'info> {
         seeds = [b"pool",
                  custody_token_a.key().as_ref(),
                  custody_token_b.key().as_ref(),
-                 pool.time_in_force.to_le_bytes().as_slice(),
-                 pool.counter.to_le_bytes().as_slice()],
-        bump = pool.bump
+                 pool.load()?.time_in_force.to_le_bytes().as_slice(),
+                 pool.load()?.counter.to_le_bytes().as_slice()],
+        bump = pool.load()?.bump
     )]
-    pub pool: Box<Account<'info, Pool>>,
+    pub pool: AccountLoader<'info, Pool>,
 
-    token_program: Program<'info, Token>,
+    // position tokens of the order's pool side, only required to redeem them with the order
+    #[account(
+        mut,
+        seeds = [b"position_mint",
+                 pool.key().as_ref(),
+                 [order.side as u8].as_slice()],
+        bump
+    )]
+    pub position_mint: Option<Box<Account<'info, token::Mint>>>,
+
+    #[account(mut)]
+    pub user_position_account: Option<Box<Account<'info, token::TokenAccount>>>,
+
+    /// CHECK: temporary token account to pay out native SOL, seeds = [b"native_payout", payer],
+    /// only required if the native mint token account is omitted
+    #[account(mut)]
+    pub native_payout_account: Option<AccountInfo<'info>>,
+
+    system_program: Program<'info, System>,
+    token_program_a: Interface<'info, TokenInterface>,
+    token_program_b: Interface<'info, TokenInterface>,
+    token_program: Option<Program<'info, Token>>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct CancelOrderParams {
     lp_amount: u64,
+    // position tokens of the payer to burn and redeem along with the order
+    position_amount: u64,
+    // close the user's native mint token account to receive SOL
+    unwrap_native: bool,
 }
 
 pub fn cancel_order(ctx: Context<CancelOrder>, params: &CancelOrderParams) -> Result<()> {
     // validate inputs
-    require_gt!(params.lp_amount, 0u64, TwammError::InvalidTokenAmount);
+    require!(
+        params.lp_amount > 0 || params.position_amount > 0,
+        TwammError::InvalidTokenAmount
+    );
 
-    let token_pair = ctx.accounts.token_pair.as_mut();
+    let (mut token_pair, mut slots) = TokenPair::load_slots_mut(&ctx.accounts.token_pair)?;
     require!(
         token_pair.allow_withdrawals,
         TwammError::WithdrawalsNotAllowed
     );
 
-    // check if order is being canceled by the owner or pool is complete
-    // and then cancel can be permissionless
+    // check if order is being canceled by the owner or the delegate, or pool
+    // is complete and then cancel can be permissionless
     let current_time = token_pair.get_time()?;
-    let pool_complete = ctx.accounts.pool.is_complete(current_time)?;
+    let pool_complete = ctx.accounts.pool.load()?.is_complete(current_time)?;
     if ctx.accounts.owner.key() != ctx.accounts.payer.key() {
-        if !pool_complete {
+        if !pool_complete && !ctx.accounts.order.is_authorized(&ctx.accounts.payer.key()) {
             return Err(ProgramError::IllegalOwner.into());
         }
-        require_keys_eq!(
-            get_associated_token_address(
-                &ctx.accounts.owner.key(),
+        // only the owner can choose arbitrary recipient accounts
+        let recipient = ctx.accounts.order.get_recipient();
+        for (user_account, mint) in [
+            (
+                &ctx.accounts.user_account_token_a,
                 &ctx.accounts.custody_token_a.mint,
             ),
-            ctx.accounts.user_account_token_a.key()
-        );
-        require_keys_eq!(
-            get_associated_token_address(
-                &ctx.accounts.owner.key(),
+            (
+                &ctx.accounts.user_account_token_b,
                 &ctx.accounts.custody_token_b.mint,
             ),
-            ctx.accounts.user_account_token_b.key()
-        );
+        ] {
+            match user_account {
+                Some(user_account) => require_keys_eq!(
+                    get_associated_token_address(&recipient, mint),
+                    user_account.key()
+                ),
+                // lamports are paid to the owner's wallet
+                None => require_keys_eq!(
+                    recipient,
+                    ctx.accounts.owner.key(),
+                    TwammError::InvalidTokenAccount
+                ),
+            }
+        }
     }
 
     let order = ctx.accounts.order.as_mut();
+    let mut pool = ctx.accounts.pool.load_mut()?;
+    let expiration_time = pool.expiration_time;
+    let schedule = pool.schedule;
+    let pool_side = pool.get_side_mut(order.side, order.exact_output);
+    // Note: order.pool address is enforced with seeds
+
+    // delayed order moves to the pool side if its start slot has joined it
+    order.sync_start(pool_side)?;
     let lp_amount = if params.lp_amount > order.lp_balance || pool_complete {
         order.lp_balance
     } else {
         params.lp_amount
     };
 
-    let pool = ctx.accounts.pool.as_mut();
-    let expiration_time = pool.expiration_time;
-    let pool_side = if order.side == OrderSide::Buy {
-        &mut pool.buy_side
-    } else {
-        &mut pool.sell_side
-    };
-    // Note: order.pool address is enforced with seeds
-
     assert!(
         lp_amount <= pool_side.lp_supply,
         "Unexpected LP balance error"
@@ -154,36 +199,48 @@ pub fn cancel_order(ctx: Context<CancelOrder>, params: &CancelOrderParams) -> Re
 
     // compute balance changes
     msg!("Compute balance changes");
-    let withdraw_amount_source = math::checked_as_u64(math::checked_div(
-        math::checked_mul(lp_amount as u128, pool_side.source_balance as u128)?,
-        pool_side.lp_supply as u128,
-    )?)?;
+    let mut withdraw_amount_source = 0;
+    let mut target_pending_removed = 0;
+    let mut token_debt_removed = 0;
+    let mut withdraw_amount_target = 0;
+    let mut budget_removed = 0;
+    let mut budget_debt_removed = 0;
+    if lp_amount > 0 {
+        if order.exact_output {
+            // exact-output orders get back their own unspent budget
+            (withdraw_amount_source, budget_removed, budget_debt_removed) =
+                order.get_budget_withdrawal(pool_side, lp_amount)?;
+        } else {
+            withdraw_amount_source = math::checked_as_u64(math::checked_div(
+                math::checked_mul(lp_amount as u128, pool_side.source_balance as u128)?,
+                pool_side.lp_supply as u128,
+            )?)?;
+        }
 
-    let mut token_debt_removed = math::checked_as_u64(math::checked_ceil_div(
-        math::checked_mul(order.token_debt as u128, lp_amount as u128)?,
-        order.lp_balance as u128,
-    )?)?;
+        target_pending_removed = math::checked_as_u64(math::checked_div(
+            math::checked_mul(lp_amount as u128, pool_side.target_pending as u128)?,
+            pool_side.lp_supply as u128,
+        )?)?;
 
-    let mut withdraw_amount_target = math::checked_as_u64(math::checked_div(
-        math::checked_mul(
-            lp_amount as u128,
-            math::checked_add(
-                pool_side.target_balance as u128,
-                pool_side.token_debt_total as u128,
-            )?,
-        )?,
-        pool_side.lp_supply as u128,
-    )?)?;
+        token_debt_removed = math::checked_as_u64(math::checked_ceil_div(
+            math::checked_mul(order.token_debt as u128, lp_amount as u128)?,
+            order.lp_balance as u128,
+        )?)?;
 
-    if withdraw_amount_target > token_debt_removed {
-        withdraw_amount_target = math::checked_sub(withdraw_amount_target, token_debt_removed)?;
-    } else {
-        withdraw_amount_target = 0;
+        withdraw_amount_target = pool_side.get_proceeds(lp_amount, token_debt_removed)?;
     }
 
+    // balances held out of the pool are released when the order is closed
+    let (held_balance, held_proceeds) = if order.lp_balance == lp_amount {
+        order.remove_pending_start(pool_side)?;
+        (order.held_balance, order.held_proceeds)
+    } else {
+        (0, 0)
+    };
+
     let withdraw_amount_fees = math::checked_as_u64(math::checked_ceil_div(
         math::checked_mul(
-            withdraw_amount_target as u128,
+            math::checked_add(withdraw_amount_target, held_proceeds)? as u128,
             token_pair.fee_numerator as u128,
         )?,
         token_pair.fee_denominator as u128,
@@ -194,6 +251,7 @@ pub fn cancel_order(ctx: Context<CancelOrder>, params: &CancelOrderParams) -> Re
     let order_lp_balance = order.lp_balance;
     if order_lp_balance == lp_amount {
         pool_side.num_traders = math::checked_sub(pool_side.num_traders, 1)?;
+        order.remove_price_bounds(pool_side)?;
         order.lp_balance = 0;
     } else {
         order.lp_balance = math::checked_sub(order_lp_balance, lp_amount)?;
@@ -202,9 +260,19 @@ pub fn cancel_order(ctx: Context<CancelOrder>, params: &CancelOrderParams) -> Re
         }
         order.token_debt = math::checked_sub(order.token_debt, token_debt_removed)?;
     }
-
-    order.settlement_debt = order.get_unsettled_amount(expiration_time, current_time)?;
-    order.unsettled_balance = math::checked_sub(order.unsettled_balance, withdraw_amount_source)?;
+    order.budget = math::checked_sub(order.budget, budget_removed)?;
+    order.budget_debt = math::checked_sub(order.budget_debt, budget_debt_removed)?;
+
+    order.settlement_debt = order.get_unsettled_amount(&schedule, expiration_time, current_time)?;
+    // unsettled balance of exact-output orders is in target tokens
+    order.unsettled_balance = math::checked_sub(
+        order.unsettled_balance,
+        if order.exact_output {
+            target_pending_removed
+        } else {
+            withdraw_amount_source
+        },
+    )?;
     order.last_balance_change_time = current_time;
     let order_debt_removed = if order_lp_balance == lp_amount {
         order.settlement_debt
@@ -221,13 +289,84 @@ pub fn cancel_order(ctx: Context<CancelOrder>, params: &CancelOrderParams) -> Re
     pool_side.target_balance = math::checked_sub(pool_side.target_balance, withdraw_amount_target)?;
     pool_side.lp_supply = math::checked_sub(pool_side.lp_supply, lp_amount)?;
     pool_side.token_debt_total = math::checked_sub(pool_side.token_debt_total, token_debt_removed)?;
-    pool_side.settlement_debt_total = pool_side
-        .get_unsettled_amount(expiration_time, current_time)?
-        .saturating_sub(order_debt_removed);
+    pool_side.budget_total = math::checked_sub(pool_side.budget_total, budget_removed)?;
+    pool_side.budget_debt_total =
+        math::checked_sub(pool_side.budget_debt_total, budget_debt_removed)?;
+    let side_unsettled_amount = if order.exact_output {
+        pool_side.get_unsettled_target_amount(&schedule, expiration_time, current_time)?
+    } else {
+        pool_side.get_unsettled_amount(&schedule, expiration_time, current_time)?
+    };
+    pool_side.settlement_debt_total = side_unsettled_amount.saturating_sub(order_debt_removed);
+    pool_side.target_pending = math::checked_sub(pool_side.target_pending, target_pending_removed)?;
     pool_side.last_balance_change_time = current_time;
+    pool_side.held_balance_total = math::checked_sub(pool_side.held_balance_total, held_balance)?;
+    pool_side.held_proceeds_total =
+        math::checked_sub(pool_side.held_proceeds_total, held_proceeds)?;
+
+    // burn position tokens of the payer and redeem them with the order
+    let mut position_amount_source = 0;
+    let mut position_amount_target = 0;
+    let mut position_amount_fees = 0;
+    if params.position_amount > 0 {
+        let (position_mint, user_position_account, position_token_program) = match (
+            &ctx.accounts.position_mint,
+            &ctx.accounts.user_position_account,
+            &ctx.accounts.token_program,
+        ) {
+            (Some(mint), Some(user_account), Some(token_program)) => {
+                (mint, user_account, token_program)
+            }
+            _ => {
+                msg!("Error: Position tokens require position accounts and token program");
+                return err!(TwammError::InvalidTokenAccount);
+            }
+        };
+        // position tokens are bearer tokens, proceeds go to the order's recipient
+        // only if the payer is the owner
+        if ctx.accounts.payer.key() != ctx.accounts.owner.key() || order.exact_output {
+            return err!(TwammError::OrderNotAuthorized);
+        }
+        require_keys_eq!(
+            user_position_account.mint,
+            position_mint.key(),
+            TwammError::InvalidTokenAccount
+        );
+
+        let position_amount = std::cmp::min(params.position_amount, user_position_account.amount);
+        msg!("Redeem position tokens: {}", position_amount);
+        (position_amount_source, position_amount_target) =
+            pool_side.redeem_position(position_amount, &schedule, expiration_time, current_time)?;
+        position_amount_fees = math::checked_as_u64(math::checked_ceil_div(
+            math::checked_mul(
+                position_amount_target as u128,
+                token_pair.fee_numerator as u128,
+            )?,
+            token_pair.fee_denominator as u128,
+        )?)?;
+
+        let context = CpiContext::new(
+            position_token_program.to_account_info(),
+            Burn {
+                mint: position_mint.to_account_info(),
+                from: user_position_account.to_account_info(),
+                authority: ctx.accounts.payer.to_account_info(),
+            },
+        );
+        token::burn(context, position_amount)?;
+    }
 
     // transfer tokens to the user
     msg!("Transfer tokens to the user");
+    let withdraw_amount_fees = math::checked_add(withdraw_amount_fees, position_amount_fees)?;
+    let withdraw_amount_source = math::checked_add(
+        math::checked_add(withdraw_amount_source, held_balance)?,
+        position_amount_source,
+    )?;
+    let withdraw_amount_target = math::checked_add(
+        math::checked_add(withdraw_amount_target, held_proceeds)?,
+        position_amount_target,
+    )?;
     let (withdraw_amount_a, withdraw_amount_b) = if order.side == OrderSide::Sell {
         (
             withdraw_amount_source,
@@ -240,21 +379,71 @@ pub fn cancel_order(ctx: Context<CancelOrder>, params: &CancelOrderParams) -> Re
         )
     };
 
-    token_pair.transfer_tokens(
-        ctx.accounts.custody_token_a.to_account_info(),
-        ctx.accounts.user_account_token_a.to_account_info(),
-        ctx.accounts.transfer_authority.clone(),
-        ctx.accounts.token_program.to_account_info(),
-        withdraw_amount_a,
-    )?;
+    for (user_account, custody, mint, token_program, amount) in [
+        (
+            &ctx.accounts.user_account_token_a,
+            &ctx.accounts.custody_token_a,
+            &ctx.accounts.mint_token_a,
+            &ctx.accounts.token_program_a,
+            withdraw_amount_a,
+        ),
+        (
+            &ctx.accounts.user_account_token_b,
+            &ctx.accounts.custody_token_b,
+            &ctx.accounts.mint_token_b,
+            &ctx.accounts.token_program_b,
+            withdraw_amount_b,
+        ),
+    ] {
+        match (user_account, &ctx.accounts.native_payout_account) {
+            (Some(user_account), _) => token_pair.transfer_tokens(
+                custody.to_account_info(),
+                user_account.to_account_info(),
+                mint.to_account_info(),
+                ctx.accounts.transfer_authority.clone(),
+                token_program.to_account_info(),
+                amount,
+            )?,
+            (None, Some(payout_account)) => token_pair.withdraw_native_tokens(
+                custody.to_account_info(),
+                payout_account.clone(),
+                mint.to_account_info(),
+                ctx.accounts.transfer_authority.clone(),
+                ctx.accounts.payer.to_account_info(),
+                ctx.accounts.owner.clone(),
+                ctx.accounts.system_program.to_account_info(),
+                token_program.to_account_info(),
+                amount,
+            )?,
+            _ => {
+                msg!("Error: Native SOL payout requires the payout account");
+                return err!(TwammError::InvalidTokenAccount);
+            }
+        }
+    }
 
-    token_pair.transfer_tokens(
-        ctx.accounts.custody_token_b.to_account_info(),
-        ctx.accounts.user_account_token_b.to_account_info(),
-        ctx.accounts.transfer_authority.clone(),
-        ctx.accounts.token_program.to_account_info(),
-        withdraw_amount_b,
-    )?;
+    // unwrap native SOL if requested, the owner must sign to close the account
+    if params.unwrap_native {
+        for (user_account, token_program) in [
+            (
+                &ctx.accounts.user_account_token_a,
+                &ctx.accounts.token_program_a,
+            ),
+            (
+                &ctx.accounts.user_account_token_b,
+                &ctx.accounts.token_program_b,
+            ),
+        ] {
+            if let Some(user_account) = user_account.as_ref().filter(|a| a.is_native()) {
+                msg!("Unwrap native SOL");
+                TokenPair::unwrap_native_tokens(
+                    user_account.to_account_info(),
+                    ctx.accounts.owner.to_account_info(),
+                    token_program.to_account_info(),
+                )?;
+            }
+        }
+    }
 
     // update token pair stats
     msg!("Update token pair stats");
@@ -283,8 +472,8 @@ pub fn cancel_order(ctx: Context<CancelOrder>, params: &CancelOrderParams) -> Re
     // update pool state and close pool account if pool is empty and not current
     msg!("Update pool state");
     pool.update_state(token_pair.min_time_till_expiration, current_time)?;
-    if let Ok(tif_index) = token_pair.get_tif_index(pool.time_in_force) {
-        if token_pair.pool_counters[tif_index] != pool.counter {
+    if let Ok(tif_index) = TokenPair::get_tif_index(&slots, pool.time_in_force) {
+        if slots[tif_index].pool_counter != pool.counter {
             token_pair.stats_a.pending_withdrawals = token_pair
                 .stats_a
                 .pending_withdrawals
@@ -296,10 +485,11 @@ pub fn cancel_order(ctx: Context<CancelOrder>, params: &CancelOrderParams) -> Re
 
             if pool.is_empty() {
                 msg!("Close pool account");
-                if pool.counter == math::checked_add(token_pair.pool_counters[tif_index], 1)? {
-                    token_pair.future_pool_present[tif_index] = false;
+                if pool.counter == math::checked_add(slots[tif_index].pool_counter, 1)? {
+                    slots[tif_index].future_pool_present = false;
                 }
-                pool.set_inner(Pool::default());
+                *pool = Pool::default();
+                drop(pool);
                 ctx.accounts
                     .pool
                     .close(ctx.accounts.transfer_authority.to_account_info())?;
@@ -0,0 +1,147 @@
+//! Cancel stop order instruction handler
+
+use {
+    crate::{
+        error::TwammError,
+        state::{order::OrderSide, stop_order::StopOrder, token_pair::TokenPair},
+    },
+    anchor_lang::{prelude::*, AccountsClose},
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface},
+};
+
+#[derive(Accounts)]
+pub struct CancelStopOrder<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    // user token accounts can be omitted for the native mint to receive lamports
+    #[account(
+        mut,
+        constraint = user_account_token_a.mint == custody_token_a.mint,
+        has_one = owner
+    )]
+    pub user_account_token_a: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        constraint = user_account_token_b.mint == custody_token_b.mint,
+        has_one = owner
+    )]
+    pub user_account_token_b: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    #[account(constraint = mint_token_a.key() == token_pair.load()?.config_a.mint)]
+    pub mint_token_a: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(constraint = mint_token_b.key() == token_pair.load()?.config_b.mint)]
+    pub mint_token_b: Box<InterfaceAccount<'info, Mint>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        mut,
+        seeds = [b"transfer_authority"],
+        bump = token_pair.load()?.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = custody_token_a.key() == token_pair.load()?.config_a.custody
+    )]
+    pub custody_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = custody_token_b.key() == token_pair.load()?.config_b.custody
+    )]
+    pub custody_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"stop_order",
+                 owner.key().as_ref(),
+                 token_pair.key().as_ref(),
+                 stop_order.id.to_le_bytes().as_slice()],
+        bump = stop_order.bump
+    )]
+    pub stop_order: Box<Account<'info, StopOrder>>,
+
+    /// CHECK: temporary token account to pay out native SOL, seeds = [b"native_payout", owner],
+    /// only required if the native mint token account is omitted
+    #[account(mut)]
+    pub native_payout_account: Option<AccountInfo<'info>>,
+
+    system_program: Program<'info, System>,
+    token_program_a: Interface<'info, TokenInterface>,
+    token_program_b: Interface<'info, TokenInterface>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct CancelStopOrderParams {}
+
+pub fn cancel_stop_order(
+    ctx: Context<CancelStopOrder>,
+    _params: &CancelStopOrderParams,
+) -> Result<()> {
+    let token_pair = ctx.accounts.token_pair.load()?;
+    let stop_order = ctx.accounts.stop_order.as_mut();
+
+    msg!("Transfer tokens to the user");
+    msg!("Amount refunded: {}", stop_order.amount);
+    let (user_account, custody, mint, token_program) = if stop_order.side == OrderSide::Sell {
+        (
+            &ctx.accounts.user_account_token_a,
+            &ctx.accounts.custody_token_a,
+            &ctx.accounts.mint_token_a,
+            &ctx.accounts.token_program_a,
+        )
+    } else {
+        (
+            &ctx.accounts.user_account_token_b,
+            &ctx.accounts.custody_token_b,
+            &ctx.accounts.mint_token_b,
+            &ctx.accounts.token_program_b,
+        )
+    };
+    match (user_account, &ctx.accounts.native_payout_account) {
+        (Some(user_account), _) => token_pair.transfer_tokens(
+            custody.to_account_info(),
+            user_account.to_account_info(),
+            mint.to_account_info(),
+            ctx.accounts.transfer_authority.clone(),
+            token_program.to_account_info(),
+            stop_order.amount,
+        )?,
+        (None, Some(payout_account)) => token_pair.withdraw_native_tokens(
+            custody.to_account_info(),
+            payout_account.clone(),
+            mint.to_account_info(),
+            ctx.accounts.transfer_authority.clone(),
+            ctx.accounts.owner.to_account_info(),
+            ctx.accounts.owner.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+            token_program.to_account_info(),
+            stop_order.amount,
+        )?,
+        _ => {
+            msg!("Error: Native SOL payout requires the payout account");
+            return err!(TwammError::InvalidTokenAccount);
+        }
+    }
+
+    // prefunded order rent is returned with the account
+    msg!("Close stop order account");
+    stop_order.set_inner(StopOrder::default());
+    ctx.accounts
+        .stop_order
+        .close(ctx.accounts.owner.to_account_info())?;
+
+    Ok(())
+}
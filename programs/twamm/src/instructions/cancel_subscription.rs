@@ -0,0 +1,122 @@
+//! Cancel subscription instruction handler
+
+use {
+    crate::state::{order::OrderSide, subscription::Subscription, token_pair::TokenPair},
+    anchor_lang::{prelude::*, AccountsClose},
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface},
+};
+
+#[derive(Accounts)]
+pub struct CancelSubscription<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = user_account_token_a.mint == custody_token_a.mint,
+        has_one = owner
+    )]
+    pub user_account_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_account_token_b.mint == custody_token_b.mint,
+        has_one = owner
+    )]
+    pub user_account_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    #[account(constraint = mint_token_a.key() == token_pair.load()?.config_a.mint)]
+    pub mint_token_a: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(constraint = mint_token_b.key() == token_pair.load()?.config_b.mint)]
+    pub mint_token_b: Box<InterfaceAccount<'info, Mint>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        mut,
+        seeds = [b"transfer_authority"],
+        bump = token_pair.load()?.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = custody_token_a.key() == token_pair.load()?.config_a.custody
+    )]
+    pub custody_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = custody_token_b.key() == token_pair.load()?.config_b.custody
+    )]
+    pub custody_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"subscription",
+                 owner.key().as_ref(),
+                 token_pair.key().as_ref(),
+                 subscription.id.to_le_bytes().as_slice()],
+        bump = subscription.bump
+    )]
+    pub subscription: Box<Account<'info, Subscription>>,
+
+    token_program_a: Interface<'info, TokenInterface>,
+    token_program_b: Interface<'info, TokenInterface>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct CancelSubscriptionParams {}
+
+pub fn cancel_subscription(
+    ctx: Context<CancelSubscription>,
+    _params: &CancelSubscriptionParams,
+) -> Result<()> {
+    // orders that have already been placed are cancelled separately
+    let token_pair = ctx.accounts.token_pair.load()?;
+    let subscription = ctx.accounts.subscription.as_mut();
+    let refund_amount = subscription.get_escrowed_amount()?;
+
+    msg!("Transfer tokens to the user");
+    msg!("Amount refunded: {}", refund_amount);
+    let (user_account, custody, mint, token_program) = if subscription.side == OrderSide::Sell {
+        (
+            &ctx.accounts.user_account_token_a,
+            &ctx.accounts.custody_token_a,
+            &ctx.accounts.mint_token_a,
+            &ctx.accounts.token_program_a,
+        )
+    } else {
+        (
+            &ctx.accounts.user_account_token_b,
+            &ctx.accounts.custody_token_b,
+            &ctx.accounts.mint_token_b,
+            &ctx.accounts.token_program_b,
+        )
+    };
+    token_pair.transfer_tokens(
+        custody.to_account_info(),
+        user_account.to_account_info(),
+        mint.to_account_info(),
+        ctx.accounts.transfer_authority.clone(),
+        token_program.to_account_info(),
+        refund_amount,
+    )?;
+
+    // prefunded rent of the orders not placed is returned with the account
+    msg!("Close subscription account");
+    subscription.set_inner(Subscription::default());
+    ctx.accounts
+        .subscription
+        .close(ctx.accounts.owner.to_account_info())?;
+
+    Ok(())
+}
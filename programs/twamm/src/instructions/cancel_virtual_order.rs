@@ -0,0 +1,278 @@
+//! Cancel virtual order instruction handler
+
+use {
+    crate::{
+        error::TwammError,
+        math,
+        state::{
+            order::OrderSide, token_pair::TokenPair, virtual_checkpoint::VirtualCheckpoint,
+            virtual_order::VirtualOrder, virtual_pool::VirtualPool,
+        },
+    },
+    anchor_lang::{prelude::*, AccountsClose},
+    anchor_spl::{
+        associated_token::get_associated_token_address,
+        token_interface::{Mint, TokenAccount, TokenInterface},
+    },
+};
+
+#[derive(Accounts)]
+pub struct CancelVirtualOrder<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: user's wallet
+    #[account(mut)]
+    pub owner: AccountInfo<'info>,
+
+    // user token accounts can be omitted for the native mint to receive lamports
+    #[account(
+        mut,
+        constraint = user_account_token_a.mint == custody_token_a.mint,
+        has_one = owner
+    )]
+    pub user_account_token_a: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        constraint = user_account_token_b.mint == custody_token_b.mint,
+        has_one = owner
+    )]
+    pub user_account_token_b: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    #[account(constraint = mint_token_a.key() == token_pair.load()?.config_a.mint)]
+    pub mint_token_a: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(constraint = mint_token_b.key() == token_pair.load()?.config_b.mint)]
+    pub mint_token_b: Box<InterfaceAccount<'info, Mint>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        mut,
+        seeds = [b"transfer_authority"],
+        bump = token_pair.load()?.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = custody_token_a.key() == token_pair.load()?.config_a.custody
+    )]
+    pub custody_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = custody_token_b.key() == token_pair.load()?.config_b.custody
+    )]
+    pub custody_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"virtual_order",
+                 owner.key().as_ref(),
+                 virtual_pool.key().as_ref(),
+                 order.expiration_time.to_le_bytes().as_slice()],
+        bump = order.bump
+    )]
+    pub order: Box<Account<'info, VirtualOrder>>,
+
+    #[account(
+        mut,
+        seeds = [b"virtual_pool",
+                 token_pair.key().as_ref()],
+        bump = virtual_pool.bump
+    )]
+    pub virtual_pool: Box<Account<'info, VirtualPool>>,
+
+    /// CHECK: temporary token account to pay out native SOL, seeds = [b"native_payout", payer],
+    /// only required if the native mint token account is omitted
+    #[account(mut)]
+    pub native_payout_account: Option<AccountInfo<'info>>,
+
+    system_program: Program<'info, System>,
+    token_program_a: Interface<'info, TokenInterface>,
+    token_program_b: Interface<'info, TokenInterface>,
+    // remaining accounts:
+    //   checkpoint accounts for order start and expiration buckets that have been
+    //   released from the virtual pool ring
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct CancelVirtualOrderParams {}
+
+pub fn cancel_virtual_order<'info>(
+    ctx: Context<'_, '_, '_, 'info, CancelVirtualOrder<'info>>,
+    _params: &CancelVirtualOrderParams,
+) -> Result<()> {
+    let mut token_pair = ctx.accounts.token_pair.load_mut()?;
+    require!(
+        token_pair.allow_withdrawals,
+        TwammError::WithdrawalsNotAllowed
+    );
+
+    // check if order is being canceled by the owner or order is complete
+    // and then cancel can be permissionless
+    let virtual_pool = ctx.accounts.virtual_pool.as_mut();
+    let order = ctx.accounts.order.as_mut();
+    if ctx.accounts.owner.key() != ctx.accounts.payer.key() {
+        if !virtual_pool.is_order_complete(order) {
+            return Err(ProgramError::IllegalOwner.into());
+        }
+        // omitted accounts are paid to the owner's wallet
+        if let Some(user_account) = &ctx.accounts.user_account_token_a {
+            require_keys_eq!(
+                get_associated_token_address(
+                    &ctx.accounts.owner.key(),
+                    &ctx.accounts.custody_token_a.mint,
+                ),
+                user_account.key()
+            );
+        }
+        if let Some(user_account) = &ctx.accounts.user_account_token_b {
+            require_keys_eq!(
+                get_associated_token_address(
+                    &ctx.accounts.owner.key(),
+                    &ctx.accounts.custody_token_b.mint,
+                ),
+                user_account.key()
+            );
+        }
+    }
+
+    // remove the order from the pool, unexecuted part of the current
+    // interval is returned to the user
+    let mut checkpoints = vec![];
+    for account in ctx.remaining_accounts {
+        let checkpoint = Account::<VirtualCheckpoint>::try_from(account)?;
+        if checkpoints
+            .iter()
+            .any(|other: &Account<VirtualCheckpoint>| other.key() == checkpoint.key())
+            || checkpoint.virtual_pool != virtual_pool.key()
+            || (checkpoint.bucket.time != order.start_time
+                && checkpoint.bucket.time != order.expiration_time)
+        {
+            msg!(
+                "Error: Checkpoint {} doesn't belong to the order",
+                account.key
+            );
+            return err!(TwammError::InvalidPoolState);
+        }
+        checkpoints.push(checkpoint);
+    }
+    let mut buckets: Vec<_> = checkpoints
+        .iter()
+        .map(|checkpoint| checkpoint.bucket)
+        .collect();
+
+    msg!("Update pool rates");
+    let (withdraw_amount_source, withdraw_amount_target) =
+        virtual_pool.remove_order(order, &mut buckets)?;
+
+    // checkpoints are closed once the last order that refers to them is cancelled
+    for (checkpoint, bucket) in checkpoints.iter_mut().zip(buckets) {
+        if bucket.num_orders == 0 {
+            checkpoint.close(ctx.accounts.transfer_authority.to_account_info())?;
+        } else {
+            checkpoint.bucket = bucket;
+            checkpoint.exit(&crate::ID)?;
+        }
+    }
+
+    let withdraw_amount_fees = math::checked_as_u64(math::checked_ceil_div(
+        math::checked_mul(
+            withdraw_amount_target as u128,
+            token_pair.fee_numerator as u128,
+        )?,
+        token_pair.fee_denominator as u128,
+    )?)?;
+
+    // transfer tokens to the user
+    msg!("Transfer tokens to the user");
+    let (withdraw_amount_a, withdraw_amount_b) = if order.side == OrderSide::Sell {
+        (
+            withdraw_amount_source,
+            math::checked_sub(withdraw_amount_target, withdraw_amount_fees)?,
+        )
+    } else {
+        (
+            math::checked_sub(withdraw_amount_target, withdraw_amount_fees)?,
+            withdraw_amount_source,
+        )
+    };
+
+    for (user_account, custody, mint, token_program, amount) in [
+        (
+            &ctx.accounts.user_account_token_a,
+            &ctx.accounts.custody_token_a,
+            &ctx.accounts.mint_token_a,
+            &ctx.accounts.token_program_a,
+            withdraw_amount_a,
+        ),
+        (
+            &ctx.accounts.user_account_token_b,
+            &ctx.accounts.custody_token_b,
+            &ctx.accounts.mint_token_b,
+            &ctx.accounts.token_program_b,
+            withdraw_amount_b,
+        ),
+    ] {
+        match (user_account, &ctx.accounts.native_payout_account) {
+            (Some(user_account), _) => token_pair.transfer_tokens(
+                custody.to_account_info(),
+                user_account.to_account_info(),
+                mint.to_account_info(),
+                ctx.accounts.transfer_authority.clone(),
+                token_program.to_account_info(),
+                amount,
+            )?,
+            (None, Some(payout_account)) => token_pair.withdraw_native_tokens(
+                custody.to_account_info(),
+                payout_account.clone(),
+                mint.to_account_info(),
+                ctx.accounts.transfer_authority.clone(),
+                ctx.accounts.payer.to_account_info(),
+                ctx.accounts.owner.clone(),
+                ctx.accounts.system_program.to_account_info(),
+                token_program.to_account_info(),
+                amount,
+            )?,
+            _ => {
+                msg!("Error: Native SOL payout requires the payout account");
+                return err!(TwammError::InvalidTokenAccount);
+            }
+        }
+    }
+
+    // update token pair stats
+    msg!("Update token pair stats");
+    if order.side == OrderSide::Sell {
+        token_pair.stats_b.fees_collected = token_pair
+            .stats_b
+            .fees_collected
+            .saturating_add(withdraw_amount_fees);
+    } else {
+        token_pair.stats_a.fees_collected = token_pair
+            .stats_a
+            .fees_collected
+            .saturating_add(withdraw_amount_fees);
+    };
+
+    // close order account
+    msg!("Close order account");
+    // rent exempt payment is not refundable to prevent spoofing
+    order.set_inner(VirtualOrder::default());
+    ctx.accounts
+        .order
+        .close(ctx.accounts.transfer_authority.to_account_info())?;
+
+    Ok(())
+}
@@ -0,0 +1,57 @@
+//! Close intent nonce instruction handler
+
+use {
+    crate::{
+        error::TwammError,
+        state::{order_intent::IntentNonce, token_pair::TokenPair},
+    },
+    anchor_lang::{prelude::*, AccountsClose},
+};
+
+#[derive(Accounts)]
+pub struct CloseIntentNonce<'info> {
+    /// CHECK: relayer that paid for the nonce account, receives its rent back
+    #[account(mut)]
+    pub payer: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    #[account(
+        mut,
+        seeds = [b"intent_nonce",
+                 intent_nonce.owner.as_ref(),
+                 intent_nonce.nonce.to_le_bytes().as_slice()],
+        bump = intent_nonce.bump,
+        has_one = payer
+    )]
+    pub intent_nonce: Box<Account<'info, IntentNonce>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct CloseIntentNonceParams {}
+
+pub fn close_intent_nonce(
+    ctx: Context<CloseIntentNonce>,
+    _params: &CloseIntentNonceParams,
+) -> Result<()> {
+    // anyone can close the nonce after the intent expires, rent goes to the payer
+    let current_time = ctx.accounts.token_pair.load()?.get_time()?;
+    if !ctx.accounts.intent_nonce.is_closable(current_time) {
+        msg!("Error: Intent nonce is required until the intent expires");
+        return err!(TwammError::IntentNotExpired);
+    }
+
+    msg!("Close intent nonce account");
+    ctx.accounts.intent_nonce.set_inner(IntentNonce::default());
+    ctx.accounts
+        .intent_nonce
+        .close(ctx.accounts.payer.to_account_info())?;
+
+    Ok(())
+}
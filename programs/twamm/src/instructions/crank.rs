@@ -6,12 +6,13 @@ use {
         math,
         oracle::OraclePrice,
         state::{
+            observations::Observations,
             pool::Pool,
             token_pair::{MatchingSide, SettlementType, TokenPair},
         },
     },
     anchor_lang::prelude::*,
-    anchor_spl::token::{Token, TokenAccount},
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface},
     solana_program::{
         instruction::{AccountMeta, Instruction},
         program,
@@ -30,7 +31,7 @@ pub struct Crank<'info> {
         constraint = user_account_token_a.mint == custody_token_a.mint,
         has_one = owner
     )]
-    pub user_account_token_a: Box<Account<'info, TokenAccount>>,
+    pub user_account_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
 
     // crank rewards receiver
     #[account(
@@ -38,51 +39,68 @@ pub struct Crank<'info> {
         constraint = user_account_token_b.mint == custody_token_b.mint,
         has_one = owner
     )]
-    pub user_account_token_b: Box<Account<'info, TokenAccount>>,
+    pub user_account_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
         seeds = [b"token_pair",
-                 token_pair.config_a.mint.as_ref(),
-                 token_pair.config_b.mint.as_ref()],
-        bump = token_pair.token_pair_bump)]
-    pub token_pair: Box<Account<'info, TokenPair>>,
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump)]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    #[account(constraint = mint_token_a.key() == token_pair.load()?.config_a.mint)]
+    pub mint_token_a: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(constraint = mint_token_b.key() == token_pair.load()?.config_b.mint)]
+    pub mint_token_b: Box<InterfaceAccount<'info, Mint>>,
 
     /// CHECK: empty PDA, authority for token accounts
     #[account(
         mut,
         seeds = [b"transfer_authority"],
-        bump = token_pair.transfer_authority_bump
+        bump = token_pair.load()?.transfer_authority_bump
     )]
     pub transfer_authority: AccountInfo<'info>,
 
     #[account(
         mut,
-        constraint = custody_token_a.key() == token_pair.config_a.custody
+        constraint = custody_token_a.key() == token_pair.load()?.config_a.custody
     )]
-    pub custody_token_a: Box<Account<'info, TokenAccount>>,
+    pub custody_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
-        constraint = custody_token_b.key() == token_pair.config_b.custody
+        constraint = custody_token_b.key() == token_pair.load()?.config_b.custody
     )]
-    pub custody_token_b: Box<Account<'info, TokenAccount>>,
+    pub custody_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// CHECK: oracle account for token a depending on oracle type
     #[account(
-        constraint = oracle_token_a.key() == token_pair.config_a.oracle_account
+        constraint = oracle_token_a.key() == token_pair.load()?.config_a.oracle_account
     )]
     pub oracle_token_a: AccountInfo<'info>,
 
     /// CHECK: oracle account for token b depending on oracle type
     #[account(
-        constraint = oracle_token_b.key() == token_pair.config_b.oracle_account
+        constraint = oracle_token_b.key() == token_pair.load()?.config_b.oracle_account
     )]
     pub oracle_token_b: AccountInfo<'info>,
 
-    token_program: Program<'info, Token>,
+    // fills are recorded if the pair has an observations account
+    #[account(
+        mut,
+        seeds = [b"observations",
+                 token_pair.key().as_ref()],
+        bump = observations.load()?.bump
+    )]
+    pub observations: Option<AccountLoader<'info, Observations>>,
+
+    token_program_a: Interface<'info, TokenInterface>,
+    token_program_b: Interface<'info, TokenInterface>,
     // remaining accounts:
-    //   1 to TokenPair::MAX_POOLS addresses of current pool accounts (write, unsigned)
+    //   1 to TokenPair::get_num_slots() addresses of current pool accounts (write, unsigned)
+    //   Optional virtual pool account (write, unsigned)
     //   Router program (only Jupiter for now)
     //   Router accounts (as is)
 }
@@ -94,7 +112,7 @@ pub struct CrankParams {
 
 pub fn crank(ctx: Context<Crank>, params: &CrankParams) -> Result<i64> {
     // validate inputs
-    let token_pair = ctx.accounts.token_pair.as_mut();
+    let (mut token_pair, mut slots) = TokenPair::load_slots_mut(&ctx.accounts.token_pair)?;
     require!(token_pair.allow_cranks, TwammError::CranksNotAllowed);
 
     if token_pair.crank_authority != Pubkey::default()
@@ -106,8 +124,13 @@ pub fn crank(ctx: Context<Crank>, params: &CrankParams) -> Result<i64> {
 
     // collect and validate pools
     msg!("Load pools");
-    let (mut pools, router_program) = token_pair.load_pools(ctx.remaining_accounts)?;
-    require!(!pools.is_empty(), TwammError::NothingToSettle);
+    let (pools, mut virtual_pool, router_program) =
+        token_pair.load_pools(&slots, ctx.remaining_accounts)?;
+    require!(
+        !pools.is_empty() || virtual_pool.is_some(),
+        TwammError::NothingToSettle
+    );
+    let num_pool_accounts = pools.len() + usize::from(virtual_pool.is_some());
 
     let token_a_change;
     let token_b_change;
@@ -120,7 +143,7 @@ pub fn crank(ctx: Context<Crank>, params: &CrankParams) -> Result<i64> {
     if router_program != Pubkey::default() {
         // perform swap
         msg!("Perform swap");
-        if ctx.remaining_accounts.len() < pools.len() + 5 {
+        if ctx.remaining_accounts.len() < num_pool_accounts + 5 {
             return Err(ProgramError::NotEnoughAccountKeys.into());
         }
         let initial_token_a_balance = ctx.accounts.custody_token_a.amount;
@@ -130,7 +153,7 @@ pub fn crank(ctx: Context<Crank>, params: &CrankParams) -> Result<i64> {
             &[&[b"transfer_authority", &[token_pair.transfer_authority_bump]]];
 
         let mut router_accounts = vec![];
-        for account in &ctx.remaining_accounts[(pools.len() + 1)..] {
+        for account in &ctx.remaining_accounts[(num_pool_accounts + 1)..] {
             let is_signer = account.key == &ctx.accounts.transfer_authority.key();
             router_accounts.push(if account.is_writable {
                 AccountMeta::new(*account.key, is_signer)
@@ -147,7 +170,7 @@ pub fn crank(ctx: Context<Crank>, params: &CrankParams) -> Result<i64> {
 
         program::invoke_signed(
             &instruction,
-            &ctx.remaining_accounts[(pools.len() + 1)..],
+            &ctx.remaining_accounts[(num_pool_accounts + 1)..],
             authority_seeds,
         )?;
 
@@ -193,17 +216,13 @@ pub fn crank(ctx: Context<Crank>, params: &CrankParams) -> Result<i64> {
             oracle_price.exponent,
         );
         require_gt!(swap_price.price, 0, TwammError::SettlementAmountTooSmall);
-        let swap_price_f64 = swap_price.checked_as_f64()?;
-        let oracle_price_f64 = oracle_price.checked_as_f64()?;
-        if (supply_side == MatchingSide::Sell && oracle_price_f64 < swap_price_f64)
-            || (supply_side == MatchingSide::Buy && oracle_price_f64 > swap_price_f64)
+        let ordering = oracle_price.checked_cmp(&swap_price)?;
+        if (supply_side == MatchingSide::Sell && ordering.is_lt())
+            || (supply_side == MatchingSide::Buy && ordering.is_gt())
         {
             require_gte!(
                 token_pair.max_swap_price_diff,
-                math::checked_float_div(
-                    (oracle_price_f64 - swap_price_f64).abs(),
-                    oracle_price_f64
-                )?,
+                oracle_price.get_deviation(&swap_price)?,
                 TwammError::SettlementPriceOutOfBounds
             );
         }
@@ -222,10 +241,22 @@ pub fn crank(ctx: Context<Crank>, params: &CrankParams) -> Result<i64> {
     let current_time = token_pair.get_time()?;
 
     // settle pools function takes raw pool refs for easier testing
-    let mut pool_refs: Vec<&mut Pool> = Vec::with_capacity(pools.len());
-    for pool in pools.iter_mut() {
+    let mut pool_data = pools
+        .iter()
+        .map(|pool| pool.load_mut())
+        .collect::<Result<Vec<_>>>()?;
+    let mut pool_refs: Vec<&mut Pool> = Vec::with_capacity(pool_data.len() + 1);
+    for pool in pool_data.iter_mut() {
         pool_refs.push(pool);
     }
+    let mut window_pool = if let Some(virtual_pool) = &virtual_pool {
+        Some(virtual_pool.get_window_pool(current_time)?)
+    } else {
+        None
+    };
+    if let Some(window_pool) = window_pool.as_mut() {
+        pool_refs.push(window_pool);
+    }
 
     let res = token_pair.settle_pools(
         &mut pool_refs,
@@ -275,9 +306,9 @@ pub fn crank(ctx: Context<Crank>, params: &CrankParams) -> Result<i64> {
     };
     let unsettled_amount = math::checked_sub(res.net_amount_required, res.net_amount_settled)?;
     let unsettled_percent = if unsettled_amount <= swap_amount_threshold {
-        0.0
+        0
     } else {
-        math::checked_float_div(unsettled_amount as f64, res.net_amount_required as f64)?
+        math::checked_ratio(unsettled_amount, res.net_amount_required)?
     };
     if swap_amount > 0 {
         require_gte!(
@@ -289,18 +320,22 @@ pub fn crank(ctx: Context<Crank>, params: &CrankParams) -> Result<i64> {
 
     // update pool states
     msg!("Update pool states");
-    for pool in pools.iter_mut() {
+    for (pool, pool_loader) in pool_data.iter_mut().zip(pools.iter()) {
         pool.update_state(token_pair.min_time_till_expiration, current_time)?;
         // if pool is complete, switch to the future pool
         if pool.is_complete(current_time)? {
             token_pair.finalize_pool(
+                &mut slots,
                 pool,
-                &pool.to_account_info(),
+                &pool_loader.to_account_info(),
                 &ctx.accounts.transfer_authority,
             )?;
         }
     }
-    token_pair.save_pools(&pools)?;
+    if let (Some(virtual_pool), Some(window_pool)) = (virtual_pool.as_mut(), window_pool) {
+        virtual_pool.execute(&window_pool, current_time)?;
+        virtual_pool.exit(&crate::ID)?;
+    }
 
     // update token pair stats
     msg!("Update token pair stats");
@@ -310,17 +345,19 @@ pub fn crank(ctx: Context<Crank>, params: &CrankParams) -> Result<i64> {
         &ctx.accounts.oracle_token_a,
         &ctx.accounts.oracle_token_b,
     )?;
+    if let Some(observations) = &ctx.accounts.observations {
+        observations
+            .load_mut()?
+            .record_settlement(&res, oracle_price, swap_price, current_time)?;
+    }
 
     // transfer rewards to the transaction payer
     msg!("Transfer rewards to the transaction payer");
-    assert!((0.0..=1.0).contains(&unsettled_percent));
-    let reward_share = 1.0 - unsettled_percent;
+    assert!(unsettled_percent <= math::RATIO_ONE);
+    let reward_share = math::RATIO_ONE - unsettled_percent;
     let reward_a = std::cmp::min(
         token_pair.stats_a.fees_collected,
-        math::checked_as_u64(math::checked_float_mul(
-            reward_share,
-            token_pair.config_a.crank_reward as f64,
-        )?)?,
+        math::checked_ratio_mul(token_pair.config_a.crank_reward, reward_share)?,
     );
     if reward_a > 0 {
         token_pair.stats_a.fees_collected =
@@ -329,18 +366,16 @@ pub fn crank(ctx: Context<Crank>, params: &CrankParams) -> Result<i64> {
         token_pair.transfer_tokens(
             ctx.accounts.custody_token_a.to_account_info(),
             ctx.accounts.user_account_token_a.to_account_info(),
+            ctx.accounts.mint_token_a.to_account_info(),
             ctx.accounts.transfer_authority.clone(),
-            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.token_program_a.to_account_info(),
             reward_a,
         )?;
     }
 
     let reward_b = std::cmp::min(
         token_pair.stats_b.fees_collected,
-        math::checked_as_u64(math::checked_float_mul(
-            reward_share,
-            token_pair.config_b.crank_reward as f64,
-        )?)?,
+        math::checked_ratio_mul(token_pair.config_b.crank_reward, reward_share)?,
     );
     if reward_b > 0 {
         token_pair.stats_b.fees_collected =
@@ -349,8 +384,9 @@ pub fn crank(ctx: Context<Crank>, params: &CrankParams) -> Result<i64> {
         token_pair.transfer_tokens(
             ctx.accounts.custody_token_b.to_account_info(),
             ctx.accounts.user_account_token_b.to_account_info(),
+            ctx.accounts.mint_token_b.to_account_info(),
             ctx.accounts.transfer_authority.clone(),
-            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.token_program_b.to_account_info(),
             reward_b,
         )?;
     }
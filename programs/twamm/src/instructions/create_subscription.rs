@@ -0,0 +1,180 @@
+//! Create subscription instruction handler
+
+use {
+    crate::{
+        error::TwammError,
+        math,
+        state::{
+            order::{Order, OrderSide},
+            subscription::Subscription,
+            token_pair::TokenPair,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+
+#[derive(Accounts)]
+#[instruction(params: CreateSubscriptionParams)]
+pub struct CreateSubscription<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = user_account_token_a.mint == custody_token_a.mint,
+        has_one = owner
+    )]
+    pub user_account_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_account_token_b.mint == custody_token_b.mint,
+        has_one = owner
+    )]
+    pub user_account_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    #[account(constraint = mint_token_a.key() == token_pair.load()?.config_a.mint)]
+    pub mint_token_a: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(constraint = mint_token_b.key() == token_pair.load()?.config_b.mint)]
+    pub mint_token_b: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = custody_token_a.key() == token_pair.load()?.config_a.custody
+    )]
+    pub custody_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = custody_token_b.key() == token_pair.load()?.config_b.custody
+    )]
+    pub custody_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = Subscription::LEN,
+        seeds = [b"subscription",
+                 owner.key().as_ref(),
+                 token_pair.key().as_ref(),
+                 params.subscription_id.to_le_bytes().as_slice()],
+        bump
+    )]
+    pub subscription: Box<Account<'info, Subscription>>,
+
+    system_program: Program<'info, System>,
+    token_program_a: Interface<'info, TokenInterface>,
+    token_program_b: Interface<'info, TokenInterface>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct CreateSubscriptionParams {
+    side: OrderSide,
+    time_in_force: u32,
+    amount_per_period: u64,
+    num_periods: u32,
+    keeper_bounty: u64,
+    // orders are placed with the matching subscription order id
+    subscription_id: u64,
+}
+
+pub fn create_subscription(
+    ctx: Context<CreateSubscription>,
+    params: &CreateSubscriptionParams,
+) -> Result<()> {
+    // validate inputs
+    require_gt!(
+        params.amount_per_period,
+        0u64,
+        TwammError::InvalidTokenAmount
+    );
+    require_gt!(params.num_periods, 0u32, TwammError::InvalidTokenAmount);
+    Order::validate_user_id(params.subscription_id)?;
+
+    let (token_pair, slots) = TokenPair::load_slots(&ctx.accounts.token_pair)?;
+    require!(token_pair.allow_deposits, TwammError::DepositsNotAllowed);
+    TokenPair::get_tif_index(&slots, params.time_in_force)?;
+
+    // initialize the subscription
+    msg!("Initialize subscription");
+    let subscription = ctx.accounts.subscription.as_mut();
+    subscription.owner = ctx.accounts.owner.key();
+    subscription.id = params.subscription_id;
+    subscription.token_pair = ctx.accounts.token_pair.key();
+    subscription.time = token_pair.get_time()?;
+    subscription.side = params.side;
+    subscription.time_in_force = params.time_in_force;
+    subscription.amount_per_period = params.amount_per_period;
+    subscription.keeper_bounty = params.keeper_bounty;
+    subscription.periods_remaining = params.num_periods;
+    subscription.periods_placed = 0;
+    subscription.last_pool_counter = 0;
+    subscription.bump = *ctx
+        .bumps
+        .get("subscription")
+        .ok_or(ProgramError::InvalidSeeds)?;
+
+    // escrow all periods upfront, grossed up by the token transfer fee
+    let (user_account, custody, mint, token_program) = if params.side == OrderSide::Sell {
+        (
+            &ctx.accounts.user_account_token_a,
+            &ctx.accounts.custody_token_a,
+            &ctx.accounts.mint_token_a,
+            &ctx.accounts.token_program_a,
+        )
+    } else {
+        (
+            &ctx.accounts.user_account_token_b,
+            &ctx.accounts.custody_token_b,
+            &ctx.accounts.mint_token_b,
+            &ctx.accounts.token_program_b,
+        )
+    };
+    let escrowed_amount = subscription.get_escrowed_amount()?;
+    let transfer_amount = math::checked_add(
+        escrowed_amount,
+        TokenPair::get_inverse_transfer_fee(&mint.to_account_info(), escrowed_amount)?,
+    )?;
+    msg!("Amount escrowed: {}", escrowed_amount);
+
+    msg!("Transfer tokens to custodies");
+    let context = CpiContext::new(
+        token_program.to_account_info(),
+        TransferChecked {
+            from: user_account.to_account_info(),
+            mint: mint.to_account_info(),
+            to: custody.to_account_info(),
+            authority: ctx.accounts.owner.to_account_info(),
+        },
+    );
+    anchor_spl::token_interface::transfer_checked(context, transfer_amount, mint.decimals)?;
+
+    // keepers create order accounts for each period, prefund their rent
+    msg!("Prefund order rent");
+    let context = CpiContext::new(
+        ctx.accounts.system_program.to_account_info(),
+        anchor_lang::system_program::Transfer {
+            from: ctx.accounts.owner.to_account_info(),
+            to: ctx.accounts.subscription.to_account_info(),
+        },
+    );
+    anchor_lang::system_program::transfer(
+        context,
+        math::checked_mul(
+            Rent::get()?.minimum_balance(Order::LEN),
+            params.num_periods as u64,
+        )?,
+    )?;
+
+    Ok(())
+}
@@ -9,7 +9,7 @@ use {
         },
     },
     anchor_lang::prelude::*,
-    anchor_spl::token::{Token, TokenAccount},
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface},
 };
 
 #[derive(Accounts)]
@@ -28,45 +28,52 @@ pub struct DeleteTestPair<'info> {
         mut,
         constraint = user_account_token_a.mint == custody_token_a.mint,
     )]
-    pub user_account_token_a: Box<Account<'info, TokenAccount>>,
+    pub user_account_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
         constraint = user_account_token_b.mint == custody_token_b.mint,
     )]
-    pub user_account_token_b: Box<Account<'info, TokenAccount>>,
+    pub user_account_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
         seeds = [b"token_pair",
-                 token_pair.config_a.mint.as_ref(),
-                 token_pair.config_b.mint.as_ref()],
-        bump = token_pair.token_pair_bump,
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump,
         close = transfer_authority
     )]
-    pub token_pair: Box<Account<'info, TokenPair>>,
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    #[account(constraint = mint_token_a.key() == token_pair.load()?.config_a.mint)]
+    pub mint_token_a: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(constraint = mint_token_b.key() == token_pair.load()?.config_b.mint)]
+    pub mint_token_b: Box<InterfaceAccount<'info, Mint>>,
 
     /// CHECK: empty PDA, authority for token accounts
     #[account(
         mut,
         seeds = [b"transfer_authority"],
-        bump = token_pair.transfer_authority_bump
+        bump = token_pair.load()?.transfer_authority_bump
     )]
     pub transfer_authority: AccountInfo<'info>,
 
     #[account(
         mut,
-        constraint = custody_token_a.key() == token_pair.config_a.custody
+        constraint = custody_token_a.key() == token_pair.load()?.config_a.custody
     )]
-    pub custody_token_a: Box<Account<'info, TokenAccount>>,
+    pub custody_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
-        constraint = custody_token_b.key() == token_pair.config_b.custody
+        constraint = custody_token_b.key() == token_pair.load()?.config_b.custody
     )]
-    pub custody_token_b: Box<Account<'info, TokenAccount>>,
+    pub custody_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    token_program: Program<'info, Token>,
+    token_program_a: Interface<'info, TokenInterface>,
+    token_program_b: Interface<'info, TokenInterface>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -96,21 +103,23 @@ pub fn delete_test_pair<'info>(
         return Ok(signatures_left);
     }
 
-    let token_pair = ctx.accounts.token_pair.as_mut();
+    let token_pair = ctx.accounts.token_pair.load()?;
 
     token_pair.transfer_tokens(
         ctx.accounts.custody_token_a.to_account_info(),
         ctx.accounts.user_account_token_a.to_account_info(),
+        ctx.accounts.mint_token_a.to_account_info(),
         ctx.accounts.transfer_authority.clone(),
-        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.token_program_a.to_account_info(),
         ctx.accounts.custody_token_a.amount,
     )?;
 
     token_pair.transfer_tokens(
         ctx.accounts.custody_token_b.to_account_info(),
         ctx.accounts.user_account_token_b.to_account_info(),
+        ctx.accounts.mint_token_b.to_account_info(),
         ctx.accounts.transfer_authority.clone(),
-        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.token_program_b.to_account_info(),
         ctx.accounts.custody_token_b.amount,
     )?;
 
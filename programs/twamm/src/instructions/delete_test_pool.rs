@@ -10,7 +10,7 @@ use {
         },
     },
     anchor_lang::prelude::*,
-    anchor_spl::token::TokenAccount,
+    anchor_spl::token_interface::TokenAccount,
 };
 
 #[derive(Accounts)]
@@ -27,41 +27,41 @@ pub struct DeleteTestPool<'info> {
 
     #[account(
         seeds = [b"token_pair",
-                 token_pair.config_a.mint.as_ref(),
-                 token_pair.config_b.mint.as_ref()],
-        bump = token_pair.token_pair_bump
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
     )]
-    pub token_pair: Box<Account<'info, TokenPair>>,
+    pub token_pair: AccountLoader<'info, TokenPair>,
 
     /// CHECK: empty PDA, authority for token accounts
     #[account(
         mut,
         seeds = [b"transfer_authority"],
-        bump = token_pair.transfer_authority_bump
+        bump = token_pair.load()?.transfer_authority_bump
     )]
     pub transfer_authority: AccountInfo<'info>,
 
     #[account(
-        constraint = custody_token_a.key() == token_pair.config_a.custody
+        constraint = custody_token_a.key() == token_pair.load()?.config_a.custody
     )]
-    pub custody_token_a: Box<Account<'info, TokenAccount>>,
+    pub custody_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
-        constraint = custody_token_b.key() == token_pair.config_b.custody
+        constraint = custody_token_b.key() == token_pair.load()?.config_b.custody
     )]
-    pub custody_token_b: Box<Account<'info, TokenAccount>>,
+    pub custody_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
         seeds = [b"pool",
                  custody_token_a.key().as_ref(),
                  custody_token_b.key().as_ref(),
-                 pool.time_in_force.to_le_bytes().as_slice(),
-                 pool.counter.to_le_bytes().as_slice()],
-        bump = pool.bump,
+                 pool.load()?.time_in_force.to_le_bytes().as_slice(),
+                 pool.load()?.counter.to_le_bytes().as_slice()],
+        bump = pool.load()?.bump,
         close = transfer_authority
     )]
-    pub pool: Box<Account<'info, Pool>>,
+    pub pool: AccountLoader<'info, Pool>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -0,0 +1,249 @@
+//! Execute subscription instruction handler
+
+use {
+    crate::{
+        error::TwammError,
+        math,
+        state::{
+            self,
+            order::{Order, OrderSide},
+            pool::{Pool, PoolStatus},
+            subscription::Subscription,
+            token_pair::TokenPair,
+        },
+    },
+    anchor_lang::{prelude::*, AccountsClose},
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface},
+};
+
+#[derive(Accounts)]
+pub struct ExecuteSubscription<'info> {
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+
+    // receives the keeper bounty, can be omitted if the subscription pays no bounty
+    #[account(mut)]
+    pub keeper_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// CHECK: subscription owner's wallet, receives subscription rent once it's complete
+    #[account(
+        mut,
+        constraint = owner.key() == subscription.owner
+    )]
+    pub owner: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    #[account(constraint = mint_token_a.key() == token_pair.load()?.config_a.mint)]
+    pub mint_token_a: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(constraint = mint_token_b.key() == token_pair.load()?.config_b.mint)]
+    pub mint_token_b: Box<InterfaceAccount<'info, Mint>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        mut,
+        seeds = [b"transfer_authority"],
+        bump = token_pair.load()?.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = custody_token_a.key() == token_pair.load()?.config_a.custody
+    )]
+    pub custody_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = custody_token_b.key() == token_pair.load()?.config_b.custody
+    )]
+    pub custody_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"subscription",
+                 owner.key().as_ref(),
+                 token_pair.key().as_ref(),
+                 subscription.id.to_le_bytes().as_slice()],
+        bump = subscription.bump
+    )]
+    pub subscription: Box<Account<'info, Subscription>>,
+
+    #[account(
+        init_if_needed,
+        payer = keeper,
+        space = Order::LEN,
+        seeds = [b"order",
+                 owner.key().as_ref(),
+                 current_pool.key().as_ref(),
+                 Order::get_id_seed(Order::get_subscription_order_id(subscription.id)).as_slice()],
+        bump
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    // Currently active pool for the subscription's time in force
+    #[account(
+        init_if_needed,
+        payer = keeper,
+        space = Pool::LEN,
+        seeds = [b"pool",
+                 custody_token_a.key().as_ref(),
+                 custody_token_b.key().as_ref(),
+                 subscription.time_in_force.to_le_bytes().as_slice(),
+                 TokenPair::load_pool_counter(&token_pair, subscription.time_in_force)?.to_le_bytes().as_slice()],
+        bump
+    )]
+    pub current_pool: AccountLoader<'info, Pool>,
+
+    system_program: Program<'info, System>,
+    token_program_a: Interface<'info, TokenInterface>,
+    token_program_b: Interface<'info, TokenInterface>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct ExecuteSubscriptionParams {}
+
+pub fn execute_subscription(
+    ctx: Context<ExecuteSubscription>,
+    _params: &ExecuteSubscriptionParams,
+) -> Result<()> {
+    let (token_pair, mut slots) = TokenPair::load_slots_mut(&ctx.accounts.token_pair)?;
+    require!(token_pair.allow_deposits, TwammError::DepositsNotAllowed);
+
+    let subscription = ctx.accounts.subscription.as_mut();
+    let tif_index = TokenPair::get_tif_index(&slots, subscription.time_in_force)?;
+    let current_time = token_pair.get_time()?;
+
+    // initialize the current pool if needed
+    if !slots[tif_index].current_pool_present {
+        msg!("Initialize current pool");
+        state::load_or_init_mut(&ctx.accounts.current_pool)?.init_current(
+            &mut slots[tif_index],
+            ctx.accounts.token_pair.key(),
+            current_time,
+            *ctx.bumps
+                .get("current_pool")
+                .ok_or(ProgramError::InvalidSeeds)?,
+        )?;
+    }
+
+    // one order per pool period
+    let mut pool = state::load_or_init_mut(&ctx.accounts.current_pool)?;
+    if !subscription.is_due(pool.counter) {
+        msg!("Error: Subscription has no order due in the current pool");
+        return err!(TwammError::SubscriptionNotDue);
+    }
+
+    msg!("Update pool state");
+    match pool.update_state(token_pair.min_time_till_expiration, current_time)? {
+        PoolStatus::Locked => return err!(TwammError::LockedPool),
+        PoolStatus::Expired => return err!(TwammError::ExpiredPool),
+        _ => {}
+    }
+
+    // create the order or top up the order rolled into the pool with the same id
+    let order = ctx.accounts.order.as_mut();
+    let new_order = order.is_empty();
+    if new_order {
+        msg!("Initialize order");
+        order.init(
+            subscription.owner,
+            Order::get_subscription_order_id(subscription.id),
+            subscription.side,
+            ctx.accounts.current_pool.key(),
+            current_time,
+            *ctx.bumps.get("order").ok_or(ProgramError::InvalidSeeds)?,
+        );
+    } else {
+        require_eq!(order.side, subscription.side, TwammError::OrderSideMismatch);
+        require!(!order.exact_output, TwammError::OrderTypeMismatch);
+    }
+
+    // update pool balances
+    msg!("Update pool balances");
+    let amount = subscription.amount_per_period;
+    let expiration_time = pool.expiration_time;
+    let schedule = pool.schedule;
+    let pool_side = pool.get_side_mut(subscription.side, false);
+    if new_order {
+        pool_side.num_traders = math::checked_add(pool_side.num_traders, 1)?;
+    }
+    if order.is_held() {
+        // existing order is out of the pool, tokens join it once conditions are met
+        order.add_held_balance(pool_side, amount)?;
+    } else {
+        msg!("Update user order");
+        order.deposit(pool_side, amount, &schedule, expiration_time, current_time)?;
+    }
+
+    // update subscription
+    msg!("Update subscription");
+    subscription.periods_remaining = math::checked_sub(subscription.periods_remaining, 1)?;
+    subscription.periods_placed = math::checked_add(subscription.periods_placed, 1)?;
+    subscription.last_pool_counter = pool.counter;
+
+    // pay the keeper bounty from escrowed tokens
+    if subscription.keeper_bounty > 0 {
+        msg!("Transfer bounty to the keeper");
+        let (custody, mint, token_program) = if subscription.side == OrderSide::Sell {
+            (
+                &ctx.accounts.custody_token_a,
+                &ctx.accounts.mint_token_a,
+                &ctx.accounts.token_program_a,
+            )
+        } else {
+            (
+                &ctx.accounts.custody_token_b,
+                &ctx.accounts.mint_token_b,
+                &ctx.accounts.token_program_b,
+            )
+        };
+        let Some(keeper_account) = ctx.accounts.keeper_account.as_ref() else {
+            msg!("Error: Keeper token account is required to receive the bounty");
+            return err!(TwammError::InvalidTokenAccount);
+        };
+        require_keys_eq!(
+            keeper_account.mint,
+            custody.mint,
+            TwammError::InvalidTokenAccount
+        );
+        token_pair.transfer_tokens(
+            custody.to_account_info(),
+            keeper_account.to_account_info(),
+            mint.to_account_info(),
+            ctx.accounts.transfer_authority.clone(),
+            token_program.to_account_info(),
+            subscription.keeper_bounty,
+        )?;
+    }
+
+    // refund the keeper the rent of the order account it created
+    let complete = subscription.periods_remaining == 0;
+    if new_order {
+        msg!("Refund order rent to the keeper");
+        state::transfer_sol_from_owned(
+            ctx.accounts.subscription.to_account_info(),
+            ctx.accounts.keeper.to_account_info(),
+            Rent::get()?.minimum_balance(Order::LEN),
+        )?;
+    }
+
+    // close the subscription once all periods are placed
+    if complete {
+        msg!("Close subscription account");
+        ctx.accounts.subscription.set_inner(Subscription::default());
+        ctx.accounts
+            .subscription
+            .close(ctx.accounts.owner.to_account_info())?;
+    }
+
+    Ok(())
+}
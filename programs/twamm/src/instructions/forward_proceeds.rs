@@ -0,0 +1,244 @@
+//! Forward proceeds instruction handler
+
+use {
+    crate::{
+        error::TwammError,
+        math,
+        state::{
+            order::{Order, OrderSide},
+            pool::{Pool, PoolStatus},
+            token_pair::TokenPair,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface},
+};
+
+#[derive(Accounts)]
+pub struct ForwardProceeds<'info> {
+    #[account(
+        mut,
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    #[account(
+        mut,
+        seeds = [b"order",
+                 order.owner.as_ref(),
+                 pool.key().as_ref(),
+                 Order::get_id_seed(order.id).as_slice()],
+        bump = order.bump
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool",
+                 token_pair.load()?.config_a.custody.as_ref(),
+                 token_pair.load()?.config_b.custody.as_ref(),
+                 pool.load()?.time_in_force.to_le_bytes().as_slice(),
+                 pool.load()?.counter.to_le_bytes().as_slice()],
+        bump = pool.load()?.bump
+    )]
+    pub pool: AccountLoader<'info, Pool>,
+
+    #[account(
+        seeds = [b"token_pair",
+                 next_token_pair.load()?.config_a.mint.as_ref(),
+                 next_token_pair.load()?.config_b.mint.as_ref()],
+        bump = next_token_pair.load()?.token_pair_bump
+    )]
+    pub next_token_pair: AccountLoader<'info, TokenPair>,
+
+    #[account(
+        mut,
+        constraint = next_order.key() == order.next_order,
+        constraint = next_order.owner == order.owner,
+        seeds = [b"order",
+                 next_order.owner.as_ref(),
+                 next_pool.key().as_ref(),
+                 Order::get_id_seed(next_order.id).as_slice()],
+        bump = next_order.bump
+    )]
+    pub next_order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool",
+                 next_token_pair.load()?.config_a.custody.as_ref(),
+                 next_token_pair.load()?.config_b.custody.as_ref(),
+                 next_pool.load()?.time_in_force.to_le_bytes().as_slice(),
+                 next_pool.load()?.counter.to_le_bytes().as_slice()],
+        bump = next_pool.load()?.bump
+    )]
+    pub next_pool: AccountLoader<'info, Pool>,
+
+    // mint of the token shared by both pairs
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        mut,
+        seeds = [b"transfer_authority"],
+        bump = token_pair.load()?.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub custody: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub next_custody: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct ForwardProceedsParams {}
+
+pub fn forward_proceeds(
+    ctx: Context<ForwardProceeds>,
+    _params: &ForwardProceedsParams,
+) -> Result<()> {
+    let (mut token_pair, slots) = TokenPair::load_slots_mut(&ctx.accounts.token_pair)?;
+    let next_token_pair = ctx.accounts.next_token_pair.load()?;
+    require!(
+        token_pair.allow_withdrawals,
+        TwammError::WithdrawalsNotAllowed
+    );
+    require!(
+        next_token_pair.allow_deposits,
+        TwammError::DepositsNotAllowed
+    );
+
+    // validate custodies of the shared token
+    let order = ctx.accounts.order.as_mut();
+    let next_order = ctx.accounts.next_order.as_mut();
+    let (mint, custody) = if order.side == OrderSide::Sell {
+        (token_pair.config_b.mint, token_pair.config_b.custody)
+    } else {
+        (token_pair.config_a.mint, token_pair.config_a.custody)
+    };
+    let (next_mint, next_custody) = if next_order.side == OrderSide::Sell {
+        (
+            next_token_pair.config_a.mint,
+            next_token_pair.config_a.custody,
+        )
+    } else {
+        (
+            next_token_pair.config_b.mint,
+            next_token_pair.config_b.custody,
+        )
+    };
+    require_keys_eq!(mint, next_mint, TwammError::InvalidOrderLink);
+    require_keys_eq!(ctx.accounts.mint.key(), mint, TwammError::InvalidOrderLink);
+    require_keys_eq!(
+        ctx.accounts.custody.key(),
+        custody,
+        TwammError::InvalidOrderLink
+    );
+    require_keys_eq!(
+        ctx.accounts.next_custody.key(),
+        next_custody,
+        TwammError::InvalidOrderLink
+    );
+
+    // take the order's share of filled tokens
+    msg!("Update order and pool data");
+    let mut pool = ctx.accounts.pool.load_mut()?;
+    let pool_side = pool.get_side_mut(order.side, order.exact_output);
+    order.sync_start(pool_side)?;
+    let (withdraw_amount, held_proceeds) = order.withdraw_proceeds(pool_side)?;
+    let total_amount = math::checked_add(withdraw_amount, held_proceeds)?;
+    if total_amount == 0 {
+        msg!("Error: Nothing to forward at this time");
+        return err!(TwammError::InvalidTokenAmount);
+    }
+
+    let withdraw_amount_fees = math::checked_as_u64(math::checked_ceil_div(
+        math::checked_mul(total_amount as u128, token_pair.fee_numerator as u128)?,
+        token_pair.fee_denominator as u128,
+    )?)?;
+    let transfer_amount = math::checked_sub(total_amount, withdraw_amount_fees)?;
+
+    // credit the amount the next custody receives after the token transfer fee, if any
+    let amount = math::checked_sub(
+        transfer_amount,
+        TokenPair::get_transfer_fee(&ctx.accounts.mint.to_account_info(), transfer_amount)?,
+    )?;
+    require_gt!(amount, 0u64, TwammError::InvalidTokenAmount);
+    msg!("Amount forwarded: {}", amount);
+
+    // deposit proceeds to the next order
+    let current_time = next_token_pair.get_time()?;
+    let mut next_pool = ctx.accounts.next_pool.load_mut()?;
+    msg!("Update next pool state");
+    match next_pool.update_state(next_token_pair.min_time_till_expiration, current_time)? {
+        PoolStatus::Locked => return err!(TwammError::LockedPool),
+        PoolStatus::Expired => return err!(TwammError::ExpiredPool),
+        _ => {}
+    }
+
+    msg!("Update next pool balances");
+    let expiration_time = next_pool.expiration_time;
+    let schedule = next_pool.schedule;
+    let next_pool_side = next_pool.get_side_mut(next_order.side, false);
+    if next_order.add_pending_balance(next_pool_side, amount)? {
+        msg!("Add tokens to the delayed next order");
+    } else if next_order.is_held() || !next_order.is_started(current_time) {
+        // next order is out of the pool, tokens join it once conditions are met
+        next_order.add_held_balance(next_pool_side, amount)?;
+    } else {
+        next_order.deposit(
+            next_pool_side,
+            amount,
+            &schedule,
+            expiration_time,
+            current_time,
+        )?;
+    }
+
+    // move tokens between custodies of the two pairs
+    msg!("Transfer tokens to the next custody");
+    token_pair.transfer_tokens(
+        ctx.accounts.custody.to_account_info(),
+        ctx.accounts.next_custody.to_account_info(),
+        ctx.accounts.mint.to_account_info(),
+        ctx.accounts.transfer_authority.clone(),
+        ctx.accounts.token_program.to_account_info(),
+        transfer_amount,
+    )?;
+
+    // update token pair stats
+    msg!("Update token pair stats");
+    let is_current_pool = TokenPair::is_current_pool(&slots, &pool);
+    if order.side == OrderSide::Sell {
+        token_pair.stats_b.fees_collected = token_pair
+            .stats_b
+            .fees_collected
+            .saturating_add(withdraw_amount_fees);
+        if !is_current_pool {
+            token_pair.stats_b.pending_withdrawals = token_pair
+                .stats_b
+                .pending_withdrawals
+                .saturating_sub(total_amount);
+        }
+    } else {
+        token_pair.stats_a.fees_collected = token_pair
+            .stats_a
+            .fees_collected
+            .saturating_add(withdraw_amount_fees);
+        if !is_current_pool {
+            token_pair.stats_a.pending_withdrawals = token_pair
+                .stats_a
+                .pending_withdrawals
+                .saturating_sub(total_amount);
+        }
+    }
+
+    Ok(())
+}
@@ -12,25 +12,26 @@ use {
 pub struct GetOutstandingAmount<'info> {
     #[account(
         seeds = [b"token_pair",
-                 token_pair.config_a.mint.as_ref(),
-                 token_pair.config_b.mint.as_ref()],
-        bump = token_pair.token_pair_bump
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
     )]
-    pub token_pair: Box<Account<'info, TokenPair>>,
+    pub token_pair: AccountLoader<'info, TokenPair>,
 
     /// CHECK: oracle account for token a depending on oracle type
     #[account(
-        constraint = oracle_token_a.key() == token_pair.config_a.oracle_account
+        constraint = oracle_token_a.key() == token_pair.load()?.config_a.oracle_account
     )]
     pub oracle_token_a: AccountInfo<'info>,
 
     /// CHECK: oracle account for token b depending on oracle type
     #[account(
-        constraint = oracle_token_b.key() == token_pair.config_b.oracle_account
+        constraint = oracle_token_b.key() == token_pair.load()?.config_b.oracle_account
     )]
     pub oracle_token_b: AccountInfo<'info>,
     // remaining accounts:
-    //   1 to TokenPair::MAX_POOLS addresses of current pool accounts (write, unsigned)
+    //   1 to TokenPair::get_num_slots() addresses of current pool accounts (write, unsigned)
+    //   Optional virtual pool account (write, unsigned)
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -40,19 +41,34 @@ pub fn get_outstanding_amount(
     ctx: Context<GetOutstandingAmount>,
     _params: &GetOutstandingAmountParams,
 ) -> Result<i64> {
-    let token_pair = &ctx.accounts.token_pair;
-    let (mut pools, _router_program) = token_pair.load_pools(ctx.remaining_accounts)?;
-    if pools.is_empty() {
+    let (token_pair, slots) = TokenPair::load_slots(&ctx.accounts.token_pair)?;
+    let (pools, virtual_pool, _router_program) =
+        token_pair.load_pools(&slots, ctx.remaining_accounts)?;
+    if pools.is_empty() && virtual_pool.is_none() {
         return Ok(0);
     }
+    let current_time = token_pair.get_time()?;
 
     let oracle_price = token_pair
         .get_token_pair_oracle_price(&ctx.accounts.oracle_token_a, &ctx.accounts.oracle_token_b)?;
 
-    let mut pool_refs: Vec<&mut Pool> = Vec::with_capacity(pools.len());
-    for pool in pools.iter_mut() {
+    // pools are read-only here, settle copies of their data
+    let mut pool_data = pools
+        .iter()
+        .map(|pool| pool.load().map(|pool| *pool))
+        .collect::<Result<Vec<Pool>>>()?;
+    let mut pool_refs: Vec<&mut Pool> = Vec::with_capacity(pool_data.len() + 1);
+    for pool in pool_data.iter_mut() {
         pool_refs.push(pool);
     }
+    let mut window_pool = if let Some(virtual_pool) = &virtual_pool {
+        Some(virtual_pool.get_window_pool(current_time)?)
+    } else {
+        None
+    };
+    if let Some(window_pool) = window_pool.as_mut() {
+        pool_refs.push(window_pool);
+    }
 
     let res = token_pair.settle_pools(
         &mut pool_refs,
@@ -61,7 +77,7 @@ pub fn get_outstanding_amount(
         0,
         oracle_price,
         oracle_price,
-        token_pair.get_time()?,
+        current_time,
     )?;
 
     // return net unsettled amount
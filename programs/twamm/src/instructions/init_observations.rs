@@ -0,0 +1,52 @@
+//! Init observations instruction handler
+
+use {
+    crate::state::{observations::Observations, token_pair::TokenPair},
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct InitObservations<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    // new pairs get it in init_token_pair, anyone can create it for an existing pair
+    #[account(
+        init,
+        payer = payer,
+        space = Observations::LEN,
+        seeds = [b"observations",
+                 token_pair.key().as_ref()],
+        bump
+    )]
+    pub observations: AccountLoader<'info, Observations>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct InitObservationsParams {}
+
+pub fn init_observations(
+    ctx: Context<InitObservations>,
+    _params: &InitObservationsParams,
+) -> Result<()> {
+    let mut observations = ctx.accounts.observations.load_init()?;
+    observations.version = Observations::VERSION;
+    observations.token_pair = ctx.accounts.token_pair.key();
+    observations.num_observations = 0;
+    observations.bump = *ctx
+        .bumps
+        .get("observations")
+        .ok_or(ProgramError::InvalidSeeds)?;
+
+    Ok(())
+}
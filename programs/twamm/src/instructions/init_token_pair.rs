@@ -5,18 +5,21 @@ use {
         error::TwammError,
         oracle::OracleType,
         state::{
+            self,
             multisig::{AdminInstruction, Multisig},
+            observations::Observations,
             token_pair::TokenPair,
         },
     },
     anchor_lang::prelude::*,
     anchor_spl::{
         associated_token::AssociatedToken,
-        token::{Mint, Token, TokenAccount},
+        token_interface::{Mint, TokenAccount, TokenInterface},
     },
 };
 
 #[derive(Accounts)]
+#[instruction(params: InitTokenPairParams)]
 pub struct InitTokenPair<'info> {
     #[account(mut)]
     pub admin: Signer<'info>,
@@ -36,14 +39,25 @@ pub struct InitTokenPair<'info> {
     #[account(
         init_if_needed,
         payer = admin,
-        space = TokenPair::LEN,
+        space = TokenPair::get_len(params.time_in_force_intervals.len()),
         constraint = mint_token_a.key() != mint_token_b.key(),
         seeds = [b"token_pair",
                  mint_token_a.key().as_ref(),
                  mint_token_b.key().as_ref()],
         bump
     )]
-    pub token_pair: Box<Account<'info, TokenPair>>,
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    // created along with the token pair, same as above
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = Observations::LEN,
+        seeds = [b"observations",
+                 token_pair.key().as_ref()],
+        bump
+    )]
+    pub observations: AccountLoader<'info, Observations>,
 
     /// CHECK: empty PDA, will be set as authority for token accounts
     #[account(
@@ -52,8 +66,8 @@ pub struct InitTokenPair<'info> {
     )]
     pub transfer_authority: AccountInfo<'info>,
 
-    pub mint_token_a: Box<Account<'info, Mint>>,
-    pub mint_token_b: Box<Account<'info, Mint>>,
+    pub mint_token_a: Box<InterfaceAccount<'info, Mint>>,
+    pub mint_token_b: Box<InterfaceAccount<'info, Mint>>,
 
     // token custodies can be shared between multiply pairs
     #[account(
@@ -61,22 +75,25 @@ pub struct InitTokenPair<'info> {
         payer = admin,
         constraint = mint_token_a.key() == custody_token_a.mint,
         associated_token::mint = mint_token_a,
-        associated_token::authority = transfer_authority
+        associated_token::authority = transfer_authority,
+        associated_token::token_program = token_program_a
     )]
-    pub custody_token_a: Box<Account<'info, TokenAccount>>,
+    pub custody_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         init_if_needed,
         payer = admin,
         constraint = mint_token_b.key() == custody_token_b.mint,
         associated_token::mint = mint_token_b,
-        associated_token::authority = transfer_authority
+        associated_token::authority = transfer_authority,
+        associated_token::token_program = token_program_b
     )]
-    pub custody_token_b: Box<Account<'info, TokenAccount>>,
+    pub custody_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
 
     system_program: Program<'info, System>,
     rent: Sysvar<'info, Rent>,
-    token_program: Program<'info, Token>,
+    token_program_a: Interface<'info, TokenInterface>,
+    token_program_b: Interface<'info, TokenInterface>,
     associated_token_program: Program<'info, AssociatedToken>,
 }
 
@@ -94,11 +111,12 @@ pub struct InitTokenPairParams {
     pub crank_reward_token_b: u64,
     pub min_swap_amount_token_a: u64,
     pub min_swap_amount_token_b: u64,
-    pub max_swap_price_diff: f64,
-    pub max_unsettled_amount: f64,
-    pub min_time_till_expiration: f64,
-    pub max_oracle_price_error_token_a: f64,
-    pub max_oracle_price_error_token_b: f64,
+    // limits are fixed-point ratios with math::RATIO_DECIMALS
+    pub max_swap_price_diff: u64,
+    pub max_unsettled_amount: u64,
+    pub min_time_till_expiration: u64,
+    pub max_oracle_price_error_token_a: u64,
+    pub max_oracle_price_error_token_b: u64,
     pub max_oracle_price_age_sec_token_a: u32,
     pub max_oracle_price_age_sec_token_b: u32,
     pub oracle_type_token_a: OracleType,
@@ -106,13 +124,18 @@ pub struct InitTokenPairParams {
     pub oracle_account_token_a: Pubkey,
     pub oracle_account_token_b: Pubkey,
     pub crank_authority: Pubkey,
-    pub time_in_force_intervals: [u32; 10], // TokenPair::MAX_POOLS
+    // one time in force slot per interval, up to TokenPair::MAX_POOLS
+    pub time_in_force_intervals: Vec<u32>,
 }
 
 pub fn init_token_pair<'info>(
     ctx: Context<'_, '_, '_, 'info, InitTokenPair<'info>>,
     params: &InitTokenPairParams,
 ) -> Result<u8> {
+    // mints with extensions that could move or lock custody funds are not supported
+    TokenPair::validate_mint_extensions(&ctx.accounts.mint_token_a.to_account_info())?;
+    TokenPair::validate_mint_extensions(&ctx.accounts.mint_token_b.to_account_info())?;
+
     // validate signatures
     let mut multisig = ctx.accounts.multisig.load_mut()?;
 
@@ -130,12 +153,13 @@ pub fn init_token_pair<'info>(
     }
 
     // record token pair data
-    let token_pair = ctx.accounts.token_pair.as_mut();
+    let mut token_pair = state::load_or_init_mut(&ctx.accounts.token_pair)?;
     if token_pair.config_a.mint != Pubkey::default() {
         // return error if token pair is already initialized
         return Err(ProgramError::AccountAlreadyInitialized.into());
     }
 
+    token_pair.version = TokenPair::VERSION;
     token_pair.allow_deposits = params.allow_deposits;
     token_pair.allow_withdrawals = params.allow_withdrawals;
     token_pair.allow_cranks = params.allow_cranks;
@@ -184,12 +208,7 @@ pub fn init_token_pair<'info>(
     token_pair.stats_b.routed_volume_usd = 0;
     token_pair.stats_b.settled_volume_usd = 0;
 
-    token_pair
-        .tifs
-        .copy_from_slice(params.time_in_force_intervals.as_slice());
-    token_pair.pool_counters.fill(0);
-    token_pair.current_pool_present.fill(false);
-    token_pair.future_pool_present.fill(false);
+    token_pair.set_num_slots(&[], params.time_in_force_intervals.len())?;
 
     token_pair.token_pair_bump = *ctx
         .bumps
@@ -200,13 +219,30 @@ pub fn init_token_pair<'info>(
         .get("transfer_authority")
         .ok_or(ProgramError::InvalidSeeds)?;
 
+    let mut observations = state::load_or_init_mut(&ctx.accounts.observations)?;
+    observations.version = Observations::VERSION;
+    observations.token_pair = ctx.accounts.token_pair.key();
+    observations.num_observations = 0;
+    observations.bump = *ctx
+        .bumps
+        .get("observations")
+        .ok_or(ProgramError::InvalidSeeds)?;
+
     token_pair.inception_time = if cfg!(feature = "test") {
         0
     } else {
         token_pair.get_time()?
     };
 
-    if !token_pair.validate() {
+    drop(token_pair);
+
+    // time in force slots follow the header
+    let (token_pair, mut slots) = TokenPair::load_slots_init(&ctx.accounts.token_pair)?;
+    for (slot, time_in_force) in slots.iter_mut().zip(&params.time_in_force_intervals) {
+        slot.time_in_force = *time_in_force;
+    }
+
+    if !token_pair.validate() || !token_pair.validate_slots(&slots) {
         err!(TwammError::InvalidTokenPairConfig)
     } else {
         Ok(0)
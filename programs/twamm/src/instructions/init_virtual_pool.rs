@@ -0,0 +1,94 @@
+//! Init virtual pool instruction handler
+
+use {
+    crate::{
+        error::TwammError,
+        state::{
+            multisig::{AdminInstruction, Multisig},
+            token_pair::TokenPair,
+            virtual_pool::VirtualPool,
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct InitVirtualPool<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    // instruction can be called multiple times due to multisig use, hence init_if_needed
+    // instead of init. Uniqueness is enforced manually in the instruction handler.
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = VirtualPool::LEN,
+        seeds = [b"virtual_pool",
+                 token_pair.key().as_ref()],
+        bump
+    )]
+    pub virtual_pool: Box<Account<'info, VirtualPool>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct InitVirtualPoolParams {
+    pub interval: u32,
+}
+
+pub fn init_virtual_pool<'info>(
+    ctx: Context<'_, '_, '_, 'info, InitVirtualPool<'info>>,
+    params: &InitVirtualPoolParams,
+) -> Result<u8> {
+    // validate inputs
+    require_gt!(params.interval, 0u32, TwammError::InvalidTimeInForce);
+
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.admin,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::InitVirtualPool, params)?,
+    )?;
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    // record virtual pool data
+    let virtual_pool = ctx.accounts.virtual_pool.as_mut();
+    if virtual_pool.interval != 0 {
+        // return error if virtual pool is already initialized
+        return Err(ProgramError::AccountAlreadyInitialized.into());
+    }
+
+    virtual_pool.token_pair = ctx.accounts.token_pair.key();
+    virtual_pool.interval = params.interval;
+    virtual_pool.set_last_update_time(ctx.accounts.token_pair.load()?.get_time()?);
+    virtual_pool.bump = *ctx
+        .bumps
+        .get("virtual_pool")
+        .ok_or(ProgramError::InvalidSeeds)?;
+
+    Ok(0)
+}
@@ -0,0 +1,177 @@
+//! Link order instruction handler
+
+use {
+    crate::{
+        error::TwammError,
+        math,
+        state::{
+            order::{Order, OrderSide},
+            pool::{Pool, PoolStatus},
+            token_pair::TokenPair,
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+#[instruction(params: LinkOrderParams)]
+pub struct LinkOrder<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    #[account(
+        mut,
+        seeds = [b"order",
+                 owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 Order::get_id_seed(order.id).as_slice()],
+        bump = order.bump
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        seeds = [b"pool",
+                 token_pair.load()?.config_a.custody.as_ref(),
+                 token_pair.load()?.config_b.custody.as_ref(),
+                 pool.load()?.time_in_force.to_le_bytes().as_slice(),
+                 pool.load()?.counter.to_le_bytes().as_slice()],
+        bump = pool.load()?.bump
+    )]
+    pub pool: AccountLoader<'info, Pool>,
+
+    #[account(
+        seeds = [b"token_pair",
+                 next_token_pair.load()?.config_a.mint.as_ref(),
+                 next_token_pair.load()?.config_b.mint.as_ref()],
+        bump = next_token_pair.load()?.token_pair_bump
+    )]
+    pub next_token_pair: AccountLoader<'info, TokenPair>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = Order::LEN,
+        seeds = [b"order",
+                 owner.key().as_ref(),
+                 next_pool.key().as_ref(),
+                 Order::get_id_seed(params.next_order_id).as_slice()],
+        bump
+    )]
+    pub next_order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool",
+                 next_token_pair.load()?.config_a.custody.as_ref(),
+                 next_token_pair.load()?.config_b.custody.as_ref(),
+                 next_pool.load()?.time_in_force.to_le_bytes().as_slice(),
+                 next_pool.load()?.counter.to_le_bytes().as_slice()],
+        bump = next_pool.load()?.bump
+    )]
+    pub next_pool: AccountLoader<'info, Pool>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LinkOrderParams {
+    // side of the order in the next token pair, must sell proceeds of the linked order
+    next_side: OrderSide,
+    // zero for the default order in the next pool
+    next_order_id: u64,
+}
+
+pub fn link_order(ctx: Context<LinkOrder>, params: &LinkOrderParams) -> Result<()> {
+    Order::validate_user_id(params.next_order_id)?;
+    let token_pair = ctx.accounts.token_pair.load()?;
+    let (next_token_pair, next_slots) = TokenPair::load_slots(&ctx.accounts.next_token_pair)?;
+    require!(
+        next_token_pair.allow_deposits,
+        TwammError::DepositsNotAllowed
+    );
+    require_keys_neq!(
+        ctx.accounts.token_pair.key(),
+        ctx.accounts.next_token_pair.key(),
+        TwammError::InvalidOrderLink
+    );
+
+    // proceeds of the order must be the source token of the next order
+    let order = ctx.accounts.order.as_mut();
+    let proceeds_mint = if order.side == OrderSide::Sell {
+        token_pair.config_b.mint
+    } else {
+        token_pair.config_a.mint
+    };
+    let next_source_mint = if params.next_side == OrderSide::Sell {
+        next_token_pair.config_a.mint
+    } else {
+        next_token_pair.config_b.mint
+    };
+    require_keys_eq!(
+        proceeds_mint,
+        next_source_mint,
+        TwammError::InvalidOrderLink
+    );
+
+    // next pool must accept new orders
+    let current_time = next_token_pair.get_time()?;
+    let mut next_pool = ctx.accounts.next_pool.load_mut()?;
+    msg!("Update next pool state");
+    match next_pool.update_state(next_token_pair.min_time_till_expiration, current_time)? {
+        PoolStatus::Locked => return err!(TwammError::LockedPool),
+        PoolStatus::Expired => return err!(TwammError::ExpiredPool),
+        _ => {}
+    }
+
+    // proceeds stream until the order's pool expires, the next pool must stay open as long
+    let pool = ctx.accounts.pool.load()?;
+    if !TokenPair::is_current_pool(&next_slots, &next_pool)
+        || next_pool.expiration_time < pool.expiration_time
+    {
+        msg!(
+            "Error: Next pool expires at {}, before the order's pool at {}, or is not current",
+            { next_pool.expiration_time },
+            { pool.expiration_time }
+        );
+        return err!(TwammError::InvalidOrderLink);
+    }
+
+    // create an empty next order or check that the existing one can receive proceeds
+    let next_order = ctx.accounts.next_order.as_mut();
+    if next_order.is_empty() {
+        msg!("Initialize next order");
+        next_order.init(
+            ctx.accounts.owner.key(),
+            params.next_order_id,
+            params.next_side,
+            ctx.accounts.next_pool.key(),
+            current_time,
+            *ctx.bumps
+                .get("next_order")
+                .ok_or(ProgramError::InvalidSeeds)?,
+        );
+
+        let pool_side = next_pool.get_side_mut(params.next_side, false);
+        pool_side.num_traders = math::checked_add(pool_side.num_traders, 1)?;
+    } else {
+        require_eq!(
+            next_order.side,
+            params.next_side,
+            TwammError::OrderSideMismatch
+        );
+        require!(!next_order.exact_output, TwammError::OrderTypeMismatch);
+    }
+
+    msg!("Link order");
+    order.next_order = next_order.key();
+
+    Ok(())
+}
@@ -0,0 +1,57 @@
+//! Migrate account instruction handler
+
+use {crate::state::legacy::LegacyAccount, anchor_lang::prelude::*};
+
+#[derive(Accounts)]
+pub struct MigrateAccount<'info> {
+    // pays for the extra rent if the new layout is larger
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: TokenPair, Pool, Order or Multisig account, validated in the handler
+    #[account(
+        mut,
+        owner = crate::ID
+    )]
+    pub account: AccountInfo<'info>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct MigrateAccountParams {}
+
+pub fn migrate_account(ctx: Context<MigrateAccount>, _params: &MigrateAccountParams) -> Result<()> {
+    let account = &ctx.accounts.account;
+    let legacy = match LegacyAccount::try_read(account)? {
+        Some(legacy) => legacy,
+        None => {
+            msg!("Account is already migrated");
+            return Ok(());
+        }
+    };
+
+    msg!("Migrate {} account", legacy);
+    resize_account(&ctx, legacy.get_migrated_len())?;
+    legacy.write(account)
+}
+
+/// Resizes the account, keeps it rent exempt, and zeroes data after the discriminator
+fn resize_account(ctx: &Context<MigrateAccount>, new_len: usize) -> Result<()> {
+    let account = &ctx.accounts.account;
+    let required_lamports = Rent::get()?
+        .minimum_balance(new_len)
+        .saturating_sub(account.try_lamports()?);
+    if required_lamports > 0 {
+        let cpi_accounts = anchor_lang::system_program::Transfer {
+            from: ctx.accounts.payer.to_account_info(),
+            to: account.clone(),
+        };
+        let cpi_context =
+            CpiContext::new(ctx.accounts.system_program.to_account_info(), cpi_accounts);
+        anchor_lang::system_program::transfer(cpi_context, required_lamports)?;
+    }
+    account.realloc(new_len, false)?;
+    account.try_borrow_mut_data()?[8..].fill(0);
+    Ok(())
+}
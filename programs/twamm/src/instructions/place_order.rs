@@ -3,15 +3,21 @@
 use {
     crate::{
         error::TwammError,
-        math, state,
+        math,
+        oracle::{OraclePrice, OracleType},
+        state,
         state::{
             order::{Order, OrderSide},
-            pool::{Pool, PoolStatus},
+            pool::{Pool, PoolStatus, Schedule},
             token_pair::TokenPair,
         },
     },
     anchor_lang::{prelude::*, Discriminator},
-    anchor_spl::token::{Token, TokenAccount, Transfer},
+    anchor_spl::{
+        associated_token::AssociatedToken,
+        token::{self, Token},
+        token_interface::{Mint, TokenAccount, TokenInterface},
+    },
 };
 
 #[derive(Accounts)]
@@ -20,39 +26,60 @@ pub struct PlaceOrder<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
 
+    // user token accounts can be omitted for the native mint to pay with lamports
     #[account(
         mut,
         constraint = user_account_token_a.mint == custody_token_a.mint,
         has_one = owner
     )]
-    pub user_account_token_a: Box<Account<'info, TokenAccount>>,
+    pub user_account_token_a: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
 
     #[account(
         mut,
         constraint = user_account_token_b.mint == custody_token_b.mint,
         has_one = owner
     )]
-    pub user_account_token_b: Box<Account<'info, TokenAccount>>,
+    pub user_account_token_b: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
 
     #[account(
         mut,
         seeds = [b"token_pair",
-                 token_pair.config_a.mint.as_ref(),
-                 token_pair.config_b.mint.as_ref()],
-        bump = token_pair.token_pair_bump
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    /// CHECK: oracle account for token a depending on oracle type, only required
+    /// for orders with price conditions
+    #[account(
+        constraint = oracle_token_a.key() == token_pair.load()?.config_a.oracle_account
+    )]
+    pub oracle_token_a: Option<AccountInfo<'info>>,
+
+    /// CHECK: oracle account for token b depending on oracle type, only required
+    /// for orders with price conditions
+    #[account(
+        constraint = oracle_token_b.key() == token_pair.load()?.config_b.oracle_account
     )]
-    pub token_pair: Box<Account<'info, TokenPair>>,
+    pub oracle_token_b: Option<AccountInfo<'info>>,
+
+    #[account(constraint = mint_token_a.key() == token_pair.load()?.config_a.mint)]
+    pub mint_token_a: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(constraint = mint_token_b.key() == token_pair.load()?.config_b.mint)]
+    pub mint_token_b: Box<InterfaceAccount<'info, Mint>>,
 
     #[account(
         mut,
-        constraint = custody_token_a.key() == token_pair.config_a.custody
+        constraint = custody_token_a.key() == token_pair.load()?.config_a.custody
     )]
-    pub custody_token_a: Box<Account<'info, TokenAccount>>,
+    pub custody_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(mut,
-        constraint = custody_token_b.key() == token_pair.config_b.custody
+        constraint = custody_token_b.key() == token_pair.load()?.config_b.custody
     )]
-    pub custody_token_b: Box<Account<'info, TokenAccount>>,
+    pub custody_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         init_if_needed,
@@ -60,7 +87,8 @@ pub struct PlaceOrder<'info> {
         space = Order::LEN,
         seeds = [b"order",
                  owner.key().as_ref(),
-                 target_pool.key().as_ref()],
+                 target_pool.key().as_ref(),
+                 Order::get_id_seed(params.order_id).as_slice()],
         bump
     )]
     pub order: Box<Account<'info, Order>>,
@@ -71,56 +99,176 @@ pub struct PlaceOrder<'info> {
         payer = owner,
         space = Pool::LEN,
         seeds = [b"pool",
-                 token_pair.config_a.custody.as_ref(),
-                 token_pair.config_b.custody.as_ref(),
-                 token_pair.tifs[token_pair.get_tif_index(params.time_in_force)?].to_le_bytes().as_slice(),
-                 token_pair.pool_counters[token_pair.get_tif_index(params.time_in_force)?].to_le_bytes().as_slice()],
+                 token_pair.load()?.config_a.custody.as_ref(),
+                 token_pair.load()?.config_b.custody.as_ref(),
+                 params.time_in_force.to_le_bytes().as_slice(),
+                 TokenPair::load_pool_counter(&token_pair, params.time_in_force)?.to_le_bytes().as_slice()],
         bump
     )]
-    pub current_pool: Box<Account<'info, Pool>>,
+    pub current_pool: AccountLoader<'info, Pool>,
 
     /// CHECK: Pool to deposit tokens to, seeds should match either current_pool or with counter + 1
     #[account(mut)]
     pub target_pool: AccountInfo<'info>,
 
+    /// CHECK: empty PDA, authority for token accounts, only required to mint position tokens
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = token_pair.load()?.transfer_authority_bump
+    )]
+    pub transfer_authority: Option<AccountInfo<'info>>,
+
+    // position tokens are minted for the deposit instead of crediting the order if set
+    #[account(
+        init_if_needed,
+        payer = owner,
+        mint::authority = transfer_authority,
+        mint::decimals = if params.side == OrderSide::Sell {
+            token_pair.load()?.config_a.decimals
+        } else {
+            token_pair.load()?.config_b.decimals
+        },
+        seeds = [b"position_mint",
+                 target_pool.key().as_ref(),
+                 [params.side as u8].as_slice()],
+        bump
+    )]
+    pub position_mint: Option<Box<Account<'info, token::Mint>>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = position_mint,
+        associated_token::authority = owner
+    )]
+    pub user_position_account: Option<Box<Account<'info, token::TokenAccount>>>,
+
     system_program: Program<'info, System>,
-    token_program: Program<'info, Token>,
+    token_program_a: Interface<'info, TokenInterface>,
+    token_program_b: Interface<'info, TokenInterface>,
+    token_program: Option<Program<'info, Token>>,
+    associated_token_program: Option<Program<'info, AssociatedToken>>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct PlaceOrderParams {
     side: OrderSide,
     time_in_force: u32,
+    // max budget for exact-output orders
     amount: u64,
+    // target tokens to acquire, zero for exact-input orders
+    target_amount: u64,
+    // ignored for existing orders, use update_order to change it
+    limit_price: OraclePrice,
+    // max relative deviation from the pair price at placement as a fixed-point ratio,
+    // zero if not set, ignored for existing orders
+    max_price_deviation: u64,
+    // zero to start immediately, ignored for existing orders
+    start_time: i64,
+    // zero for the default order in the pool
+    order_id: u64,
+    // roll unfilled balance into the next pool at expiry, ignored for existing orders
+    auto_roll: bool,
+    // carry proceeds over to the next pool instead of paying them out on roll
+    roll_proceeds: bool,
 }
 
 pub fn place_order(ctx: Context<PlaceOrder>, params: &PlaceOrderParams) -> Result<()> {
     // validate inputs
     require_gt!(params.amount, 0u64, TwammError::InvalidTokenAmount);
+    Order::validate_user_id(params.order_id)?;
+
+    // credit the amount custody receives after the token transfer fee, if any
+    let source_mint = if params.side == OrderSide::Sell {
+        ctx.accounts.mint_token_a.to_account_info()
+    } else {
+        ctx.accounts.mint_token_b.to_account_info()
+    };
+    let amount = math::checked_sub(
+        params.amount,
+        TokenPair::get_transfer_fee(&source_mint, params.amount)?,
+    )?;
+    require_gt!(amount, 0u64, TwammError::InvalidTokenAmount);
 
-    let token_pair = ctx.accounts.token_pair.as_mut();
+    if !params.limit_price.is_valid_order_price() {
+        msg!("Error: Limit price exponent is out of range");
+        return err!(TwammError::InvalidTokenPairPrice);
+    }
+
+    let exact_output = params.target_amount > 0;
+    if exact_output
+        && (params.limit_price.price > 0
+            || params.start_time > 0
+            || params.auto_roll
+            || params.max_price_deviation > 0)
+    {
+        msg!("Error: Exact-output orders can't have limit, start time or auto-roll");
+        return err!(TwammError::OrderTypeMismatch);
+    }
+
+    let (token_pair, mut slots) = TokenPair::load_slots_mut(&ctx.accounts.token_pair)?;
     require!(token_pair.allow_deposits, TwammError::DepositsNotAllowed);
 
-    let tif_index = token_pair.get_tif_index(params.time_in_force)?;
+    let tif_index = TokenPair::get_tif_index(&slots, params.time_in_force)?;
+
+    if (params.limit_price.price > 0 || params.max_price_deviation > 0)
+        && (matches!(token_pair.config_a.oracle_type, OracleType::None)
+            || matches!(token_pair.config_b.oracle_type, OracleType::None))
+    {
+        msg!("Error: Limit and price band orders require price oracles for both tokens");
+        return err!(TwammError::UnsupportedOracle);
+    }
 
     // create a new user order PDA or check that it matches given side and pool if order already exists
     let target_pool = &ctx.accounts.target_pool;
     let order = ctx.accounts.order.as_mut();
     let current_time = token_pair.get_time()?;
-    if order.lp_balance == 0 {
+    let new_order = order.is_empty();
+    let mut price_breached = false;
+    if new_order {
         msg!("Initialize order");
-        order.owner = ctx.accounts.owner.key();
-        order.time = current_time;
-        order.side = params.side;
-        order.pool = target_pool.key();
-        order.lp_balance = 0;
-        order.token_debt = 0;
-        order.unsettled_balance = 0;
-        order.settlement_debt = 0;
-        order.last_balance_change_time = current_time;
-        order.bump = *ctx.bumps.get("order").ok_or(ProgramError::InvalidSeeds)?;
+        order.init(
+            ctx.accounts.owner.key(),
+            params.order_id,
+            params.side,
+            target_pool.key(),
+            current_time,
+            *ctx.bumps.get("order").ok_or(ProgramError::InvalidSeeds)?,
+        );
+        order.start_time = std::cmp::max(params.start_time, current_time);
+        order.exact_output = exact_output;
+        order.limit_price = params.limit_price;
+        order.max_price_deviation = params.max_price_deviation;
+        if order.has_price_conditions() {
+            let pair_price = match (&ctx.accounts.oracle_token_a, &ctx.accounts.oracle_token_b) {
+                (Some(oracle_token_a), Some(oracle_token_b)) => {
+                    token_pair.get_token_pair_oracle_price(oracle_token_a, oracle_token_b)?
+                }
+                _ => {
+                    msg!("Error: Orders with price conditions require oracle accounts");
+                    return err!(TwammError::InvalidOracleAccount);
+                }
+            };
+            if params.max_price_deviation > 0 {
+                order.reference_price = pair_price;
+                msg!(
+                    "Reference price: {}e{}",
+                    order.reference_price.price,
+                    order.reference_price.exponent
+                );
+            }
+            // order starts out of the pool if its limit is already breached
+            price_breached = !order.is_price_acceptable(&pair_price)?;
+        }
+        order.auto_roll = params.auto_roll;
+        order.roll_proceeds = params.auto_roll && params.roll_proceeds;
     } else {
         require_eq!(order.side, params.side, TwammError::OrderSideMismatch);
+        require_eq!(
+            order.exact_output,
+            exact_output,
+            TwammError::OrderTypeMismatch
+        );
         require_keys_eq!(
             order.pool,
             target_pool.key(),
@@ -129,38 +277,36 @@ pub fn place_order(ctx: Context<PlaceOrder>, params: &PlaceOrderParams) -> Resul
     };
 
     // validate pool addresses and initialize a new pool if needed
-    if !token_pair.current_pool_present[tif_index] {
+    if !slots[tif_index].current_pool_present {
         msg!("Initialize current pool");
-        let current_pool = ctx.accounts.current_pool.as_mut();
-        current_pool.status = PoolStatus::Active;
-        current_pool.time_in_force = params.time_in_force;
-        current_pool.expiration_time =
-            math::checked_add(current_time, params.time_in_force as i64)?;
-        current_pool.token_pair = token_pair.key();
-        current_pool.counter = token_pair.pool_counters[tif_index];
-        current_pool.bump = *ctx
-            .bumps
-            .get("current_pool")
-            .ok_or(ProgramError::InvalidSeeds)?;
-        token_pair.current_pool_present[tif_index] = true;
+        state::load_or_init_mut(&ctx.accounts.current_pool)?.init_current(
+            &mut slots[tif_index],
+            ctx.accounts.token_pair.key(),
+            current_time,
+            *ctx.bumps
+                .get("current_pool")
+                .ok_or(ProgramError::InvalidSeeds)?,
+        )?;
     }
-    assert!(ctx.accounts.current_pool.expiration_time != 0);
+    let current_expiration_time =
+        state::load_or_init_mut(&ctx.accounts.current_pool)?.expiration_time;
+    assert!(current_expiration_time != 0);
 
-    let mut pool_acc;
-    let pool;
+    let target_pool_loader;
+    let mut pool;
     let current_pool_key = ctx.accounts.current_pool.key();
     if target_pool.key() == current_pool_key {
-        pool = ctx.accounts.current_pool.as_mut();
+        pool = state::load_or_init_mut(&ctx.accounts.current_pool)?;
     } else {
         msg!("Validate future pool");
-        assert!(token_pair.current_pool_present[tif_index]);
-        let future_counter = math::checked_add(token_pair.pool_counters[tif_index], 1)?;
+        assert!(slots[tif_index].current_pool_present);
+        let future_counter = math::checked_add(slots[tif_index].pool_counter, 1)?;
         let (future_pool_address, future_pool_bump) = Pubkey::find_program_address(
             &[
                 b"pool",
                 ctx.accounts.custody_token_a.key().as_ref(),
                 ctx.accounts.custody_token_b.key().as_ref(),
-                token_pair.tifs[tif_index].to_le_bytes().as_slice(),
+                slots[tif_index].time_in_force.to_le_bytes().as_slice(),
                 future_counter.to_le_bytes().as_slice(),
             ],
             &crate::ID,
@@ -191,7 +337,7 @@ pub fn place_order(ctx: Context<PlaceOrder>, params: &PlaceOrderParams) -> Resul
                     b"pool",
                     ctx.accounts.custody_token_a.key().as_ref(),
                     ctx.accounts.custody_token_b.key().as_ref(),
-                    token_pair.tifs[tif_index].to_le_bytes().as_slice(),
+                    slots[tif_index].time_in_force.to_le_bytes().as_slice(),
                     future_counter.to_le_bytes().as_slice(),
                     &[future_pool_bump],
                 ]],
@@ -202,21 +348,24 @@ pub fn place_order(ctx: Context<PlaceOrder>, params: &PlaceOrderParams) -> Resul
             pool_data[..8].copy_from_slice(Pool::discriminator().as_slice());
         }
 
-        pool_acc = Account::<Pool>::try_from(target_pool)?;
-        pool = &mut pool_acc;
+        target_pool_loader = AccountLoader::<Pool>::try_from(target_pool)?;
+        pool = target_pool_loader.load_mut()?;
         if pool.expiration_time == 0 {
             msg!("Configure future pool");
+            pool.version = Pool::VERSION;
             pool.status = PoolStatus::Active;
             pool.time_in_force = params.time_in_force;
-            pool.expiration_time = math::checked_add(
-                ctx.accounts.current_pool.expiration_time,
-                params.time_in_force as i64,
-            )?;
-            pool.token_pair = token_pair.key();
+            pool.expiration_time =
+                math::checked_add(current_expiration_time, params.time_in_force as i64)?;
+            pool.token_pair = ctx.accounts.token_pair.key();
             pool.counter = future_counter;
+            pool.schedule = Schedule {
+                shape: slots[tif_index].schedule_shape,
+                start_time: current_expiration_time,
+            };
             pool.bump = future_pool_bump;
         }
-        token_pair.future_pool_present[tif_index] = true;
+        slots[tif_index].future_pool_present = true;
     };
 
     // update and check the state
@@ -226,87 +375,194 @@ pub fn place_order(ctx: Context<PlaceOrder>, params: &PlaceOrderParams) -> Resul
         PoolStatus::Expired => return err!(TwammError::ExpiredPool),
         _ => {}
     }
-
-    // compute lp and debt amounts
-    msg!("Compute amounts");
-    let expiration_time = pool.expiration_time;
-    let pool_side = if params.side == OrderSide::Buy {
-        &mut pool.buy_side
-    } else {
-        &mut pool.sell_side
-    };
-    let lp_amount;
-    let debt_amount;
-    if pool_side.source_balance == 0 {
-        if pool_side.target_balance != 0 && pool_side.num_traders != 0 {
-            msg!("Error: Attempt to deposit into finalized pool");
-            return err!(TwammError::InvalidPoolState);
-        }
-        lp_amount = params.amount;
-        debt_amount = 0;
-    } else {
-        lp_amount = math::checked_as_u64(math::checked_div(
-            math::checked_mul(params.amount as u128, pool_side.lp_supply as u128)?,
-            pool_side.source_balance as u128,
-        )?)?;
-        debt_amount = math::checked_as_u64(math::checked_ceil_div(
-            math::checked_mul(
-                params.amount as u128,
-                math::checked_add(
-                    pool_side.target_balance as u128,
-                    pool_side.token_debt_total as u128,
-                )?,
-            )?,
-            pool_side.source_balance as u128,
-        )?)?;
+    if !order.is_started(current_time)
+        && pool.is_locked(token_pair.min_time_till_expiration, order.start_time)?
+    {
+        msg!("Error: Order start time is outside of the pool period");
+        return err!(TwammError::InvalidStartTime);
     }
 
     // update pool balances
     msg!("Update pool balances");
-    let current_time = token_pair.get_time()?;
-    pool_side.settlement_debt_total =
-        pool_side.get_unsettled_amount(expiration_time, current_time)?;
-    pool_side.last_balance_change_time = current_time;
-    pool_side.source_balance = math::checked_add(pool_side.source_balance, params.amount)?;
-    pool_side.lp_supply = math::checked_add(pool_side.lp_supply, lp_amount)?;
-    pool_side.token_debt_total = math::checked_add(pool_side.token_debt_total, debt_amount)?;
-    if order.lp_balance == 0 {
+    let expiration_time = pool.expiration_time;
+    let schedule = pool.schedule;
+    let pool_side = pool.get_side_mut(params.side, exact_output);
+    if new_order {
         pool_side.num_traders = math::checked_add(pool_side.num_traders, 1)?;
     }
-    if target_pool.key() != current_pool_key {
-        pool.exit(&crate::ID)?;
+    order.sync_start(pool_side)?;
+    let lp_balance = order.lp_balance;
+    let delayed = order.pending_start
+        || (new_order && !order.is_started(current_time) && !order.has_price_conditions());
+    if delayed && pool_side.add_pending_start(order.start_time, amount, new_order)? {
+        // settlement moves tokens to the pool at the order's start time
+        order.add_held_balance(pool_side, amount)?;
+        order.pending_start = true;
+    } else if order.is_held() || !order.is_started(current_time) || price_breached {
+        // order is out of the pool, tokens join it once conditions are met
+        order.add_held_balance(pool_side, amount)?;
+    } else if exact_output {
+        // the whole amount is the order's budget, settlement is bounded by the average
+        // price it allows for the target left to acquire
+        let (budget_left, _, _) = order.get_budget_withdrawal(pool_side, order.lp_balance)?;
+        let limit_price = token_pair.get_budget_price(
+            params.side,
+            math::checked_add(budget_left, amount)?,
+            math::checked_add(order.get_target_pending(pool_side)?, params.target_amount)?,
+        )?;
+
+        msg!("Update user order");
+        order.deposit_target(
+            pool_side,
+            params.target_amount,
+            amount,
+            limit_price,
+            &schedule,
+            expiration_time,
+            current_time,
+        )?;
+    } else {
+        msg!("Update user order");
+        order.deposit(pool_side, amount, &schedule, expiration_time, current_time)?;
     }
 
-    // update user order
-    msg!("Update user order");
-    order.lp_balance = math::checked_add(order.lp_balance, lp_amount)?;
-    order.token_debt = math::checked_add(order.token_debt, debt_amount)?;
-    order.settlement_debt = order.get_unsettled_amount(expiration_time, current_time)?;
-    order.unsettled_balance = math::checked_add(order.unsettled_balance, params.amount)?;
-    order.last_balance_change_time = current_time;
+    // position tokens are minted for the lp credited by the deposit, the order pays in
+    // debt that position tokens don't carry
+    let mut position_amount = 0;
+    let mut amount_owed = 0;
+    if ctx.accounts.position_mint.is_some() {
+        // held and delayed deposits don't credit lp, there is nothing to mint tokens for
+        position_amount = math::checked_sub(order.lp_balance, lp_balance)?;
+        require_gt!(position_amount, 0u64, TwammError::OrderNotTokenizable);
+        amount_owed = order.tokenize(
+            pool_side,
+            position_amount,
+            &schedule,
+            expiration_time,
+            current_time,
+        )?;
+    }
 
-    // transfer tokens to the custodies
-    msg!("Transfer tokens to custodies");
-    let context = if params.side == OrderSide::Sell {
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.user_account_token_a.to_account_info(),
-                to: ctx.accounts.custody_token_a.to_account_info(),
-                authority: ctx.accounts.owner.to_account_info(),
-            },
+    let transfer_amount = params.amount;
+
+    // wrap native SOL if the user's wrapped balance is not sufficient
+    let (user_account, custody, mint, token_program) = if params.side == OrderSide::Sell {
+        (
+            &ctx.accounts.user_account_token_a,
+            &ctx.accounts.custody_token_a,
+            &ctx.accounts.mint_token_a,
+            &ctx.accounts.token_program_a,
         )
     } else {
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.user_account_token_b.to_account_info(),
-                to: ctx.accounts.custody_token_b.to_account_info(),
-                authority: ctx.accounts.owner.to_account_info(),
-            },
+        (
+            &ctx.accounts.user_account_token_b,
+            &ctx.accounts.custody_token_b,
+            &ctx.accounts.mint_token_b,
+            &ctx.accounts.token_program_b,
         )
     };
-    anchor_spl::token::transfer(context, params.amount)?;
+    if let Some(user_account) = user_account {
+        if user_account.is_native() && user_account.amount < transfer_amount {
+            msg!("Wrap native SOL");
+            TokenPair::wrap_native_tokens(
+                ctx.accounts.owner.to_account_info(),
+                user_account.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+                token_program.to_account_info(),
+                math::checked_sub(transfer_amount, user_account.amount)?,
+            )?;
+        }
+    }
+
+    // transfer tokens to the custodies
+    msg!("Transfer tokens to custodies");
+    match user_account {
+        Some(user_account) => token_pair.deposit_tokens(
+            user_account.to_account_info(),
+            custody.to_account_info(),
+            mint.to_account_info(),
+            ctx.accounts.owner.to_account_info(),
+            token_program.to_account_info(),
+            transfer_amount,
+        )?,
+        None => TokenPair::deposit_native_tokens(
+            ctx.accounts.owner.to_account_info(),
+            custody.to_account_info(),
+            mint.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+            token_program.to_account_info(),
+            transfer_amount,
+        )?,
+    }
+
+    if position_amount > 0 {
+        let (transfer_authority, position_mint, user_position_account, position_token_program) =
+            match (
+                &ctx.accounts.transfer_authority,
+                &ctx.accounts.position_mint,
+                &ctx.accounts.user_position_account,
+                &ctx.accounts.token_program,
+            ) {
+                (Some(authority), Some(mint), Some(user_account), Some(token_program)) => {
+                    (authority, mint, user_account, token_program)
+                }
+                _ => {
+                    msg!("Error: Position tokens require the transfer authority and token program");
+                    return err!(TwammError::InvalidTokenAccount);
+                }
+            };
+
+        msg!("Mint position tokens to the user");
+        token_pair.mint_tokens(
+            position_mint.to_account_info(),
+            user_position_account.to_account_info(),
+            transfer_authority.clone(),
+            position_token_program.to_account_info(),
+            position_amount,
+        )?;
+
+        // pay in the debt, custody must receive the full amount after the token transfer fee
+        if amount_owed > 0 {
+            msg!("Transfer owed tokens to the custody: {}", amount_owed);
+            let (user_account, custody, mint, token_program) = if params.side == OrderSide::Sell {
+                (
+                    &ctx.accounts.user_account_token_b,
+                    &ctx.accounts.custody_token_b,
+                    &ctx.accounts.mint_token_b,
+                    &ctx.accounts.token_program_b,
+                )
+            } else {
+                (
+                    &ctx.accounts.user_account_token_a,
+                    &ctx.accounts.custody_token_a,
+                    &ctx.accounts.mint_token_a,
+                    &ctx.accounts.token_program_a,
+                )
+            };
+            let transfer_amount = math::checked_add(
+                amount_owed,
+                TokenPair::get_inverse_transfer_fee(&mint.to_account_info(), amount_owed)?,
+            )?;
+            match user_account {
+                Some(user_account) => token_pair.deposit_tokens(
+                    user_account.to_account_info(),
+                    custody.to_account_info(),
+                    mint.to_account_info(),
+                    ctx.accounts.owner.to_account_info(),
+                    token_program.to_account_info(),
+                    transfer_amount,
+                )?,
+                None => TokenPair::deposit_native_tokens(
+                    ctx.accounts.owner.to_account_info(),
+                    custody.to_account_info(),
+                    mint.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                    token_program.to_account_info(),
+                    transfer_amount,
+                )?,
+            }
+        }
+    }
 
     Ok(())
 }
@@ -0,0 +1,199 @@
+//! Place stop order instruction handler
+
+use {
+    crate::{
+        error::TwammError,
+        math,
+        oracle::{OraclePrice, OracleType},
+        state::{
+            order::{Order, OrderSide},
+            stop_order::StopOrder,
+            token_pair::TokenPair,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface},
+};
+
+#[derive(Accounts)]
+#[instruction(params: PlaceStopOrderParams)]
+pub struct PlaceStopOrder<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    // user token accounts can be omitted for the native mint to pay with lamports
+    #[account(
+        mut,
+        constraint = user_account_token_a.mint == custody_token_a.mint,
+        has_one = owner
+    )]
+    pub user_account_token_a: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        constraint = user_account_token_b.mint == custody_token_b.mint,
+        has_one = owner
+    )]
+    pub user_account_token_b: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    #[account(constraint = mint_token_a.key() == token_pair.load()?.config_a.mint)]
+    pub mint_token_a: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(constraint = mint_token_b.key() == token_pair.load()?.config_b.mint)]
+    pub mint_token_b: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = custody_token_a.key() == token_pair.load()?.config_a.custody
+    )]
+    pub custody_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = custody_token_b.key() == token_pair.load()?.config_b.custody
+    )]
+    pub custody_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = StopOrder::LEN,
+        seeds = [b"stop_order",
+                 owner.key().as_ref(),
+                 token_pair.key().as_ref(),
+                 params.order_id.to_le_bytes().as_slice()],
+        bump
+    )]
+    pub stop_order: Box<Account<'info, StopOrder>>,
+
+    system_program: Program<'info, System>,
+    token_program_a: Interface<'info, TokenInterface>,
+    token_program_b: Interface<'info, TokenInterface>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct PlaceStopOrderParams {
+    side: OrderSide,
+    time_in_force: u32,
+    amount: u64,
+    trigger_price: OraclePrice,
+    // zero if not set
+    limit_price: OraclePrice,
+    // max relative deviation from the pair price at trigger as a fixed-point ratio,
+    // zero if not set
+    max_price_deviation: u64,
+    // id of the order placed once triggered, zero for the default order in the pool
+    order_id: u64,
+}
+
+pub fn place_stop_order(ctx: Context<PlaceStopOrder>, params: &PlaceStopOrderParams) -> Result<()> {
+    // validate inputs
+    require_gt!(params.amount, 0u64, TwammError::InvalidTokenAmount);
+    Order::validate_user_id(params.order_id)?;
+    require_gt!(
+        params.trigger_price.price,
+        0u64,
+        TwammError::OrderNotTriggerable
+    );
+    if !params.limit_price.is_valid_order_price() || !params.trigger_price.is_valid_order_price() {
+        msg!("Error: Limit and trigger price exponents are out of range");
+        return err!(TwammError::InvalidTokenPairPrice);
+    }
+
+    // credit the amount custody receives after the token transfer fee, if any
+    let source_mint = if params.side == OrderSide::Sell {
+        ctx.accounts.mint_token_a.to_account_info()
+    } else {
+        ctx.accounts.mint_token_b.to_account_info()
+    };
+    let amount = math::checked_sub(
+        params.amount,
+        TokenPair::get_transfer_fee(&source_mint, params.amount)?,
+    )?;
+    require_gt!(amount, 0u64, TwammError::InvalidTokenAmount);
+
+    let (token_pair, slots) = TokenPair::load_slots(&ctx.accounts.token_pair)?;
+    require!(token_pair.allow_deposits, TwammError::DepositsNotAllowed);
+    TokenPair::get_tif_index(&slots, params.time_in_force)?;
+    if matches!(token_pair.config_a.oracle_type, OracleType::None)
+        || matches!(token_pair.config_b.oracle_type, OracleType::None)
+    {
+        msg!("Error: Stop orders require price oracles for both tokens");
+        return err!(TwammError::UnsupportedOracle);
+    }
+
+    // tokens are escrowed out of any pool until the trigger price is crossed
+    msg!("Initialize stop order");
+    let stop_order = ctx.accounts.stop_order.as_mut();
+    stop_order.owner = ctx.accounts.owner.key();
+    stop_order.id = params.order_id;
+    stop_order.token_pair = ctx.accounts.token_pair.key();
+    stop_order.time = token_pair.get_time()?;
+    stop_order.side = params.side;
+    stop_order.time_in_force = params.time_in_force;
+    stop_order.amount = amount;
+    stop_order.trigger_price = params.trigger_price;
+    stop_order.limit_price = params.limit_price;
+    stop_order.max_price_deviation = params.max_price_deviation;
+    stop_order.bump = *ctx
+        .bumps
+        .get("stop_order")
+        .ok_or(ProgramError::InvalidSeeds)?;
+
+    // the keeper that triggers the order creates the order account, prefund its rent
+    msg!("Prefund order rent");
+    let context = CpiContext::new(
+        ctx.accounts.system_program.to_account_info(),
+        anchor_lang::system_program::Transfer {
+            from: ctx.accounts.owner.to_account_info(),
+            to: ctx.accounts.stop_order.to_account_info(),
+        },
+    );
+    anchor_lang::system_program::transfer(context, Rent::get()?.minimum_balance(Order::LEN))?;
+
+    // transfer tokens to the custodies
+    msg!("Transfer tokens to custodies");
+    let (user_account, custody, mint, token_program) = if params.side == OrderSide::Sell {
+        (
+            &ctx.accounts.user_account_token_a,
+            &ctx.accounts.custody_token_a,
+            &ctx.accounts.mint_token_a,
+            &ctx.accounts.token_program_a,
+        )
+    } else {
+        (
+            &ctx.accounts.user_account_token_b,
+            &ctx.accounts.custody_token_b,
+            &ctx.accounts.mint_token_b,
+            &ctx.accounts.token_program_b,
+        )
+    };
+    match user_account {
+        Some(user_account) => token_pair.deposit_tokens(
+            user_account.to_account_info(),
+            custody.to_account_info(),
+            mint.to_account_info(),
+            ctx.accounts.owner.to_account_info(),
+            token_program.to_account_info(),
+            params.amount,
+        )?,
+        None => TokenPair::deposit_native_tokens(
+            ctx.accounts.owner.to_account_info(),
+            custody.to_account_info(),
+            mint.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+            token_program.to_account_info(),
+            params.amount,
+        )?,
+    }
+
+    Ok(())
+}
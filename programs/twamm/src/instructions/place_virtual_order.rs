@@ -0,0 +1,246 @@
+//! Place virtual order instruction handler
+
+use {
+    crate::{
+        error::TwammError,
+        math,
+        state::{
+            self, order::OrderSide, token_pair::TokenPair, virtual_checkpoint::VirtualCheckpoint,
+            virtual_order::VirtualOrder, virtual_pool::VirtualPool,
+        },
+    },
+    anchor_lang::{prelude::*, Discriminator},
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface},
+};
+
+#[derive(Accounts)]
+#[instruction(params: PlaceVirtualOrderParams)]
+pub struct PlaceVirtualOrder<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    // user token accounts can be omitted for the native mint to pay with lamports
+    #[account(
+        mut,
+        constraint = user_account_token_a.mint == custody_token_a.mint,
+        has_one = owner
+    )]
+    pub user_account_token_a: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        constraint = user_account_token_b.mint == custody_token_b.mint,
+        has_one = owner
+    )]
+    pub user_account_token_b: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    #[account(constraint = mint_token_a.key() == token_pair.load()?.config_a.mint)]
+    pub mint_token_a: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(constraint = mint_token_b.key() == token_pair.load()?.config_b.mint)]
+    pub mint_token_b: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = custody_token_a.key() == token_pair.load()?.config_a.custody
+    )]
+    pub custody_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = custody_token_b.key() == token_pair.load()?.config_b.custody
+    )]
+    pub custody_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = VirtualOrder::LEN,
+        seeds = [b"virtual_order",
+                 owner.key().as_ref(),
+                 virtual_pool.key().as_ref(),
+                 params.expiration_time.to_le_bytes().as_slice()],
+        bump
+    )]
+    pub order: Box<Account<'info, VirtualOrder>>,
+
+    #[account(
+        mut,
+        seeds = [b"virtual_pool",
+                 token_pair.key().as_ref()],
+        bump = virtual_pool.bump
+    )]
+    pub virtual_pool: Box<Account<'info, VirtualPool>>,
+
+    system_program: Program<'info, System>,
+    token_program_a: Interface<'info, TokenInterface>,
+    token_program_b: Interface<'info, TokenInterface>,
+    // remaining accounts:
+    //   checkpoint accounts for buckets that are released to make room for the order,
+    //   seeds = [b"virtual_checkpoint", virtual_pool, bucket time]
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct PlaceVirtualOrderParams {
+    side: OrderSide,
+    amount: u64,
+    // must be aligned to the virtual pool interval
+    expiration_time: i64,
+}
+
+pub fn place_virtual_order<'info>(
+    ctx: Context<'_, '_, '_, 'info, PlaceVirtualOrder<'info>>,
+    params: &PlaceVirtualOrderParams,
+) -> Result<()> {
+    // validate inputs
+    require_gt!(params.amount, 0u64, TwammError::InvalidTokenAmount);
+
+    // credit the amount custody receives after the token transfer fee, if any
+    let source_mint = if params.side == OrderSide::Sell {
+        ctx.accounts.mint_token_a.to_account_info()
+    } else {
+        ctx.accounts.mint_token_b.to_account_info()
+    };
+    let amount = math::checked_sub(
+        params.amount,
+        TokenPair::get_transfer_fee(&source_mint, params.amount)?,
+    )?;
+    require_gt!(amount, 0u64, TwammError::InvalidTokenAmount);
+
+    let token_pair = ctx.accounts.token_pair.load()?;
+    require!(token_pair.allow_deposits, TwammError::DepositsNotAllowed);
+
+    let virtual_pool = ctx.accounts.virtual_pool.as_mut();
+    let current_time = token_pair.get_time()?;
+    let start_time = virtual_pool.get_next_boundary(current_time)?;
+    if virtual_pool.get_boundary(params.expiration_time)? != params.expiration_time
+        || params.expiration_time <= start_time
+    {
+        msg!(
+            "Error: Expiration time must be a multiple of {} and greater than {}",
+            virtual_pool.interval,
+            start_time
+        );
+        return err!(TwammError::InvalidExpirationTime);
+    }
+    if math::checked_sub(params.expiration_time, start_time)? > virtual_pool.get_max_duration()? {
+        msg!(
+            "Error: Order duration can't exceed {} seconds",
+            virtual_pool.get_max_duration()?
+        );
+        return err!(TwammError::InvalidExpirationTime);
+    }
+
+    // initialize the order, tokens are released from the next interval boundary
+    // till the expiration time
+    msg!("Initialize order");
+    let order = ctx.accounts.order.as_mut();
+    order.owner = ctx.accounts.owner.key();
+    order.time = current_time;
+    order.side = params.side;
+    order.virtual_pool = virtual_pool.key();
+    order.amount = amount;
+    order.sell_rate = math::checked_div(
+        math::checked_mul(amount as u128, VirtualPool::RATE_SCALE)?,
+        math::checked_sub(params.expiration_time, start_time)? as u128,
+    )?;
+    order.start_time = start_time;
+    order.expiration_time = params.expiration_time;
+    order.bump = *ctx.bumps.get("order").ok_or(ProgramError::InvalidSeeds)?;
+
+    // update pool rates
+    msg!("Update pool rates");
+    let evicted = virtual_pool.add_order(order)?;
+
+    // buckets the pool has advanced past are still needed by orders that haven't
+    // been cancelled, move them to checkpoint accounts
+    let virtual_pool_key = virtual_pool.key();
+    for bucket in evicted {
+        msg!("Move bucket {} to checkpoint", bucket.time);
+        let time_bytes = bucket.time.to_le_bytes();
+        let (checkpoint_key, checkpoint_bump) = Pubkey::find_program_address(
+            &[
+                b"virtual_checkpoint",
+                virtual_pool_key.as_ref(),
+                time_bytes.as_slice(),
+            ],
+            &crate::ID,
+        );
+        let checkpoint_account = ctx
+            .remaining_accounts
+            .iter()
+            .find(|account| account.key == &checkpoint_key)
+            .ok_or(TwammError::InvalidPoolState)?;
+        if !state::is_empty_account(checkpoint_account)? {
+            msg!("Error: Checkpoint {} already exists", checkpoint_key);
+            return err!(TwammError::InvalidPoolState);
+        }
+        state::initialize_account(
+            ctx.accounts.owner.to_account_info(),
+            checkpoint_account.clone(),
+            ctx.accounts.system_program.to_account_info(),
+            &crate::ID,
+            &[&[
+                b"virtual_checkpoint",
+                virtual_pool_key.as_ref(),
+                time_bytes.as_slice(),
+                &[checkpoint_bump],
+            ]],
+            VirtualCheckpoint::LEN,
+        )?;
+        let checkpoint = VirtualCheckpoint {
+            virtual_pool: virtual_pool_key,
+            bucket,
+            bump: checkpoint_bump,
+        };
+        let mut data = checkpoint_account.try_borrow_mut_data()?;
+        data[..8].copy_from_slice(VirtualCheckpoint::discriminator().as_slice());
+        checkpoint.serialize(&mut &mut data[8..])?;
+    }
+
+    // transfer tokens to the custodies
+    msg!("Transfer tokens to custodies");
+    let (user_account, custody, mint, token_program) = if params.side == OrderSide::Sell {
+        (
+            &ctx.accounts.user_account_token_a,
+            &ctx.accounts.custody_token_a,
+            &ctx.accounts.mint_token_a,
+            &ctx.accounts.token_program_a,
+        )
+    } else {
+        (
+            &ctx.accounts.user_account_token_b,
+            &ctx.accounts.custody_token_b,
+            &ctx.accounts.mint_token_b,
+            &ctx.accounts.token_program_b,
+        )
+    };
+    match user_account {
+        Some(user_account) => token_pair.deposit_tokens(
+            user_account.to_account_info(),
+            custody.to_account_info(),
+            mint.to_account_info(),
+            ctx.accounts.owner.to_account_info(),
+            token_program.to_account_info(),
+            params.amount,
+        )?,
+        None => TokenPair::deposit_native_tokens(
+            ctx.accounts.owner.to_account_info(),
+            custody.to_account_info(),
+            mint.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+            token_program.to_account_info(),
+            params.amount,
+        )?,
+    }
+
+    Ok(())
+}
@@ -0,0 +1,251 @@
+//! Redeem position instruction handler
+
+use {
+    crate::{
+        error::TwammError,
+        math,
+        state::{order::OrderSide, pool::Pool, token_pair::TokenPair},
+    },
+    anchor_lang::{prelude::*, AccountsClose},
+    anchor_spl::{
+        token::{Burn, Mint, Token, TokenAccount},
+        token_interface,
+    },
+};
+
+#[derive(Accounts)]
+#[instruction(params: RedeemPositionParams)]
+pub struct RedeemPosition<'info> {
+    #[account()]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = user_account_token_a.mint == custody_token_a.mint,
+        has_one = owner
+    )]
+    pub user_account_token_a: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_account_token_b.mint == custody_token_b.mint,
+        has_one = owner
+    )]
+    pub user_account_token_b: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_position_account.mint == position_mint.key(),
+        has_one = owner
+    )]
+    pub user_position_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    #[account(constraint = mint_token_a.key() == token_pair.load()?.config_a.mint)]
+    pub mint_token_a: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(constraint = mint_token_b.key() == token_pair.load()?.config_b.mint)]
+    pub mint_token_b: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        mut,
+        seeds = [b"transfer_authority"],
+        bump = token_pair.load()?.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = custody_token_a.key() == token_pair.load()?.config_a.custody
+    )]
+    pub custody_token_a: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = custody_token_b.key() == token_pair.load()?.config_b.custody
+    )]
+    pub custody_token_b: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool",
+                 custody_token_a.key().as_ref(),
+                 custody_token_b.key().as_ref(),
+                 pool.load()?.time_in_force.to_le_bytes().as_slice(),
+                 pool.load()?.counter.to_le_bytes().as_slice()],
+        bump = pool.load()?.bump
+    )]
+    pub pool: AccountLoader<'info, Pool>,
+
+    #[account(
+        mut,
+        seeds = [b"position_mint",
+                 pool.key().as_ref(),
+                 [params.side as u8].as_slice()],
+        bump
+    )]
+    pub position_mint: Box<Account<'info, Mint>>,
+
+    token_program: Program<'info, Token>,
+    token_program_a: Interface<'info, token_interface::TokenInterface>,
+    token_program_b: Interface<'info, token_interface::TokenInterface>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct RedeemPositionParams {
+    side: OrderSide,
+    lp_amount: u64,
+    // close the user's native mint token account to receive SOL
+    unwrap_native: bool,
+}
+
+pub fn redeem_position(ctx: Context<RedeemPosition>, params: &RedeemPositionParams) -> Result<()> {
+    // validate inputs
+    let lp_amount = std::cmp::min(params.lp_amount, ctx.accounts.user_position_account.amount);
+    require_gt!(lp_amount, 0u64, TwammError::InvalidTokenAmount);
+
+    let (mut token_pair, mut slots) = TokenPair::load_slots_mut(&ctx.accounts.token_pair)?;
+    require!(
+        token_pair.allow_withdrawals,
+        TwammError::WithdrawalsNotAllowed
+    );
+
+    let current_time = token_pair.get_time()?;
+    let mut pool = ctx.accounts.pool.load_mut()?;
+    let expiration_time = pool.expiration_time;
+    let schedule = pool.schedule;
+    let pool_side = pool.get_side_mut(params.side, false);
+
+    // compute balance changes, position tokens carry their share of the token debt
+    msg!("Update pool data");
+    let (withdraw_amount_source, withdraw_amount_target) =
+        pool_side.redeem_position(lp_amount, &schedule, expiration_time, current_time)?;
+
+    let withdraw_amount_fees = math::checked_as_u64(math::checked_ceil_div(
+        math::checked_mul(
+            withdraw_amount_target as u128,
+            token_pair.fee_numerator as u128,
+        )?,
+        token_pair.fee_denominator as u128,
+    )?)?;
+
+    // burn position tokens
+    msg!("Burn position tokens");
+    let context = CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        Burn {
+            mint: ctx.accounts.position_mint.to_account_info(),
+            from: ctx.accounts.user_position_account.to_account_info(),
+            authority: ctx.accounts.owner.to_account_info(),
+        },
+    );
+    anchor_spl::token::burn(context, lp_amount)?;
+
+    // transfer tokens to the user
+    msg!("Transfer tokens to the user");
+    let (withdraw_amount_a, withdraw_amount_b) = if params.side == OrderSide::Sell {
+        (
+            withdraw_amount_source,
+            math::checked_sub(withdraw_amount_target, withdraw_amount_fees)?,
+        )
+    } else {
+        (
+            math::checked_sub(withdraw_amount_target, withdraw_amount_fees)?,
+            withdraw_amount_source,
+        )
+    };
+
+    token_pair.transfer_tokens(
+        ctx.accounts.custody_token_a.to_account_info(),
+        ctx.accounts.user_account_token_a.to_account_info(),
+        ctx.accounts.mint_token_a.to_account_info(),
+        ctx.accounts.transfer_authority.clone(),
+        ctx.accounts.token_program_a.to_account_info(),
+        withdraw_amount_a,
+    )?;
+
+    token_pair.transfer_tokens(
+        ctx.accounts.custody_token_b.to_account_info(),
+        ctx.accounts.user_account_token_b.to_account_info(),
+        ctx.accounts.mint_token_b.to_account_info(),
+        ctx.accounts.transfer_authority.clone(),
+        ctx.accounts.token_program_b.to_account_info(),
+        withdraw_amount_b,
+    )?;
+
+    // unwrap native SOL if requested
+    if params.unwrap_native {
+        for (user_account, token_program) in [
+            (
+                &ctx.accounts.user_account_token_a,
+                &ctx.accounts.token_program_a,
+            ),
+            (
+                &ctx.accounts.user_account_token_b,
+                &ctx.accounts.token_program_b,
+            ),
+        ] {
+            if user_account.is_native() {
+                msg!("Unwrap native SOL");
+                TokenPair::unwrap_native_tokens(
+                    user_account.to_account_info(),
+                    ctx.accounts.owner.to_account_info(),
+                    token_program.to_account_info(),
+                )?;
+            }
+        }
+    }
+
+    // update token pair stats
+    msg!("Update token pair stats");
+    if params.side == OrderSide::Sell {
+        token_pair.stats_b.fees_collected = token_pair
+            .stats_b
+            .fees_collected
+            .saturating_add(withdraw_amount_fees);
+    } else {
+        token_pair.stats_a.fees_collected = token_pair
+            .stats_a
+            .fees_collected
+            .saturating_add(withdraw_amount_fees);
+    };
+
+    // update pool state and close pool account if pool is empty and not current
+    msg!("Update pool state");
+    pool.update_state(token_pair.min_time_till_expiration, current_time)?;
+    if let Ok(tif_index) = TokenPair::get_tif_index(&slots, pool.time_in_force) {
+        if slots[tif_index].pool_counter != pool.counter {
+            token_pair.stats_a.pending_withdrawals = token_pair
+                .stats_a
+                .pending_withdrawals
+                .saturating_sub(withdraw_amount_a);
+            token_pair.stats_b.pending_withdrawals = token_pair
+                .stats_b
+                .pending_withdrawals
+                .saturating_sub(withdraw_amount_b);
+
+            if pool.is_empty() {
+                msg!("Close pool account");
+                if pool.counter == math::checked_add(slots[tif_index].pool_counter, 1)? {
+                    slots[tif_index].future_pool_present = false;
+                }
+                *pool = Pool::default();
+                drop(pool);
+                ctx.accounts
+                    .pool
+                    .close(ctx.accounts.transfer_authority.to_account_info())?;
+            }
+        }
+    }
+
+    Ok(())
+}
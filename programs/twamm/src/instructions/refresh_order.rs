@@ -0,0 +1,109 @@
+//! Refresh order instruction handler
+
+use {
+    crate::{
+        error::TwammError,
+        state::{order::Order, pool::Pool, token_pair::TokenPair},
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct RefreshOrder<'info> {
+    #[account(
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    /// CHECK: oracle account for token a depending on oracle type
+    #[account(
+        constraint = oracle_token_a.key() == token_pair.load()?.config_a.oracle_account
+    )]
+    pub oracle_token_a: AccountInfo<'info>,
+
+    /// CHECK: oracle account for token b depending on oracle type
+    #[account(
+        constraint = oracle_token_b.key() == token_pair.load()?.config_b.oracle_account
+    )]
+    pub oracle_token_b: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"order",
+                 order.owner.as_ref(),
+                 pool.key().as_ref(),
+                 Order::get_id_seed(order.id).as_slice()],
+        bump = order.bump
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool",
+                 token_pair.load()?.config_a.custody.as_ref(),
+                 token_pair.load()?.config_b.custody.as_ref(),
+                 pool.load()?.time_in_force.to_le_bytes().as_slice(),
+                 pool.load()?.counter.to_le_bytes().as_slice()],
+        bump = pool.load()?.bump
+    )]
+    pub pool: AccountLoader<'info, Pool>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct RefreshOrderParams {}
+
+pub fn refresh_order(ctx: Context<RefreshOrder>, _params: &RefreshOrderParams) -> Result<()> {
+    let token_pair = ctx.accounts.token_pair.load()?;
+    let current_time = token_pair.get_time()?;
+
+    let mut pool = ctx.accounts.pool.load_mut()?;
+    if pool.is_complete(current_time)? {
+        msg!("Error: Pool is complete and order can only be cancelled");
+        return err!(TwammError::InvalidPoolState);
+    }
+    let pool_expired = pool.is_expired(current_time)?;
+    let expiration_time = pool.expiration_time;
+    let schedule = pool.schedule;
+
+    // delayed order moves to the pool side if its start slot has joined it,
+    // or joins on its own if settlement hasn't moved the slot yet
+    let order = ctx.accounts.order.as_mut();
+    let pool_side = pool.get_side_mut(order.side, order.exact_output);
+    order.sync_start(pool_side)?;
+    if order.is_started(current_time) {
+        order.remove_pending_start(pool_side)?;
+    }
+
+    // check order conditions
+    msg!("Check order conditions");
+    let conditions_met = if !order.is_started(current_time) {
+        false
+    } else if order.has_price_conditions() && !order.exact_output {
+        // budget prices of exact-output orders pause their pool side instead
+        let oracle_price = token_pair.get_token_pair_oracle_price(
+            &ctx.accounts.oracle_token_a,
+            &ctx.accounts.oracle_token_b,
+        )?;
+        order.is_price_acceptable(&oracle_price)?
+    } else {
+        true
+    };
+
+    // move order balance out of the pool or back, exact-output orders use their own sides
+    if order.lp_balance > 0 && !conditions_met {
+        msg!("Move order balance out of the pool");
+        let amount = order.hold(pool_side, &schedule, expiration_time, current_time)?;
+        msg!("Amount held: {}", amount);
+    } else if order.is_held() && conditions_met && !pool_expired {
+        msg!("Move order balance back to the pool");
+        let amount = order.release(pool_side, &schedule, expiration_time, current_time)?;
+        msg!("Amount released: {}", amount);
+    } else {
+        msg!("Order is up to date");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,347 @@
+//! Roll order instruction handler
+
+use {
+    crate::{
+        error::TwammError,
+        math,
+        state::{
+            self,
+            order::{Order, OrderSide},
+            pool::{Pool, PoolStatus},
+            token_pair::TokenPair,
+        },
+    },
+    anchor_lang::{prelude::*, AccountsClose},
+    anchor_spl::{
+        associated_token::get_associated_token_address,
+        token_interface::{Mint, TokenAccount, TokenInterface},
+    },
+};
+
+#[derive(Accounts)]
+pub struct RollOrder<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: user's wallet
+    #[account()]
+    pub owner: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = user_account_token_a.mint == custody_token_a.mint
+    )]
+    pub user_account_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_account_token_b.mint == custody_token_b.mint
+    )]
+    pub user_account_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    #[account(constraint = mint_token_a.key() == token_pair.load()?.config_a.mint)]
+    pub mint_token_a: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(constraint = mint_token_b.key() == token_pair.load()?.config_b.mint)]
+    pub mint_token_b: Box<InterfaceAccount<'info, Mint>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        mut,
+        seeds = [b"transfer_authority"],
+        bump = token_pair.load()?.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = custody_token_a.key() == token_pair.load()?.config_a.custody
+    )]
+    pub custody_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = custody_token_b.key() == token_pair.load()?.config_b.custody
+    )]
+    pub custody_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"order",
+                 owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 Order::get_id_seed(order.id).as_slice()],
+        bump = order.bump
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool",
+                 custody_token_a.key().as_ref(),
+                 custody_token_b.key().as_ref(),
+                 pool.load()?.time_in_force.to_le_bytes().as_slice(),
+                 pool.load()?.counter.to_le_bytes().as_slice()],
+        bump = pool.load()?.bump
+    )]
+    pub pool: AccountLoader<'info, Pool>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = Order::LEN,
+        seeds = [b"order",
+                 owner.key().as_ref(),
+                 current_pool.key().as_ref(),
+                 Order::get_id_seed(order.id).as_slice()],
+        bump
+    )]
+    pub current_order: Box<Account<'info, Order>>,
+
+    // Currently active pool for the same time in force
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = Pool::LEN,
+        seeds = [b"pool",
+                 custody_token_a.key().as_ref(),
+                 custody_token_b.key().as_ref(),
+                 pool.load()?.time_in_force.to_le_bytes().as_slice(),
+                 TokenPair::load_pool_counter(&token_pair, pool.load()?.time_in_force)?.to_le_bytes().as_slice()],
+        bump
+    )]
+    pub current_pool: AccountLoader<'info, Pool>,
+
+    system_program: Program<'info, System>,
+    token_program_a: Interface<'info, TokenInterface>,
+    token_program_b: Interface<'info, TokenInterface>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct RollOrderParams {}
+
+pub fn roll_order(ctx: Context<RollOrder>, _params: &RollOrderParams) -> Result<()> {
+    let (mut token_pair, mut slots) = TokenPair::load_slots_mut(&ctx.accounts.token_pair)?;
+    require!(token_pair.allow_deposits, TwammError::DepositsNotAllowed);
+    require!(
+        token_pair.allow_withdrawals,
+        TwammError::WithdrawalsNotAllowed
+    );
+
+    // rolling is permissionless, so proceeds can only be paid to the recipient's ATAs
+    let recipient = ctx.accounts.order.get_recipient();
+    require_keys_eq!(
+        get_associated_token_address(&recipient, &ctx.accounts.custody_token_a.mint),
+        ctx.accounts.user_account_token_a.key()
+    );
+    require_keys_eq!(
+        get_associated_token_address(&recipient, &ctx.accounts.custody_token_b.mint),
+        ctx.accounts.user_account_token_b.key()
+    );
+
+    // only orders of completed pools that have been rotated out can be rolled
+    let current_time = token_pair.get_time()?;
+    let mut pool = ctx.accounts.pool.load_mut()?;
+    let tif_index = TokenPair::get_tif_index(&slots, pool.time_in_force)?;
+    if !pool.is_complete(current_time)? || pool.counter >= slots[tif_index].pool_counter {
+        msg!("Error: Pool is not complete or hasn't been rotated out yet");
+        return err!(TwammError::InvalidPoolState);
+    }
+
+    let order = ctx.accounts.order.as_mut();
+    if !order.auto_roll || order.exact_output {
+        msg!("Error: Order is not set to auto-roll");
+        return err!(TwammError::OrderNotRollable);
+    }
+
+    // move the order's share of the completed pool out together with its held balances
+    msg!("Compute balance changes");
+    let expiration_time = pool.expiration_time;
+    let schedule = pool.schedule;
+    let pool_side = pool.get_side_mut(order.side, false);
+    order.sync_start(pool_side)?;
+    order.remove_pending_start(pool_side)?;
+    // order that waits for its conditions stays out of the pool after the roll
+    let held = order.is_held();
+    order.hold(pool_side, &schedule, expiration_time, current_time)?;
+    let (roll_amount, proceeds) = order.take_held(pool_side)?;
+    if roll_amount == 0 {
+        msg!("Error: Order has no unfilled balance to roll");
+        return err!(TwammError::OrderNotRollable);
+    }
+    msg!("Amount rolled: {}", roll_amount);
+    pool_side.num_traders = math::checked_sub(pool_side.num_traders, 1)?;
+
+    // initialize the current pool if needed
+    if !slots[tif_index].current_pool_present {
+        msg!("Initialize current pool");
+        state::load_or_init_mut(&ctx.accounts.current_pool)?.init_current(
+            &mut slots[tif_index],
+            ctx.accounts.token_pair.key(),
+            current_time,
+            *ctx.bumps
+                .get("current_pool")
+                .ok_or(ProgramError::InvalidSeeds)?,
+        )?;
+    }
+
+    let mut current_pool = state::load_or_init_mut(&ctx.accounts.current_pool)?;
+    msg!("Update current pool state");
+    match current_pool.update_state(token_pair.min_time_till_expiration, current_time)? {
+        PoolStatus::Locked => return err!(TwammError::LockedPool),
+        PoolStatus::Expired => return err!(TwammError::ExpiredPool),
+        _ => {}
+    }
+
+    // create the order in the current pool or top up the existing one
+    let current_order = ctx.accounts.current_order.as_mut();
+    let new_order = current_order.is_empty();
+    if new_order {
+        msg!("Initialize current order");
+        current_order.init(
+            order.owner,
+            order.id,
+            order.side,
+            ctx.accounts.current_pool.key(),
+            current_time,
+            *ctx.bumps
+                .get("current_order")
+                .ok_or(ProgramError::InvalidSeeds)?,
+        );
+        current_order.delegate = order.delegate;
+        current_order.beneficiary = order.beneficiary;
+        current_order.limit_price = order.limit_price;
+        current_order.reference_price = order.reference_price;
+        current_order.max_price_deviation = order.max_price_deviation;
+        current_order.auto_roll = order.auto_roll;
+        current_order.roll_proceeds = order.roll_proceeds;
+        current_order.next_order = order.next_order;
+    } else {
+        require_eq!(
+            current_order.side,
+            order.side,
+            TwammError::OrderSideMismatch
+        );
+        require!(!current_order.exact_output, TwammError::OrderTypeMismatch);
+    }
+
+    msg!("Update current pool balances");
+    let current_expiration_time = current_pool.expiration_time;
+    let current_schedule = current_pool.schedule;
+    let current_pool_side = current_pool.get_side_mut(order.side, false);
+    if new_order {
+        current_pool_side.num_traders = math::checked_add(current_pool_side.num_traders, 1)?;
+    }
+    if current_order.is_held() || (new_order && held) {
+        // order is out of the pool, tokens join it once conditions are met
+        current_order.add_held_balance(current_pool_side, roll_amount)?;
+    } else {
+        current_order.deposit(
+            current_pool_side,
+            roll_amount,
+            &current_schedule,
+            current_expiration_time,
+            current_time,
+        )?;
+    }
+
+    // carry proceeds over to the current order or pay them out to the owner,
+    // fees on carried proceeds are charged once they are withdrawn
+    let mut withdraw_amount_fees = 0;
+    if order.roll_proceeds {
+        current_pool_side.held_proceeds_total =
+            math::checked_add(current_pool_side.held_proceeds_total, proceeds)?;
+        current_order.held_proceeds = math::checked_add(current_order.held_proceeds, proceeds)?;
+    } else if proceeds > 0 {
+        withdraw_amount_fees = math::checked_as_u64(math::checked_ceil_div(
+            math::checked_mul(proceeds as u128, token_pair.fee_numerator as u128)?,
+            token_pair.fee_denominator as u128,
+        )?)?;
+        let transfer_amount = math::checked_sub(proceeds, withdraw_amount_fees)?;
+        msg!("Amount withdrawn: {}", transfer_amount);
+
+        msg!("Transfer proceeds to the user");
+        let (user_account, custody, mint, token_program) = if order.side == OrderSide::Sell {
+            (
+                &ctx.accounts.user_account_token_b,
+                &ctx.accounts.custody_token_b,
+                &ctx.accounts.mint_token_b,
+                &ctx.accounts.token_program_b,
+            )
+        } else {
+            (
+                &ctx.accounts.user_account_token_a,
+                &ctx.accounts.custody_token_a,
+                &ctx.accounts.mint_token_a,
+                &ctx.accounts.token_program_a,
+            )
+        };
+        token_pair.transfer_tokens(
+            custody.to_account_info(),
+            user_account.to_account_info(),
+            mint.to_account_info(),
+            ctx.accounts.transfer_authority.clone(),
+            token_program.to_account_info(),
+            transfer_amount,
+        )?;
+    }
+
+    // update token pair stats, balances of the completed pool are no longer pending
+    msg!("Update token pair stats");
+    if order.side == OrderSide::Sell {
+        token_pair.stats_a.pending_withdrawals = token_pair
+            .stats_a
+            .pending_withdrawals
+            .saturating_sub(roll_amount);
+        token_pair.stats_b.pending_withdrawals = token_pair
+            .stats_b
+            .pending_withdrawals
+            .saturating_sub(proceeds);
+        token_pair.stats_b.fees_collected = token_pair
+            .stats_b
+            .fees_collected
+            .saturating_add(withdraw_amount_fees);
+    } else {
+        token_pair.stats_b.pending_withdrawals = token_pair
+            .stats_b
+            .pending_withdrawals
+            .saturating_sub(roll_amount);
+        token_pair.stats_a.pending_withdrawals = token_pair
+            .stats_a
+            .pending_withdrawals
+            .saturating_sub(proceeds);
+        token_pair.stats_a.fees_collected = token_pair
+            .stats_a
+            .fees_collected
+            .saturating_add(withdraw_amount_fees);
+    }
+
+    // close the rolled order
+    msg!("Close rolled order account");
+    // rent exempt payment is not refundable to prevent spoofing
+    order.set_inner(Order::default());
+    ctx.accounts
+        .order
+        .close(ctx.accounts.transfer_authority.to_account_info())?;
+
+    // close the completed pool account if it is empty
+    if pool.is_empty() {
+        msg!("Close pool account");
+        *pool = Pool::default();
+        drop(pool);
+        ctx.accounts
+            .pool
+            .close(ctx.accounts.transfer_authority.to_account_info())?;
+    }
+
+    Ok(())
+}
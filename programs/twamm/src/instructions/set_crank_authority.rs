@@ -23,11 +23,11 @@ pub struct SetCrankAuthority<'info> {
     #[account(
         mut,
         seeds = [b"token_pair",
-                 token_pair.config_a.mint.as_ref(),
-                 token_pair.config_b.mint.as_ref()],
-        bump = token_pair.token_pair_bump
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
     )]
-    pub token_pair: Box<Account<'info, TokenPair>>,
+    pub token_pair: AccountLoader<'info, TokenPair>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -56,7 +56,7 @@ pub fn set_crank_authority<'info>(
     }
 
     // set new crank authority
-    ctx.accounts.token_pair.crank_authority = params.crank_authority;
+    ctx.accounts.token_pair.load_mut()?.crank_authority = params.crank_authority;
 
     Ok(0)
 }
@@ -26,11 +26,11 @@ pub struct SetFees<'info> {
     #[account(
         mut,
         seeds = [b"token_pair",
-                 token_pair.config_a.mint.as_ref(),
-                 token_pair.config_b.mint.as_ref()],
-        bump = token_pair.token_pair_bump
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
     )]
-    pub token_pair: Box<Account<'info, TokenPair>>,
+    pub token_pair: AccountLoader<'info, TokenPair>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -64,7 +64,7 @@ pub fn set_fees<'info>(
     }
 
     // update permissions
-    let token_pair = ctx.accounts.token_pair.as_mut();
+    let mut token_pair = ctx.accounts.token_pair.load_mut()?;
     token_pair.fee_numerator = params.fee_numerator;
     token_pair.fee_denominator = params.fee_denominator;
     token_pair.settle_fee_numerator = params.settle_fee_numerator;
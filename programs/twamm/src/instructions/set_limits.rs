@@ -26,20 +26,21 @@ pub struct SetLimits<'info> {
     #[account(
         mut,
         seeds = [b"token_pair",
-                 token_pair.config_a.mint.as_ref(),
-                 token_pair.config_b.mint.as_ref()],
-        bump = token_pair.token_pair_bump
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
     )]
-    pub token_pair: Box<Account<'info, TokenPair>>,
+    pub token_pair: AccountLoader<'info, TokenPair>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct SetLimitsParams {
     pub min_swap_amount_token_a: u64,
     pub min_swap_amount_token_b: u64,
-    pub max_swap_price_diff: f64,
-    pub max_unsettled_amount: f64,
-    pub min_time_till_expiration: f64,
+    // limits are fixed-point ratios with math::RATIO_DECIMALS
+    pub max_swap_price_diff: u64,
+    pub max_unsettled_amount: u64,
+    pub min_time_till_expiration: u64,
 }
 
 pub fn set_limits<'info>(
@@ -63,7 +64,7 @@ pub fn set_limits<'info>(
     }
 
     // update permissions
-    let token_pair = ctx.accounts.token_pair.as_mut();
+    let mut token_pair = ctx.accounts.token_pair.load_mut()?;
     token_pair.config_a.min_swap_amount = params.min_swap_amount_token_a;
     token_pair.config_b.min_swap_amount = params.min_swap_amount_token_b;
     token_pair.max_swap_price_diff = params.max_swap_price_diff;
@@ -27,17 +27,18 @@ pub struct SetOracleConfig<'info> {
     #[account(
         mut,
         seeds = [b"token_pair",
-                 token_pair.config_a.mint.as_ref(),
-                 token_pair.config_b.mint.as_ref()],
-        bump = token_pair.token_pair_bump
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
     )]
-    pub token_pair: Box<Account<'info, TokenPair>>,
+    pub token_pair: AccountLoader<'info, TokenPair>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct SetOracleConfigParams {
-    pub max_oracle_price_error_token_a: f64,
-    pub max_oracle_price_error_token_b: f64,
+    // fixed-point ratios with math::RATIO_DECIMALS
+    pub max_oracle_price_error_token_a: u64,
+    pub max_oracle_price_error_token_b: u64,
     pub max_oracle_price_age_sec_token_a: u32,
     pub max_oracle_price_age_sec_token_b: u32,
     pub oracle_type_token_a: OracleType,
@@ -67,7 +68,7 @@ pub fn set_oracle_config<'info>(
     }
 
     // update permissions
-    let token_pair = ctx.accounts.token_pair.as_mut();
+    let mut token_pair = ctx.accounts.token_pair.load_mut()?;
     token_pair.config_a.max_oracle_price_error = params.max_oracle_price_error_token_a;
     token_pair.config_a.max_oracle_price_age_sec = params.max_oracle_price_age_sec_token_a;
     token_pair.config_a.oracle_type = params.oracle_type_token_a;
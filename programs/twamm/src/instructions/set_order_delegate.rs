@@ -0,0 +1,56 @@
+//! Set order delegate instruction handler
+
+use {
+    crate::state::{order::Order, pool::Pool, token_pair::TokenPair},
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct SetOrderDelegate<'info> {
+    #[account()]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    #[account(
+        mut,
+        seeds = [b"order",
+                 owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 Order::get_id_seed(order.id).as_slice()],
+        bump = order.bump
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        seeds = [b"pool",
+                 token_pair.load()?.config_a.custody.as_ref(),
+                 token_pair.load()?.config_b.custody.as_ref(),
+                 pool.load()?.time_in_force.to_le_bytes().as_slice(),
+                 pool.load()?.counter.to_le_bytes().as_slice()],
+        bump = pool.load()?.bump
+    )]
+    pub pool: AccountLoader<'info, Pool>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SetOrderDelegateParams {
+    // default pubkey to remove the delegate
+    delegate: Pubkey,
+}
+
+pub fn set_order_delegate(
+    ctx: Context<SetOrderDelegate>,
+    params: &SetOrderDelegateParams,
+) -> Result<()> {
+    msg!("Set order delegate: {}", params.delegate);
+    ctx.accounts.order.delegate = params.delegate;
+
+    Ok(())
+}
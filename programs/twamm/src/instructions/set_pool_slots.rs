@@ -0,0 +1,111 @@
+//! Set pool slots instruction handler
+
+use {
+    crate::{
+        error::TwammError,
+        state::{
+            self,
+            multisig::{AdminInstruction, Multisig},
+            token_pair::TokenPair,
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct SetPoolSlots<'info> {
+    // pays rent for added slots and gets it back for removed ones
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        mut,
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SetPoolSlotsParams {
+    // new slots are added with zero time in force, only unused slots can be removed
+    pub num_slots: u8,
+}
+
+pub fn set_pool_slots<'info>(
+    ctx: Context<'_, '_, '_, 'info, SetPoolSlots<'info>>,
+    params: &SetPoolSlotsParams,
+) -> Result<u8> {
+    // validate inputs
+    let num_slots = params.num_slots as usize;
+    require!(
+        num_slots > 0 && num_slots <= TokenPair::MAX_POOLS,
+        TwammError::InvalidTimeInForce
+    );
+
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.admin,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::SetPoolSlots, params)?,
+    )?;
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    // update the number of time in force slots
+    {
+        let (mut token_pair, slots) = TokenPair::load_slots_mut(&ctx.accounts.token_pair)?;
+        if num_slots == token_pair.get_num_slots() {
+            return Ok(0);
+        }
+        token_pair.set_num_slots(&slots, num_slots)?;
+    }
+
+    // resize the account and keep it rent exempt, new slots are zeroed
+    msg!("Resize token pair account");
+    let token_pair_info = ctx.accounts.token_pair.to_account_info();
+    let new_len = TokenPair::get_len(num_slots);
+    let required_lamports = Rent::get()?.minimum_balance(new_len);
+    let current_lamports = token_pair_info.try_lamports()?;
+    if required_lamports > current_lamports {
+        let cpi_accounts = anchor_lang::system_program::Transfer {
+            from: ctx.accounts.admin.to_account_info(),
+            to: token_pair_info.clone(),
+        };
+        let cpi_context =
+            CpiContext::new(ctx.accounts.system_program.to_account_info(), cpi_accounts);
+        anchor_lang::system_program::transfer(cpi_context, required_lamports - current_lamports)?;
+    } else {
+        state::transfer_sol_from_owned(
+            token_pair_info.clone(),
+            ctx.accounts.admin.to_account_info(),
+            current_lamports - required_lamports,
+        )?;
+    }
+    token_pair_info.realloc(new_len, true)?;
+
+    let (token_pair, slots) = TokenPair::load_slots(&ctx.accounts.token_pair)?;
+    if !token_pair.validate() || !token_pair.validate_slots(&slots) {
+        err!(TwammError::InvalidTokenPairConfig)
+    } else {
+        Ok(0)
+    }
+}
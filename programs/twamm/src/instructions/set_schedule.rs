@@ -0,0 +1,82 @@
+//! Set schedule instruction handler
+
+use {
+    crate::{
+        error::TwammError,
+        state::{
+            multisig::{AdminInstruction, Multisig},
+            pool::{ScheduleKind, ScheduleShape},
+            token_pair::TokenPair,
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct SetSchedule<'info> {
+    #[account()]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        mut,
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SetScheduleParams {
+    pub time_in_force_index: u8,
+    // applies to pools created after the change
+    pub schedule_kind: ScheduleKind,
+    // only used by custom schedules
+    pub custom_weights: [u16; 8], // Schedule::NUM_SEGMENTS
+}
+
+pub fn set_schedule<'info>(
+    ctx: Context<'_, '_, '_, 'info, SetSchedule<'info>>,
+    params: &SetScheduleParams,
+) -> Result<u8> {
+    // validate inputs
+    let index = params.time_in_force_index as usize;
+    require!(
+        index < ctx.accounts.token_pair.load()?.get_num_slots(),
+        TwammError::InvalidTimeInForce
+    );
+
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.admin,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::SetSchedule, params)?,
+    )?;
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    // update schedule shape
+    let (token_pair, mut slots) = TokenPair::load_slots_mut(&ctx.accounts.token_pair)?;
+    slots[index].schedule_shape = ScheduleShape::new(params.schedule_kind, params.custom_weights);
+
+    if !token_pair.validate() || !token_pair.validate_slots(&slots) {
+        err!(TwammError::InvalidTokenPairConfig)
+    } else {
+        Ok(0)
+    }
+}
@@ -26,20 +26,20 @@ pub struct SetTestOraclePrice<'info> {
     #[account(
         mut,
         seeds = [b"token_pair",
-                 token_pair.config_a.mint.as_ref(),
-                 token_pair.config_b.mint.as_ref()],
-        bump = token_pair.token_pair_bump
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
     )]
-    pub token_pair: Box<Account<'info, TokenPair>>,
+    pub token_pair: AccountLoader<'info, TokenPair>,
 
     #[account(
         init_if_needed,
         payer = admin,
         space = TestOracle::LEN,
-        constraint = oracle_token_a.key() == token_pair.config_a.oracle_account,
+        constraint = oracle_token_a.key() == token_pair.load()?.config_a.oracle_account,
         seeds = [b"token_a_oracle",
-                 token_pair.config_a.mint.as_ref(),
-                 token_pair.config_b.mint.as_ref()],
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
         bump
     )]
     pub oracle_token_a: Box<Account<'info, TestOracle>>,
@@ -48,10 +48,10 @@ pub struct SetTestOraclePrice<'info> {
         init_if_needed,
         payer = admin,
         space = TestOracle::LEN,
-        constraint = oracle_token_b.key() == token_pair.config_b.oracle_account,
+        constraint = oracle_token_b.key() == token_pair.load()?.config_b.oracle_account,
         seeds = [b"token_b_oracle",
-                 token_pair.config_a.mint.as_ref(),
-                 token_pair.config_b.mint.as_ref()],
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
         bump
     )]
     pub oracle_token_b: Box<Account<'info, TestOracle>>,
@@ -26,11 +26,11 @@ pub struct SetTestTime<'info> {
     #[account(
         mut,
         seeds = [b"token_pair",
-                 token_pair.config_a.mint.as_ref(),
-                 token_pair.config_b.mint.as_ref()],
-        bump = token_pair.token_pair_bump
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
     )]
-    pub token_pair: Box<Account<'info, TokenPair>>,
+    pub token_pair: AccountLoader<'info, TokenPair>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -64,7 +64,7 @@ pub fn set_test_time<'info>(
 
     // update time data
     if cfg!(feature = "test") {
-        ctx.accounts.token_pair.inception_time = params.time;
+        ctx.accounts.token_pair.load_mut()?.inception_time = params.time;
     }
 
     Ok(0)
@@ -26,11 +26,11 @@ pub struct SetTimeInForce<'info> {
     #[account(
         mut,
         seeds = [b"token_pair",
-                 token_pair.config_a.mint.as_ref(),
-                 token_pair.config_b.mint.as_ref()],
-        bump = token_pair.token_pair_bump
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
     )]
-    pub token_pair: Box<Account<'info, TokenPair>>,
+    pub token_pair: AccountLoader<'info, TokenPair>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -45,7 +45,10 @@ pub fn set_time_in_force<'info>(
 ) -> Result<u8> {
     // validate inputs
     let index = params.time_in_force_index as usize;
-    require!(index < TokenPair::MAX_POOLS, TwammError::InvalidTimeInForce);
+    require!(
+        index < ctx.accounts.token_pair.load()?.get_num_slots(),
+        TwammError::InvalidTimeInForce
+    );
 
     // validate signatures
     let mut multisig = ctx.accounts.multisig.load_mut()?;
@@ -64,19 +67,20 @@ pub fn set_time_in_force<'info>(
     }
 
     // update time in force
-    let token_pair = ctx.accounts.token_pair.as_mut();
-    if params.new_time_in_force == token_pair.tifs[index] {
+    let (token_pair, mut slots) = TokenPair::load_slots_mut(&ctx.accounts.token_pair)?;
+    let slot = &mut slots[index];
+    if params.new_time_in_force == slot.time_in_force {
         return Ok(0);
     }
 
-    if token_pair.current_pool_present[index] || token_pair.future_pool_present[index] {
+    if slot.current_pool_present || slot.future_pool_present {
         msg!("Error: Couldn't change time in force while non-expired pools present");
         return err!(TwammError::InvalidPoolState);
     }
 
-    token_pair.tifs[index] = params.new_time_in_force;
+    slot.time_in_force = params.new_time_in_force;
 
-    if !token_pair.validate() {
+    if !token_pair.validate() || !token_pair.validate_slots(&slots) {
         err!(TwammError::InvalidTokenPairConfig)
     } else {
         Ok(0)
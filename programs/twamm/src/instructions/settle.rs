@@ -5,12 +5,13 @@ use {
         error::TwammError,
         math,
         state::{
+            observations::Observations,
             pool::Pool,
             token_pair::{MatchingSide, SettlementType, TokenPair},
         },
     },
     anchor_lang::prelude::*,
-    anchor_spl::token::{Token, TokenAccount, Transfer},
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked},
 };
 
 #[derive(Accounts)]
@@ -25,7 +26,7 @@ pub struct Settle<'info> {
         constraint = user_account_token_a.mint == custody_token_a.mint,
         has_one = owner
     )]
-    pub user_account_token_a: Box<Account<'info, TokenAccount>>,
+    pub user_account_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
 
     // source of funds for the settlement
     #[account(
@@ -33,51 +34,68 @@ pub struct Settle<'info> {
         constraint = user_account_token_b.mint == custody_token_b.mint,
         has_one = owner
     )]
-    pub user_account_token_b: Box<Account<'info, TokenAccount>>,
+    pub user_account_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
         seeds = [b"token_pair",
-                 token_pair.config_a.mint.as_ref(),
-                 token_pair.config_b.mint.as_ref()],
-        bump = token_pair.token_pair_bump
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
     )]
-    pub token_pair: Box<Account<'info, TokenPair>>,
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    #[account(constraint = mint_token_a.key() == token_pair.load()?.config_a.mint)]
+    pub mint_token_a: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(constraint = mint_token_b.key() == token_pair.load()?.config_b.mint)]
+    pub mint_token_b: Box<InterfaceAccount<'info, Mint>>,
 
     /// CHECK: empty PDA, authority for token accounts
     #[account(
         seeds = [b"transfer_authority"],
-        bump = token_pair.transfer_authority_bump
+        bump = token_pair.load()?.transfer_authority_bump
     )]
     pub transfer_authority: AccountInfo<'info>,
 
     #[account(
         mut,
-        constraint = custody_token_a.key() == token_pair.config_a.custody
+        constraint = custody_token_a.key() == token_pair.load()?.config_a.custody
     )]
-    pub custody_token_a: Box<Account<'info, TokenAccount>>,
+    pub custody_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
-        constraint = custody_token_b.key() == token_pair.config_b.custody
+        constraint = custody_token_b.key() == token_pair.load()?.config_b.custody
     )]
-    pub custody_token_b: Box<Account<'info, TokenAccount>>,
+    pub custody_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// CHECK: oracle account for token a depending on oracle type
     #[account(
-        constraint = oracle_token_a.key() == token_pair.config_a.oracle_account
+        constraint = oracle_token_a.key() == token_pair.load()?.config_a.oracle_account
     )]
     pub oracle_token_a: AccountInfo<'info>,
 
     /// CHECK: oracle account for token b depending on oracle type
     #[account(
-        constraint = oracle_token_b.key() == token_pair.config_b.oracle_account
+        constraint = oracle_token_b.key() == token_pair.load()?.config_b.oracle_account
     )]
     pub oracle_token_b: AccountInfo<'info>,
 
-    token_program: Program<'info, Token>,
+    // fills are recorded if the pair has an observations account
+    #[account(
+        mut,
+        seeds = [b"observations",
+                 token_pair.key().as_ref()],
+        bump = observations.load()?.bump
+    )]
+    pub observations: Option<AccountLoader<'info, Observations>>,
+
+    token_program_a: Interface<'info, TokenInterface>,
+    token_program_b: Interface<'info, TokenInterface>,
     // remaining accounts:
-    //   1 to TokenPair::MAX_POOLS addresses of current pool accounts (write, unsigned)
+    //   1 to TokenPair::get_num_slots() addresses of current pool accounts (write, unsigned)
+    //   Optional virtual pool account (write, unsigned)
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -96,7 +114,7 @@ pub fn settle(ctx: Context<Settle>, params: &SettleParams) -> Result<i64> {
         TwammError::InvalidTokenAmount
     );
 
-    let token_pair = ctx.accounts.token_pair.as_mut();
+    let (mut token_pair, mut slots) = TokenPair::load_slots_mut(&ctx.accounts.token_pair)?;
     require!(
         token_pair.allow_settlements,
         TwammError::SettlementsNotAllowed
@@ -109,8 +127,12 @@ pub fn settle(ctx: Context<Settle>, params: &SettleParams) -> Result<i64> {
 
     // collect and validate pools
     msg!("Load pools");
-    let mut pools = token_pair.load_pools(ctx.remaining_accounts)?.0;
-    require!(!pools.is_empty(), TwammError::NothingToSettle);
+    let (pools, mut virtual_pool, _router_program) =
+        token_pair.load_pools(&slots, ctx.remaining_accounts)?;
+    require!(
+        !pools.is_empty() || virtual_pool.is_some(),
+        TwammError::NothingToSettle
+    );
 
     // compute token balance changes
     msg!("Compute token balance changes");
@@ -144,10 +166,22 @@ pub fn settle(ctx: Context<Settle>, params: &SettleParams) -> Result<i64> {
     let current_time = token_pair.get_time()?;
 
     // settle pools function takes raw pool refs for easier testing
-    let mut pool_refs: Vec<&mut Pool> = Vec::with_capacity(pools.len());
-    for pool in pools.iter_mut() {
+    let mut pool_data = pools
+        .iter()
+        .map(|pool| pool.load_mut())
+        .collect::<Result<Vec<_>>>()?;
+    let mut pool_refs: Vec<&mut Pool> = Vec::with_capacity(pool_data.len() + 1);
+    for pool in pool_data.iter_mut() {
         pool_refs.push(pool);
     }
+    let mut window_pool = if let Some(virtual_pool) = &virtual_pool {
+        Some(virtual_pool.get_window_pool(current_time)?)
+    } else {
+        None
+    };
+    if let Some(window_pool) = window_pool.as_mut() {
+        pool_refs.push(window_pool);
+    }
 
     let res = token_pair.settle_pools(
         &mut pool_refs,
@@ -211,39 +245,61 @@ pub fn settle(ctx: Context<Settle>, params: &SettleParams) -> Result<i64> {
     )?)?;
     let net_amount_settled_after_fees = math::checked_sub(res.net_amount_settled, settle_fee)?;
     if params.supply_side == MatchingSide::Buy {
+        // the user covers the transfer fee, so custody receives the full amount
+        let transfer_fee = TokenPair::get_inverse_transfer_fee(
+            &ctx.accounts.mint_token_b.to_account_info(),
+            res.source_amount_received,
+        )?;
         let context = CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
+            ctx.accounts.token_program_b.to_account_info(),
+            TransferChecked {
                 from: ctx.accounts.user_account_token_b.to_account_info(),
+                mint: ctx.accounts.mint_token_b.to_account_info(),
                 to: ctx.accounts.custody_token_b.to_account_info(),
                 authority: ctx.accounts.owner.to_account_info(),
             },
         );
-        anchor_spl::token::transfer(context, res.source_amount_received)?;
+        anchor_spl::token_interface::transfer_checked(
+            context,
+            math::checked_add(res.source_amount_received, transfer_fee)?,
+            ctx.accounts.mint_token_b.decimals,
+        )?;
         token_pair.transfer_tokens(
             ctx.accounts.custody_token_a.to_account_info(),
             ctx.accounts.user_account_token_a.to_account_info(),
+            ctx.accounts.mint_token_a.to_account_info(),
             ctx.accounts.transfer_authority.clone(),
-            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.token_program_a.to_account_info(),
             net_amount_settled_after_fees,
         )?;
         token_pair.stats_a.fees_collected =
             token_pair.stats_a.fees_collected.wrapping_add(settle_fee);
     } else {
+        // the user covers the transfer fee, so custody receives the full amount
+        let transfer_fee = TokenPair::get_inverse_transfer_fee(
+            &ctx.accounts.mint_token_a.to_account_info(),
+            res.source_amount_received,
+        )?;
         let context = CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
+            ctx.accounts.token_program_a.to_account_info(),
+            TransferChecked {
                 from: ctx.accounts.user_account_token_a.to_account_info(),
+                mint: ctx.accounts.mint_token_a.to_account_info(),
                 to: ctx.accounts.custody_token_a.to_account_info(),
                 authority: ctx.accounts.owner.to_account_info(),
             },
         );
-        anchor_spl::token::transfer(context, res.source_amount_received)?;
+        anchor_spl::token_interface::transfer_checked(
+            context,
+            math::checked_add(res.source_amount_received, transfer_fee)?,
+            ctx.accounts.mint_token_a.decimals,
+        )?;
         token_pair.transfer_tokens(
             ctx.accounts.custody_token_b.to_account_info(),
             ctx.accounts.user_account_token_b.to_account_info(),
+            ctx.accounts.mint_token_b.to_account_info(),
             ctx.accounts.transfer_authority.clone(),
-            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.token_program_b.to_account_info(),
             net_amount_settled_after_fees,
         )?;
         token_pair.stats_b.fees_collected =
@@ -252,18 +308,22 @@ pub fn settle(ctx: Context<Settle>, params: &SettleParams) -> Result<i64> {
 
     // update pool states
     msg!("Update pool states");
-    for pool in pools.iter_mut() {
+    for (pool, pool_loader) in pool_data.iter_mut().zip(pools.iter()) {
         pool.update_state(token_pair.min_time_till_expiration, current_time)?;
         // if pool is complete, switch to the future pool
         if pool.is_complete(current_time)? {
             token_pair.finalize_pool(
+                &mut slots,
                 pool,
-                &pool.to_account_info(),
+                &pool_loader.to_account_info(),
                 &ctx.accounts.transfer_authority,
             )?;
         }
     }
-    token_pair.save_pools(&pools)?;
+    if let (Some(virtual_pool), Some(window_pool)) = (virtual_pool.as_mut(), window_pool) {
+        virtual_pool.execute(&window_pool, current_time)?;
+        virtual_pool.exit(&crate::ID)?;
+    }
 
     // update token pair stats
     msg!("Update token pair stats");
@@ -273,6 +333,14 @@ pub fn settle(ctx: Context<Settle>, params: &SettleParams) -> Result<i64> {
         &ctx.accounts.oracle_token_a,
         &ctx.accounts.oracle_token_b,
     )?;
+    if let Some(observations) = &ctx.accounts.observations {
+        observations.load_mut()?.record_settlement(
+            &res,
+            oracle_price,
+            oracle_price,
+            current_time,
+        )?;
+    }
 
     // return net unsettled amount
     let net_amount_required = if res.net_amount_required >= i64::MAX as u64 {
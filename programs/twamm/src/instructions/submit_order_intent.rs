@@ -0,0 +1,270 @@
+//! Submit order intent instruction handler
+
+use {
+    crate::{
+        error::TwammError,
+        math,
+        state::{
+            self,
+            order::{Order, OrderSide},
+            order_intent::{IntentNonce, OrderIntent},
+            pool::{Pool, PoolStatus},
+            token_pair::TokenPair,
+        },
+    },
+    anchor_lang::{
+        prelude::*,
+        solana_program::{program_option::COption, sysvar},
+    },
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+
+#[derive(Accounts)]
+#[instruction(params: SubmitOrderIntentParams)]
+pub struct SubmitOrderIntent<'info> {
+    #[account(mut)]
+    pub relayer: Signer<'info>,
+
+    /// CHECK: intent signer, signature is verified with the ed25519 program instruction
+    pub owner: AccountInfo<'info>,
+
+    // source token account with intent_authority approved as a delegate
+    #[account(
+        mut,
+        constraint = user_account.owner == owner.key()
+    )]
+    pub user_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump,
+        constraint = token_pair.key() == params.intent.token_pair
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    #[account(constraint = mint_token_a.key() == token_pair.load()?.config_a.mint)]
+    pub mint_token_a: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(constraint = mint_token_b.key() == token_pair.load()?.config_b.mint)]
+    pub mint_token_b: Box<InterfaceAccount<'info, Mint>>,
+
+    /// CHECK: empty PDA, owner's delegate for the amounts of signed intents,
+    /// only this instruction signs with it
+    #[account(
+        seeds = [b"intent_authority",
+                 owner.key().as_ref()],
+        bump
+    )]
+    pub intent_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = custody_token_a.key() == token_pair.load()?.config_a.custody
+    )]
+    pub custody_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = custody_token_b.key() == token_pair.load()?.config_b.custody
+    )]
+    pub custody_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = relayer,
+        space = IntentNonce::LEN,
+        seeds = [b"intent_nonce",
+                 owner.key().as_ref(),
+                 params.intent.nonce.to_le_bytes().as_slice()],
+        bump
+    )]
+    pub intent_nonce: Box<Account<'info, IntentNonce>>,
+
+    #[account(
+        init_if_needed,
+        payer = relayer,
+        space = Order::LEN,
+        seeds = [b"order",
+                 owner.key().as_ref(),
+                 current_pool.key().as_ref(),
+                 Order::get_id_seed(params.intent.nonce).as_slice()],
+        bump
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    // Currently active pool for the intent's time in force
+    #[account(
+        init_if_needed,
+        payer = relayer,
+        space = Pool::LEN,
+        seeds = [b"pool",
+                 token_pair.load()?.config_a.custody.as_ref(),
+                 token_pair.load()?.config_b.custody.as_ref(),
+                 params.intent.time_in_force.to_le_bytes().as_slice(),
+                 TokenPair::load_pool_counter(&token_pair, params.intent.time_in_force)?.to_le_bytes().as_slice()],
+        bump
+    )]
+    pub current_pool: AccountLoader<'info, Pool>,
+
+    /// CHECK: instructions sysvar, used to load the signature verification instruction
+    #[account(address = sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+
+    system_program: Program<'info, System>,
+    token_program_a: Interface<'info, TokenInterface>,
+    token_program_b: Interface<'info, TokenInterface>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SubmitOrderIntentParams {
+    // must match the message signed by the owner
+    intent: OrderIntent,
+}
+
+pub fn submit_order_intent(
+    ctx: Context<SubmitOrderIntent>,
+    params: &SubmitOrderIntentParams,
+) -> Result<()> {
+    let intent = &params.intent;
+
+    // validate inputs
+    require_gt!(intent.amount, 0u64, TwammError::InvalidTokenAmount);
+    Order::validate_user_id(intent.nonce)?;
+    intent.verify_signature(ctx.accounts.owner.key, &ctx.accounts.instructions_sysvar)?;
+
+    let (token_pair, mut slots) = TokenPair::load_slots_mut(&ctx.accounts.token_pair)?;
+    require!(token_pair.allow_deposits, TwammError::DepositsNotAllowed);
+
+    let tif_index = TokenPair::get_tif_index(&slots, intent.time_in_force)?;
+    let current_time = token_pair.get_time()?;
+    if current_time > intent.expiry {
+        msg!("Error: Order intent has expired");
+        return err!(TwammError::IntentExpired);
+    }
+
+    // record the nonce so the intent can't be replayed
+    let intent_nonce = ctx.accounts.intent_nonce.as_mut();
+    intent_nonce.owner = ctx.accounts.owner.key();
+    intent_nonce.payer = ctx.accounts.relayer.key();
+    intent_nonce.nonce = intent.nonce;
+    intent_nonce.expiry = intent.expiry;
+    intent_nonce.time = current_time;
+    intent_nonce.bump = *ctx
+        .bumps
+        .get("intent_nonce")
+        .ok_or(ProgramError::InvalidSeeds)?;
+
+    // credit the amount custody receives after the token transfer fee, if any
+    let (custody, mint, token_program) = if intent.side == OrderSide::Sell {
+        (
+            &ctx.accounts.custody_token_a,
+            &ctx.accounts.mint_token_a,
+            &ctx.accounts.token_program_a,
+        )
+    } else {
+        (
+            &ctx.accounts.custody_token_b,
+            &ctx.accounts.mint_token_b,
+            &ctx.accounts.token_program_b,
+        )
+    };
+    require_keys_eq!(
+        ctx.accounts.user_account.mint,
+        custody.mint,
+        TwammError::InvalidTokenAccount
+    );
+    let amount = math::checked_sub(
+        intent.amount,
+        TokenPair::get_transfer_fee(&mint.to_account_info(), intent.amount)?,
+    )?;
+    require_gt!(amount, 0u64, TwammError::InvalidTokenAmount);
+
+    // initialize the current pool if needed
+    if !slots[tif_index].current_pool_present {
+        msg!("Initialize current pool");
+        state::load_or_init_mut(&ctx.accounts.current_pool)?.init_current(
+            &mut slots[tif_index],
+            ctx.accounts.token_pair.key(),
+            current_time,
+            *ctx.bumps
+                .get("current_pool")
+                .ok_or(ProgramError::InvalidSeeds)?,
+        )?;
+    }
+
+    let mut pool = state::load_or_init_mut(&ctx.accounts.current_pool)?;
+    msg!("Update pool state");
+    match pool.update_state(token_pair.min_time_till_expiration, current_time)? {
+        PoolStatus::Locked => return err!(TwammError::LockedPool),
+        PoolStatus::Expired => return err!(TwammError::ExpiredPool),
+        _ => {}
+    }
+
+    // create the order or top up the existing one with the same id
+    let order = ctx.accounts.order.as_mut();
+    let new_order = order.is_empty();
+    if new_order {
+        msg!("Initialize order");
+        order.init(
+            ctx.accounts.owner.key(),
+            intent.nonce,
+            intent.side,
+            ctx.accounts.current_pool.key(),
+            current_time,
+            *ctx.bumps.get("order").ok_or(ProgramError::InvalidSeeds)?,
+        );
+    } else {
+        require_eq!(order.side, intent.side, TwammError::OrderSideMismatch);
+        require!(!order.exact_output, TwammError::OrderTypeMismatch);
+    }
+
+    // update pool balances
+    msg!("Update pool balances");
+    let expiration_time = pool.expiration_time;
+    let schedule = pool.schedule;
+    let pool_side = pool.get_side_mut(intent.side, false);
+    if new_order {
+        pool_side.num_traders = math::checked_add(pool_side.num_traders, 1)?;
+    }
+    if order.add_pending_balance(pool_side, amount)? {
+        msg!("Add tokens to the delayed order");
+    } else if order.is_held() || !order.is_started(current_time) {
+        // existing order is out of the pool, tokens join it once conditions are met
+        order.add_held_balance(pool_side, amount)?;
+    } else {
+        msg!("Update user order");
+        order.deposit(pool_side, amount, &schedule, expiration_time, current_time)?;
+    }
+
+    // transfer the signed amount with the delegated authority, the rest of
+    // the allowance stays with the owner
+    msg!("Transfer tokens to custodies");
+    require!(
+        ctx.accounts.user_account.delegate == COption::Some(ctx.accounts.intent_authority.key())
+            && ctx.accounts.user_account.delegated_amount >= intent.amount,
+        TwammError::InvalidTokenAccount
+    );
+    let owner_key = ctx.accounts.owner.key();
+    let authority_bump = *ctx
+        .bumps
+        .get("intent_authority")
+        .ok_or(ProgramError::InvalidSeeds)?;
+    let authority_seeds: &[&[&[u8]]] =
+        &[&[b"intent_authority", owner_key.as_ref(), &[authority_bump]]];
+    let context = CpiContext::new(
+        token_program.to_account_info(),
+        TransferChecked {
+            from: ctx.accounts.user_account.to_account_info(),
+            mint: mint.to_account_info(),
+            to: custody.to_account_info(),
+            authority: ctx.accounts.intent_authority.to_account_info(),
+        },
+    )
+    .with_signer(authority_seeds);
+    anchor_spl::token_interface::transfer_checked(context, intent.amount, mint.decimals)?;
+
+    Ok(())
+}
@@ -0,0 +1,196 @@
+//! Tokenize order instruction handler
+
+use {
+    crate::{
+        error::TwammError,
+        math,
+        state::{
+            order::{Order, OrderSide},
+            pool::Pool,
+            token_pair::TokenPair,
+        },
+    },
+    anchor_lang::{prelude::*, AccountsClose},
+    anchor_spl::{
+        associated_token::AssociatedToken,
+        token::{Mint, Token, TokenAccount},
+        token_interface,
+    },
+};
+
+#[derive(Accounts)]
+pub struct TokenizeOrder<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        mut,
+        seeds = [b"transfer_authority"],
+        bump = token_pair.load()?.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"order",
+                 owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 Order::get_id_seed(order.id).as_slice()],
+        bump = order.bump
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool",
+                 token_pair.load()?.config_a.custody.as_ref(),
+                 token_pair.load()?.config_b.custody.as_ref(),
+                 pool.load()?.time_in_force.to_le_bytes().as_slice(),
+                 pool.load()?.counter.to_le_bytes().as_slice()],
+        bump = pool.load()?.bump
+    )]
+    pub pool: AccountLoader<'info, Pool>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        mint::authority = transfer_authority,
+        mint::decimals = if order.side == OrderSide::Sell {
+            token_pair.load()?.config_a.decimals
+        } else {
+            token_pair.load()?.config_b.decimals
+        },
+        seeds = [b"position_mint",
+                 pool.key().as_ref(),
+                 [order.side as u8].as_slice()],
+        bump
+    )]
+    pub position_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = position_mint,
+        associated_token::authority = owner
+    )]
+    pub user_position_account: Box<Account<'info, TokenAccount>>,
+
+    // user's account, custody and mint of the order's target token, the order pays
+    // in debt that position tokens don't carry
+    #[account(mut, has_one = owner)]
+    pub user_account: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(mut)]
+    pub custody: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    pub mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    target_token_program: Interface<'info, token_interface::TokenInterface>,
+    associated_token_program: Program<'info, AssociatedToken>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct TokenizeOrderParams {
+    lp_amount: u64,
+}
+
+pub fn tokenize_order(ctx: Context<TokenizeOrder>, params: &TokenizeOrderParams) -> Result<()> {
+    // validate inputs
+    require_gt!(params.lp_amount, 0u64, TwammError::InvalidTokenAmount);
+
+    let token_pair = ctx.accounts.token_pair.load()?;
+    let current_time = token_pair.get_time()?;
+    let mut pool = ctx.accounts.pool.load_mut()?;
+    let expiration_time = pool.expiration_time;
+    let schedule = pool.schedule;
+    let order = ctx.accounts.order.as_mut();
+    let (mint, custody) = if order.side == OrderSide::Sell {
+        (token_pair.config_b.mint, token_pair.config_b.custody)
+    } else {
+        (token_pair.config_a.mint, token_pair.config_a.custody)
+    };
+    require_keys_eq!(
+        ctx.accounts.mint.key(),
+        mint,
+        TwammError::InvalidTokenAccount
+    );
+    require_keys_eq!(
+        ctx.accounts.custody.key(),
+        custody,
+        TwammError::InvalidTokenAccount
+    );
+    require_keys_eq!(
+        ctx.accounts.user_account.mint,
+        mint,
+        TwammError::InvalidTokenAccount
+    );
+
+    // move LP shares from the order to position tokens, every position token carries
+    // the same token debt, the order settles the difference in target tokens
+    msg!("Update order data");
+    let pool_side = pool.get_side_mut(order.side, order.exact_output);
+    order.sync_start(pool_side)?;
+    let lp_amount = std::cmp::min(params.lp_amount, order.lp_balance);
+    let amount_owed = order.tokenize(
+        pool_side,
+        lp_amount,
+        &schedule,
+        expiration_time,
+        current_time,
+    )?;
+
+    // mint position tokens to the user
+    msg!("Mint position tokens to the user");
+    token_pair.mint_tokens(
+        ctx.accounts.position_mint.to_account_info(),
+        ctx.accounts.user_position_account.to_account_info(),
+        ctx.accounts.transfer_authority.clone(),
+        ctx.accounts.token_program.to_account_info(),
+        lp_amount,
+    )?;
+
+    // pay in the debt, custody must receive the full amount after the token transfer fee
+    if amount_owed > 0 {
+        msg!("Transfer owed tokens to the custody: {}", amount_owed);
+        let mint = ctx.accounts.mint.to_account_info();
+        let transfer_amount = math::checked_add(
+            amount_owed,
+            TokenPair::get_inverse_transfer_fee(&mint, amount_owed)?,
+        )?;
+        token_pair.deposit_tokens(
+            ctx.accounts.user_account.to_account_info(),
+            ctx.accounts.custody.to_account_info(),
+            mint,
+            ctx.accounts.owner.to_account_info(),
+            ctx.accounts.target_token_program.to_account_info(),
+            transfer_amount,
+        )?;
+    }
+
+    // close order account if no longer needed, position tokens count as a trader
+    // of the pool side on their own
+    if order.is_empty() {
+        msg!("Close order account");
+        let pool_side = pool.get_side_mut(order.side, order.exact_output);
+        pool_side.num_traders = math::checked_sub(pool_side.num_traders, 1)?;
+        // rent exempt payment is not refundable to prevent spoofing
+        order.set_inner(Order::default());
+        ctx.accounts
+            .order
+            .close(ctx.accounts.transfer_authority.to_account_info())?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,105 @@
+//! Transfer order instruction handler
+
+use {
+    crate::state::{order::Order, pool::Pool, token_pair::TokenPair},
+    anchor_lang::{prelude::*, AccountsClose},
+};
+
+#[derive(Accounts)]
+#[instruction(params: TransferOrderParams)]
+pub struct TransferOrder<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// CHECK: new owner's wallet
+    #[account()]
+    pub new_owner: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        mut,
+        seeds = [b"transfer_authority"],
+        bump = token_pair.load()?.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"order",
+                 owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 Order::get_id_seed(order.id).as_slice()],
+        bump = order.bump
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = Order::LEN,
+        seeds = [b"order",
+                 new_owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 Order::get_id_seed(params.order_id).as_slice()],
+        bump
+    )]
+    pub new_order: Box<Account<'info, Order>>,
+
+    #[account(
+        seeds = [b"pool",
+                 token_pair.load()?.config_a.custody.as_ref(),
+                 token_pair.load()?.config_b.custody.as_ref(),
+                 pool.load()?.time_in_force.to_le_bytes().as_slice(),
+                 pool.load()?.counter.to_le_bytes().as_slice()],
+        bump = pool.load()?.bump
+    )]
+    pub pool: AccountLoader<'info, Pool>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct TransferOrderParams {
+    // order id under the new owner, zero for the default order in the pool
+    order_id: u64,
+}
+
+pub fn transfer_order(ctx: Context<TransferOrder>, params: &TransferOrderParams) -> Result<()> {
+    Order::validate_user_id(params.order_id)?;
+
+    // move order data to the new owner's address
+    msg!("Initialize new order");
+    let order = ctx.accounts.order.as_mut();
+    let new_order = ctx.accounts.new_order.as_mut();
+    new_order.set_inner(Order {
+        owner: ctx.accounts.new_owner.key(),
+        // delegate, beneficiary and order link belong to the previous owner
+        delegate: Pubkey::default(),
+        beneficiary: Pubkey::default(),
+        next_order: Pubkey::default(),
+        id: params.order_id,
+        bump: *ctx
+            .bumps
+            .get("new_order")
+            .ok_or(ProgramError::InvalidSeeds)?,
+        ..Order::clone(order)
+    });
+
+    // close the previous order account
+    msg!("Close order account");
+    // rent exempt payment is not refundable to prevent spoofing
+    order.set_inner(Order::default());
+    ctx.accounts
+        .order
+        .close(ctx.accounts.transfer_authority.to_account_info())?;
+
+    Ok(())
+}
@@ -0,0 +1,189 @@
+//! Trigger order instruction handler
+
+use {
+    crate::{
+        error::TwammError,
+        math,
+        state::{
+            self,
+            order::Order,
+            pool::{Pool, PoolStatus},
+            stop_order::StopOrder,
+            token_pair::TokenPair,
+        },
+    },
+    anchor_lang::{prelude::*, AccountsClose},
+};
+
+#[derive(Accounts)]
+pub struct TriggerOrder<'info> {
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+
+    /// CHECK: stop order owner's wallet
+    #[account(constraint = owner.key() == stop_order.owner)]
+    pub owner: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    /// CHECK: oracle account for token a depending on oracle type
+    #[account(
+        constraint = oracle_token_a.key() == token_pair.load()?.config_a.oracle_account
+    )]
+    pub oracle_token_a: AccountInfo<'info>,
+
+    /// CHECK: oracle account for token b depending on oracle type
+    #[account(
+        constraint = oracle_token_b.key() == token_pair.load()?.config_b.oracle_account
+    )]
+    pub oracle_token_b: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"stop_order",
+                 owner.key().as_ref(),
+                 token_pair.key().as_ref(),
+                 stop_order.id.to_le_bytes().as_slice()],
+        bump = stop_order.bump
+    )]
+    pub stop_order: Box<Account<'info, StopOrder>>,
+
+    #[account(
+        init_if_needed,
+        payer = keeper,
+        space = Order::LEN,
+        seeds = [b"order",
+                 owner.key().as_ref(),
+                 current_pool.key().as_ref(),
+                 Order::get_id_seed(stop_order.id).as_slice()],
+        bump
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    // Currently active pool for the stop order's time in force
+    #[account(
+        init_if_needed,
+        payer = keeper,
+        space = Pool::LEN,
+        seeds = [b"pool",
+                 token_pair.load()?.config_a.custody.as_ref(),
+                 token_pair.load()?.config_b.custody.as_ref(),
+                 stop_order.time_in_force.to_le_bytes().as_slice(),
+                 TokenPair::load_pool_counter(&token_pair, stop_order.time_in_force)?.to_le_bytes().as_slice()],
+        bump
+    )]
+    pub current_pool: AccountLoader<'info, Pool>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct TriggerOrderParams {}
+
+pub fn trigger_order(ctx: Context<TriggerOrder>, _params: &TriggerOrderParams) -> Result<()> {
+    let (token_pair, mut slots) = TokenPair::load_slots_mut(&ctx.accounts.token_pair)?;
+    require!(token_pair.allow_deposits, TwammError::DepositsNotAllowed);
+
+    // check the trigger price against the oracle
+    msg!("Check trigger price");
+    let stop_order = ctx.accounts.stop_order.as_mut();
+    let oracle_price = token_pair
+        .get_token_pair_oracle_price(&ctx.accounts.oracle_token_a, &ctx.accounts.oracle_token_b)?;
+    if !stop_order.is_trigger_reached(&oracle_price)? {
+        msg!(
+            "Error: Pair price {}e{} has not crossed the trigger price {}e{}",
+            oracle_price.price,
+            oracle_price.exponent,
+            stop_order.trigger_price.price,
+            stop_order.trigger_price.exponent
+        );
+        return err!(TwammError::TriggerPriceNotReached);
+    }
+
+    // escrowed tokens join the pool that is current at the time of the trigger
+    let tif_index = TokenPair::get_tif_index(&slots, stop_order.time_in_force)?;
+    let current_time = token_pair.get_time()?;
+    if !slots[tif_index].current_pool_present {
+        msg!("Initialize current pool");
+        state::load_or_init_mut(&ctx.accounts.current_pool)?.init_current(
+            &mut slots[tif_index],
+            ctx.accounts.token_pair.key(),
+            current_time,
+            *ctx.bumps
+                .get("current_pool")
+                .ok_or(ProgramError::InvalidSeeds)?,
+        )?;
+    }
+
+    let mut pool = state::load_or_init_mut(&ctx.accounts.current_pool)?;
+    msg!("Update pool state");
+    match pool.update_state(token_pair.min_time_till_expiration, current_time)? {
+        PoolStatus::Locked => return err!(TwammError::LockedPool),
+        PoolStatus::Expired => return err!(TwammError::ExpiredPool),
+        _ => {}
+    }
+
+    // create the order or top up the existing one with the same id
+    let order = ctx.accounts.order.as_mut();
+    let new_order = order.is_empty();
+    let mut price_breached = false;
+    if new_order {
+        msg!("Initialize order");
+        order.init(
+            stop_order.owner,
+            stop_order.id,
+            stop_order.side,
+            ctx.accounts.current_pool.key(),
+            current_time,
+            *ctx.bumps.get("order").ok_or(ProgramError::InvalidSeeds)?,
+        );
+        order.limit_price = stop_order.limit_price;
+        order.max_price_deviation = stop_order.max_price_deviation;
+        if stop_order.max_price_deviation > 0 {
+            order.reference_price = oracle_price;
+        }
+        // order starts out of the pool if its limit is already breached
+        price_breached = !order.is_price_acceptable(&oracle_price)?;
+    } else {
+        require_eq!(order.side, stop_order.side, TwammError::OrderSideMismatch);
+        require!(!order.exact_output, TwammError::OrderTypeMismatch);
+    }
+
+    // update pool balances
+    msg!("Update pool balances");
+    let amount = stop_order.amount;
+    let expiration_time = pool.expiration_time;
+    let schedule = pool.schedule;
+    let pool_side = pool.get_side_mut(stop_order.side, false);
+    if new_order {
+        pool_side.num_traders = math::checked_add(pool_side.num_traders, 1)?;
+    }
+    order.sync_start(pool_side)?;
+    if order.pending_start && pool_side.add_pending_start(order.start_time, amount, false)? {
+        // settlement moves tokens to the pool at the order's start time
+        order.add_held_balance(pool_side, amount)?;
+    } else if order.is_held() || !order.is_started(current_time) || price_breached {
+        // order is out of the pool, tokens join it once conditions are met
+        order.add_held_balance(pool_side, amount)?;
+    } else {
+        msg!("Update user order");
+        order.deposit(pool_side, amount, &schedule, expiration_time, current_time)?;
+    }
+    msg!("Amount placed: {}", amount);
+
+    // stop order rent includes the prefunded rent of the order the keeper created
+    msg!("Close stop order account");
+    stop_order.set_inner(StopOrder::default());
+    ctx.accounts
+        .stop_order
+        .close(ctx.accounts.keeper.to_account_info())?;
+
+    Ok(())
+}
@@ -0,0 +1,93 @@
+//! Update order instruction handler
+
+use {
+    crate::{
+        error::TwammError,
+        oracle::{OraclePrice, OracleType},
+        state::{order::Order, pool::Pool, token_pair::TokenPair},
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct UpdateOrder<'info> {
+    // order owner or delegate
+    #[account()]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    #[account(
+        mut,
+        constraint = order.is_authorized(&authority.key()) @ TwammError::OrderNotAuthorized,
+        seeds = [b"order",
+                 order.owner.as_ref(),
+                 pool.key().as_ref(),
+                 Order::get_id_seed(order.id).as_slice()],
+        bump = order.bump
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool",
+                 token_pair.load()?.config_a.custody.as_ref(),
+                 token_pair.load()?.config_b.custody.as_ref(),
+                 pool.load()?.time_in_force.to_le_bytes().as_slice(),
+                 pool.load()?.counter.to_le_bytes().as_slice()],
+        bump = pool.load()?.bump
+    )]
+    pub pool: AccountLoader<'info, Pool>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct UpdateOrderParams {
+    limit_price: OraclePrice,
+    auto_roll: bool,
+    roll_proceeds: bool,
+}
+
+pub fn update_order(ctx: Context<UpdateOrder>, params: &UpdateOrderParams) -> Result<()> {
+    let token_pair = ctx.accounts.token_pair.load()?;
+    let order = ctx.accounts.order.as_mut();
+
+    // validate inputs
+    if order.exact_output && (params.limit_price.price > 0 || params.auto_roll) {
+        msg!("Error: Exact-output orders can't have limit or auto-roll");
+        return err!(TwammError::OrderTypeMismatch);
+    }
+    if !params.limit_price.is_valid_order_price() {
+        msg!("Error: Limit price exponent is out of range");
+        return err!(TwammError::InvalidTokenPairPrice);
+    }
+    if params.limit_price.price > 0
+        && (matches!(token_pair.config_a.oracle_type, OracleType::None)
+            || matches!(token_pair.config_b.oracle_type, OracleType::None))
+    {
+        msg!("Error: Limit orders require price oracles for both tokens");
+        return err!(TwammError::UnsupportedOracle);
+    }
+
+    // tokens are moved in or out of the pool by refresh_order if conditions change,
+    // a looser limit keeps the pool side bounds until they are reset,
+    // exact-output orders keep the budget price set on deposit
+    msg!("Update order");
+    let mut pool = ctx.accounts.pool.load_mut()?;
+    let pool_side = pool.get_side_mut(order.side, order.exact_output);
+    order.sync_start(pool_side)?;
+    if !order.exact_output {
+        order.remove_price_bounds(pool_side)?;
+        order.limit_price = params.limit_price;
+        order.add_price_bounds(pool_side)?;
+    }
+    order.auto_roll = params.auto_roll;
+    order.roll_proceeds = params.auto_roll && params.roll_proceeds;
+
+    Ok(())
+}
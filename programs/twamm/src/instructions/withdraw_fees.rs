@@ -10,7 +10,7 @@ use {
         },
     },
     anchor_lang::prelude::*,
-    anchor_spl::token::{Token, TokenAccount},
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface},
     solana_program::sysvar,
 };
 
@@ -29,37 +29,43 @@ pub struct WithdrawFees<'info> {
     #[account(
         mut,
         seeds = [b"token_pair",
-                 token_pair.config_a.mint.as_ref(),
-                 token_pair.config_b.mint.as_ref()],
-        bump = token_pair.token_pair_bump
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
     )]
-    pub token_pair: Box<Account<'info, TokenPair>>,
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    #[account(constraint = mint_token_a.key() == token_pair.load()?.config_a.mint)]
+    pub mint_token_a: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(constraint = mint_token_b.key() == token_pair.load()?.config_b.mint)]
+    pub mint_token_b: Box<InterfaceAccount<'info, Mint>>,
 
     /// CHECK: empty PDA, authority for token accounts
     #[account(
         mut,
         seeds = [b"transfer_authority"],
-        bump = token_pair.transfer_authority_bump
+        bump = token_pair.load()?.transfer_authority_bump
     )]
     pub transfer_authority: AccountInfo<'info>,
 
     #[account(
         mut,
-        constraint = custody_token_a.key() == token_pair.config_a.custody
+        constraint = custody_token_a.key() == token_pair.load()?.config_a.custody
     )]
-    pub custody_token_a: Box<Account<'info, TokenAccount>>,
+    pub custody_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
-        constraint = custody_token_b.key() == token_pair.config_b.custody
+        constraint = custody_token_b.key() == token_pair.load()?.config_b.custody
     )]
-    pub custody_token_b: Box<Account<'info, TokenAccount>>,
+    pub custody_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(mut)]
-    pub receiver_token_a: Box<Account<'info, TokenAccount>>,
+    pub receiver_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(mut)]
-    pub receiver_token_b: Box<Account<'info, TokenAccount>>,
+    pub receiver_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// CHECK: SOL fees receiver
     #[account(
@@ -68,7 +74,8 @@ pub struct WithdrawFees<'info> {
     )]
     pub receiver_sol: AccountInfo<'info>,
 
-    token_program: Program<'info, Token>,
+    token_program_a: Interface<'info, TokenInterface>,
+    token_program_b: Interface<'info, TokenInterface>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -99,46 +106,50 @@ pub fn withdraw_fees<'info>(
     }
 
     // transfer token fees from the custody to the receiver
-    let token_pair = ctx.accounts.token_pair.as_mut();
+    let mut token_pair = ctx.accounts.token_pair.load_mut()?;
 
     if params.amount_token_a > 0 {
+        let fees_collected = token_pair.stats_a.fees_collected;
         msg!(
             "Withdraw token A fees: {} / {}",
             params.amount_token_a,
-            token_pair.stats_a.fees_collected
+            fees_collected
         );
-        if token_pair.stats_a.fees_collected < params.amount_token_a {
+        if fees_collected < params.amount_token_a {
             return Err(ProgramError::InsufficientFunds.into());
         }
         token_pair.stats_a.fees_collected =
-            math::checked_sub(token_pair.stats_a.fees_collected, params.amount_token_a)?;
+            math::checked_sub(fees_collected, params.amount_token_a)?;
 
         token_pair.transfer_tokens(
             ctx.accounts.custody_token_a.to_account_info(),
             ctx.accounts.receiver_token_a.to_account_info(),
+            ctx.accounts.mint_token_a.to_account_info(),
             ctx.accounts.transfer_authority.clone(),
-            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.token_program_a.to_account_info(),
             params.amount_token_a,
         )?;
     }
 
     if params.amount_token_b > 0 {
+        let fees_collected = token_pair.stats_b.fees_collected;
         msg!(
             "Withdraw token B fees: {} / {}",
             params.amount_token_b,
-            token_pair.stats_b.fees_collected
+            fees_collected
         );
-        if token_pair.stats_b.fees_collected < params.amount_token_b {
+        if fees_collected < params.amount_token_b {
             return Err(ProgramError::InsufficientFunds.into());
         }
         token_pair.stats_b.fees_collected =
-            math::checked_sub(token_pair.stats_b.fees_collected, params.amount_token_b)?;
+            math::checked_sub(fees_collected, params.amount_token_b)?;
 
         token_pair.transfer_tokens(
             ctx.accounts.custody_token_b.to_account_info(),
             ctx.accounts.receiver_token_b.to_account_info(),
+            ctx.accounts.mint_token_b.to_account_info(),
             ctx.accounts.transfer_authority.clone(),
-            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.token_program_b.to_account_info(),
             params.amount_token_b,
         )?;
     }
@@ -0,0 +1,218 @@
+//! Withdraw proceeds instruction handler
+
+use {
+    crate::{
+        error::TwammError,
+        math,
+        state::{
+            order::{Order, OrderSide},
+            pool::Pool,
+            token_pair::TokenPair,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface},
+};
+
+#[derive(Accounts)]
+pub struct WithdrawProceeds<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    // user token accounts can be omitted for the native mint to receive lamports
+    #[account(
+        mut,
+        constraint = user_account_token_a.mint == custody_token_a.mint
+    )]
+    pub user_account_token_a: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        constraint = user_account_token_b.mint == custody_token_b.mint
+    )]
+    pub user_account_token_b: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        seeds = [b"token_pair",
+                 token_pair.load()?.config_a.mint.as_ref(),
+                 token_pair.load()?.config_b.mint.as_ref()],
+        bump = token_pair.load()?.token_pair_bump
+    )]
+    pub token_pair: AccountLoader<'info, TokenPair>,
+
+    #[account(constraint = mint_token_a.key() == token_pair.load()?.config_a.mint)]
+    pub mint_token_a: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(constraint = mint_token_b.key() == token_pair.load()?.config_b.mint)]
+    pub mint_token_b: Box<InterfaceAccount<'info, Mint>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        mut,
+        seeds = [b"transfer_authority"],
+        bump = token_pair.load()?.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = custody_token_a.key() == token_pair.load()?.config_a.custody
+    )]
+    pub custody_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = custody_token_b.key() == token_pair.load()?.config_b.custody
+    )]
+    pub custody_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"order",
+                 owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 Order::get_id_seed(order.id).as_slice()],
+        bump = order.bump
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool",
+                 custody_token_a.key().as_ref(),
+                 custody_token_b.key().as_ref(),
+                 pool.load()?.time_in_force.to_le_bytes().as_slice(),
+                 pool.load()?.counter.to_le_bytes().as_slice()],
+        bump = pool.load()?.bump
+    )]
+    pub pool: AccountLoader<'info, Pool>,
+
+    /// CHECK: temporary token account to pay out native SOL, seeds = [b"native_payout", owner],
+    /// only required if the native mint token account is omitted
+    #[account(mut)]
+    pub native_payout_account: Option<AccountInfo<'info>>,
+
+    system_program: Program<'info, System>,
+    token_program_a: Interface<'info, TokenInterface>,
+    token_program_b: Interface<'info, TokenInterface>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct WithdrawProceedsParams {
+    // close the user's native mint token account to receive SOL
+    unwrap_native: bool,
+}
+
+pub fn withdraw_proceeds(
+    ctx: Context<WithdrawProceeds>,
+    params: &WithdrawProceedsParams,
+) -> Result<()> {
+    let (mut token_pair, slots) = TokenPair::load_slots_mut(&ctx.accounts.token_pair)?;
+    require!(
+        token_pair.allow_withdrawals,
+        TwammError::WithdrawalsNotAllowed
+    );
+
+    let order = ctx.accounts.order.as_mut();
+    let mut pool = ctx.accounts.pool.load_mut()?;
+    let pool_side = pool.get_side_mut(order.side, order.exact_output);
+    order.sync_start(pool_side)?;
+
+    // take the order's share of filled tokens
+    msg!("Update order and pool data");
+    let (withdraw_amount, held_proceeds) = order.withdraw_proceeds(pool_side)?;
+    let total_amount = math::checked_add(withdraw_amount, held_proceeds)?;
+    require_gt!(total_amount, 0u64, TwammError::InvalidTokenAmount);
+
+    let withdraw_amount_fees = math::checked_as_u64(math::checked_ceil_div(
+        math::checked_mul(total_amount as u128, token_pair.fee_numerator as u128)?,
+        token_pair.fee_denominator as u128,
+    )?)?;
+
+    // transfer tokens to the user
+    msg!("Transfer tokens to the user");
+    let transfer_amount = math::checked_sub(total_amount, withdraw_amount_fees)?;
+    msg!("Amount withdrawn: {}", transfer_amount);
+    let (user_account, custody, mint, token_program) = if order.side == OrderSide::Sell {
+        (
+            &ctx.accounts.user_account_token_b,
+            &ctx.accounts.custody_token_b,
+            &ctx.accounts.mint_token_b,
+            &ctx.accounts.token_program_b,
+        )
+    } else {
+        (
+            &ctx.accounts.user_account_token_a,
+            &ctx.accounts.custody_token_a,
+            &ctx.accounts.mint_token_a,
+            &ctx.accounts.token_program_a,
+        )
+    };
+    match (user_account, &ctx.accounts.native_payout_account) {
+        (Some(user_account), _) => {
+            token_pair.transfer_tokens(
+                custody.to_account_info(),
+                user_account.to_account_info(),
+                mint.to_account_info(),
+                ctx.accounts.transfer_authority.clone(),
+                token_program.to_account_info(),
+                transfer_amount,
+            )?;
+
+            // unwrap native SOL if requested
+            if params.unwrap_native && user_account.is_native() {
+                msg!("Unwrap native SOL");
+                TokenPair::unwrap_native_tokens(
+                    user_account.to_account_info(),
+                    ctx.accounts.owner.to_account_info(),
+                    token_program.to_account_info(),
+                )?;
+            }
+        }
+        (None, Some(payout_account)) => token_pair.withdraw_native_tokens(
+            custody.to_account_info(),
+            payout_account.clone(),
+            mint.to_account_info(),
+            ctx.accounts.transfer_authority.clone(),
+            ctx.accounts.owner.to_account_info(),
+            ctx.accounts.owner.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+            token_program.to_account_info(),
+            transfer_amount,
+        )?,
+        _ => {
+            msg!("Error: Native SOL payout requires the payout account");
+            return err!(TwammError::InvalidTokenAccount);
+        }
+    }
+
+    // update token pair stats
+    msg!("Update token pair stats");
+    let is_current_pool = TokenPair::is_current_pool(&slots, &pool);
+    if order.side == OrderSide::Sell {
+        token_pair.stats_b.fees_collected = token_pair
+            .stats_b
+            .fees_collected
+            .saturating_add(withdraw_amount_fees);
+        if !is_current_pool {
+            token_pair.stats_b.pending_withdrawals = token_pair
+                .stats_b
+                .pending_withdrawals
+                .saturating_sub(transfer_amount);
+        }
+    } else {
+        token_pair.stats_a.fees_collected = token_pair
+            .stats_a
+            .fees_collected
+            .saturating_add(withdraw_amount_fees);
+        if !is_current_pool {
+            token_pair.stats_a.pending_withdrawals = token_pair
+                .stats_a
+                .pending_withdrawals
+                .saturating_sub(transfer_amount);
+        }
+    }
+
+    Ok(())
+}
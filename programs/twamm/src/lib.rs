@@ -102,6 +102,20 @@ pub mod twamm {
         instructions::set_time_in_force(ctx, &params)
     }
 
+    pub fn set_schedule<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetSchedule<'info>>,
+        params: SetScheduleParams,
+    ) -> Result<u8> {
+        instructions::set_schedule(ctx, &params)
+    }
+
+    pub fn set_pool_slots<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetPoolSlots<'info>>,
+        params: SetPoolSlotsParams,
+    ) -> Result<u8> {
+        instructions::set_pool_slots(ctx, &params)
+    }
+
     pub fn set_test_oracle_price<'info>(
         ctx: Context<'_, '_, '_, 'info, SetTestOraclePrice<'info>>,
         params: SetTestOraclePriceParams,
@@ -137,6 +151,20 @@ pub mod twamm {
         instructions::withdraw_fees(ctx, &params)
     }
 
+    pub fn init_virtual_pool<'info>(
+        ctx: Context<'_, '_, '_, 'info, InitVirtualPool<'info>>,
+        params: InitVirtualPoolParams,
+    ) -> Result<u8> {
+        instructions::init_virtual_pool(ctx, &params)
+    }
+
+    pub fn init_observations(
+        ctx: Context<InitObservations>,
+        params: InitObservationsParams,
+    ) -> Result<()> {
+        instructions::init_observations(ctx, &params)
+    }
+
     pub fn get_outstanding_amount(
         ctx: Context<GetOutstandingAmount>,
         params: GetOutstandingAmountParams,
@@ -156,7 +184,140 @@ pub mod twamm {
         instructions::place_order(ctx, &params)
     }
 
+    pub fn submit_order_intent(
+        ctx: Context<SubmitOrderIntent>,
+        params: SubmitOrderIntentParams,
+    ) -> Result<()> {
+        instructions::submit_order_intent(ctx, &params)
+    }
+
+    pub fn close_intent_nonce(
+        ctx: Context<CloseIntentNonce>,
+        params: CloseIntentNonceParams,
+    ) -> Result<()> {
+        instructions::close_intent_nonce(ctx, &params)
+    }
+
     pub fn cancel_order(ctx: Context<CancelOrder>, params: CancelOrderParams) -> Result<()> {
         instructions::cancel_order(ctx, &params)
     }
+
+    pub fn withdraw_proceeds(
+        ctx: Context<WithdrawProceeds>,
+        params: WithdrawProceedsParams,
+    ) -> Result<()> {
+        instructions::withdraw_proceeds(ctx, &params)
+    }
+
+    pub fn set_order_delegate(
+        ctx: Context<SetOrderDelegate>,
+        params: SetOrderDelegateParams,
+    ) -> Result<()> {
+        instructions::set_order_delegate(ctx, &params)
+    }
+
+    pub fn set_order_beneficiary(
+        ctx: Context<SetOrderBeneficiary>,
+        params: SetOrderBeneficiaryParams,
+    ) -> Result<()> {
+        instructions::set_order_beneficiary(ctx, &params)
+    }
+
+    pub fn update_order(ctx: Context<UpdateOrder>, params: UpdateOrderParams) -> Result<()> {
+        instructions::update_order(ctx, &params)
+    }
+
+    pub fn transfer_order(ctx: Context<TransferOrder>, params: TransferOrderParams) -> Result<()> {
+        instructions::transfer_order(ctx, &params)
+    }
+
+    pub fn tokenize_order(ctx: Context<TokenizeOrder>, params: TokenizeOrderParams) -> Result<()> {
+        instructions::tokenize_order(ctx, &params)
+    }
+
+    pub fn redeem_position(
+        ctx: Context<RedeemPosition>,
+        params: RedeemPositionParams,
+    ) -> Result<()> {
+        instructions::redeem_position(ctx, &params)
+    }
+
+    pub fn link_order(ctx: Context<LinkOrder>, params: LinkOrderParams) -> Result<()> {
+        instructions::link_order(ctx, &params)
+    }
+
+    pub fn forward_proceeds(
+        ctx: Context<ForwardProceeds>,
+        params: ForwardProceedsParams,
+    ) -> Result<()> {
+        instructions::forward_proceeds(ctx, &params)
+    }
+
+    pub fn refresh_order(ctx: Context<RefreshOrder>, params: RefreshOrderParams) -> Result<()> {
+        instructions::refresh_order(ctx, &params)
+    }
+
+    pub fn roll_order(ctx: Context<RollOrder>, params: RollOrderParams) -> Result<()> {
+        instructions::roll_order(ctx, &params)
+    }
+
+    pub fn place_stop_order(
+        ctx: Context<PlaceStopOrder>,
+        params: PlaceStopOrderParams,
+    ) -> Result<()> {
+        instructions::place_stop_order(ctx, &params)
+    }
+
+    pub fn cancel_stop_order(
+        ctx: Context<CancelStopOrder>,
+        params: CancelStopOrderParams,
+    ) -> Result<()> {
+        instructions::cancel_stop_order(ctx, &params)
+    }
+
+    pub fn trigger_order(ctx: Context<TriggerOrder>, params: TriggerOrderParams) -> Result<()> {
+        instructions::trigger_order(ctx, &params)
+    }
+
+    pub fn create_subscription(
+        ctx: Context<CreateSubscription>,
+        params: CreateSubscriptionParams,
+    ) -> Result<()> {
+        instructions::create_subscription(ctx, &params)
+    }
+
+    pub fn execute_subscription(
+        ctx: Context<ExecuteSubscription>,
+        params: ExecuteSubscriptionParams,
+    ) -> Result<()> {
+        instructions::execute_subscription(ctx, &params)
+    }
+
+    pub fn cancel_subscription(
+        ctx: Context<CancelSubscription>,
+        params: CancelSubscriptionParams,
+    ) -> Result<()> {
+        instructions::cancel_subscription(ctx, &params)
+    }
+
+    pub fn place_virtual_order<'info>(
+        ctx: Context<'_, '_, '_, 'info, PlaceVirtualOrder<'info>>,
+        params: PlaceVirtualOrderParams,
+    ) -> Result<()> {
+        instructions::place_virtual_order(ctx, &params)
+    }
+
+    pub fn cancel_virtual_order<'info>(
+        ctx: Context<'_, '_, '_, 'info, CancelVirtualOrder<'info>>,
+        params: CancelVirtualOrderParams,
+    ) -> Result<()> {
+        instructions::cancel_virtual_order(ctx, &params)
+    }
+
+    pub fn migrate_account(
+        ctx: Context<MigrateAccount>,
+        params: MigrateAccountParams,
+    ) -> Result<()> {
+        instructions::migrate_account(ctx, &params)
+    }
 }
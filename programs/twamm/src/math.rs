@@ -251,6 +251,20 @@ pub fn checked_token_mul(
     ))
 }
 
+// Fixed-point ratios are stored as integers with RATIO_DECIMALS implied decimals
+pub const RATIO_DECIMALS: u8 = 9;
+pub const RATIO_ONE: u64 = 1_000_000_000;
+
+/// Returns arg1 / arg2 as a fixed-point ratio rounded down
+pub fn checked_ratio(arg1: u64, arg2: u64) -> Result<u64> {
+    checked_decimal_div(arg1, 0, arg2, 0, -(RATIO_DECIMALS as i32))
+}
+
+/// Returns the amount multiplied by the fixed-point ratio rounded down
+pub fn checked_ratio_mul(amount: u64, ratio: u64) -> Result<u64> {
+    checked_decimal_mul(amount, 0, ratio, -(RATIO_DECIMALS as i32), 0)
+}
+
 pub fn checked_pow<T>(arg: T, exp: usize) -> Result<T>
 where
     T: num_traits::PrimInt + Display,
@@ -313,3 +327,25 @@ where
         err!(TwammError::MathOverflow)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_checked_ratio() {
+        assert_eq!(checked_ratio(1, 2).unwrap(), 500_000_000);
+        assert_eq!(checked_ratio(3, 3).unwrap(), RATIO_ONE);
+        assert_eq!(checked_ratio(5, 2).unwrap(), 2_500_000_000);
+        // rounded down
+        assert_eq!(checked_ratio(1, 3).unwrap(), 333_333_333);
+        assert_eq!(checked_ratio(0, 7).unwrap(), 0);
+        assert!(checked_ratio(1, 0).is_err());
+        assert!(checked_ratio(u64::MAX, 1).is_err());
+
+        assert_eq!(checked_ratio_mul(1000, 500_000_000).unwrap(), 500);
+        assert_eq!(checked_ratio_mul(1000, 333_333_333).unwrap(), 333);
+        assert_eq!(checked_ratio_mul(1000, RATIO_ONE).unwrap(), 1000);
+        assert_eq!(checked_ratio_mul(u64::MAX, RATIO_ONE).unwrap(), u64::MAX);
+    }
+}
@@ -8,6 +8,11 @@ use {
 const ORACLE_EXPONENT_SCALE: i32 = -9;
 const ORACLE_PRICE_SCALE: u64 = 1_000_000_000;
 const ORACLE_MAX_PRICE: u64 = (1 << 28) - 1;
+// exponent of fill prices stored in pool stats
+pub const FILL_PRICE_EXPONENT: i32 = ORACLE_EXPONENT_SCALE;
+// range of exponents accepted for user-supplied order prices
+const MIN_ORDER_PRICE_EXPONENT: i32 = 2 * ORACLE_EXPONENT_SCALE;
+const MAX_ORDER_PRICE_EXPONENT: i32 = 0;
 
 #[derive(Copy, Clone, AnchorSerialize, AnchorDeserialize, Debug)]
 pub enum OracleType {
@@ -22,7 +27,7 @@ impl Default for OracleType {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Debug)]
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
 pub struct OraclePrice {
     pub price: u64,
     pub exponent: i32,
@@ -44,7 +49,7 @@ impl TestOracle {
 pub fn get_oracle_price(
     oracle_type: OracleType,
     oracle_account: &AccountInfo,
-    max_price_error: f64,
+    max_price_error: u64,
     max_price_age_sec: u32,
     current_time: i64,
 ) -> Result<OraclePrice> {
@@ -85,7 +90,7 @@ pub fn get_asset_amount_usd(
 
 pub fn get_test_price(
     test_price_info: &AccountInfo,
-    max_price_error: f64,
+    max_price_error: u64,
     max_price_age_sec: u32,
     current_time: i64,
 ) -> Result<OraclePrice> {
@@ -103,8 +108,7 @@ pub fn get_test_price(
     }
 
     if oracle_acc.price == 0
-        || math::checked_float_div(oracle_acc.conf as f64, oracle_acc.price as f64)?
-            > max_price_error
+        || math::checked_ratio(oracle_acc.conf, oracle_acc.price)? > max_price_error
     {
         msg!("Error: Test oracle price is out of bounds");
         return err!(TwammError::InvalidOraclePrice);
@@ -119,7 +123,7 @@ pub fn get_test_price(
 
 pub fn get_pyth_price(
     pyth_price_info: &AccountInfo,
-    max_price_error: f64,
+    max_price_error: u64,
     max_price_age_sec: u32,
     current_time: i64,
 ) -> Result<OraclePrice> {
@@ -138,8 +142,7 @@ pub fn get_pyth_price(
     }
 
     if pyth_price.price <= 0
-        || math::checked_float_div(pyth_price.conf as f64, pyth_price.price as f64)?
-            > max_price_error
+        || math::checked_ratio(pyth_price.conf, pyth_price.price as u64)? > max_price_error
     {
         msg!("Error: Pyth oracle price is out of bounds");
         return err!(TwammError::InvalidOraclePrice);
@@ -221,7 +224,118 @@ impl OraclePrice {
         }
     }
 
-    pub fn checked_as_f64(&self) -> Result<f64> {
-        math::checked_float_mul(self.price as f64, math::checked_powi(10.0, self.exponent)?)
+    /// Checks if the user-supplied order price can be compared with oracle prices,
+    /// zero price is accepted as not set
+    pub fn is_valid_order_price(&self) -> bool {
+        self.price == 0
+            || (MIN_ORDER_PRICE_EXPONENT..=MAX_ORDER_PRICE_EXPONENT).contains(&self.exponent)
+    }
+
+    /// Returns price mantissa with the fixed FILL_PRICE_EXPONENT, used for stored prices
+    pub fn get_fill_price(&self) -> Result<u64> {
+        Ok(self.scale_to_exponent(FILL_PRICE_EXPONENT)?.price)
+    }
+
+    /// Compares prices with different exponents without rounding
+    pub fn checked_cmp(&self, other: &OraclePrice) -> Result<std::cmp::Ordering> {
+        let (price, other_price) = self.get_common_mantissas(other)?;
+        Ok(price.cmp(&other_price))
+    }
+
+    /// Returns relative difference from this price to the other one as a fixed-point ratio
+    pub fn get_deviation(&self, other: &OraclePrice) -> Result<u64> {
+        let (price, other_price) = self.get_common_mantissas(other)?;
+        let deviation = math::checked_div(
+            math::checked_mul(price.abs_diff(other_price), math::RATIO_ONE as u128)?,
+            price,
+        )?;
+        // deviation is only compared against limits, saturate instead of failing
+        Ok(u64::try_from(deviation).unwrap_or(u64::MAX))
+    }
+
+    // returns mantissas of both prices scaled to the smaller exponent
+    fn get_common_mantissas(&self, other: &OraclePrice) -> Result<(u128, u128)> {
+        let exponent = std::cmp::min(self.exponent, other.exponent);
+        Ok((
+            math::checked_mul(
+                self.price as u128,
+                math::checked_pow(10u128, math::checked_sub(self.exponent, exponent)? as usize)?,
+            )?,
+            math::checked_mul(
+                other.price as u128,
+                math::checked_pow(
+                    10u128,
+                    math::checked_sub(other.exponent, exponent)? as usize,
+                )?,
+            )?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_checked_cmp() {
+        let price = OraclePrice::new(3000, -2);
+        assert!(price.checked_cmp(&OraclePrice::new(30, 0)).unwrap().is_eq());
+        assert!(price
+            .checked_cmp(&OraclePrice::new(30_000_000_001, -9))
+            .unwrap()
+            .is_lt());
+        assert!(price
+            .checked_cmp(&OraclePrice::new(29_999_999_999, -9))
+            .unwrap()
+            .is_gt());
+        assert!(OraclePrice::new(0, -5)
+            .checked_cmp(&OraclePrice::new(1, 3))
+            .unwrap()
+            .is_lt());
+
+        // exponents too far apart overflow instead of rounding
+        assert!(OraclePrice::new(u64::MAX, 30)
+            .checked_cmp(&OraclePrice::new(1, -30))
+            .is_err());
+    }
+
+    #[test]
+    fn test_get_deviation() {
+        let reference = OraclePrice::new(3000, -2);
+        assert_eq!(reference.get_deviation(&reference).unwrap(), 0);
+        assert_eq!(
+            reference.get_deviation(&OraclePrice::new(33, 0)).unwrap(),
+            100_000_000
+        );
+        assert_eq!(
+            reference
+                .get_deviation(&OraclePrice::new(2_700_000, -5))
+                .unwrap(),
+            100_000_000
+        );
+        assert_eq!(
+            reference.get_deviation(&OraclePrice::new(0, 0)).unwrap(),
+            math::RATIO_ONE
+        );
+
+        // large deviations saturate
+        assert_eq!(
+            OraclePrice::new(1, -9)
+                .get_deviation(&OraclePrice::new(u64::MAX, 0))
+                .unwrap(),
+            u64::MAX
+        );
+        assert!(OraclePrice::new(0, 0).get_deviation(&reference).is_err());
+    }
+
+    #[test]
+    fn test_is_valid_order_price() {
+        assert!(OraclePrice::new(0, 100).is_valid_order_price());
+        assert!(OraclePrice::new(3000, -2).is_valid_order_price());
+        assert!(OraclePrice::new(1, 0).is_valid_order_price());
+        assert!(OraclePrice::new(1, -18).is_valid_order_price());
+        assert!(!OraclePrice::new(1, 1).is_valid_order_price());
+        assert!(!OraclePrice::new(1, -19).is_valid_order_price());
+        assert!(!OraclePrice::new(1, i32::MIN).is_valid_order_price());
     }
 }
@@ -1,14 +1,39 @@
+pub mod legacy;
 pub mod multisig;
+pub mod observations;
 pub mod order;
+pub mod order_intent;
 pub mod pool;
+pub mod stop_order;
+pub mod subscription;
 pub mod token_pair;
+pub mod virtual_checkpoint;
+pub mod virtual_order;
+pub mod virtual_pool;
 
-use {crate::math, anchor_lang::prelude::*};
+use {
+    crate::math,
+    anchor_lang::{prelude::*, ZeroCopy},
+    std::cell::RefMut,
+};
 
 pub fn is_empty_account(account_info: &AccountInfo) -> Result<bool> {
     Ok(account_info.try_data_is_empty()? || account_info.try_lamports()? == 0)
 }
 
+/// Loads zero-copy account that could have been just created with init_if_needed.
+/// Discriminator is only written on exit, so new accounts stay loadable for init
+/// for the duration of the instruction.
+pub fn load_or_init_mut<'a, T: ZeroCopy + Owner>(
+    account: &'a AccountLoader<T>,
+) -> Result<RefMut<'a, T>> {
+    if account.as_ref().try_borrow_data()?[..8] == [0u8; 8] {
+        account.load_init()
+    } else {
+        account.load_mut()
+    }
+}
+
 pub fn initialize_account<'info>(
     payer: AccountInfo<'info>,
     target_account: AccountInfo<'info>,
@@ -0,0 +1,759 @@
+//! Account layouts of previous versions, version 0 layouts were created
+//! before versioning was introduced. Account sizes are fixed per layout version,
+//! any layout change needs a new version and a conversion here.
+
+use {
+    crate::{
+        error::TwammError,
+        math,
+        oracle::{OracleType, FILL_PRICE_EXPONENT},
+        state::{
+            multisig::Multisig,
+            order::{Order, OrderSide},
+            pool::{Pool, PoolSide, PoolStatus, Schedule},
+            token_pair::{PoolSlot, TokenConfig, TokenPair, TokenStats},
+        },
+    },
+    anchor_lang::{prelude::*, Discriminator},
+};
+
+#[derive(Copy, Clone, AnchorSerialize, AnchorDeserialize, Debug)]
+pub struct LegacyPoolSide {
+    pub source_balance: u64,
+    pub target_balance: u64,
+    pub lp_supply: u64,
+    pub token_debt_total: u64,
+    pub fills_volume: u64,
+    pub weighted_fills_sum: f64,
+    pub min_fill_price: f64,
+    pub max_fill_price: f64,
+    pub num_traders: u64,
+    pub settlement_debt_total: u64,
+    pub last_balance_change_time: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug)]
+pub struct LegacyPool {
+    pub status: PoolStatus,
+    pub time_in_force: u32,
+    pub expiration_time: i64,
+    pub token_pair: Pubkey,
+    pub buy_side: LegacyPoolSide,
+    pub sell_side: LegacyPoolSide,
+    pub counter: u64,
+    pub bump: u8,
+}
+
+#[derive(Copy, Clone, AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyTokenConfig {
+    pub crank_reward: u64,
+    pub min_swap_amount: u64,
+    pub max_oracle_price_error: f64,
+    pub max_oracle_price_age_sec: u32,
+    pub oracle_type: OracleType,
+    pub oracle_account: Pubkey,
+    pub mint: Pubkey,
+    pub custody: Pubkey,
+    pub decimals: u8,
+}
+
+#[derive(Copy, Clone, AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyTokenStats {
+    pub pending_withdrawals: u64,
+    pub fees_collected: u64,
+    pub order_volume_usd: u64,
+    pub routed_volume_usd: u64,
+    pub settled_volume_usd: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyTokenPair {
+    pub allow_deposits: bool,
+    pub allow_withdrawals: bool,
+    pub allow_cranks: bool,
+    pub allow_settlements: bool,
+    pub fee_numerator: u64,
+    pub fee_denominator: u64,
+    pub settle_fee_numerator: u64,
+    pub settle_fee_denominator: u64,
+    pub max_swap_price_diff: f64,
+    pub max_unsettled_amount: f64,
+    pub min_time_till_expiration: f64,
+    pub crank_authority: Pubkey,
+    pub config_a: LegacyTokenConfig,
+    pub config_b: LegacyTokenConfig,
+    pub stats_a: LegacyTokenStats,
+    pub stats_b: LegacyTokenStats,
+    pub tifs: [u32; 10],
+    pub pool_counters: [u64; 10],
+    pub current_pool_present: [bool; 10],
+    pub future_pool_present: [bool; 10],
+    pub token_pair_bump: u8,
+    pub transfer_authority_bump: u8,
+    pub inception_time: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug)]
+pub struct LegacyOrder {
+    pub owner: Pubkey,
+    pub time: i64,
+    pub side: OrderSide,
+    pub pool: Pubkey,
+    pub lp_balance: u64,
+    pub token_debt: u64,
+    pub unsettled_balance: u64,
+    pub settlement_debt: u64,
+    pub last_balance_change_time: i64,
+    pub bump: u8,
+}
+
+// packed zero-copy layout, same as Borsh encoding
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyMultisig {
+    pub num_signers: u8,
+    pub num_signed: u8,
+    pub min_signatures: u8,
+    pub instruction_accounts_len: u8,
+    pub instruction_data_len: u16,
+    pub instruction_hash: u64,
+    pub signers: [Pubkey; 6],
+    pub signed: [bool; 6],
+    pub bump: u8,
+}
+
+/// Account in a version 0 layout, read before the account is resized
+pub enum LegacyAccount {
+    TokenPair(Box<LegacyTokenPair>),
+    Pool(LegacyPool),
+    Order(LegacyOrder),
+    Multisig(LegacyMultisig),
+}
+
+/// Converts a float ratio of older layouts to a fixed-point ratio
+pub fn ratio_from_f64(value: f64) -> Result<u64> {
+    math::checked_as_u64(math::checked_float_mul(value, math::RATIO_ONE as f64)?.round())
+}
+
+/// Converts a float price of older layouts to a fill price with FILL_PRICE_EXPONENT
+pub fn fill_price_from_f64(value: f64) -> Result<u64> {
+    math::checked_as_u64(
+        math::checked_float_mul(value, math::checked_powi(10.0, -FILL_PRICE_EXPONENT)?)?.round(),
+    )
+}
+
+/// Converts a float volume weighted price sum of older layouts to fixed-point
+pub fn fills_sum_from_f64(value: f64) -> Result<u128> {
+    math::checked_as_u128(
+        math::checked_float_mul(value, math::checked_powi(10.0, -FILL_PRICE_EXPONENT)?)?.round(),
+    )
+}
+
+impl LegacyPoolSide {
+    pub fn migrate(&self) -> Result<PoolSide> {
+        Ok(PoolSide {
+            source_balance: self.source_balance,
+            target_balance: self.target_balance,
+            lp_supply: self.lp_supply,
+            token_debt_total: self.token_debt_total,
+            fills_volume: self.fills_volume,
+            weighted_fills_sum: fills_sum_from_f64(self.weighted_fills_sum)?,
+            min_fill_price: fill_price_from_f64(self.min_fill_price)?,
+            max_fill_price: fill_price_from_f64(self.max_fill_price)?,
+            num_traders: self.num_traders,
+            settlement_debt_total: self.settlement_debt_total,
+            last_balance_change_time: self.last_balance_change_time,
+            ..Default::default()
+        })
+    }
+}
+
+impl LegacyTokenConfig {
+    pub fn migrate(&self) -> Result<TokenConfig> {
+        Ok(TokenConfig {
+            crank_reward: self.crank_reward,
+            min_swap_amount: self.min_swap_amount,
+            max_oracle_price_error: ratio_from_f64(self.max_oracle_price_error)?,
+            max_oracle_price_age_sec: self.max_oracle_price_age_sec,
+            oracle_type: self.oracle_type,
+            oracle_account: self.oracle_account,
+            mint: self.mint,
+            custody: self.custody,
+            decimals: self.decimals,
+        })
+    }
+}
+
+impl From<LegacyTokenStats> for TokenStats {
+    fn from(stats: LegacyTokenStats) -> Self {
+        TokenStats {
+            pending_withdrawals: stats.pending_withdrawals,
+            fees_collected: stats.fees_collected,
+            order_volume_usd: stats.order_volume_usd,
+            routed_volume_usd: stats.routed_volume_usd,
+            settled_volume_usd: stats.settled_volume_usd,
+        }
+    }
+}
+
+impl LegacyPool {
+    // size the account was created with
+    pub const LEN: usize = 8 + std::mem::size_of::<LegacyPool>();
+
+    /// Writes pool data to the zeroed account in the current layout
+    pub fn migrate(&self, pool: &mut Pool) -> Result<()> {
+        pool.version = Pool::VERSION;
+        pool.status = self.status;
+        pool.time_in_force = self.time_in_force;
+        pool.expiration_time = self.expiration_time;
+        pool.token_pair = self.token_pair;
+        pool.buy_side = self.buy_side.migrate()?;
+        pool.sell_side = self.sell_side.migrate()?;
+        pool.counter = self.counter;
+        // legacy pools release tokens linearly over the pool period
+        pool.schedule = Schedule {
+            shape: Default::default(),
+            start_time: math::checked_sub(self.expiration_time, self.time_in_force as i64)?,
+        };
+        pool.bump = self.bump;
+        Ok(())
+    }
+}
+
+impl LegacyTokenPair {
+    // size the account was created with
+    pub const LEN: usize = 8 + std::mem::size_of::<LegacyTokenPair>();
+
+    /// Writes token pair data to the zeroed account in the current layout
+    pub fn migrate(&self, token_pair: &mut TokenPair) -> Result<()> {
+        token_pair.version = TokenPair::VERSION;
+        token_pair.allow_deposits = self.allow_deposits;
+        token_pair.allow_withdrawals = self.allow_withdrawals;
+        token_pair.allow_cranks = self.allow_cranks;
+        token_pair.allow_settlements = self.allow_settlements;
+        token_pair.fee_numerator = self.fee_numerator;
+        token_pair.fee_denominator = self.fee_denominator;
+        token_pair.settle_fee_numerator = self.settle_fee_numerator;
+        token_pair.settle_fee_denominator = self.settle_fee_denominator;
+        token_pair.max_swap_price_diff = ratio_from_f64(self.max_swap_price_diff)?;
+        token_pair.max_unsettled_amount = ratio_from_f64(self.max_unsettled_amount)?;
+        token_pair.min_time_till_expiration = ratio_from_f64(self.min_time_till_expiration)?;
+        token_pair.crank_authority = self.crank_authority;
+        token_pair.config_a = self.config_a.migrate()?;
+        token_pair.config_b = self.config_b.migrate()?;
+        token_pair.stats_a = self.stats_a.into();
+        token_pair.stats_b = self.stats_b.into();
+
+        // legacy pairs have a fixed number of slots
+        token_pair.set_num_slots(&[], self.tifs.len())?;
+
+        token_pair.token_pair_bump = self.token_pair_bump;
+        token_pair.transfer_authority_bump = self.transfer_authority_bump;
+        token_pair.inception_time = self.inception_time;
+        Ok(())
+    }
+
+    /// Writes time in force slots that follow the migrated header, schedules are linear
+    pub fn migrate_slots(&self, slots: &mut [PoolSlot]) {
+        for (idx, slot) in slots.iter_mut().enumerate() {
+            slot.time_in_force = self.tifs[idx];
+            slot.pool_counter = self.pool_counters[idx];
+            slot.current_pool_present = self.current_pool_present[idx];
+            slot.future_pool_present = self.future_pool_present[idx];
+        }
+    }
+}
+
+impl LegacyOrder {
+    // size the account was created with
+    pub const LEN: usize = 8 + std::mem::size_of::<LegacyOrder>();
+
+    /// Returns order data in the current layout
+    pub fn migrate(&self) -> Order {
+        Order {
+            version: Order::VERSION,
+            owner: self.owner,
+            time: self.time,
+            start_time: self.time,
+            side: self.side,
+            pool: self.pool,
+            lp_balance: self.lp_balance,
+            token_debt: self.token_debt,
+            unsettled_balance: self.unsettled_balance,
+            settlement_debt: self.settlement_debt,
+            last_balance_change_time: self.last_balance_change_time,
+            bump: self.bump,
+            ..Default::default()
+        }
+    }
+}
+
+impl LegacyMultisig {
+    pub const LEN: usize = 8 + 213;
+
+    /// Writes multisig data to the zeroed account in the current layout
+    pub fn migrate(&self, multisig: &mut Multisig) {
+        multisig.version = Multisig::VERSION;
+        multisig.num_signers = self.num_signers;
+        multisig.num_signed = self.num_signed;
+        multisig.min_signatures = self.min_signatures;
+        multisig.instruction_accounts_len = self.instruction_accounts_len;
+        multisig.instruction_data_len = self.instruction_data_len;
+        multisig.instruction_hash = self.instruction_hash;
+        multisig.signers = self.signers;
+        multisig.signed = self.signed;
+        multisig.bump = self.bump;
+    }
+}
+
+impl LegacyAccount {
+    /// Reads the account in a version 0 layout, returns None if it is already migrated.
+    /// Version 0 layouts don't have the version field and are recognized by their size.
+    pub fn try_read(account: &AccountInfo) -> Result<Option<LegacyAccount>> {
+        let data = account.try_borrow_data()?;
+        if data.len() <= 8 {
+            return Err(ErrorCode::AccountDiscriminatorNotFound.into());
+        }
+        let discriminator = &data[..8];
+        let (current_len, current_version, legacy_len) =
+            if discriminator == TokenPair::discriminator() {
+                // time in force slots follow the header in the current layout
+                let num_slots = data.get(8..TokenPair::LEN).map_or(0, |header| {
+                    bytemuck::from_bytes::<TokenPair>(header).get_num_slots()
+                });
+                (
+                    TokenPair::get_len(num_slots),
+                    TokenPair::VERSION,
+                    LegacyTokenPair::LEN,
+                )
+            } else if discriminator == Pool::discriminator() {
+                (Pool::LEN, Pool::VERSION, LegacyPool::LEN)
+            } else if discriminator == Order::discriminator() {
+                (Order::LEN, Order::VERSION, LegacyOrder::LEN)
+            } else if discriminator == Multisig::discriminator() {
+                (Multisig::LEN, Multisig::VERSION, LegacyMultisig::LEN)
+            } else {
+                return Err(ErrorCode::AccountDiscriminatorMismatch.into());
+            };
+        if data.len() == current_len && data[8] == current_version {
+            return Ok(None);
+        }
+        require_eq!(
+            data.len(),
+            legacy_len,
+            TwammError::UnsupportedAccountVersion
+        );
+
+        let mut legacy_data = &data[8..];
+        Ok(Some(if discriminator == TokenPair::discriminator() {
+            LegacyAccount::TokenPair(Box::new(LegacyTokenPair::deserialize(&mut legacy_data)?))
+        } else if discriminator == Pool::discriminator() {
+            LegacyAccount::Pool(LegacyPool::deserialize(&mut legacy_data)?)
+        } else if discriminator == Order::discriminator() {
+            LegacyAccount::Order(LegacyOrder::deserialize(&mut legacy_data)?)
+        } else {
+            LegacyAccount::Multisig(LegacyMultisig::deserialize(&mut legacy_data)?)
+        }))
+    }
+
+    /// Returns the account size in the current layout
+    pub fn get_migrated_len(&self) -> usize {
+        match self {
+            LegacyAccount::TokenPair(legacy) => TokenPair::get_len(legacy.tifs.len()),
+            LegacyAccount::Pool(_) => Pool::LEN,
+            LegacyAccount::Order(_) => Order::LEN,
+            LegacyAccount::Multisig(_) => Multisig::LEN,
+        }
+    }
+
+    /// Writes data in the current layout to the resized account with zeroed data
+    pub fn write(&self, account: &AccountInfo) -> Result<()> {
+        match self {
+            LegacyAccount::TokenPair(legacy) => {
+                let loader = AccountLoader::<TokenPair>::try_from(account)?;
+                legacy.migrate(&mut *loader.load_mut()?)?;
+                let (token_pair, mut slots) = TokenPair::load_slots_mut(&loader)?;
+                legacy.migrate_slots(&mut slots);
+                if !token_pair.validate() || !token_pair.validate_slots(&slots) {
+                    return err!(TwammError::InvalidTokenPairConfig);
+                }
+            }
+            LegacyAccount::Pool(legacy) => {
+                let pool = AccountLoader::<Pool>::try_from(account)?;
+                legacy.migrate(&mut *pool.load_mut()?)?;
+            }
+            LegacyAccount::Order(legacy) => {
+                legacy
+                    .migrate()
+                    .try_serialize(&mut &mut account.try_borrow_mut_data()?[..])?;
+            }
+            LegacyAccount::Multisig(legacy) => {
+                let multisig = AccountLoader::<Multisig>::try_from(account)?;
+                legacy.migrate(&mut *multisig.load_mut()?);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for LegacyAccount {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            LegacyAccount::TokenPair(_) => write!(f, "token pair"),
+            LegacyAccount::Pool(_) => write!(f, "pool"),
+            LegacyAccount::Order(_) => write!(f, "order"),
+            LegacyAccount::Multisig(_) => write!(f, "multisig"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, crate::state::pool::ScheduleShape};
+
+    // runs the closure with the data wrapped into a program-owned account
+    fn with_account<R>(data: &mut [u8], f: impl FnOnce(&AccountInfo) -> R) -> R {
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let account =
+            AccountInfo::new(&key, false, true, &mut lamports, data, &crate::ID, false, 0);
+        f(&account)
+    }
+
+    // returns account data of the baseline layout, padded to the size accounts were created with
+    fn get_legacy_data(
+        discriminator: [u8; 8],
+        legacy: &impl AnchorSerialize,
+        len: usize,
+    ) -> Vec<u8> {
+        let mut data = discriminator.to_vec();
+        legacy.serialize(&mut data).unwrap();
+        assert!(data.len() <= len);
+        data.resize(len, 0);
+        data
+    }
+
+    // converts account data the same way migrate_account does and checks the result is final
+    fn migrate(mut data: Vec<u8>) -> Vec<u8> {
+        let legacy = with_account(&mut data, LegacyAccount::try_read)
+            .unwrap()
+            .unwrap();
+        let mut new_data = vec![0u8; legacy.get_migrated_len()];
+        new_data[..8].copy_from_slice(&data[..8]);
+        with_account(&mut new_data, |account| legacy.write(account)).unwrap();
+        assert!(with_account(&mut new_data, LegacyAccount::try_read)
+            .unwrap()
+            .is_none());
+        new_data
+    }
+
+    fn get_legacy_pool_side(seed: u64) -> LegacyPoolSide {
+        LegacyPoolSide {
+            source_balance: seed,
+            target_balance: seed + 1,
+            lp_supply: seed + 2,
+            token_debt_total: seed + 3,
+            fills_volume: seed + 4,
+            weighted_fills_sum: 120.5,
+            min_fill_price: 1.25,
+            max_fill_price: 2.5,
+            num_traders: seed + 5,
+            settlement_debt_total: seed + 6,
+            last_balance_change_time: seed as i64 + 7,
+        }
+    }
+
+    fn get_legacy_token_config(seed: u8) -> LegacyTokenConfig {
+        LegacyTokenConfig {
+            crank_reward: seed as u64,
+            min_swap_amount: seed as u64 + 1,
+            max_oracle_price_error: 0.05,
+            max_oracle_price_age_sec: seed as u32 + 2,
+            oracle_type: OracleType::Pyth,
+            oracle_account: Pubkey::new_from_array([seed; 32]),
+            mint: Pubkey::new_from_array([seed + 1; 32]),
+            custody: Pubkey::new_from_array([seed + 2; 32]),
+            decimals: seed,
+        }
+    }
+
+    #[test]
+    fn test_migrate_token_pair() {
+        let stats = LegacyTokenStats {
+            pending_withdrawals: 1,
+            fees_collected: 2,
+            order_volume_usd: 3,
+            routed_volume_usd: 4,
+            settled_volume_usd: 5,
+        };
+        let legacy = LegacyTokenPair {
+            allow_deposits: true,
+            allow_withdrawals: false,
+            allow_cranks: true,
+            allow_settlements: true,
+            fee_numerator: 3,
+            fee_denominator: 1000,
+            settle_fee_numerator: 5,
+            settle_fee_denominator: 10000,
+            max_swap_price_diff: 0.1,
+            max_unsettled_amount: 0.25,
+            min_time_till_expiration: 0.05,
+            crank_authority: Pubkey::new_from_array([7; 32]),
+            config_a: get_legacy_token_config(9),
+            config_b: get_legacy_token_config(6),
+            stats_a: stats,
+            stats_b: LegacyTokenStats {
+                fees_collected: 20,
+                ..stats
+            },
+            tifs: [0, 300, 0, 900, 0, 0, 0, 0, 3600, 86400],
+            pool_counters: [0, 11, 0, 3, 0, 0, 0, 0, 1, 0],
+            current_pool_present: [
+                false, true, false, true, false, false, false, false, false, false,
+            ],
+            future_pool_present: [
+                false, true, false, false, false, false, false, false, false, false,
+            ],
+            token_pair_bump: 254,
+            transfer_authority_bump: 253,
+            inception_time: 1234,
+        };
+        let mut data = migrate(get_legacy_data(
+            TokenPair::discriminator(),
+            &legacy,
+            LegacyTokenPair::LEN,
+        ));
+        assert_eq!(data.len(), TokenPair::get_len(10));
+
+        with_account(&mut data, |account| {
+            let token_pair = AccountLoader::<TokenPair>::try_from(account).unwrap();
+            let (token_pair, slots) = TokenPair::load_slots(&token_pair).unwrap();
+            assert_eq!(token_pair.version, TokenPair::VERSION);
+            assert!(token_pair.allow_deposits && !token_pair.allow_withdrawals);
+            assert!(token_pair.allow_cranks && token_pair.allow_settlements);
+            assert_eq!(
+                (token_pair.fee_numerator, token_pair.fee_denominator),
+                (3, 1000)
+            );
+            assert_eq!(
+                (
+                    token_pair.settle_fee_numerator,
+                    token_pair.settle_fee_denominator
+                ),
+                (5, 10000)
+            );
+            assert_eq!({ token_pair.max_swap_price_diff }, 100_000_000);
+            assert_eq!({ token_pair.max_unsettled_amount }, 250_000_000);
+            assert_eq!({ token_pair.min_time_till_expiration }, 50_000_000);
+            assert_eq!(token_pair.crank_authority, legacy.crank_authority);
+
+            let config_a = token_pair.config_a;
+            assert_eq!({ config_a.max_oracle_price_error }, 50_000_000);
+            assert_eq!((config_a.crank_reward, config_a.min_swap_amount), (9, 10));
+            assert_eq!({ config_a.max_oracle_price_age_sec }, 11);
+            assert!(matches!(config_a.oracle_type, OracleType::Pyth));
+            assert_eq!(config_a.oracle_account, legacy.config_a.oracle_account);
+            assert_eq!(config_a.mint, legacy.config_a.mint);
+            assert_eq!(config_a.custody, legacy.config_a.custody);
+            assert_eq!(config_a.decimals, 9);
+            assert_eq!(token_pair.config_b.mint, legacy.config_b.mint);
+            assert_eq!(token_pair.config_b.decimals, 6);
+            assert_eq!({ token_pair.stats_a.settled_volume_usd }, 5);
+            assert_eq!({ token_pair.stats_b.fees_collected }, 20);
+
+            // all baseline slots are kept with linear schedules
+            assert_eq!(token_pair.get_num_slots(), 10);
+            assert!(token_pair.validate_slots(&slots));
+            for (i, slot) in slots.iter().enumerate() {
+                assert_eq!({ slot.time_in_force }, legacy.tifs[i]);
+                assert_eq!({ slot.pool_counter }, legacy.pool_counters[i]);
+                assert_eq!(slot.current_pool_present, legacy.current_pool_present[i]);
+                assert_eq!(slot.future_pool_present, legacy.future_pool_present[i]);
+                assert!({ slot.schedule_shape } == ScheduleShape::default());
+            }
+            assert_eq!(TokenPair::get_tif_index(&slots, 900).unwrap(), 3);
+
+            assert_eq!(token_pair.token_pair_bump, 254);
+            assert_eq!(token_pair.transfer_authority_bump, 253);
+            assert_eq!({ token_pair.inception_time }, 1234);
+        });
+    }
+
+    #[test]
+    fn test_migrate_pool() {
+        let legacy = LegacyPool {
+            status: PoolStatus::Locked,
+            time_in_force: 300,
+            expiration_time: 1000,
+            token_pair: Pubkey::new_from_array([3; 32]),
+            buy_side: get_legacy_pool_side(10),
+            sell_side: get_legacy_pool_side(20),
+            counter: 17,
+            bump: 255,
+        };
+        let mut data = migrate(get_legacy_data(
+            Pool::discriminator(),
+            &legacy,
+            LegacyPool::LEN,
+        ));
+        assert_eq!(data.len(), Pool::LEN);
+
+        with_account(&mut data, |account| {
+            let pool = AccountLoader::<Pool>::try_from(account).unwrap();
+            let pool = pool.load().unwrap();
+            assert_eq!(pool.version, Pool::VERSION);
+            assert_eq!(pool.status, PoolStatus::Locked);
+            assert_eq!({ pool.time_in_force }, 300);
+            assert_eq!({ pool.expiration_time }, 1000);
+            assert_eq!(pool.token_pair, legacy.token_pair);
+            assert_eq!({ pool.counter }, 17);
+            assert_eq!(pool.bump, 255);
+
+            // legacy pools release linearly over the whole pool period
+            let schedule = pool.schedule;
+            assert_eq!(schedule.shape, ScheduleShape::default());
+            assert_eq!({ schedule.start_time }, 700);
+
+            for (side, seed) in [(pool.buy_side, 10), (pool.sell_side, 20)] {
+                assert_eq!({ side.source_balance }, seed);
+                assert_eq!({ side.target_balance }, seed + 1);
+                assert_eq!({ side.lp_supply }, seed + 2);
+                assert_eq!({ side.token_debt_total }, seed + 3);
+                assert_eq!({ side.fills_volume }, seed + 4);
+                assert_eq!({ side.weighted_fills_sum }, 120_500_000_000);
+                assert_eq!({ side.min_fill_price }, 1_250_000_000);
+                assert_eq!({ side.max_fill_price }, 2_500_000_000);
+                assert_eq!({ side.num_traders }, seed + 5);
+                assert_eq!({ side.settlement_debt_total }, seed + 6);
+                assert_eq!({ side.last_balance_change_time }, seed as i64 + 7);
+                assert_eq!({ side.held_balance_total }, 0);
+                assert_eq!({ side.held_proceeds_total }, 0);
+                assert_eq!({ side.target_pending }, 0);
+            }
+            assert_eq!({ pool.buy_output_side.lp_supply }, 0);
+            assert_eq!({ pool.sell_output_side.lp_supply }, 0);
+        });
+    }
+
+    #[test]
+    fn test_migrate_order() {
+        let legacy = LegacyOrder {
+            owner: Pubkey::new_from_array([4; 32]),
+            time: 100,
+            side: OrderSide::Sell,
+            pool: Pubkey::new_from_array([5; 32]),
+            lp_balance: 1000,
+            token_debt: 20,
+            unsettled_balance: 900,
+            settlement_debt: 30,
+            last_balance_change_time: 150,
+            bump: 252,
+        };
+        let mut data = migrate(get_legacy_data(
+            Order::discriminator(),
+            &legacy,
+            LegacyOrder::LEN,
+        ));
+        assert_eq!(data.len(), Order::LEN);
+
+        with_account(&mut data, |account| {
+            let order = Account::<Order>::try_from(account).unwrap();
+            assert_eq!(order.version, Order::VERSION);
+            assert_eq!(order.owner, legacy.owner);
+            assert_eq!(order.time, 100);
+            assert_eq!(order.start_time, 100);
+            assert_eq!(order.side, OrderSide::Sell);
+            assert_eq!(order.pool, legacy.pool);
+            assert_eq!(order.lp_balance, 1000);
+            assert_eq!(order.token_debt, 20);
+            assert_eq!(order.unsettled_balance, 900);
+            assert_eq!(order.settlement_debt, 30);
+            assert_eq!(order.last_balance_change_time, 150);
+            assert_eq!(order.bump, 252);
+
+            // new fields are not set
+            assert_eq!(order.id, 0);
+            assert_eq!(order.delegate, Pubkey::default());
+            assert_eq!(order.beneficiary, Pubkey::default());
+            assert!(!order.exact_output && !order.auto_roll);
+            assert_eq!(order.limit_price.price, 0);
+            assert_eq!(order.max_price_deviation, 0);
+            assert_eq!(order.next_order, Pubkey::default());
+            assert!(order.get_recipient() == legacy.owner);
+        });
+    }
+
+    #[test]
+    fn test_migrate_multisig() {
+        let legacy = LegacyMultisig {
+            num_signers: 3,
+            num_signed: 1,
+            min_signatures: 2,
+            instruction_accounts_len: 4,
+            instruction_data_len: 100,
+            instruction_hash: 0xdead_beef,
+            signers: [
+                Pubkey::new_from_array([1; 32]),
+                Pubkey::new_from_array([2; 32]),
+                Pubkey::new_from_array([3; 32]),
+                Pubkey::default(),
+                Pubkey::default(),
+                Pubkey::default(),
+            ],
+            signed: [false, true, false, false, false, false],
+            bump: 251,
+        };
+        let mut data = migrate(get_legacy_data(
+            Multisig::discriminator(),
+            &legacy,
+            LegacyMultisig::LEN,
+        ));
+        assert_eq!(data.len(), Multisig::LEN);
+
+        with_account(&mut data, |account| {
+            let multisig = AccountLoader::<Multisig>::try_from(account).unwrap();
+            let multisig = multisig.load().unwrap();
+            assert_eq!(multisig.version, Multisig::VERSION);
+            assert_eq!(multisig.num_signers, 3);
+            assert_eq!(multisig.num_signed, 1);
+            assert_eq!(multisig.min_signatures, 2);
+            assert_eq!(multisig.instruction_accounts_len, 4);
+            assert_eq!({ multisig.instruction_data_len }, 100);
+            assert_eq!({ multisig.instruction_hash }, 0xdead_beef);
+            assert_eq!({ multisig.signers }, legacy.signers);
+            assert_eq!(multisig.signed, legacy.signed);
+            assert_eq!(multisig.bump, 251);
+        });
+    }
+
+    #[test]
+    fn test_migrate_unknown_layout() {
+        // accounts of unknown size are rejected
+        let mut data = Pool::discriminator().to_vec();
+        data.resize(LegacyPool::LEN + 1, 0);
+        assert!(with_account(&mut data, LegacyAccount::try_read).is_err());
+
+        // accounts of other types are rejected
+        let mut data = [1u8; 64].to_vec();
+        assert!(with_account(&mut data, LegacyAccount::try_read).is_err());
+    }
+
+    #[test]
+    fn test_ratio_from_f64() {
+        assert_eq!(ratio_from_f64(0.0).unwrap(), 0);
+        assert_eq!(ratio_from_f64(1.0).unwrap(), math::RATIO_ONE);
+        assert_eq!(ratio_from_f64(0.1).unwrap(), 100_000_000);
+        assert_eq!(ratio_from_f64(0.0125).unwrap(), 12_500_000);
+        // rounded to the nearest fixed-point value
+        assert_eq!(ratio_from_f64(1.0 / 3.0).unwrap(), 333_333_333);
+        assert_eq!(ratio_from_f64(2.0 / 3.0).unwrap(), 666_666_667);
+        assert!(ratio_from_f64(-0.1).is_err());
+        assert!(ratio_from_f64(f64::NAN).is_err());
+        assert!(ratio_from_f64(f64::INFINITY).is_err());
+
+        assert_eq!(fill_price_from_f64(30.0).unwrap(), 30_000_000_000);
+        assert_eq!(
+            fills_sum_from_f64(2400.0 * 30.0).unwrap(),
+            72_000_000_000_000
+        );
+    }
+}
@@ -7,10 +7,12 @@ use {
     std::hash::Hasher,
 };
 
-#[repr(packed)]
-#[account(zero_copy)]
+#[account(zero_copy(unsafe))]
+#[repr(C, packed)]
 #[derive(Default)]
 pub struct Multisig {
+    // account layout version, older accounts are converted with migrate_account
+    pub version: u8,
     pub num_signers: u8,
     pub num_signed: u8,
     pub min_signatures: u8,
@@ -36,11 +38,17 @@ pub enum AdminInstruction {
     SetTestTime,
     DeleteTestPool,
     DeleteTestPair,
+    InitVirtualPool,
+    SetSchedule,
+    SetPoolSlots,
 }
 
+const _: () = assert!(Multisig::LEN == 8 + std::mem::size_of::<Multisig>());
+
 impl Multisig {
     pub const MAX_SIGNERS: usize = 6;
-    pub const LEN: usize = 8 + std::mem::size_of::<Multisig>();
+    pub const VERSION: u8 = 1;
+    pub const LEN: usize = 8 + 214;
 
     /// Returns instruction accounts and data hash.
     /// Hash is not cryptographic and is meant to perform a fast check that admins are signing
@@ -115,6 +123,7 @@ impl Multisig {
         }
 
         *self = Multisig {
+            version: Multisig::VERSION,
             num_signers: admin_signers.len() as u8,
             num_signed: 0,
             min_signatures,
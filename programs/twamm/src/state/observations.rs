@@ -0,0 +1,251 @@
+//! Observations struct keeps recent fills of a token pair in a ring buffer
+
+use {
+    crate::{
+        math,
+        oracle::OraclePrice,
+        state::token_pair::{MatchingSide, Settlement},
+    },
+    anchor_lang::prelude::*,
+};
+
+#[zero_copy(unsafe)]
+#[repr(C, packed)]
+#[derive(Default, Debug)]
+pub struct Observation {
+    pub time: i64,
+    // token B per token A with oracle::FILL_PRICE_EXPONENT
+    pub fill_price: u64,
+    // matched amount of token A
+    pub volume: u64,
+    // external supply side or Internal for matches between pool sides
+    pub side: MatchingSide,
+}
+
+#[account(zero_copy(unsafe))]
+#[repr(C, packed)]
+pub struct Observations {
+    // account layout version
+    pub version: u8,
+    pub token_pair: Pubkey,
+    // total number of recorded observations, the oldest ones are overwritten
+    pub num_observations: u64,
+    pub observations: [Observation; 128], // Observations::MAX_OBSERVATIONS
+    pub bump: u8,
+}
+
+const _: () = assert!(Observations::LEN == 8 + std::mem::size_of::<Observations>());
+
+impl Observations {
+    pub const VERSION: u8 = 1;
+    pub const LEN: usize = 8 + 3242;
+    pub const MAX_OBSERVATIONS: usize = 128;
+
+    /// Records a fill, overwriting the oldest observation once the buffer is full
+    pub fn record(
+        &mut self,
+        time: i64,
+        fill_price: u64,
+        volume: u64,
+        side: MatchingSide,
+    ) -> Result<()> {
+        let idx = (self.num_observations % Self::MAX_OBSERVATIONS as u64) as usize;
+        self.observations[idx] = Observation {
+            time,
+            fill_price,
+            volume,
+            side,
+        };
+        self.num_observations = math::checked_add(self.num_observations, 1)?;
+        Ok(())
+    }
+
+    /// Records internal and external fills of the settlement. Pool sides are matched
+    /// with each other at the oracle price and the net amount is settled with the
+    /// supplied tokens at the exchange rate.
+    pub fn record_settlement(
+        &mut self,
+        settlement: &Settlement,
+        oracle_price: OraclePrice,
+        exchange_rate: OraclePrice,
+        current_time: i64,
+    ) -> Result<()> {
+        let external_volume = match settlement.settlement_side {
+            MatchingSide::Sell => settlement.net_amount_settled,
+            MatchingSide::Buy => settlement.source_amount_received,
+            MatchingSide::Internal => 0,
+        };
+        let internal_volume = if settlement.settlement_side == MatchingSide::Sell {
+            math::checked_sub(settlement.total_amount_settled_a, external_volume)?
+        } else {
+            settlement.total_amount_settled_a
+        };
+        if internal_volume > 0 {
+            self.record(
+                current_time,
+                oracle_price.get_fill_price()?,
+                internal_volume,
+                MatchingSide::Internal,
+            )?;
+        }
+        if external_volume > 0 {
+            self.record(
+                current_time,
+                exchange_rate.get_fill_price()?,
+                external_volume,
+                settlement.settlement_side,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn get_fixture() -> Box<Observations> {
+        Box::new(Observations {
+            version: Observations::VERSION,
+            token_pair: Pubkey::default(),
+            num_observations: 0,
+            observations: [Observation::default(); Observations::MAX_OBSERVATIONS],
+            bump: 0,
+        })
+    }
+
+    fn get_settlement(
+        settlement_side: MatchingSide,
+        net_amount_settled: u64,
+        source_amount_received: u64,
+        total_amount_settled_a: u64,
+    ) -> Settlement {
+        Settlement {
+            net_amount_settled,
+            net_amount_required: 0,
+            source_amount_received,
+            total_amount_settled_a,
+            total_amount_settled_b: 0,
+            settlement_side,
+        }
+    }
+
+    #[test]
+    fn test_record_wrap_around() {
+        let mut observations = get_fixture();
+        let max = Observations::MAX_OBSERVATIONS as u64;
+        for i in 0..max {
+            observations
+                .record(i as i64, 100 + i, 1000 + i, MatchingSide::Sell)
+                .unwrap();
+        }
+        assert_eq!({ observations.num_observations }, max);
+        assert_eq!({ observations.observations[0].time }, 0);
+        assert_eq!(
+            { observations.observations[max as usize - 1].volume },
+            1000 + max - 1
+        );
+
+        // the oldest observations are overwritten
+        observations
+            .record(max as i64, 1, 2, MatchingSide::Buy)
+            .unwrap();
+        observations
+            .record(max as i64 + 1, 3, 4, MatchingSide::Internal)
+            .unwrap();
+        assert_eq!({ observations.num_observations }, max + 2);
+        let first = observations.observations[0];
+        assert_eq!(
+            ({ first.time }, { first.fill_price }, { first.volume }),
+            (max as i64, 1, 2)
+        );
+        assert_eq!(first.side, MatchingSide::Buy);
+        let second = observations.observations[1];
+        assert_eq!({ second.time }, max as i64 + 1);
+        assert_eq!(second.side, MatchingSide::Internal);
+        let third = observations.observations[2];
+        assert_eq!({ third.time }, 2);
+        assert_eq!(third.side, MatchingSide::Sell);
+    }
+
+    #[test]
+    fn test_record_settlement() {
+        let oracle_price = OraclePrice::new(3000, -2);
+        let exchange_rate = OraclePrice::new(2990, -2);
+
+        // net sell amount is swapped externally, the rest is matched internally
+        let mut observations = get_fixture();
+        observations
+            .record_settlement(
+                &get_settlement(MatchingSide::Sell, 300, 0, 1000),
+                oracle_price,
+                exchange_rate,
+                10,
+            )
+            .unwrap();
+        assert_eq!({ observations.num_observations }, 2);
+        let internal = observations.observations[0];
+        assert_eq!(internal.side, MatchingSide::Internal);
+        assert_eq!({ internal.volume }, 700);
+        assert_eq!({ internal.fill_price }, 30_000_000_000);
+        assert_eq!({ internal.time }, 10);
+        let external = observations.observations[1];
+        assert_eq!(external.side, MatchingSide::Sell);
+        assert_eq!({ external.volume }, 300);
+        assert_eq!({ external.fill_price }, 29_900_000_000);
+
+        // buy side receives token a from the swap on top of internal matches
+        let mut observations = get_fixture();
+        observations
+            .record_settlement(
+                &get_settlement(MatchingSide::Buy, 9000, 250, 1000),
+                oracle_price,
+                exchange_rate,
+                10,
+            )
+            .unwrap();
+        assert_eq!({ observations.num_observations }, 2);
+        assert_eq!({ observations.observations[0].volume }, 1000);
+        assert_eq!(observations.observations[1].side, MatchingSide::Buy);
+        assert_eq!({ observations.observations[1].volume }, 250);
+
+        // fully external settlement has no internal fill
+        let mut observations = get_fixture();
+        observations
+            .record_settlement(
+                &get_settlement(MatchingSide::Sell, 500, 0, 500),
+                oracle_price,
+                exchange_rate,
+                10,
+            )
+            .unwrap();
+        assert_eq!({ observations.num_observations }, 1);
+        assert_eq!(observations.observations[0].side, MatchingSide::Sell);
+
+        // internal settlement has no external fill
+        let mut observations = get_fixture();
+        observations
+            .record_settlement(
+                &get_settlement(MatchingSide::Internal, 0, 0, 400),
+                oracle_price,
+                exchange_rate,
+                10,
+            )
+            .unwrap();
+        assert_eq!({ observations.num_observations }, 1);
+        assert_eq!(observations.observations[0].side, MatchingSide::Internal);
+        assert_eq!({ observations.observations[0].volume }, 400);
+
+        // nothing settled, nothing recorded
+        let mut observations = get_fixture();
+        observations
+            .record_settlement(
+                &get_settlement(MatchingSide::Internal, 0, 0, 0),
+                oracle_price,
+                exchange_rate,
+                10,
+            )
+            .unwrap();
+        assert_eq!({ observations.num_observations }, 0);
+    }
+}
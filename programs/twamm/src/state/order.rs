@@ -1,4 +1,12 @@
-use {crate::math, anchor_lang::prelude::*};
+use {
+    crate::{
+        error::TwammError,
+        math,
+        oracle::OraclePrice,
+        state::pool::{PendingStart, PoolSide, Schedule},
+    },
+    anchor_lang::prelude::*,
+};
 
 #[derive(Copy, Clone, Eq, PartialEq, AnchorSerialize, AnchorDeserialize, Debug)]
 pub enum OrderSide {
@@ -24,45 +32,607 @@ impl std::fmt::Display for OrderSide {
 #[account]
 #[derive(Default, Debug)]
 pub struct Order {
+    // account layout version, older accounts are converted with migrate_account
+    pub version: u8,
     pub owner: Pubkey,
+    // can cancel and update the order on behalf of the owner, default if not set
+    pub delegate: Pubkey,
+    // receives order payouts instead of the owner, default if not set
+    pub beneficiary: Pubkey,
+    // user-chosen id to have multiple orders in the same pool, zero for the default order
+    pub id: u64,
     pub time: i64,
+    // time when the order joins the pool
+    pub start_time: i64,
     pub side: OrderSide,
+    // order acquires a fixed amount of target tokens, unsettled balance is in target tokens
+    pub exact_output: bool,
     pub pool: Pubkey,
     pub lp_balance: u64,
     pub token_debt: u64,
     pub unsettled_balance: u64,
     pub settlement_debt: u64,
     pub last_balance_change_time: i64,
+    // budget deposited by an exact-output order and its debt in the side's spent budget,
+    // the order is refunded its budget minus its share of the spent tokens
+    pub budget: u64,
+    pub budget_debt: u64,
+    // worst acceptable token pair price, zero if not set,
+    // exact-output orders use the average price their budget allows
+    pub limit_price: OraclePrice,
+    // pair price at placement and max relative deviation from it, zero if not set
+    pub reference_price: OraclePrice,
+    // fixed-point ratio with math::RATIO_DECIMALS
+    pub max_price_deviation: u64,
+    // move unfilled balance, and optionally proceeds, to the next pool once this one completes
+    pub auto_roll: bool,
+    pub roll_proceeds: bool,
+    // order in another token pair that proceeds are streamed to, default if not linked
+    pub next_order: Pubkey,
+    // source and target tokens temporarily moved out of the pool
+    pub held_balance: u64,
+    pub held_proceeds: u64,
+    // held balance waits in the pool side's pending start slot for start_time
+    pub pending_start: bool,
     pub bump: u8,
 }
 
 impl Order {
-    pub const LEN: usize = 8 + std::mem::size_of::<Order>();
+    pub const VERSION: u8 = 1;
+    pub const LEN: usize = 8 + 295;
+    // order ids with this bit set are reserved for orders placed by subscriptions
+    pub const SUBSCRIPTION_ID_FLAG: u64 = 1 << 63;
+
+    /// Initializes a new order that starts now and has no balances or conditions
+    pub fn init(
+        &mut self,
+        owner: Pubkey,
+        id: u64,
+        side: OrderSide,
+        pool: Pubkey,
+        current_time: i64,
+        bump: u8,
+    ) {
+        *self = Order {
+            version: Order::VERSION,
+            owner,
+            id,
+            time: current_time,
+            start_time: current_time,
+            side,
+            pool,
+            last_balance_change_time: current_time,
+            bump,
+            ..Default::default()
+        };
+    }
+
+    /// Returns the order id seed, zero id maps to the original order address
+    pub fn get_id_seed(id: u64) -> Vec<u8> {
+        if id == 0 {
+            vec![]
+        } else {
+            id.to_le_bytes().to_vec()
+        }
+    }
+
+    /// Returns the id of orders placed by the subscription, they never share
+    /// an address with orders placed by the owner directly
+    pub fn get_subscription_order_id(subscription_id: u64) -> u64 {
+        subscription_id | Order::SUBSCRIPTION_ID_FLAG
+    }
+
+    /// Checks if the id can be used for orders placed by the owner directly
+    pub fn validate_user_id(id: u64) -> Result<()> {
+        if id & Order::SUBSCRIPTION_ID_FLAG != 0 {
+            msg!("Error: Order id {} is reserved for subscription orders", id);
+            return err!(TwammError::ReservedOrderId);
+        }
+        Ok(())
+    }
 
     /// Returns unsettled amounts of tokens at the given time
-    pub fn get_unsettled_amount(&self, expiration_time: i64, current_time: i64) -> Result<u64> {
+    pub fn get_unsettled_amount(
+        &self,
+        schedule: &Schedule,
+        expiration_time: i64,
+        current_time: i64,
+    ) -> Result<u64> {
         // adjust current time to increase chances of pool completion before expiration
         let adjusted_current_time = math::checked_add(current_time, 30)?;
         if expiration_time <= adjusted_current_time {
             return Ok(self.unsettled_balance);
         }
 
-        let time_till_expiration = math::checked_sub(expiration_time, adjusted_current_time)?;
-        let time_since_balance_change =
-            math::checked_sub(current_time, self.last_balance_change_time)?;
+        let (released, remaining) = schedule.get_release_share(
+            math::checked_sub(expiration_time, 30)?,
+            self.last_balance_change_time,
+            current_time,
+        )?;
 
         Ok(std::cmp::min(
             math::checked_as_u64(math::checked_add(
                 math::checked_div(
-                    math::checked_mul(
-                        self.unsettled_balance as u128,
-                        time_since_balance_change as u128,
-                    )?,
-                    math::checked_add(time_till_expiration, time_since_balance_change)? as u128,
+                    math::checked_mul(self.unsettled_balance as u128, released)?,
+                    remaining,
                 )?,
                 self.settlement_debt as u128,
             )?)?,
             self.unsettled_balance,
         ))
     }
+
+    /// Checks if the given account can manage the order
+    pub fn is_authorized(&self, authority: &Pubkey) -> bool {
+        *authority == self.owner
+            || (self.delegate != Pubkey::default() && *authority == self.delegate)
+    }
+
+    /// Returns the wallet that receives order payouts
+    pub fn get_recipient(&self) -> Pubkey {
+        if self.beneficiary != Pubkey::default() {
+            self.beneficiary
+        } else {
+            self.owner
+        }
+    }
+
+    /// Checks if the order has no balances
+    pub fn is_empty(&self) -> bool {
+        self.lp_balance == 0 && self.held_balance == 0 && self.held_proceeds == 0
+    }
+
+    /// Checks if the order is moved out of the pool and waits for conditions to be met
+    pub fn is_held(&self) -> bool {
+        self.lp_balance == 0 && self.held_balance > 0
+    }
+
+    /// Checks if the order has reached its start time
+    pub fn is_started(&self, current_time: i64) -> bool {
+        current_time >= self.start_time
+    }
+
+    /// Checks if the order has a limit price or a deviation band
+    pub fn has_price_conditions(&self) -> bool {
+        self.limit_price.price > 0 || self.max_price_deviation > 0
+    }
+
+    /// Checks if the given token pair price satisfies the order's limit price
+    /// and stays within the deviation band around the reference price
+    pub fn is_price_acceptable(&self, pair_price: &OraclePrice) -> Result<bool> {
+        if self.max_price_deviation > 0 && self.reference_price.price > 0 {
+            let deviation = self.reference_price.get_deviation(pair_price)?;
+            if deviation > self.max_price_deviation {
+                return Ok(false);
+            }
+        }
+        if self.limit_price.price == 0 {
+            return Ok(true);
+        }
+        let ordering = pair_price.checked_cmp(&self.limit_price)?;
+        if self.side == OrderSide::Buy {
+            Ok(ordering.is_le())
+        } else {
+            Ok(ordering.is_ge())
+        }
+    }
+
+    /// Returns min and max acceptable token pair prices with FILL_PRICE_EXPONENT
+    /// from the limit price and the deviation band, zero if not set
+    pub fn get_price_bounds(&self) -> Result<(u64, u64)> {
+        let mut min_price = 0;
+        let mut max_price = 0;
+        if self.max_price_deviation > 0 && self.reference_price.price > 0 {
+            let reference_price = self.reference_price.get_fill_price()?;
+            let deviation = math::checked_ratio_mul(reference_price, self.max_price_deviation)?;
+            min_price = reference_price.saturating_sub(deviation);
+            max_price = math::checked_add(reference_price, deviation)?;
+        }
+        if self.limit_price.price > 0 {
+            let limit_price = self.limit_price.get_fill_price()?;
+            if self.side == OrderSide::Buy {
+                // keep tiny limits from reading as not set
+                let limit_price = std::cmp::max(limit_price, 1);
+                max_price = if max_price == 0 {
+                    limit_price
+                } else {
+                    std::cmp::min(max_price, limit_price)
+                };
+            } else {
+                min_price = std::cmp::max(min_price, limit_price);
+            }
+        }
+        Ok((min_price, max_price))
+    }
+
+    /// Adds the budget price of an exact-output order to the pool side bounds, called once
+    /// the order joins the pool. Exact-input orders are held out by refresh_order instead.
+    pub fn add_price_bounds(&self, pool_side: &mut PoolSide) -> Result<()> {
+        if !self.exact_output || self.lp_balance == 0 || !self.has_price_conditions() {
+            return Ok(());
+        }
+        let (min_price, max_price) = self.get_price_bounds()?;
+        pool_side.add_price_bounds(min_price, max_price)
+    }
+
+    /// Removes the budget price of an exact-output order from the pool side bounds, called
+    /// before the order leaves the pool
+    pub fn remove_price_bounds(&self, pool_side: &mut PoolSide) -> Result<()> {
+        if !self.exact_output || self.lp_balance == 0 || !self.has_price_conditions() {
+            return Ok(());
+        }
+        pool_side.remove_price_bounds()
+    }
+
+    /// Adds source tokens to the pool side on behalf of the order
+    pub fn deposit(
+        &mut self,
+        pool_side: &mut PoolSide,
+        amount: u64,
+        schedule: &Schedule,
+        expiration_time: i64,
+        current_time: i64,
+    ) -> Result<()> {
+        let joins_pool = self.lp_balance == 0;
+        let (lp_amount, debt_amount) =
+            pool_side.deposit(amount, schedule, expiration_time, current_time)?;
+
+        self.lp_balance = math::checked_add(self.lp_balance, lp_amount)?;
+        self.token_debt = math::checked_add(self.token_debt, debt_amount)?;
+        self.settlement_debt =
+            self.get_unsettled_amount(schedule, expiration_time, current_time)?;
+        self.unsettled_balance = math::checked_add(self.unsettled_balance, amount)?;
+        self.last_balance_change_time = current_time;
+
+        if joins_pool {
+            self.add_price_bounds(pool_side)?;
+        }
+        Ok(())
+    }
+
+    /// Adds target tokens to acquire to the exact-output side on behalf of the order.
+    /// Limit price is the average price the order's budget allows, settlement pauses
+    /// the side instead of spending more than the budget of any order in it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn deposit_target(
+        &mut self,
+        pool_side: &mut PoolSide,
+        target_amount: u64,
+        budget: u64,
+        limit_price: OraclePrice,
+        schedule: &Schedule,
+        expiration_time: i64,
+        current_time: i64,
+    ) -> Result<()> {
+        self.remove_price_bounds(pool_side)?;
+        let (lp_amount, debt_amount, budget_debt) = pool_side.deposit_target(
+            target_amount,
+            budget,
+            schedule,
+            expiration_time,
+            current_time,
+        )?;
+
+        self.lp_balance = math::checked_add(self.lp_balance, lp_amount)?;
+        self.token_debt = math::checked_add(self.token_debt, debt_amount)?;
+        self.budget = math::checked_add(self.budget, budget)?;
+        self.budget_debt = math::checked_add(self.budget_debt, budget_debt)?;
+        self.settlement_debt =
+            self.get_unsettled_amount(schedule, expiration_time, current_time)?;
+        self.unsettled_balance = math::checked_add(self.unsettled_balance, target_amount)?;
+        self.last_balance_change_time = current_time;
+        self.limit_price = limit_price;
+
+        self.add_price_bounds(pool_side)
+    }
+
+    /// Returns target tokens the exact-output order has left to acquire
+    pub fn get_target_pending(&self, pool_side: &PoolSide) -> Result<u64> {
+        if self.lp_balance == 0 {
+            return Ok(0);
+        }
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(self.lp_balance as u128, pool_side.target_pending as u128)?,
+            pool_side.lp_supply as u128,
+        )?)
+    }
+
+    /// Returns unspent budget of the exact-output order for the given lp amount,
+    /// and amounts of budget and budget debt to be removed with it
+    pub fn get_budget_withdrawal(
+        &self,
+        pool_side: &PoolSide,
+        lp_amount: u64,
+    ) -> Result<(u64, u64, u64)> {
+        if lp_amount == 0 || self.lp_balance == 0 {
+            return Ok((0, 0, 0));
+        }
+        let (budget_removed, budget_debt_removed) = if lp_amount >= self.lp_balance {
+            (self.budget, self.budget_debt)
+        } else {
+            (
+                math::checked_as_u64(math::checked_div(
+                    math::checked_mul(self.budget as u128, lp_amount as u128)?,
+                    self.lp_balance as u128,
+                )?)?,
+                std::cmp::min(
+                    math::checked_as_u64(math::checked_ceil_div(
+                        math::checked_mul(self.budget_debt as u128, lp_amount as u128)?,
+                        self.lp_balance as u128,
+                    )?)?,
+                    self.budget_debt,
+                ),
+            )
+        };
+
+        // spent amount is rounded up to keep the side solvent
+        let spent = math::checked_as_u64(math::checked_ceil_div(
+            math::checked_mul(
+                lp_amount as u128,
+                math::checked_add(
+                    pool_side.get_budget_spent() as u128,
+                    pool_side.budget_debt_total as u128,
+                )?,
+            )?,
+            pool_side.lp_supply as u128,
+        )?)?
+        .saturating_sub(budget_debt_removed);
+        let refund = std::cmp::min(
+            budget_removed.saturating_sub(spent),
+            pool_side.source_balance,
+        );
+
+        Ok((refund, budget_removed, budget_debt_removed))
+    }
+
+    /// Moves the delayed order to the pool side once its pending start slot has joined it
+    pub fn sync_start(&mut self, pool_side: &mut PoolSide) -> Result<()> {
+        if !self.pending_start {
+            return Ok(());
+        }
+        let amount = self.held_balance;
+        let slot = pool_side.get_pending_start_mut(self.start_time)?;
+        if !slot.active {
+            return Ok(());
+        }
+
+        // the last order in the slot takes the remainder
+        let (lp_amount, debt_amount) = if slot.num_orders == 1 {
+            (slot.lp_amount, slot.debt_amount)
+        } else {
+            (
+                math::checked_as_u64(math::checked_div(
+                    math::checked_mul(amount as u128, slot.lp_amount as u128)?,
+                    slot.balance as u128,
+                )?)?,
+                math::checked_as_u64(math::checked_ceil_div(
+                    math::checked_mul(amount as u128, slot.debt_amount as u128)?,
+                    slot.balance as u128,
+                )?)?,
+            )
+        };
+        slot.balance = math::checked_sub(slot.balance, amount)?;
+        slot.lp_amount = math::checked_sub(slot.lp_amount, lp_amount)?;
+        slot.debt_amount = slot.debt_amount.saturating_sub(debt_amount);
+        slot.num_orders = math::checked_sub(slot.num_orders, 1)?;
+        if slot.num_orders == 0 {
+            *slot = PendingStart::default();
+        }
+
+        // order joins the release schedule at its start time
+        self.pending_start = false;
+        self.held_balance = 0;
+        self.lp_balance = math::checked_add(self.lp_balance, lp_amount)?;
+        self.token_debt = math::checked_add(self.token_debt, debt_amount)?;
+        self.unsettled_balance = math::checked_add(self.unsettled_balance, amount)?;
+        self.settlement_debt = 0;
+        self.last_balance_change_time = self.start_time;
+        self.add_price_bounds(pool_side)
+    }
+
+    /// Takes the delayed order out of its pending start slot before it leaves the pool,
+    /// the held balance stays with the order
+    pub fn remove_pending_start(&mut self, pool_side: &mut PoolSide) -> Result<()> {
+        if !self.pending_start {
+            return Ok(());
+        }
+        pool_side.remove_pending_start(self.start_time, self.held_balance)?;
+        self.pending_start = false;
+        Ok(())
+    }
+
+    /// Moves lp balance of the order to position tokens and returns target tokens the order
+    /// owes for the debt position tokens don't carry, tokens released by the difference
+    /// are added to held proceeds
+    pub fn tokenize(
+        &mut self,
+        pool_side: &mut PoolSide,
+        lp_amount: u64,
+        schedule: &Schedule,
+        expiration_time: i64,
+        current_time: i64,
+    ) -> Result<u64> {
+        // position tokens share the exact-input pool side with no conditions of their own
+        if self.exact_output || self.has_price_conditions() {
+            msg!("Error: Only exact-input orders without price conditions can be tokenized");
+            return err!(TwammError::OrderNotTokenizable);
+        }
+        if lp_amount == 0 || lp_amount > self.lp_balance {
+            msg!("Error: Order has {} lp to tokenize", self.lp_balance);
+            return err!(TwammError::OrderNotTokenizable);
+        }
+
+        let token_debt = if lp_amount == self.lp_balance {
+            self.token_debt
+        } else {
+            std::cmp::min(
+                math::checked_as_u64(math::checked_ceil_div(
+                    math::checked_mul(self.token_debt as u128, lp_amount as u128)?,
+                    self.lp_balance as u128,
+                )?)?,
+                self.token_debt,
+            )
+        };
+        let (released, owed) = pool_side.add_position(lp_amount, token_debt)?;
+
+        // pool side balances don't change since position tokens keep the share of the pool
+        self.settlement_debt =
+            self.get_unsettled_amount(schedule, expiration_time, current_time)?;
+        if lp_amount == self.lp_balance {
+            self.unsettled_balance = 0;
+            self.settlement_debt = 0;
+        } else {
+            self.unsettled_balance = math::checked_sub(
+                self.unsettled_balance,
+                math::checked_as_u64(math::checked_div(
+                    math::checked_mul(self.unsettled_balance as u128, lp_amount as u128)?,
+                    self.lp_balance as u128,
+                )?)?,
+            )?;
+            self.settlement_debt = math::checked_sub(
+                self.settlement_debt,
+                math::checked_as_u64(math::checked_div(
+                    math::checked_mul(self.settlement_debt as u128, lp_amount as u128)?,
+                    self.lp_balance as u128,
+                )?)?,
+            )?;
+        }
+        self.lp_balance = math::checked_sub(self.lp_balance, lp_amount)?;
+        self.token_debt = math::checked_sub(self.token_debt, token_debt)?;
+        self.held_proceeds = math::checked_add(self.held_proceeds, released)?;
+        pool_side.held_proceeds_total = math::checked_add(pool_side.held_proceeds_total, released)?;
+        self.last_balance_change_time = current_time;
+
+        Ok(owed)
+    }
+
+    /// Takes filled tokens claimable by the order out of the pool side and held proceeds,
+    /// returns amounts taken from the pool side and from held proceeds
+    pub fn withdraw_proceeds(&mut self, pool_side: &mut PoolSide) -> Result<(u64, u64)> {
+        let withdraw_amount = std::cmp::min(
+            pool_side.get_proceeds(self.lp_balance, self.token_debt)?,
+            pool_side.target_balance,
+        );
+        let held_proceeds = self.held_proceeds;
+
+        // increase the debt by the withdrawn amount, so the order keeps its share
+        // of the pool and doesn't claim the same tokens again
+        self.token_debt = math::checked_add(self.token_debt, withdraw_amount)?;
+        self.held_proceeds = 0;
+        pool_side.token_debt_total =
+            math::checked_add(pool_side.token_debt_total, withdraw_amount)?;
+        pool_side.target_balance = math::checked_sub(pool_side.target_balance, withdraw_amount)?;
+        pool_side.held_proceeds_total =
+            math::checked_sub(pool_side.held_proceeds_total, held_proceeds)?;
+
+        Ok((withdraw_amount, held_proceeds))
+    }
+
+    /// Moves order's share of the pool side out of the pool and returns the amount
+    /// of source tokens held
+    pub fn hold(
+        &mut self,
+        pool_side: &mut PoolSide,
+        schedule: &Schedule,
+        expiration_time: i64,
+        current_time: i64,
+    ) -> Result<u64> {
+        if self.lp_balance == 0 {
+            return Ok(0);
+        }
+        assert!(
+            self.lp_balance <= pool_side.lp_supply,
+            "Unexpected LP balance error"
+        );
+
+        // compute order's share of the pool side
+        let source_amount = math::checked_as_u64(math::checked_div(
+            math::checked_mul(self.lp_balance as u128, pool_side.source_balance as u128)?,
+            pool_side.lp_supply as u128,
+        )?)?;
+        let target_amount = pool_side.get_proceeds(self.lp_balance, self.token_debt)?;
+
+        // remove settlement debt accrued by the order, the rest of the side keeps its debt
+        let unsettled_amount =
+            pool_side.get_unsettled_amount(schedule, expiration_time, current_time)?;
+        let order_debt_removed =
+            self.get_unsettled_amount(schedule, expiration_time, current_time)?;
+        self.remove_price_bounds(pool_side)?;
+
+        // update pool side
+        pool_side.source_balance = math::checked_sub(pool_side.source_balance, source_amount)?;
+        pool_side.target_balance = math::checked_sub(pool_side.target_balance, target_amount)?;
+        pool_side.lp_supply = math::checked_sub(pool_side.lp_supply, self.lp_balance)?;
+        pool_side.token_debt_total =
+            math::checked_sub(pool_side.token_debt_total, self.token_debt)?;
+        pool_side.settlement_debt_total = unsettled_amount.saturating_sub(order_debt_removed);
+        pool_side.last_balance_change_time = current_time;
+        pool_side.held_balance_total =
+            math::checked_add(pool_side.held_balance_total, source_amount)?;
+        pool_side.held_proceeds_total =
+            math::checked_add(pool_side.held_proceeds_total, target_amount)?;
+
+        // update order
+        self.lp_balance = 0;
+        self.token_debt = 0;
+        self.unsettled_balance = 0;
+        self.settlement_debt = 0;
+        self.last_balance_change_time = current_time;
+        self.held_balance = math::checked_add(self.held_balance, source_amount)?;
+        self.held_proceeds = math::checked_add(self.held_proceeds, target_amount)?;
+
+        Ok(source_amount)
+    }
+
+    /// Adds source tokens to the balance held out of the pool, they join the pool
+    /// once the order's conditions are met
+    pub fn add_held_balance(&mut self, pool_side: &mut PoolSide, amount: u64) -> Result<()> {
+        pool_side.held_balance_total = math::checked_add(pool_side.held_balance_total, amount)?;
+        self.held_balance = math::checked_add(self.held_balance, amount)?;
+        Ok(())
+    }
+
+    /// Adds source tokens to the pending start slot of a delayed order, returns false if the
+    /// order is not waiting in one. Tokens are held until settlement moves the slot to the side.
+    pub fn add_pending_balance(&mut self, pool_side: &mut PoolSide, amount: u64) -> Result<bool> {
+        self.sync_start(pool_side)?;
+        if !self.pending_start || !pool_side.add_pending_start(self.start_time, amount, false)? {
+            return Ok(false);
+        }
+        self.add_held_balance(pool_side, amount)?;
+        Ok(true)
+    }
+
+    /// Takes all held tokens of the order off the pool side and returns held
+    /// source and target amounts
+    pub fn take_held(&mut self, pool_side: &mut PoolSide) -> Result<(u64, u64)> {
+        let (held_balance, held_proceeds) = (self.held_balance, self.held_proceeds);
+        pool_side.held_balance_total =
+            math::checked_sub(pool_side.held_balance_total, held_balance)?;
+        pool_side.held_proceeds_total =
+            math::checked_sub(pool_side.held_proceeds_total, held_proceeds)?;
+        self.held_balance = 0;
+        self.held_proceeds = 0;
+        Ok((held_balance, held_proceeds))
+    }
+
+    /// Moves held source tokens back to the pool and returns the amount released
+    pub fn release(
+        &mut self,
+        pool_side: &mut PoolSide,
+        schedule: &Schedule,
+        expiration_time: i64,
+        current_time: i64,
+    ) -> Result<u64> {
+        let amount = self.held_balance;
+        if amount == 0 {
+            return Ok(0);
+        }
+
+        self.held_balance = 0;
+        pool_side.held_balance_total = math::checked_sub(pool_side.held_balance_total, amount)?;
+        self.deposit(pool_side, amount, schedule, expiration_time, current_time)?;
+
+        Ok(amount)
+    }
 }
@@ -0,0 +1,287 @@
+use {
+    crate::{error::TwammError, state::order::OrderSide},
+    anchor_lang::{
+        prelude::*,
+        solana_program::{ed25519_program, sysvar::instructions},
+    },
+};
+
+// size of the signature offsets entry in ed25519 program instruction data
+const ED25519_OFFSETS_LEN: usize = 14;
+// offsets start after num_signatures and padding bytes
+const ED25519_OFFSETS_START: usize = 2;
+
+/// Order intent signed off-chain by the order owner, the signed message is
+/// the program id followed by the serialized intent
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct OrderIntent {
+    pub token_pair: Pubkey,
+    pub side: OrderSide,
+    pub time_in_force: u32,
+    pub amount: u64,
+    // unix time after which the intent can't be submitted
+    pub expiry: i64,
+    // used as the order id, each nonce can be submitted only once
+    pub nonce: u64,
+}
+
+/// Marks a submitted intent nonce to prevent replays, closed to the payer
+/// once the intent has expired and can't be submitted again
+#[account]
+#[derive(Default, Debug)]
+pub struct IntentNonce {
+    pub owner: Pubkey,
+    // relayer that paid rent for the account
+    pub payer: Pubkey,
+    pub nonce: u64,
+    // expiry of the submitted intent
+    pub expiry: i64,
+    pub time: i64,
+    pub bump: u8,
+}
+
+impl OrderIntent {
+    /// Returns the message the owner signs, prefixed with the program id so the
+    /// signature can't be submitted to another deployment
+    pub fn get_message(&self) -> Result<Vec<u8>> {
+        let mut message = crate::ID.to_bytes().to_vec();
+        message.extend_from_slice(&self.try_to_vec()?);
+        Ok(message)
+    }
+
+    /// Checks that the instruction preceding the current one is an ed25519 program
+    /// instruction that verified the owner's signature of this intent
+    pub fn verify_signature(
+        &self,
+        owner: &Pubkey,
+        instructions_sysvar: &AccountInfo,
+    ) -> Result<()> {
+        let current_index = instructions::load_current_index_checked(instructions_sysvar)?;
+        if current_index == 0 {
+            msg!("Error: Missing ed25519 signature verification instruction");
+            return err!(TwammError::InvalidIntentSignature);
+        }
+        let ix = instructions::load_instruction_at_checked(
+            (current_index - 1) as usize,
+            instructions_sysvar,
+        )?;
+        require_keys_eq!(
+            ix.program_id,
+            ed25519_program::ID,
+            TwammError::InvalidIntentSignature
+        );
+
+        // single signature with the key and the message stored in the same instruction
+        let data = &ix.data;
+        if data.len() < ED25519_OFFSETS_START + ED25519_OFFSETS_LEN || data[0] != 1 {
+            return err!(TwammError::InvalidIntentSignature);
+        }
+        let read_u16 = |pos: usize| -> usize {
+            u16::from_le_bytes([
+                data[ED25519_OFFSETS_START + pos],
+                data[ED25519_OFFSETS_START + pos + 1],
+            ]) as usize
+        };
+        let signature_ix_index = read_u16(2);
+        let public_key_offset = read_u16(4);
+        let public_key_ix_index = read_u16(6);
+        let message_offset = read_u16(8);
+        let message_size = read_u16(10);
+        let message_ix_index = read_u16(12);
+        let this_ix = u16::MAX as usize;
+        if signature_ix_index != this_ix
+            || public_key_ix_index != this_ix
+            || message_ix_index != this_ix
+        {
+            return err!(TwammError::InvalidIntentSignature);
+        }
+
+        let public_key = data
+            .get(public_key_offset..public_key_offset + 32)
+            .ok_or(TwammError::InvalidIntentSignature)?;
+        let message = data
+            .get(message_offset..message_offset + message_size)
+            .ok_or(TwammError::InvalidIntentSignature)?;
+        if public_key != owner.as_ref() || message != self.get_message()?.as_slice() {
+            msg!("Error: Signed message doesn't match the order intent");
+            return err!(TwammError::InvalidIntentSignature);
+        }
+
+        Ok(())
+    }
+}
+
+impl IntentNonce {
+    pub const LEN: usize = 8 + std::mem::size_of::<IntentNonce>();
+
+    /// Checks if the account can be closed, expired intents are rejected on submission
+    /// so the nonce is no longer needed to prevent replays
+    pub fn is_closable(&self, current_time: i64) -> bool {
+        current_time > self.expiry
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        anchor_lang::solana_program::sysvar::instructions::{
+            construct_instructions_data, store_current_index, BorrowedInstruction,
+        },
+    };
+
+    fn get_intent() -> OrderIntent {
+        OrderIntent {
+            token_pair: Pubkey::new_unique(),
+            side: OrderSide::Sell,
+            time_in_force: 300,
+            amount: 1000,
+            expiry: 500,
+            nonce: 7,
+        }
+    }
+
+    // returns ed25519 program instruction data with the key and the message inline
+    fn get_ed25519_data(public_key: &Pubkey, message: &[u8]) -> Vec<u8> {
+        let public_key_offset = ED25519_OFFSETS_START + ED25519_OFFSETS_LEN;
+        let signature_offset = public_key_offset + 32;
+        let message_offset = signature_offset + 64;
+        let mut data = vec![1u8, 0];
+        for value in [
+            signature_offset,
+            u16::MAX as usize,
+            public_key_offset,
+            u16::MAX as usize,
+            message_offset,
+            message.len(),
+            u16::MAX as usize,
+        ] {
+            data.extend_from_slice(&(value as u16).to_le_bytes());
+        }
+        data.extend_from_slice(public_key.as_ref());
+        data.extend_from_slice(&[0u8; 64]);
+        data.extend_from_slice(message);
+        data
+    }
+
+    // runs signature verification for the last of the given (program id, data) instructions
+    fn verify(intent: &OrderIntent, owner: &Pubkey, instructions: &[(Pubkey, Vec<u8>)]) -> bool {
+        let borrowed = instructions
+            .iter()
+            .map(|(program_id, data)| BorrowedInstruction {
+                program_id,
+                accounts: vec![],
+                data,
+            })
+            .collect::<Vec<_>>();
+        let mut data = construct_instructions_data(&borrowed);
+        store_current_index(&mut data, (instructions.len() - 1) as u16);
+
+        let key = instructions::ID;
+        let mut lamports = 0;
+        let account =
+            AccountInfo::new(&key, false, false, &mut lamports, &mut data, &key, false, 0);
+        intent.verify_signature(owner, &account).is_ok()
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let intent = get_intent();
+        let owner = Pubkey::new_unique();
+        let submit_ix = (crate::ID, vec![]);
+        let signature_ix = (
+            ed25519_program::ID,
+            get_ed25519_data(&owner, &intent.get_message().unwrap()),
+        );
+        assert!(verify(
+            &intent,
+            &owner,
+            &[signature_ix.clone(), submit_ix.clone()]
+        ));
+
+        // signed by another key
+        assert!(!verify(
+            &intent,
+            &Pubkey::new_unique(),
+            &[signature_ix.clone(), submit_ix.clone()]
+        ));
+
+        // signed message is a different intent
+        let other_intent = OrderIntent {
+            amount: 2000,
+            ..intent.clone()
+        };
+        assert!(!verify(
+            &other_intent,
+            &owner,
+            &[signature_ix.clone(), submit_ix.clone()]
+        ));
+
+        // signed message has no program id domain separator
+        let legacy_ix = (
+            ed25519_program::ID,
+            get_ed25519_data(&owner, &intent.try_to_vec().unwrap()),
+        );
+        assert!(!verify(&intent, &owner, &[legacy_ix, submit_ix.clone()]));
+
+        // missing or misplaced ed25519 instruction
+        assert!(!verify(&intent, &owner, std::slice::from_ref(&submit_ix)));
+        assert!(!verify(
+            &intent,
+            &owner,
+            &[
+                signature_ix.clone(),
+                (Pubkey::new_unique(), vec![]),
+                submit_ix.clone()
+            ]
+        ));
+        assert!(!verify(
+            &intent,
+            &owner,
+            &[(Pubkey::new_unique(), signature_ix.1.clone()), submit_ix]
+        ));
+    }
+
+    #[test]
+    fn test_replayed_signature() {
+        // each submitted nonce is recorded, a signature replayed for another nonce
+        // or another token pair doesn't match the intent
+        let intent = get_intent();
+        let owner = Pubkey::new_unique();
+        let instructions = [
+            (
+                ed25519_program::ID,
+                get_ed25519_data(&owner, &intent.get_message().unwrap()),
+            ),
+            (crate::ID, vec![]),
+        ];
+        assert!(verify(&intent, &owner, &instructions));
+        for replayed in [
+            OrderIntent {
+                nonce: 8,
+                ..intent.clone()
+            },
+            OrderIntent {
+                token_pair: Pubkey::new_unique(),
+                ..intent.clone()
+            },
+        ] {
+            assert!(!verify(&replayed, &owner, &instructions));
+        }
+    }
+
+    #[test]
+    fn test_intent_nonce_close() {
+        // the nonce is kept while the intent can still be submitted
+        let intent = get_intent();
+        let intent_nonce = IntentNonce {
+            payer: Pubkey::new_unique(),
+            nonce: intent.nonce,
+            expiry: intent.expiry,
+            ..Default::default()
+        };
+        assert!(!intent_nonce.is_closable(400));
+        assert!(!intent_nonce.is_closable(intent.expiry));
+        assert!(intent_nonce.is_closable(intent.expiry + 1));
+    }
+}
@@ -1,4 +1,11 @@
-use {crate::math, anchor_lang::prelude::*};
+use {
+    crate::{
+        error::TwammError,
+        math,
+        state::{order::OrderSide, token_pair::PoolSlot},
+    },
+    anchor_lang::prelude::*,
+};
 
 #[derive(Copy, Clone, Eq, PartialEq, AnchorSerialize, AnchorDeserialize, Debug)]
 pub enum PoolStatus {
@@ -23,78 +30,710 @@ impl std::fmt::Display for PoolStatus {
     }
 }
 
-#[derive(Copy, Clone, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+#[derive(Copy, Clone, Eq, PartialEq, AnchorSerialize, AnchorDeserialize, Debug)]
+pub enum ScheduleKind {
+    // equal release rate over the pool period
+    Linear,
+    // each period segment releases twice as much as the next one
+    FrontLoaded,
+    // each period segment releases twice as much as the previous one
+    BackLoaded,
+    // relative weights of equal period segments
+    Custom,
+}
+
+impl Default for ScheduleKind {
+    fn default() -> Self {
+        Self::Linear
+    }
+}
+
+#[zero_copy(unsafe)]
+#[repr(C, packed)]
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct ScheduleShape {
+    pub kind: ScheduleKind,
+    // only used by custom shapes
+    pub weights: [u16; 8], // Schedule::NUM_SEGMENTS
+}
+
+#[zero_copy(unsafe)]
+#[repr(C, packed)]
+#[derive(Default, Debug)]
+pub struct Schedule {
+    pub shape: ScheduleShape,
+    // time when the pool period starts
+    pub start_time: i64,
+}
+
+#[zero_copy(unsafe)]
+#[repr(C, packed)]
+#[derive(Default, Debug)]
+pub struct PendingStart {
+    // time when the tokens join the side, zero if the slot is free
+    pub start_time: i64,
+    pub balance: u64,
+    // orders waiting in the slot, it is freed once all of them have moved to the side
+    pub num_orders: u64,
+    // lp and debt amounts received when the slot joined the side
+    pub lp_amount: u64,
+    pub debt_amount: u64,
+    pub active: bool,
+}
+
+#[zero_copy(unsafe)]
+#[repr(C, packed)]
+#[derive(Default, Debug)]
 pub struct PoolSide {
     pub source_balance: u64,
     pub target_balance: u64,
     pub lp_supply: u64,
     pub token_debt_total: u64,
     pub fills_volume: u64,
-    pub weighted_fills_sum: f64,
-    pub min_fill_price: f64,
-    pub max_fill_price: f64,
+    // sum of fills volume multiplied by fill prices, prices have FILL_PRICE_EXPONENT
+    pub weighted_fills_sum: u128,
+    pub min_fill_price: u64,
+    pub max_fill_price: u64,
     pub num_traders: u64,
     pub settlement_debt_total: u64,
     pub last_balance_change_time: i64,
+    // source and target tokens of orders that are temporarily moved out of the pool
+    pub held_balance_total: u64,
+    pub held_proceeds_total: u64,
+    // target tokens left to acquire by exact-output orders, source balance holds their budget
+    pub target_pending: u64,
+    // budgets deposited by exact-output orders and debt in the spent part of them,
+    // spent budget is shared by lp like target_balance
+    pub budget_total: u64,
+    pub budget_debt_total: u64,
+    // lp balance moved to position tokens and token debt carried by them, every position
+    // token carries the same share of the debt, live positions count as one trader
+    pub position_supply: u64,
+    pub position_token_debt: u64,
+    // strictest budget prices of exact-output orders in the side with FILL_PRICE_EXPONENT,
+    // zero if not set, the side is not settled while the pair price is outside of them
+    pub min_price: u64,
+    pub max_price: u64,
+    // orders in the side that contribute to the bounds
+    pub num_bounded_orders: u64,
+    // source tokens of delayed orders, they are counted in held_balance_total until
+    // settlement moves them to the side at their start time
+    pub pending_starts: [PendingStart; 4], // PoolSide::MAX_PENDING_STARTS
 }
 
-#[account]
+#[account(zero_copy(unsafe))]
+#[repr(C, packed)]
 #[derive(Default, Debug)]
 pub struct Pool {
+    // account layout version, older accounts are converted with migrate_account
+    pub version: u8,
     pub status: PoolStatus,
     pub time_in_force: u32,
     pub expiration_time: i64,
     pub token_pair: Pubkey,
     pub buy_side: PoolSide,
     pub sell_side: PoolSide,
+    // sides of exact-output orders with lp denominated in target tokens
+    pub buy_output_side: PoolSide,
+    pub sell_output_side: PoolSide,
     pub counter: u64,
+    // shape of the source tokens release over the pool period
+    pub schedule: Schedule,
     pub bump: u8,
 }
 
+impl ScheduleShape {
+    pub fn new(kind: ScheduleKind, weights: [u16; Schedule::NUM_SEGMENTS]) -> Self {
+        Self { kind, weights }
+    }
+
+    /// Checks if the shape releases tokens in every period
+    pub fn validate(&self) -> bool {
+        let weights = self.weights;
+        self.kind != ScheduleKind::Custom || weights.iter().any(|&weight| weight > 0)
+    }
+}
+
+impl Schedule {
+    pub const NUM_SEGMENTS: usize = 8;
+
+    /// Returns the amount released from the given time till the end time and
+    /// the part of it released by the current time, in the same units
+    pub fn get_release_share(
+        &self,
+        end_time: i64,
+        from_time: i64,
+        current_time: i64,
+    ) -> Result<(u128, u128)> {
+        if self.shape.kind == ScheduleKind::Linear {
+            return Ok((
+                math::checked_sub(current_time, from_time)? as u128,
+                math::checked_sub(end_time, from_time)? as u128,
+            ));
+        }
+        let released_before = self.get_released_weight(end_time, from_time)?;
+        let released = math::checked_sub(
+            self.get_released_weight(end_time, current_time)?,
+            released_before,
+        )?;
+        let remaining = math::checked_sub(
+            self.get_released_weight(end_time, end_time)?,
+            released_before,
+        )?;
+        if remaining == 0 {
+            // nothing is left to release on schedule, release the rest at once
+            return Ok((1, 1));
+        }
+        Ok((released, remaining))
+    }
+
+    fn get_weights(&self) -> [u64; Schedule::NUM_SEGMENTS] {
+        let mut res = [0u64; Schedule::NUM_SEGMENTS];
+        let shape = self.shape;
+        for (i, weight) in res.iter_mut().enumerate() {
+            *weight = match shape.kind {
+                ScheduleKind::Linear => 1,
+                ScheduleKind::FrontLoaded => 1 << (Schedule::NUM_SEGMENTS - 1 - i),
+                ScheduleKind::BackLoaded => 1 << i,
+                ScheduleKind::Custom => shape.weights[i] as u64,
+            };
+        }
+        res
+    }
+
+    /// Returns the weighted time elapsed from the start of the period
+    fn get_released_weight(&self, end_time: i64, time: i64) -> Result<u128> {
+        if end_time <= self.start_time {
+            return Ok(0);
+        }
+        let duration = math::checked_sub(end_time, self.start_time)? as u128;
+        let elapsed =
+            math::checked_sub(num::clamp(time, self.start_time, end_time), self.start_time)?
+                as u128;
+        let mut res = 0u128;
+        for (i, weight) in self.get_weights().iter().enumerate() {
+            let segment_start = math::checked_div(
+                math::checked_mul(duration, i as u128)?,
+                Schedule::NUM_SEGMENTS as u128,
+            )?;
+            let segment_end = math::checked_div(
+                math::checked_mul(duration, i as u128 + 1)?,
+                Schedule::NUM_SEGMENTS as u128,
+            )?;
+            if elapsed <= segment_start {
+                break;
+            }
+            let segment_elapsed =
+                math::checked_sub(std::cmp::min(elapsed, segment_end), segment_start)?;
+            res = math::checked_add(res, math::checked_mul(segment_elapsed, *weight as u128)?)?;
+        }
+        Ok(res)
+    }
+}
+
 impl PoolSide {
+    pub const MAX_PENDING_STARTS: usize = 4;
+
     /// Returns unsettled amounts of tokens at the given time
-    pub fn get_unsettled_amount(&self, expiration_time: i64, current_time: i64) -> Result<u64> {
+    pub fn get_unsettled_amount(
+        &self,
+        schedule: &Schedule,
+        expiration_time: i64,
+        current_time: i64,
+    ) -> Result<u64> {
+        self.get_released_amount(self.source_balance, schedule, expiration_time, current_time)
+    }
+
+    /// Returns unsettled amounts of target tokens of exact-output sides at the given time
+    pub fn get_unsettled_target_amount(
+        &self,
+        schedule: &Schedule,
+        expiration_time: i64,
+        current_time: i64,
+    ) -> Result<u64> {
+        self.get_released_amount(self.target_pending, schedule, expiration_time, current_time)
+    }
+
+    fn get_released_amount(
+        &self,
+        balance: u64,
+        schedule: &Schedule,
+        expiration_time: i64,
+        current_time: i64,
+    ) -> Result<u64> {
         if current_time < self.last_balance_change_time {
-            return Ok(std::cmp::min(
-                self.settlement_debt_total,
-                self.source_balance,
-            ));
+            return Ok(std::cmp::min(self.settlement_debt_total, balance));
         }
         // adjust expiration time to increase chances of pool completion before the expiration
         let adjusted_expiration_time = math::checked_sub(expiration_time, 30)?;
         if current_time >= adjusted_expiration_time {
-            return Ok(self.source_balance);
+            return Ok(balance);
         }
 
-        let time_till_expiration = math::checked_sub(adjusted_expiration_time, current_time)?;
-        let time_since_balance_change =
-            math::checked_sub(current_time, self.last_balance_change_time)?;
+        let (released, remaining) = schedule.get_release_share(
+            adjusted_expiration_time,
+            self.last_balance_change_time,
+            current_time,
+        )?;
 
         Ok(std::cmp::min(
             math::checked_as_u64(math::checked_add(
-                math::checked_div(
-                    math::checked_mul(
-                        self.source_balance as u128,
-                        time_since_balance_change as u128,
-                    )?,
-                    math::checked_add(time_till_expiration, time_since_balance_change)? as u128,
-                )?,
+                math::checked_div(math::checked_mul(balance as u128, released)?, remaining)?,
                 self.settlement_debt_total as u128,
             )?)?,
-            self.source_balance,
+            balance,
         ))
     }
+
+    /// Adds source tokens to the pool side and returns lp and debt amounts to be
+    /// credited to the order
+    pub fn deposit(
+        &mut self,
+        amount: u64,
+        schedule: &Schedule,
+        expiration_time: i64,
+        current_time: i64,
+    ) -> Result<(u64, u64)> {
+        // compute lp and debt amounts
+        let lp_amount;
+        let debt_amount;
+        if self.source_balance == 0 {
+            if self.target_balance != 0 && self.num_traders != 0 {
+                msg!("Error: Attempt to deposit into finalized pool");
+                return err!(TwammError::InvalidPoolState);
+            }
+            lp_amount = amount;
+            debt_amount = 0;
+        } else {
+            lp_amount = math::checked_as_u64(math::checked_div(
+                math::checked_mul(amount as u128, self.lp_supply as u128)?,
+                self.source_balance as u128,
+            )?)?;
+            debt_amount = math::checked_as_u64(math::checked_ceil_div(
+                math::checked_mul(
+                    amount as u128,
+                    math::checked_add(self.target_balance as u128, self.token_debt_total as u128)?,
+                )?,
+                self.source_balance as u128,
+            )?)?;
+        }
+
+        // update balances
+        self.settlement_debt_total =
+            self.get_unsettled_amount(schedule, expiration_time, current_time)?;
+        self.last_balance_change_time = current_time;
+        self.source_balance = math::checked_add(self.source_balance, amount)?;
+        self.lp_supply = math::checked_add(self.lp_supply, lp_amount)?;
+        self.token_debt_total = math::checked_add(self.token_debt_total, debt_amount)?;
+
+        Ok((lp_amount, debt_amount))
+    }
+
+    /// Returns filled tokens the lp amount is entitled to net of the given token debt
+    pub fn get_proceeds(&self, lp_amount: u64, token_debt: u64) -> Result<u64> {
+        if lp_amount == 0 {
+            return Ok(0);
+        }
+        Ok(math::checked_as_u64(math::checked_div(
+            math::checked_mul(
+                lp_amount as u128,
+                math::checked_add(self.target_balance as u128, self.token_debt_total as u128)?,
+            )?,
+            self.lp_supply as u128,
+        )?)?
+        .saturating_sub(token_debt))
+    }
+
+    /// Returns token debt carried by the given amount of position tokens
+    pub fn get_position_debt(&self, amount: u64) -> Result<u64> {
+        if self.position_supply == 0 {
+            return Ok(0);
+        }
+        Ok(std::cmp::min(
+            math::checked_as_u64(math::checked_ceil_div(
+                math::checked_mul(amount as u128, self.position_token_debt as u128)?,
+                self.position_supply as u128,
+            )?)?,
+            self.position_token_debt,
+        ))
+    }
+
+    /// Moves lp amount with its token debt to position tokens and returns target tokens
+    /// released to and owed by the order, position tokens carry the same debt per unit
+    /// and the difference with the order's debt is settled in target tokens
+    pub fn add_position(&mut self, lp_amount: u64, token_debt: u64) -> Result<(u64, u64)> {
+        // rounded down, so the order is not released more than its share or owes less
+        let position_debt = if self.position_supply == 0 {
+            self.num_traders = math::checked_add(self.num_traders, 1)?;
+            token_debt
+        } else {
+            math::checked_as_u64(math::checked_div(
+                math::checked_mul(lp_amount as u128, self.position_token_debt as u128)?,
+                self.position_supply as u128,
+            )?)?
+        };
+        self.position_supply = math::checked_add(self.position_supply, lp_amount)?;
+        self.position_token_debt = math::checked_add(self.position_token_debt, position_debt)?;
+
+        // filled tokens per lp stay the same for the rest of the side
+        if position_debt >= token_debt {
+            let released = math::checked_sub(position_debt, token_debt)?;
+            self.token_debt_total = math::checked_add(self.token_debt_total, released)?;
+            self.target_balance = math::checked_sub(self.target_balance, released)?;
+            Ok((released, 0))
+        } else {
+            let owed = math::checked_sub(token_debt, position_debt)?;
+            self.token_debt_total = math::checked_sub(self.token_debt_total, owed)?;
+            self.target_balance = math::checked_add(self.target_balance, owed)?;
+            Ok((0, owed))
+        }
+    }
+
+    /// Removes position tokens from the pool side and returns source and target tokens
+    /// they are redeemed for
+    pub fn redeem_position(
+        &mut self,
+        amount: u64,
+        schedule: &Schedule,
+        expiration_time: i64,
+        current_time: i64,
+    ) -> Result<(u64, u64)> {
+        if amount == 0 {
+            return Ok((0, 0));
+        }
+        if amount > self.position_supply || amount > self.lp_supply {
+            msg!("Error: Position amount {} exceeds supply {}", amount, {
+                self.position_supply
+            });
+            return err!(TwammError::InvalidTokenAmount);
+        }
+
+        // compute balance changes
+        let debt_removed = self.get_position_debt(amount)?;
+        let source_amount = math::checked_as_u64(math::checked_div(
+            math::checked_mul(amount as u128, self.source_balance as u128)?,
+            self.lp_supply as u128,
+        )?)?;
+        let target_amount = std::cmp::min(
+            self.get_proceeds(amount, debt_removed)?,
+            self.target_balance,
+        );
+
+        // position tokens hold a pro-rata share of the side's unsettled amount
+        let unsettled_amount =
+            self.get_unsettled_amount(schedule, expiration_time, current_time)?;
+        let settlement_debt_removed = math::checked_as_u64(math::checked_div(
+            math::checked_mul(unsettled_amount as u128, amount as u128)?,
+            self.lp_supply as u128,
+        )?)?;
+
+        // update balances
+        self.source_balance = math::checked_sub(self.source_balance, source_amount)?;
+        self.target_balance = math::checked_sub(self.target_balance, target_amount)?;
+        self.lp_supply = math::checked_sub(self.lp_supply, amount)?;
+        self.token_debt_total = math::checked_sub(self.token_debt_total, debt_removed)?;
+        self.position_supply = math::checked_sub(self.position_supply, amount)?;
+        self.position_token_debt = math::checked_sub(self.position_token_debt, debt_removed)?;
+        self.settlement_debt_total = unsettled_amount.saturating_sub(settlement_debt_removed);
+        self.last_balance_change_time = current_time;
+        if self.position_supply == 0 {
+            self.num_traders = math::checked_sub(self.num_traders, 1)?;
+        }
+
+        Ok((source_amount, target_amount))
+    }
+
+    /// Adds target tokens to acquire to the exact-output side with the order's own budget
+    /// and returns lp, token debt and budget debt amounts to be credited to the order
+    pub fn deposit_target(
+        &mut self,
+        target_amount: u64,
+        budget: u64,
+        schedule: &Schedule,
+        expiration_time: i64,
+        current_time: i64,
+    ) -> Result<(u64, u64, u64)> {
+        // compute lp and debt amounts
+        let lp_amount;
+        let debt_amount;
+        let budget_debt;
+        if self.target_pending == 0 {
+            if self.lp_supply != 0 {
+                msg!("Error: Attempt to deposit into filled pool side");
+                return err!(TwammError::InvalidPoolState);
+            }
+            lp_amount = target_amount;
+            debt_amount = 0;
+            budget_debt = 0;
+        } else {
+            lp_amount = math::checked_as_u64(math::checked_div(
+                math::checked_mul(target_amount as u128, self.lp_supply as u128)?,
+                self.target_pending as u128,
+            )?)?;
+            debt_amount = math::checked_as_u64(math::checked_ceil_div(
+                math::checked_mul(
+                    target_amount as u128,
+                    math::checked_add(self.target_balance as u128, self.token_debt_total as u128)?,
+                )?,
+                self.target_pending as u128,
+            )?)?;
+            budget_debt = math::checked_as_u64(math::checked_ceil_div(
+                math::checked_mul(
+                    target_amount as u128,
+                    math::checked_add(
+                        self.get_budget_spent() as u128,
+                        self.budget_debt_total as u128,
+                    )?,
+                )?,
+                self.target_pending as u128,
+            )?)?;
+        }
+        if lp_amount == 0 || budget == 0 {
+            return err!(TwammError::InvalidTokenAmount);
+        }
+
+        // update balances
+        self.settlement_debt_total =
+            self.get_unsettled_target_amount(schedule, expiration_time, current_time)?;
+        self.last_balance_change_time = current_time;
+        self.target_pending = math::checked_add(self.target_pending, target_amount)?;
+        self.source_balance = math::checked_add(self.source_balance, budget)?;
+        self.budget_total = math::checked_add(self.budget_total, budget)?;
+        self.lp_supply = math::checked_add(self.lp_supply, lp_amount)?;
+        self.token_debt_total = math::checked_add(self.token_debt_total, debt_amount)?;
+        self.budget_debt_total = math::checked_add(self.budget_debt_total, budget_debt)?;
+
+        Ok((lp_amount, debt_amount, budget_debt))
+    }
+
+    /// Returns budget of exact-output orders spent by settlements
+    pub fn get_budget_spent(&self) -> u64 {
+        self.budget_total.saturating_sub(self.source_balance)
+    }
+
+    /// Records a fill of source tokens at the given price with FILL_PRICE_EXPONENT
+    pub fn update_fill_stats(&mut self, fill_volume: u64, fill_price: u64) -> Result<()> {
+        self.weighted_fills_sum = math::checked_add(
+            self.weighted_fills_sum,
+            math::checked_mul(fill_volume as u128, fill_price as u128)?,
+        )?;
+        self.fills_volume = math::checked_add(self.fills_volume, fill_volume)?;
+        if self.min_fill_price == 0 || fill_price < self.min_fill_price {
+            self.min_fill_price = fill_price;
+        }
+        if self.max_fill_price == 0 || fill_price > self.max_fill_price {
+            self.max_fill_price = fill_price;
+        }
+        Ok(())
+    }
+
+    /// Adds order price bounds to the side, bounds only get stricter until
+    /// the last bounded order leaves
+    pub fn add_price_bounds(&mut self, min_price: u64, max_price: u64) -> Result<()> {
+        if min_price > self.min_price {
+            self.min_price = min_price;
+        }
+        if max_price > 0 && (self.max_price == 0 || max_price < self.max_price) {
+            self.max_price = max_price;
+        }
+        self.num_bounded_orders = math::checked_add(self.num_bounded_orders, 1)?;
+        Ok(())
+    }
+
+    /// Removes a bounded order from the side and clears bounds once none are left
+    pub fn remove_price_bounds(&mut self) -> Result<()> {
+        self.num_bounded_orders = math::checked_sub(self.num_bounded_orders, 1)?;
+        if self.num_bounded_orders == 0 {
+            self.min_price = 0;
+            self.max_price = 0;
+        }
+        Ok(())
+    }
+
+    /// Checks if the side can be settled at the given price with FILL_PRICE_EXPONENT
+    pub fn is_price_acceptable(&self, fill_price: u64) -> bool {
+        fill_price >= self.min_price && (self.max_price == 0 || fill_price <= self.max_price)
+    }
+
+    /// Returns the pending start slot assigned to the given time
+    pub fn get_pending_start_mut(&mut self, start_time: i64) -> Result<&mut PendingStart> {
+        let pending_starts = self.pending_starts;
+        let index = pending_starts
+            .iter()
+            .position(|slot| slot.start_time == start_time)
+            .ok_or_else(|| {
+                msg!("Error: No pending start slot for time {}", start_time);
+                TwammError::InvalidPoolState
+            })?;
+        Ok(&mut self.pending_starts[index])
+    }
+
+    /// Adds source tokens that join the side at the given start time, returns false
+    /// if all slots are taken by other start times or the slot has already joined.
+    /// Tokens are added to held_balance_total by the caller.
+    pub fn add_pending_start(
+        &mut self,
+        start_time: i64,
+        amount: u64,
+        new_order: bool,
+    ) -> Result<bool> {
+        let pending_starts = self.pending_starts;
+        let index = if let Some(index) = pending_starts
+            .iter()
+            .position(|slot| slot.start_time == start_time)
+        {
+            index
+        } else if let Some(index) = pending_starts.iter().position(|slot| slot.start_time == 0) {
+            self.pending_starts[index] = PendingStart {
+                start_time,
+                ..Default::default()
+            };
+            index
+        } else {
+            return Ok(false);
+        };
+        let slot = &mut self.pending_starts[index];
+        if slot.active {
+            return Ok(false);
+        }
+        slot.balance = math::checked_add(slot.balance, amount)?;
+        if new_order {
+            slot.num_orders = math::checked_add(slot.num_orders, 1)?;
+        }
+        Ok(true)
+    }
+
+    /// Removes source tokens of an order that leaves before its slot joined the side
+    pub fn remove_pending_start(&mut self, start_time: i64, amount: u64) -> Result<()> {
+        let slot = self.get_pending_start_mut(start_time)?;
+        if slot.active {
+            msg!("Error: Pending start slot has already joined the side");
+            return err!(TwammError::InvalidPoolState);
+        }
+        slot.balance = math::checked_sub(slot.balance, amount)?;
+        slot.num_orders = math::checked_sub(slot.num_orders, 1)?;
+        if slot.num_orders == 0 {
+            *slot = PendingStart::default();
+        }
+        Ok(())
+    }
+
+    /// Moves pending tokens that reached their start time to the side, the part
+    /// released since the start time becomes settlement debt
+    pub fn activate_pending_starts(
+        &mut self,
+        schedule: &Schedule,
+        expiration_time: i64,
+        current_time: i64,
+    ) -> Result<()> {
+        for index in 0..PoolSide::MAX_PENDING_STARTS {
+            let slot = self.pending_starts[index];
+            if slot.start_time == 0
+                || slot.active
+                || slot.start_time > current_time
+                || current_time >= expiration_time
+            {
+                continue;
+            }
+            if self.source_balance == 0 && self.target_balance != 0 {
+                // orders can still join the side one by one with refresh_order
+                msg!("Pending start slot can't join the sold out side");
+                continue;
+            }
+
+            let (lp_amount, debt_amount) =
+                self.deposit(slot.balance, schedule, expiration_time, current_time)?;
+            let adjusted_expiration_time = math::checked_sub(expiration_time, 30)?;
+            let released_amount = if current_time >= adjusted_expiration_time {
+                slot.balance
+            } else {
+                let (released, remaining) = schedule.get_release_share(
+                    adjusted_expiration_time,
+                    slot.start_time,
+                    current_time,
+                )?;
+                math::checked_as_u64(math::checked_div(
+                    math::checked_mul(slot.balance as u128, released)?,
+                    remaining,
+                )?)?
+            };
+            self.settlement_debt_total = std::cmp::min(
+                math::checked_add(self.settlement_debt_total, released_amount)?,
+                self.source_balance,
+            );
+            self.held_balance_total = math::checked_sub(self.held_balance_total, slot.balance)?;
+
+            let slot = &mut self.pending_starts[index];
+            slot.lp_amount = lp_amount;
+            slot.debt_amount = debt_amount;
+            slot.active = true;
+        }
+        Ok(())
+    }
+
+    /// Checks if the exact-output side has acquired its target or spent its budget
+    pub fn is_target_settled(&self) -> bool {
+        self.source_balance == 0 || self.target_pending == 0
+    }
 }
 
+const _: () = assert!(Pool::LEN == 8 + std::mem::size_of::<Pool>());
+
 impl Pool {
-    pub const LEN: usize = 8 + std::mem::size_of::<Pool>();
+    pub const VERSION: u8 = 1;
+    pub const LEN: usize = 8 + 1440;
+
+    /// Initializes the current pool of the time in force slot, it starts now and
+    /// expires after the time in force
+    pub fn init_current(
+        &mut self,
+        slot: &mut PoolSlot,
+        token_pair_key: Pubkey,
+        current_time: i64,
+        bump: u8,
+    ) -> Result<()> {
+        self.version = Pool::VERSION;
+        self.status = PoolStatus::Active;
+        self.time_in_force = slot.time_in_force;
+        self.expiration_time = math::checked_add(current_time, self.time_in_force as i64)?;
+        self.token_pair = token_pair_key;
+        self.counter = slot.pool_counter;
+        self.schedule = Schedule {
+            shape: slot.schedule_shape,
+            start_time: current_time,
+        };
+        self.bump = bump;
+        slot.current_pool_present = true;
+
+        Ok(())
+    }
 
     /// Checks if the pool is empty
     pub fn is_empty(&self) -> bool {
         self.buy_side.source_balance == 0
             && self.buy_side.target_balance == 0
+            && self.buy_side.held_balance_total == 0
+            && self.buy_side.held_proceeds_total == 0
             && self.sell_side.source_balance == 0
             && self.sell_side.target_balance == 0
+            && self.sell_side.held_balance_total == 0
+            && self.sell_side.held_proceeds_total == 0
+            && self.buy_output_side.source_balance == 0
+            && self.buy_output_side.target_balance == 0
+            && self.sell_output_side.source_balance == 0
+            && self.sell_output_side.target_balance == 0
+            && self.buy_side.position_supply == 0
+            && self.sell_side.position_supply == 0
+    }
+
+    /// Returns the pool side that holds orders of the given side and type
+    pub fn get_side_mut(&mut self, side: OrderSide, exact_output: bool) -> &mut PoolSide {
+        match (side, exact_output) {
+            (OrderSide::Buy, false) => &mut self.buy_side,
+            (OrderSide::Sell, false) => &mut self.sell_side,
+            (OrderSide::Buy, true) => &mut self.buy_output_side,
+            (OrderSide::Sell, true) => &mut self.sell_output_side,
+        }
     }
 
     /// Checks if the pool is expired
@@ -103,19 +742,22 @@ impl Pool {
     }
 
     /// Checks if the pool is locked and doesn't accept new orders
-    pub fn is_locked(&self, min_time_till_expiration: f64, current_time: i64) -> Result<bool> {
+    pub fn is_locked(&self, min_time_till_expiration: u64, current_time: i64) -> Result<bool> {
         if self.status == PoolStatus::Locked || self.is_expired(current_time)? {
             return Ok(true);
         }
         let tte = math::checked_sub(self.expiration_time, current_time)?;
-        let tte_perc = math::checked_float_div(tte as f64, self.time_in_force as f64)?;
+        let tte_perc = math::checked_ratio(tte as u64, self.time_in_force as u64)?;
         Ok(tte_perc <= min_time_till_expiration)
     }
 
     /// Checks if the pools is finalized, i.e., expired and complete
     pub fn is_complete(&self, current_time: i64) -> Result<bool> {
         Ok(self.is_expired(current_time)?
-            && ((self.buy_side.source_balance == 0 && self.sell_side.source_balance == 0)
+            && ((self.buy_side.source_balance == 0
+                && self.sell_side.source_balance == 0
+                && self.buy_output_side.is_target_settled()
+                && self.sell_output_side.is_target_settled())
                 || current_time
                     > math::checked_add(
                         self.expiration_time,
@@ -126,7 +768,7 @@ impl Pool {
     /// Updates pool state and returns the updated value
     pub fn update_state(
         &mut self,
-        min_time_till_expiration: f64,
+        min_time_till_expiration: u64,
         current_time: i64,
     ) -> Result<PoolStatus> {
         if self.is_locked(min_time_till_expiration, current_time)? {
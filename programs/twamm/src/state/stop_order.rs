@@ -0,0 +1,39 @@
+use {
+    crate::{oracle::OraclePrice, state::order::OrderSide},
+    anchor_lang::prelude::*,
+};
+
+#[account]
+#[derive(Default, Debug)]
+pub struct StopOrder {
+    pub owner: Pubkey,
+    // user-chosen id, the order is placed with the same order id once triggered
+    pub id: u64,
+    pub token_pair: Pubkey,
+    pub time: i64,
+    pub side: OrderSide,
+    pub time_in_force: u32,
+    // escrowed source tokens
+    pub amount: u64,
+    // pair price that places the order into the current pool once crossed
+    pub trigger_price: OraclePrice,
+    // conditions of the placed order, zero if not set
+    pub limit_price: OraclePrice,
+    pub max_price_deviation: u64,
+    pub bump: u8,
+}
+
+impl StopOrder {
+    pub const LEN: usize = 8 + std::mem::size_of::<StopOrder>();
+
+    /// Checks if the given token pair price crossed the trigger price,
+    /// sell orders trigger when the price falls and buy orders when it rises
+    pub fn is_trigger_reached(&self, pair_price: &OraclePrice) -> Result<bool> {
+        let ordering = pair_price.checked_cmp(&self.trigger_price)?;
+        if self.side == OrderSide::Buy {
+            Ok(ordering.is_ge())
+        } else {
+            Ok(ordering.is_le())
+        }
+    }
+}
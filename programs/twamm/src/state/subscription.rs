@@ -0,0 +1,42 @@
+use {
+    crate::{math, state::order::OrderSide},
+    anchor_lang::prelude::*,
+};
+
+#[account]
+#[derive(Default, Debug)]
+pub struct Subscription {
+    pub owner: Pubkey,
+    // user-chosen id, orders are placed with the same order id
+    pub id: u64,
+    pub token_pair: Pubkey,
+    pub time: i64,
+    pub side: OrderSide,
+    pub time_in_force: u32,
+    pub amount_per_period: u64,
+    // source tokens paid to the keeper for each placed order
+    pub keeper_bounty: u64,
+    pub periods_remaining: u32,
+    pub periods_placed: u32,
+    // counter of the last pool the subscription placed an order to
+    pub last_pool_counter: u64,
+    pub bump: u8,
+}
+
+impl Subscription {
+    pub const LEN: usize = 8 + std::mem::size_of::<Subscription>();
+
+    /// Returns source tokens escrowed for the remaining periods including bounties
+    pub fn get_escrowed_amount(&self) -> Result<u64> {
+        math::checked_mul(
+            math::checked_add(self.amount_per_period, self.keeper_bounty)?,
+            self.periods_remaining as u64,
+        )
+    }
+
+    /// Checks if the subscription can place an order into the pool with the given counter
+    pub fn is_due(&self, pool_counter: u64) -> bool {
+        self.periods_remaining > 0
+            && (self.periods_placed == 0 || pool_counter > self.last_pool_counter)
+    }
+}
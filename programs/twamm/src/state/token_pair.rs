@@ -6,18 +6,41 @@ use {
         math, oracle,
         oracle::{OraclePrice, OracleType},
         state,
-        state::pool::{Pool, PoolSide},
+        state::{
+            order::OrderSide,
+            pool::{Pool, PoolSide, ScheduleShape},
+            virtual_pool::VirtualPool,
+        },
     },
-    anchor_lang::prelude::*,
-    anchor_spl::token::Transfer,
+    anchor_lang::{prelude::*, solana_program::program_pack::Pack},
+    anchor_spl::token_interface::{
+        spl_token_2022::{
+            self,
+            extension::{
+                transfer_fee::TransferFeeConfig, BaseStateWithExtensions, StateWithExtensions,
+            },
+        },
+        CloseAccount, InitializeAccount3, MintTo, SyncNative, TransferChecked,
+    },
+    std::cell::{Ref, RefMut},
 };
 
-#[derive(Copy, Clone, AnchorSerialize, AnchorDeserialize, Default)]
+// current pools, optional virtual pool, and router program
+pub type LoadedPools<'a> = (
+    Vec<AccountLoader<'a, Pool>>,
+    Option<Account<'a, VirtualPool>>,
+    Pubkey,
+);
+
+#[zero_copy(unsafe)]
+#[repr(C, packed)]
+#[derive(Default)]
 pub struct TokenConfig {
     pub crank_reward: u64,
     pub min_swap_amount: u64,
 
-    pub max_oracle_price_error: f64,
+    // maximum oracle confidence interval relative to the price, fixed-point ratio
+    pub max_oracle_price_error: u64,
     pub max_oracle_price_age_sec: u32,
     pub oracle_type: OracleType,
     pub oracle_account: Pubkey,
@@ -27,7 +50,9 @@ pub struct TokenConfig {
     pub decimals: u8,
 }
 
-#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default)]
+#[zero_copy(unsafe)]
+#[repr(C, packed)]
+#[derive(PartialEq, Default)]
 pub struct TokenStats {
     pub pending_withdrawals: u64,
     pub fees_collected: u64,
@@ -46,6 +71,14 @@ pub struct Settlement {
     pub settlement_side: MatchingSide,
 }
 
+// unsettled amounts per pool side, proceeds are only capped for exact-output sides
+struct Outstanding {
+    sell: Vec<u64>,
+    buy: Vec<u64>,
+    sell_proceeds_cap: Vec<u64>,
+    buy_proceeds_cap: Vec<u64>,
+}
+
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub enum SettlementType {
     Crank,
@@ -75,9 +108,13 @@ impl std::fmt::Display for MatchingSide {
     }
 }
 
-#[account]
+#[account(zero_copy(unsafe))]
+#[repr(C, packed)]
 #[derive(Default)]
 pub struct TokenPair {
+    // account layout version, older accounts are converted with migrate_account
+    pub version: u8,
+
     pub allow_deposits: bool,
     pub allow_withdrawals: bool,
     pub allow_cranks: bool,
@@ -91,14 +128,16 @@ pub struct TokenPair {
     pub settle_fee_numerator: u64,
     pub settle_fee_denominator: u64,
 
+    // limits below are fixed-point ratios with math::RATIO_DECIMALS
+
     // maximum trade price difference versus oracle price
-    pub max_swap_price_diff: f64,
+    pub max_swap_price_diff: u64,
 
     // maximum settle amount difference with value calculated off-chain
-    pub max_unsettled_amount: f64,
+    pub max_unsettled_amount: u64,
 
     // minimum time till expiration when new orders are still accepted
-    pub min_time_till_expiration: f64,
+    pub min_time_till_expiration: u64,
 
     pub crank_authority: Pubkey,
 
@@ -108,14 +147,9 @@ pub struct TokenPair {
     pub stats_a: TokenStats,
     pub stats_b: TokenStats,
 
-    // supported time in force intervals
-    pub tifs: [u32; 10], // TokenPair::MAX_POOLS
-
-    // counters to keep track of pool seeds
-    pub pool_counters: [u64; 10], // TokenPair::MAX_POOLS
-
-    pub current_pool_present: [bool; 10], // TokenPair::MAX_POOLS
-    pub future_pool_present: [bool; 10],  // TokenPair::MAX_POOLS
+    // number of time in force slots, they follow the header in the account,
+    // which is resized by set_pool_slots
+    pub num_slots: u8,
 
     pub token_pair_bump: u8,
     pub transfer_authority_bump: u8,
@@ -124,66 +158,482 @@ pub struct TokenPair {
     pub inception_time: i64,
 }
 
+// time in force slot, TokenPair::num_slots of them follow the token pair header
+#[zero_copy(unsafe)]
+#[repr(C, packed)]
+#[derive(Default, Debug)]
+pub struct PoolSlot {
+    // supported time in force interval, zero if the slot is not used
+    pub time_in_force: u32,
+
+    // release schedule shape of new pools
+    pub schedule_shape: ScheduleShape,
+
+    // counter to keep track of pool seeds
+    pub pool_counter: u64,
+
+    pub current_pool_present: bool,
+    pub future_pool_present: bool,
+}
+
+// slots are cast from account data the same way zero-copy accounts are
+unsafe impl bytemuck::Pod for PoolSlot {}
+unsafe impl bytemuck::Zeroable for PoolSlot {}
+
+const _: () = assert!(TokenPair::LEN == 8 + std::mem::size_of::<TokenPair>());
+const _: () = assert!(PoolSlot::LEN == std::mem::size_of::<PoolSlot>());
+
+impl PoolSlot {
+    pub const LEN: usize = 31;
+
+    /// Checks if the slot never had pools and can be removed
+    pub fn is_unused(&self) -> bool {
+        self.pool_counter == 0 && !self.current_pool_present && !self.future_pool_present
+    }
+}
+
 impl TokenPair {
-    pub const LEN: usize = 8 + std::mem::size_of::<TokenPair>();
-    pub const MAX_POOLS: usize = 10;
+    pub const VERSION: u8 = 1;
+    pub const LEN: usize = 8 + 436;
+    // crank and settle take up to MAX_POOLS pools and a virtual pool as remaining
+    // accounts next to 14 fixed ones, the rest of the 64 account locks per transaction
+    // is left for the router accounts of the swap
+    pub const MAX_POOLS: usize = 16;
+    // Token-2022 extension types: Uninitialized, TransferFeeConfig, MetadataPointer, TokenMetadata
+    const UNINITIALIZED_EXTENSION: u16 = 0;
+    const ALLOWED_MINT_EXTENSIONS: [u16; 3] = [1, 18, 19];
+
+    /// Returns account size for the given number of time in force slots
+    pub fn get_len(num_slots: usize) -> usize {
+        TokenPair::LEN + num_slots * PoolSlot::LEN
+    }
+
+    /// Loads the token pair header and the time in force slots that follow it
+    pub fn load_slots<'a>(
+        account: &'a AccountLoader<TokenPair>,
+    ) -> Result<(Ref<'a, TokenPair>, Ref<'a, [PoolSlot]>)> {
+        let num_slots = account.load()?.get_num_slots();
+        let data = account.as_ref().try_borrow_data()?;
+        if data.len() < TokenPair::get_len(num_slots) {
+            return Err(ProgramError::InvalidAccountData.into());
+        }
+        Ok(Ref::map_split(data, |data| {
+            let (header, slots) = data[8..].split_at(TokenPair::LEN - 8);
+            (
+                bytemuck::from_bytes(header),
+                bytemuck::cast_slice(&slots[..num_slots * PoolSlot::LEN]),
+            )
+        }))
+    }
+
+    /// Loads the token pair header and the time in force slots that follow it for writing
+    pub fn load_slots_mut<'a>(
+        account: &'a AccountLoader<TokenPair>,
+    ) -> Result<(RefMut<'a, TokenPair>, RefMut<'a, [PoolSlot]>)> {
+        let num_slots = account.load_mut()?.get_num_slots();
+        TokenPair::map_slots_mut(account, num_slots)
+    }
+
+    /// Same as load_slots_mut for the account that could have been just created
+    /// with init_if_needed
+    pub fn load_slots_init<'a>(
+        account: &'a AccountLoader<TokenPair>,
+    ) -> Result<(RefMut<'a, TokenPair>, RefMut<'a, [PoolSlot]>)> {
+        let num_slots = state::load_or_init_mut(account)?.get_num_slots();
+        TokenPair::map_slots_mut(account, num_slots)
+    }
+
+    fn map_slots_mut<'a>(
+        account: &'a AccountLoader<TokenPair>,
+        num_slots: usize,
+    ) -> Result<(RefMut<'a, TokenPair>, RefMut<'a, [PoolSlot]>)> {
+        let data = account.as_ref().try_borrow_mut_data()?;
+        if data.len() < TokenPair::get_len(num_slots) {
+            return Err(ProgramError::InvalidAccountData.into());
+        }
+        Ok(RefMut::map_split(data, |data| {
+            let (header, slots) = data[8..].split_at_mut(TokenPair::LEN - 8);
+            (
+                bytemuck::from_bytes_mut(header),
+                bytemuck::cast_slice_mut(&mut slots[..num_slots * PoolSlot::LEN]),
+            )
+        }))
+    }
+
+    /// Returns the counter of the current pool of the time in force, used in pool seeds
+    pub fn load_pool_counter(
+        account: &AccountLoader<TokenPair>,
+        time_in_force: u32,
+    ) -> Result<u64> {
+        let (_, slots) = TokenPair::load_slots(account)?;
+        Ok(slots[TokenPair::get_tif_index(&slots, time_in_force)?].pool_counter)
+    }
+
+    pub fn get_num_slots(&self) -> usize {
+        self.num_slots as usize
+    }
+
+    /// Sets the number of time in force slots, only slots that never had pools can be
+    /// removed. The account is resized by the caller, new slots are zeroed and disabled
+    /// until time in force is set.
+    pub fn set_num_slots(&mut self, slots: &[PoolSlot], num_slots: usize) -> Result<()> {
+        if num_slots > TokenPair::MAX_POOLS {
+            return err!(TwammError::InvalidTimeInForce);
+        }
+        if slots.len() > num_slots && !slots[num_slots..].iter().all(|slot| slot.is_unused()) {
+            msg!("Error: Only slots that never had pools can be removed");
+            return err!(TwammError::InvalidPoolState);
+        }
+        self.num_slots = num_slots as u8;
+        Ok(())
+    }
 
-    /// Returns the index of the given time in force value in the tifs array
-    pub fn get_tif_index(&self, time_in_force: u32) -> Result<usize> {
+    /// Returns the index of the given time in force value in the slots
+    pub fn get_tif_index(slots: &[PoolSlot], time_in_force: u32) -> Result<usize> {
         if time_in_force == 0 {
             return err!(TwammError::InvalidTimeInForce);
         }
-        Ok(self
-            .tifs
+        Ok(slots
             .iter()
-            .position(|tif| *tif == time_in_force)
+            .position(|slot| slot.time_in_force == time_in_force)
             .ok_or(TwammError::InvalidTimeInForce)?)
     }
 
+    /// Checks if the pool is the current pool of its time in force
+    pub fn is_current_pool(slots: &[PoolSlot], pool: &Pool) -> bool {
+        TokenPair::get_tif_index(slots, pool.time_in_force)
+            .map(|tif_index| slots[tif_index].pool_counter == pool.counter)
+            .unwrap_or(false)
+    }
+
     pub fn validate(&self) -> bool {
         self.fee_numerator < self.fee_denominator
             && self.settle_fee_numerator < self.settle_fee_denominator
-            && self.max_swap_price_diff >= 0.0
-            && self.max_swap_price_diff <= 1.0
-            && self.max_unsettled_amount >= 0.0
-            && self.max_unsettled_amount <= 1.0
-            && self.min_time_till_expiration >= 0.0
-            && self.min_time_till_expiration <= 1.0
+            && self.max_swap_price_diff <= math::RATIO_ONE
+            && self.max_unsettled_amount <= math::RATIO_ONE
+            && self.min_time_till_expiration <= math::RATIO_ONE
             && (!self.allow_settlements
                 || (!matches!(self.config_a.oracle_type, OracleType::None)
                     && !matches!(self.config_b.oracle_type, OracleType::None)))
             && (matches!(self.config_a.oracle_type, OracleType::None)
-                || (self.config_a.oracle_account != Pubkey::default()
-                    && self.config_a.max_oracle_price_error >= 0.0))
+                || self.config_a.oracle_account != Pubkey::default())
             && (matches!(self.config_b.oracle_type, OracleType::None)
-                || (self.config_b.oracle_account != Pubkey::default()
-                    && self.config_b.max_oracle_price_error >= 0.0))
-            && !((1..self.tifs.len())
-                .any(|i| self.tifs[i - 1] != 0 && self.tifs[i..].contains(&self.tifs[i - 1])))
+                || self.config_b.oracle_account != Pubkey::default())
+            && (1..=TokenPair::MAX_POOLS).contains(&self.get_num_slots())
+    }
+
+    pub fn validate_slots(&self, slots: &[PoolSlot]) -> bool {
+        slots.len() == self.get_num_slots()
+            && !(1..slots.len()).any(|i| {
+                let tif = slots[i - 1].time_in_force;
+                tif != 0 && slots[i..].iter().any(|slot| slot.time_in_force == tif)
+            })
+            && slots.iter().all(|slot| slot.schedule_shape.validate())
     }
 
     pub fn transfer_tokens<'info>(
         &self,
         from: AccountInfo<'info>,
         to: AccountInfo<'info>,
+        mint: AccountInfo<'info>,
         authority: AccountInfo<'info>,
         token_program: AccountInfo<'info>,
         amount: u64,
     ) -> Result<()> {
         let authority_seeds: &[&[&[u8]]] =
             &[&[b"transfer_authority", &[self.transfer_authority_bump]]];
+        let decimals = if mint.key() == self.config_a.mint {
+            self.config_a.decimals
+        } else {
+            self.config_b.decimals
+        };
+
+        let context = CpiContext::new(
+            token_program,
+            TransferChecked {
+                from,
+                mint,
+                to,
+                authority,
+            },
+        )
+        .with_signer(authority_seeds);
+
+        anchor_spl::token_interface::transfer_checked(context, amount, decimals)
+    }
+
+    /// Transfers tokens from the user's account to a custody, the user signs the transfer
+    pub fn deposit_tokens<'info>(
+        &self,
+        from: AccountInfo<'info>,
+        to: AccountInfo<'info>,
+        mint: AccountInfo<'info>,
+        authority: AccountInfo<'info>,
+        token_program: AccountInfo<'info>,
+        amount: u64,
+    ) -> Result<()> {
+        let decimals = if mint.key() == self.config_a.mint {
+            self.config_a.decimals
+        } else {
+            self.config_b.decimals
+        };
 
         let context = CpiContext::new(
             token_program,
-            Transfer {
+            TransferChecked {
                 from,
+                mint,
+                to,
+                authority,
+            },
+        );
+
+        anchor_spl::token_interface::transfer_checked(context, amount, decimals)
+    }
+
+    pub fn mint_tokens<'info>(
+        &self,
+        mint: AccountInfo<'info>,
+        to: AccountInfo<'info>,
+        authority: AccountInfo<'info>,
+        token_program: AccountInfo<'info>,
+        amount: u64,
+    ) -> Result<()> {
+        let authority_seeds: &[&[&[u8]]] =
+            &[&[b"transfer_authority", &[self.transfer_authority_bump]]];
+
+        let context = CpiContext::new(
+            token_program,
+            MintTo {
+                mint,
                 to,
                 authority,
             },
         )
         .with_signer(authority_seeds);
 
-        anchor_spl::token::transfer(context, amount)
+        anchor_spl::token_interface::mint_to(context, amount)
+    }
+
+    /// Moves lamports to the native mint token account and syncs its balance
+    pub fn wrap_native_tokens<'info>(
+        from: AccountInfo<'info>,
+        to: AccountInfo<'info>,
+        system_program: AccountInfo<'info>,
+        token_program: AccountInfo<'info>,
+        amount: u64,
+    ) -> Result<()> {
+        let context = CpiContext::new(
+            system_program,
+            anchor_lang::system_program::Transfer {
+                from,
+                to: to.clone(),
+            },
+        );
+        anchor_lang::system_program::transfer(context, amount)?;
+
+        let context = CpiContext::new(token_program, SyncNative { account: to });
+        anchor_spl::token_interface::sync_native(context)
+    }
+
+    /// Closes the native mint token account and releases all lamports to the owner
+    pub fn unwrap_native_tokens<'info>(
+        account: AccountInfo<'info>,
+        owner: AccountInfo<'info>,
+        token_program: AccountInfo<'info>,
+    ) -> Result<()> {
+        let context = CpiContext::new(
+            token_program,
+            CloseAccount {
+                account,
+                destination: owner.clone(),
+                authority: owner,
+            },
+        );
+        anchor_spl::token_interface::close_account(context)
+    }
+
+    pub fn is_native_mint(mint: &Pubkey) -> bool {
+        *mint == anchor_spl::token::spl_token::native_mint::ID
+    }
+
+    /// Returns the address and bump of the temporary token account used to pay out
+    /// native SOL to the payer's recipient
+    pub fn get_native_payout_address(payer: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"native_payout", payer.as_ref()], &crate::ID)
+    }
+
+    /// Moves the user's lamports to the native mint custody, used instead of a token
+    /// transfer if the user doesn't have a wrapped SOL account
+    pub fn deposit_native_tokens<'info>(
+        owner: AccountInfo<'info>,
+        custody: AccountInfo<'info>,
+        mint: AccountInfo<'info>,
+        system_program: AccountInfo<'info>,
+        token_program: AccountInfo<'info>,
+        amount: u64,
+    ) -> Result<()> {
+        if !Self::is_native_mint(mint.key) {
+            msg!("Error: User token account is required for non-native mints");
+            return err!(TwammError::InvalidTokenAccount);
+        }
+        Self::wrap_native_tokens(owner, custody, system_program, token_program, amount)
+    }
+
+    /// Pays out native mint custody tokens to the recipient as lamports. Tokens go
+    /// through a temporary account funded by the payer that is closed right away,
+    /// the rent is returned to the payer.
+    #[allow(clippy::too_many_arguments)]
+    pub fn withdraw_native_tokens<'info>(
+        &self,
+        custody: AccountInfo<'info>,
+        payout_account: AccountInfo<'info>,
+        mint: AccountInfo<'info>,
+        authority: AccountInfo<'info>,
+        payer: AccountInfo<'info>,
+        recipient: AccountInfo<'info>,
+        system_program: AccountInfo<'info>,
+        token_program: AccountInfo<'info>,
+        amount: u64,
+    ) -> Result<()> {
+        if !Self::is_native_mint(mint.key) {
+            msg!("Error: User token account is required for non-native mints");
+            return err!(TwammError::InvalidTokenAccount);
+        }
+        if amount == 0 {
+            return Ok(());
+        }
+        let (payout_key, payout_bump) = Self::get_native_payout_address(payer.key);
+        require_keys_eq!(
+            payout_key,
+            payout_account.key(),
+            TwammError::InvalidTokenAccount
+        );
+        let authority_seeds: &[&[&[u8]]] =
+            &[&[b"transfer_authority", &[self.transfer_authority_bump]]];
+
+        state::initialize_account(
+            payer.clone(),
+            payout_account.clone(),
+            system_program.clone(),
+            token_program.key,
+            &[&[b"native_payout", payer.key.as_ref(), &[payout_bump]]],
+            spl_token_2022::state::Account::LEN,
+        )?;
+        let context = CpiContext::new(
+            token_program.clone(),
+            InitializeAccount3 {
+                account: payout_account.clone(),
+                mint: mint.clone(),
+                authority: authority.clone(),
+            },
+        );
+        anchor_spl::token_interface::initialize_account3(context)?;
+
+        self.transfer_tokens(
+            custody,
+            payout_account.clone(),
+            mint,
+            authority.clone(),
+            token_program.clone(),
+            amount,
+        )?;
+        let rent = math::checked_sub(payout_account.try_lamports()?, amount)?;
+
+        let context = CpiContext::new(
+            token_program,
+            CloseAccount {
+                account: payout_account,
+                destination: authority.clone(),
+                authority: authority.clone(),
+            },
+        )
+        .with_signer(authority_seeds);
+        anchor_spl::token_interface::close_account(context)?;
+
+        for (to, lamports) in [(recipient, amount), (payer, rent)] {
+            let context = CpiContext::new(
+                system_program.clone(),
+                anchor_lang::system_program::Transfer {
+                    from: authority.clone(),
+                    to,
+                },
+            )
+            .with_signer(authority_seeds);
+            anchor_lang::system_program::transfer(context, lamports)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the amount withheld by the Token-2022 transfer fee extension
+    /// when the given amount is sent
+    pub fn get_transfer_fee(mint: &AccountInfo, amount: u64) -> Result<u64> {
+        match Self::get_transfer_fee_config(mint)? {
+            Some(fee_config) => Ok(fee_config
+                .calculate_epoch_fee(Clock::get()?.epoch, amount)
+                .ok_or(TwammError::MathOverflow)?),
+            None => Ok(0),
+        }
+    }
+
+    /// Returns the fee to add on top of the given amount for the recipient
+    /// to receive it in full
+    pub fn get_inverse_transfer_fee(mint: &AccountInfo, amount: u64) -> Result<u64> {
+        match Self::get_transfer_fee_config(mint)? {
+            Some(fee_config) => Ok(fee_config
+                .calculate_inverse_epoch_fee(Clock::get()?.epoch, amount)
+                .ok_or(TwammError::MathOverflow)?),
+            None => Ok(0),
+        }
+    }
+
+    /// Checks that the Token-2022 mint only has extensions that keep custody
+    /// balances and transfers under the program's control. Transfer fees are
+    /// accounted for on deposits and payouts, metadata doesn't affect transfers.
+    pub fn validate_mint_extensions(mint: &AccountInfo) -> Result<()> {
+        if mint.owner != &spl_token_2022::ID {
+            return Ok(());
+        }
+        let mint_data = mint.try_borrow_data()?;
+        if mint_data.len() <= spl_token_2022::state::Mint::LEN {
+            return Ok(());
+        }
+
+        // extensions follow the account type byte as type-length-value entries,
+        // types are compared by value to also cover ones this crate version can't parse
+        let mut offset = spl_token_2022::state::Account::LEN + 1;
+        while offset + 4 <= mint_data.len() {
+            let extension_type = u16::from_le_bytes([mint_data[offset], mint_data[offset + 1]]);
+            let extension_len =
+                u16::from_le_bytes([mint_data[offset + 2], mint_data[offset + 3]]) as usize;
+            if extension_type == TokenPair::UNINITIALIZED_EXTENSION {
+                break;
+            }
+            if !TokenPair::ALLOWED_MINT_EXTENSIONS.contains(&extension_type) {
+                msg!(
+                    "Error: Mint {} has unsupported extension type {}",
+                    mint.key,
+                    extension_type
+                );
+                return err!(TwammError::UnsupportedMintExtension);
+            }
+            offset = math::checked_add(offset + 4, extension_len)?;
+        }
+
+        Ok(())
+    }
+
+    fn get_transfer_fee_config(mint: &AccountInfo) -> Result<Option<TransferFeeConfig>> {
+        if mint.owner != &spl_token_2022::ID {
+            return Ok(None);
+        }
+        let mint_data = mint.try_borrow_data()?;
+        let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+        Ok(mint_state
+            .get_extension::<TransferFeeConfig>()
+            .ok()
+            .copied())
     }
 
     #[cfg(feature = "test")]
@@ -233,6 +683,38 @@ impl TokenPair {
         Ok(pair_price)
     }
 
+    /// Returns the average pair price an exact-output order can pay with its budget,
+    /// it is the worst acceptable price for the order's side
+    pub fn get_budget_price(
+        &self,
+        side: OrderSide,
+        budget: u64,
+        target_amount: u64,
+    ) -> Result<OraclePrice> {
+        let price = if side == OrderSide::Buy {
+            math::checked_decimal_div(
+                budget,
+                -(self.config_b.decimals as i32),
+                target_amount,
+                -(self.config_a.decimals as i32),
+                oracle::FILL_PRICE_EXPONENT,
+            )?
+        } else {
+            math::checked_decimal_ceil_div(
+                target_amount,
+                -(self.config_b.decimals as i32),
+                budget,
+                -(self.config_a.decimals as i32),
+                oracle::FILL_PRICE_EXPONENT,
+            )?
+        };
+        // keep tiny budgets from reading as no limit
+        Ok(OraclePrice::new(
+            std::cmp::max(price, 1),
+            oracle::FILL_PRICE_EXPONENT,
+        ))
+    }
+
     pub fn get_token_a_amount(
         &self,
         token_b_amount: u64,
@@ -291,10 +773,12 @@ impl TokenPair {
 
     pub fn load_pools<'a>(
         &self,
+        slots: &[PoolSlot],
         accounts: &[AccountInfo<'a>],
-    ) -> Result<(Vec<Account<'a, Pool>>, Pubkey)> {
-        let mut pools: Vec<Account<Pool>> = Vec::with_capacity(accounts.len());
-        let mut pools_found: [bool; TokenPair::MAX_POOLS] = [false; TokenPair::MAX_POOLS];
+    ) -> Result<LoadedPools<'a>> {
+        let mut pools: Vec<AccountLoader<Pool>> = Vec::with_capacity(accounts.len());
+        let mut virtual_pool: Option<Account<VirtualPool>> = None;
+        let mut pools_found = vec![false; self.get_num_slots()];
         let mut router_program = Pubkey::default();
 
         for (idx, account) in accounts.iter().enumerate() {
@@ -307,18 +791,26 @@ impl TokenPair {
             }
 
             // validate account
-            if idx >= TokenPair::MAX_POOLS {
+            if idx > self.get_num_slots() {
                 msg!("Error: Unexpected number of pool accounts");
                 return err!(TwammError::InvalidPoolAddress);
             }
             if account.owner != &crate::ID {
                 return Err(ProgramError::IllegalOwner.into());
             }
+            if account.try_data_len()? == VirtualPool::LEN {
+                if virtual_pool.is_some() {
+                    msg!("Error: Invalid pool address: Virtual pool already processed");
+                    return err!(TwammError::InvalidPoolAddress);
+                }
+                virtual_pool = Some(self.load_virtual_pool(account)?);
+                continue;
+            }
             if account.try_data_len()? != Pool::LEN {
                 return Err(ProgramError::InvalidAccountData.into());
             }
-            // deserialize pool
-            let pool = Account::<Pool>::try_from(account)?;
+            let pool_loader = AccountLoader::<Pool>::try_from(account)?;
+            let pool = pool_loader.load()?;
             // validate pool address
             let pool_address = Pubkey::create_program_address(
                 &[
@@ -339,54 +831,83 @@ impl TokenPair {
             }
 
             // validate pool
-            let tif_idx = self.get_tif_index(pool.time_in_force)?;
+            let tif_idx = TokenPair::get_tif_index(slots, pool.time_in_force)?;
             if pools_found[tif_idx] {
                 msg!("Error: Invalid pool address: Pool with the same TIF already processed");
                 return err!(TwammError::InvalidPoolAddress);
             }
-            if pool.counter != self.pool_counters[tif_idx] {
+            if pool.counter != slots[tif_idx].pool_counter {
                 msg!("Error: Invalid pool address: Pool is not current");
                 return err!(TwammError::InvalidPoolAddress);
             }
             pools_found[tif_idx] = true;
 
-            pools.push(pool);
+            drop(pool);
+            pools.push(pool_loader);
         }
 
         // check all current pools have been provided
-        if pools_found != self.current_pool_present {
+        if slots
+            .iter()
+            .zip(pools_found)
+            .any(|(slot, found)| slot.current_pool_present != found)
+        {
             msg!("Error: Not all current pools provided in accounts");
             return err!(TwammError::InvalidPoolAddress);
         }
 
-        Ok((pools, router_program))
+        Ok((pools, virtual_pool, router_program))
     }
 
-    pub fn save_pools(&self, pools: &[Account<Pool>]) -> Result<()> {
-        for pool in pools {
-            pool.exit(&crate::ID)?;
+    fn load_virtual_pool<'a>(&self, account: &AccountInfo<'a>) -> Result<Account<'a, VirtualPool>> {
+        let virtual_pool = Account::<VirtualPool>::try_from(account)?;
+        let token_pair_address = Pubkey::create_program_address(
+            &[
+                b"token_pair",
+                self.config_a.mint.as_ref(),
+                self.config_b.mint.as_ref(),
+                &[self.token_pair_bump],
+            ],
+            &crate::ID,
+        )
+        .map_err(|_| TwammError::InvalidPoolAddress)?;
+        let virtual_pool_address = Pubkey::create_program_address(
+            &[
+                b"virtual_pool",
+                token_pair_address.as_ref(),
+                &[virtual_pool.bump],
+            ],
+            &crate::ID,
+        )
+        .map_err(|_| TwammError::InvalidPoolAddress)?;
+
+        if &virtual_pool_address != account.key {
+            msg!("Error: Invalid pool address: Doesn't belong to the given token pair");
+            return err!(TwammError::InvalidPoolAddress);
         }
-        Ok(())
+
+        Ok(virtual_pool)
     }
 
     pub fn finalize_pool(
         &mut self,
+        slots: &mut [PoolSlot],
         pool: &Pool,
         pool_account: &AccountInfo,
         sol_destination: &AccountInfo,
     ) -> Result<()> {
-        let tif_idx = self.get_tif_index(pool.time_in_force)?;
-        if pool.counter == math::checked_add(self.pool_counters[tif_idx], 1)? {
-            self.future_pool_present[tif_idx] = false;
-        } else if pool.counter == self.pool_counters[tif_idx] {
+        let slot = &mut slots[TokenPair::get_tif_index(slots, pool.time_in_force)?];
+        if pool.counter == math::checked_add(slot.pool_counter, 1)? {
+            slot.future_pool_present = false;
+        } else if pool.counter == slot.pool_counter {
             // update counters
-            if self.future_pool_present[tif_idx] {
-                self.future_pool_present[tif_idx] = false;
-                self.current_pool_present[tif_idx] = true;
+            if slot.future_pool_present {
+                slot.future_pool_present = false;
+                slot.current_pool_present = true;
             } else {
-                self.current_pool_present[tif_idx] = false;
+                slot.current_pool_present = false;
             }
-            self.pool_counters[tif_idx] = math::checked_add(self.pool_counters[tif_idx], 1)?;
+            slot.pool_counter = math::checked_add(slot.pool_counter, 1)?;
 
             // update stats
             self.stats_a.pending_withdrawals = self
@@ -405,6 +926,30 @@ impl TokenPair {
                 .stats_b
                 .pending_withdrawals
                 .saturating_add(pool.buy_side.source_balance);
+
+            // tokens held out of the pool by conditional orders
+            self.stats_a.pending_withdrawals = self
+                .stats_a
+                .pending_withdrawals
+                .saturating_add(pool.sell_side.held_balance_total)
+                .saturating_add(pool.buy_side.held_proceeds_total);
+            self.stats_b.pending_withdrawals = self
+                .stats_b
+                .pending_withdrawals
+                .saturating_add(pool.sell_side.held_proceeds_total)
+                .saturating_add(pool.buy_side.held_balance_total);
+
+            // unspent budget and proceeds of exact-output orders
+            self.stats_a.pending_withdrawals = self
+                .stats_a
+                .pending_withdrawals
+                .saturating_add(pool.sell_output_side.source_balance)
+                .saturating_add(pool.buy_output_side.target_balance);
+            self.stats_b.pending_withdrawals = self
+                .stats_b
+                .pending_withdrawals
+                .saturating_add(pool.sell_output_side.target_balance)
+                .saturating_add(pool.buy_output_side.source_balance);
         }
 
         if pool.is_empty() {
@@ -419,6 +964,7 @@ impl TokenPair {
         Ok(())
     }
 
+    /// Updates volume stats of the token pair
     pub fn update_trade_stats(
         &mut self,
         settlement: &Settlement,
@@ -428,6 +974,7 @@ impl TokenPair {
     ) -> Result<()> {
         let oracle_price_a = self.get_token_a_oracle_price(oracle_token_a)?;
         let oracle_price_b = self.get_token_b_oracle_price(oracle_token_b)?;
+
         if settlement.settlement_side == MatchingSide::Sell {
             if settlement_type == SettlementType::Crank {
                 self.stats_a.routed_volume_usd =
@@ -500,8 +1047,15 @@ impl TokenPair {
         oracle_price: OraclePrice,
         current_time: i64,
     ) -> Result<Settlement> {
-        let mut outstanding_a: [u64; TokenPair::MAX_POOLS] = [0; TokenPair::MAX_POOLS];
-        let mut outstanding_b: [u64; TokenPair::MAX_POOLS] = [0; TokenPair::MAX_POOLS];
+        // current pools and virtual pool window, exact-output sides follow exact-input ones
+        let num_pools = pools.len();
+        let num_sides = num_pools * 2;
+        let mut outstanding = Outstanding {
+            sell: vec![0; num_sides],
+            buy: vec![0; num_sides],
+            sell_proceeds_cap: vec![u64::MAX; num_sides],
+            buy_proceeds_cap: vec![u64::MAX; num_sides],
+        };
         let mut total_outstanding_a = 0;
         let mut total_outstanding_b = 0;
         let mut res = Settlement {
@@ -513,61 +1067,107 @@ impl TokenPair {
             settlement_side: MatchingSide::Internal,
         };
 
-        // calculate outstanding amount per pool and in total
-        // try settle each pool with itself along the way
-        assert!(!pools.is_empty() && pools.len() < TokenPair::MAX_POOLS);
+        // calculate outstanding amount per pool side and in total
+        assert!(!pools.is_empty() && pools.len() <= TokenPair::MAX_POOLS + 1);
+        let fill_price = oracle_price.get_fill_price()?;
         for (idx, pool) in pools.iter_mut().enumerate() {
-            let outstanding_sell = pool
-                .sell_side
-                .get_unsettled_amount(pool.expiration_time, current_time)?;
-            let outstanding_buy = pool
-                .buy_side
-                .get_unsettled_amount(pool.expiration_time, current_time)?;
-            if outstanding_sell > 0 || outstanding_buy > 0 {
-                total_outstanding_a = math::checked_add(total_outstanding_a, outstanding_sell)?;
-                total_outstanding_b = math::checked_add(total_outstanding_b, outstanding_buy)?;
-                outstanding_a[idx] = math::checked_add(outstanding_a[idx], outstanding_sell)?;
-                outstanding_b[idx] = math::checked_add(outstanding_b[idx], outstanding_buy)?;
-
-                let (settled_a, settled_b) = self.settle_sides(
-                    &mut pool.sell_side,
-                    &mut pool.buy_side,
-                    outstanding_sell,
-                    outstanding_buy,
+            // delayed orders that reached their start time join the pool
+            let schedule = pool.schedule;
+            let expiration_time = pool.expiration_time;
+            pool.sell_side
+                .activate_pending_starts(&schedule, expiration_time, current_time)?;
+            pool.buy_side
+                .activate_pending_starts(&schedule, expiration_time, current_time)?;
+
+            outstanding.sell[idx] = pool.sell_side.get_unsettled_amount(
+                &pool.schedule,
+                pool.expiration_time,
+                current_time,
+            )?;
+            outstanding.buy[idx] = pool.buy_side.get_unsettled_amount(
+                &pool.schedule,
+                pool.expiration_time,
+                current_time,
+            )?;
+
+            // exact-output sides release target tokens, settle their source equivalent
+            let output_idx = num_pools + idx;
+            outstanding.sell_proceeds_cap[output_idx] = pool
+                .sell_output_side
+                .get_unsettled_target_amount(&pool.schedule, pool.expiration_time, current_time)?;
+            outstanding.buy_proceeds_cap[output_idx] = pool
+                .buy_output_side
+                .get_unsettled_target_amount(&pool.schedule, pool.expiration_time, current_time)?;
+            outstanding.sell[output_idx] = std::cmp::min(
+                self.get_token_a_amount_ceil(
+                    outstanding.sell_proceeds_cap[output_idx],
+                    oracle_price,
+                )?,
+                pool.sell_output_side.source_balance,
+            );
+            outstanding.buy[output_idx] = std::cmp::min(
+                self.get_token_b_amount_ceil(
+                    outstanding.buy_proceeds_cap[output_idx],
                     oracle_price,
-                )?;
-                outstanding_a[idx] = math::checked_sub(outstanding_a[idx], settled_a)?;
-                outstanding_b[idx] = math::checked_sub(outstanding_b[idx], settled_b)?;
+                )?,
+                pool.buy_output_side.source_balance,
+            );
+            // budget prices of exact-output orders bound their sides, the debt stays in target
+            if !pool.sell_output_side.is_price_acceptable(fill_price) {
+                msg!(
+                    "Exact-output sell side of pool {} is paused by order budgets",
+                    idx
+                );
+                outstanding.sell[output_idx] = 0;
+            }
+            if !pool.buy_output_side.is_price_acceptable(fill_price) {
+                msg!(
+                    "Exact-output buy side of pool {} is paused by order budgets",
+                    idx
+                );
+                outstanding.buy[output_idx] = 0;
+            }
+
+            total_outstanding_a = math::checked_add(
+                total_outstanding_a,
+                math::checked_add(outstanding.sell[idx], outstanding.sell[output_idx])?,
+            )?;
+            total_outstanding_b = math::checked_add(
+                total_outstanding_b,
+                math::checked_add(outstanding.buy[idx], outstanding.buy[output_idx])?,
+            )?;
+        }
+
+        // try settle each pool with itself
+        for idx in 0..num_pools {
+            let output_idx = num_pools + idx;
+            for (sell_idx, buy_idx) in [
+                (idx, idx),
+                (idx, output_idx),
+                (output_idx, idx),
+                (output_idx, output_idx),
+            ] {
+                self.settle_outstanding(pools, &mut outstanding, sell_idx, buy_idx, oracle_price)?;
             }
         }
 
         // settle pools internally
-        for idx in 0..(pools.len() - 1) {
-            if outstanding_a[idx] != 0 || outstanding_b[idx] != 0 {
-                for other_idx in (idx + 1)..pools.len() {
-                    if outstanding_a[idx] != 0 && outstanding_b[other_idx] != 0 {
-                        let (settled_a, settled_b) = self.settle_sides(
-                            &mut pools[idx].sell_side,
-                            &mut pools[other_idx].buy_side,
-                            outstanding_a[idx],
-                            outstanding_b[other_idx],
-                            oracle_price,
-                        )?;
-                        outstanding_a[idx] = math::checked_sub(outstanding_a[idx], settled_a)?;
-                        outstanding_b[other_idx] =
-                            math::checked_sub(outstanding_b[other_idx], settled_b)?;
-                    }
-                    if outstanding_b[idx] != 0 && outstanding_a[other_idx] != 0 {
-                        let (settled_a, settled_b) = self.settle_sides(
-                            &mut pools[other_idx].sell_side,
-                            &mut pools[idx].buy_side,
-                            outstanding_a[other_idx],
-                            outstanding_b[idx],
+        for idx in 0..(num_pools - 1) {
+            for other_idx in (idx + 1)..num_pools {
+                for (sell_pool_idx, buy_pool_idx) in [(idx, other_idx), (other_idx, idx)] {
+                    for (sell_offset, buy_offset) in [
+                        (0, 0),
+                        (0, num_pools),
+                        (num_pools, 0),
+                        (num_pools, num_pools),
+                    ] {
+                        self.settle_outstanding(
+                            pools,
+                            &mut outstanding,
+                            sell_pool_idx + sell_offset,
+                            buy_pool_idx + buy_offset,
                             oracle_price,
                         )?;
-                        outstanding_a[other_idx] =
-                            math::checked_sub(outstanding_a[other_idx], settled_a)?;
-                        outstanding_b[idx] = math::checked_sub(outstanding_b[idx], settled_b)?;
                     }
                 }
             }
@@ -576,10 +1176,10 @@ impl TokenPair {
         // compute net amounts
         let mut net_outstanding_a = 0;
         let mut net_outstanding_b = 0;
-        for val in outstanding_a {
+        for &val in outstanding.sell.iter() {
             net_outstanding_a = math::checked_add(net_outstanding_a, val)?;
         }
-        for val in outstanding_b {
+        for &val in outstanding.buy.iter() {
             net_outstanding_b = math::checked_add(net_outstanding_b, val)?;
         }
         if net_outstanding_a != 0 && net_outstanding_b != 0 {
@@ -621,36 +1221,48 @@ impl TokenPair {
                     self.get_token_a_amount_ceil(pool_amount_out, exchange_rate)?
                 };
             }
-            res.net_amount_settled = pool_amount_out;
-            res.source_amount_received = supply_amount_in;
+            let total_amount_out = pool_amount_out;
+            let total_amount_in = supply_amount_in;
 
-            // settle pools one by one with supplied amounts
+            // settle pool sides one by one with supplied amounts
             let mut settled_num = 0;
-            let mut settled_pools = [(false, false); TokenPair::MAX_POOLS];
-            for (idx, pool) in pools.iter_mut().enumerate() {
+            let mut settled_sides = vec![(false, false); num_sides];
+            for idx in 0..num_sides {
                 let mut settled = 0;
                 let mut received = 0;
-                if outstanding_a[idx] != 0 {
+                if outstanding.sell[idx] != 0 {
                     (settled, received) = self.settle_side_with_supply(
-                        &mut pool.sell_side,
+                        get_sell_side(pools, idx),
                         MatchingSide::Sell,
-                        std::cmp::min(pool_amount_out, outstanding_a[idx]),
+                        std::cmp::min(pool_amount_out, outstanding.sell[idx]),
                         supply_amount_in,
+                        outstanding.sell_proceeds_cap[idx],
                         exchange_rate,
                     )?;
-                    outstanding_a[idx] = math::checked_sub(outstanding_a[idx], settled)?;
-                    settled_pools[idx].0 = true;
+                    outstanding.sell[idx] = math::checked_sub(outstanding.sell[idx], settled)?;
+                    outstanding.sell_proceeds_cap[idx] =
+                        math::checked_sub(outstanding.sell_proceeds_cap[idx], received)?;
+                    if outstanding.sell_proceeds_cap[idx] == 0 {
+                        outstanding.sell[idx] = 0;
+                    }
+                    settled_sides[idx].0 = true;
                     settled_num = math::checked_add(settled_num, 1)?;
-                } else if outstanding_b[idx] != 0 {
+                } else if outstanding.buy[idx] != 0 {
                     (settled, received) = self.settle_side_with_supply(
-                        &mut pool.buy_side,
+                        get_buy_side(pools, idx),
                         MatchingSide::Buy,
-                        std::cmp::min(pool_amount_out, outstanding_b[idx]),
+                        std::cmp::min(pool_amount_out, outstanding.buy[idx]),
                         supply_amount_in,
+                        outstanding.buy_proceeds_cap[idx],
                         exchange_rate,
                     )?;
-                    outstanding_b[idx] = math::checked_sub(outstanding_b[idx], settled)?;
-                    settled_pools[idx].1 = true;
+                    outstanding.buy[idx] = math::checked_sub(outstanding.buy[idx], settled)?;
+                    outstanding.buy_proceeds_cap[idx] =
+                        math::checked_sub(outstanding.buy_proceeds_cap[idx], received)?;
+                    if outstanding.buy_proceeds_cap[idx] == 0 {
+                        outstanding.buy[idx] = 0;
+                    }
+                    settled_sides[idx].1 = true;
                     settled_num = math::checked_add(settled_num, 1)?;
                 }
                 pool_amount_out = math::checked_sub(pool_amount_out, settled)?;
@@ -659,24 +1271,22 @@ impl TokenPair {
                     // account for possible rounding error
                     if supply_amount_in > 0 {
                         let leftover = math::checked_ceil_div(supply_amount_in, settled_num)?;
-                        for (idx, pool) in pools.iter_mut().enumerate() {
+                        for (idx, settled_side) in settled_sides.iter().enumerate() {
                             let balance_change = std::cmp::min(supply_amount_in, leftover);
                             if balance_change != 0 {
-                                if settled_pools[idx].0 {
-                                    pool.sell_side.target_balance = math::checked_add(
-                                        pool.sell_side.target_balance,
-                                        balance_change,
-                                    )?;
-                                    supply_amount_in =
-                                        math::checked_sub(supply_amount_in, balance_change)?;
-                                } else if settled_pools[idx].1 {
-                                    pool.buy_side.target_balance = math::checked_add(
-                                        pool.buy_side.target_balance,
-                                        balance_change,
-                                    )?;
-                                    supply_amount_in =
-                                        math::checked_sub(supply_amount_in, balance_change)?;
-                                }
+                                let side = if settled_side.0 {
+                                    get_sell_side(pools, idx)
+                                } else if settled_side.1 {
+                                    get_buy_side(pools, idx)
+                                } else {
+                                    continue;
+                                };
+                                side.target_balance =
+                                    math::checked_add(side.target_balance, balance_change)?;
+                                side.target_pending =
+                                    side.target_pending.saturating_sub(balance_change);
+                                supply_amount_in =
+                                    math::checked_sub(supply_amount_in, balance_change)?;
                             } else {
                                 break;
                             }
@@ -689,14 +1299,25 @@ impl TokenPair {
                     break;
                 }
             }
+
+            // exact-output sides may take less than their source equivalent
+            res.net_amount_settled = math::checked_sub(total_amount_out, pool_amount_out)?;
+            res.source_amount_received = math::checked_sub(total_amount_in, supply_amount_in)?;
         }
 
         // write stats
         for (idx, pool) in pools.iter_mut().enumerate() {
-            pool.sell_side.settlement_debt_total = outstanding_a[idx];
+            pool.sell_side.settlement_debt_total = outstanding.sell[idx];
             pool.sell_side.last_balance_change_time = current_time;
-            pool.buy_side.settlement_debt_total = outstanding_b[idx];
+            pool.buy_side.settlement_debt_total = outstanding.buy[idx];
             pool.buy_side.last_balance_change_time = current_time;
+
+            // exact-output sides keep the debt in target tokens
+            let output_idx = num_pools + idx;
+            pool.sell_output_side.settlement_debt_total = outstanding.sell_proceeds_cap[output_idx];
+            pool.sell_output_side.last_balance_change_time = current_time;
+            pool.buy_output_side.settlement_debt_total = outstanding.buy_proceeds_cap[output_idx];
+            pool.buy_output_side.last_balance_change_time = current_time;
         }
 
         if res.settlement_side == MatchingSide::Sell {
@@ -716,12 +1337,55 @@ impl TokenPair {
         Ok(res)
     }
 
+    /// Settles outstanding amounts of the given sell and buy pool sides with each other
+    fn settle_outstanding(
+        &self,
+        pools: &mut [&mut Pool],
+        outstanding: &mut Outstanding,
+        sell_idx: usize,
+        buy_idx: usize,
+        exchange_rate: OraclePrice,
+    ) -> Result<()> {
+        if outstanding.sell[sell_idx] == 0 || outstanding.buy[buy_idx] == 0 {
+            return Ok(());
+        }
+        let (sell_side, buy_side) = get_matching_sides(pools, sell_idx, buy_idx);
+        let (settled_a, settled_b) = self.settle_sides(
+            sell_side,
+            buy_side,
+            outstanding.sell[sell_idx],
+            outstanding.buy[buy_idx],
+            outstanding.sell_proceeds_cap[sell_idx],
+            outstanding.buy_proceeds_cap[buy_idx],
+            exchange_rate,
+        )?;
+        outstanding.sell[sell_idx] = math::checked_sub(outstanding.sell[sell_idx], settled_a)?;
+        outstanding.buy[buy_idx] = math::checked_sub(outstanding.buy[buy_idx], settled_b)?;
+        outstanding.sell_proceeds_cap[sell_idx] =
+            math::checked_sub(outstanding.sell_proceeds_cap[sell_idx], settled_b)?;
+        outstanding.buy_proceeds_cap[buy_idx] =
+            math::checked_sub(outstanding.buy_proceeds_cap[buy_idx], settled_a)?;
+
+        // exact-output sides are done once they received the released target amount
+        if outstanding.sell_proceeds_cap[sell_idx] == 0 {
+            outstanding.sell[sell_idx] = 0;
+        }
+        if outstanding.buy_proceeds_cap[buy_idx] == 0 {
+            outstanding.buy[buy_idx] = 0;
+        }
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn settle_sides(
         &self,
         sell_side: &mut PoolSide,
         buy_side: &mut PoolSide,
         outstanding_sell: u64,
         outstanding_buy: u64,
+        max_sell_proceeds: u64,
+        max_buy_proceeds: u64,
         exchange_rate: OraclePrice,
     ) -> Result<(u64, u64)> {
         if outstanding_sell == 0 || outstanding_buy == 0 {
@@ -733,7 +1397,7 @@ impl TokenPair {
         assert!(outstanding_buy <= buy_side.source_balance);
         let expected_buy = self.get_token_b_amount(outstanding_sell, exchange_rate)?;
 
-        let (matching_sell, matching_buy) = if expected_buy <= outstanding_buy {
+        let (mut matching_sell, mut matching_buy) = if expected_buy <= outstanding_buy {
             (outstanding_sell, expected_buy)
         } else {
             (
@@ -744,6 +1408,22 @@ impl TokenPair {
                 outstanding_buy,
             )
         };
+
+        // exact-output sides don't receive more than their target amount
+        if matching_buy > max_sell_proceeds {
+            matching_buy = max_sell_proceeds;
+            matching_sell = std::cmp::min(
+                matching_sell,
+                self.get_token_a_amount(matching_buy, exchange_rate)?,
+            );
+        }
+        if matching_sell > max_buy_proceeds {
+            matching_sell = max_buy_proceeds;
+            matching_buy = std::cmp::min(
+                matching_buy,
+                self.get_token_b_amount(matching_sell, exchange_rate)?,
+            );
+        }
         if matching_sell == 0 || matching_buy == 0 {
             return Ok((0, 0));
         }
@@ -753,28 +1433,13 @@ impl TokenPair {
         sell_side.target_balance = math::checked_add(sell_side.target_balance, matching_buy)?;
         buy_side.source_balance = math::checked_sub(buy_side.source_balance, matching_buy)?;
         buy_side.target_balance = math::checked_add(buy_side.target_balance, matching_sell)?;
+        sell_side.target_pending = sell_side.target_pending.saturating_sub(matching_buy);
+        buy_side.target_pending = buy_side.target_pending.saturating_sub(matching_sell);
 
         // update stats
-        let exchange_rate_f64 = exchange_rate.checked_as_f64()?;
-        sell_side.weighted_fills_sum +=
-            math::checked_float_mul(matching_sell as f64, exchange_rate_f64)?;
-        sell_side.fills_volume = math::checked_add(sell_side.fills_volume, matching_sell)?;
-        if sell_side.min_fill_price == 0.0 || exchange_rate_f64 < sell_side.min_fill_price {
-            sell_side.min_fill_price = exchange_rate_f64;
-        }
-        if sell_side.max_fill_price == 0.0 || exchange_rate_f64 > sell_side.max_fill_price {
-            sell_side.max_fill_price = exchange_rate_f64;
-        }
-
-        buy_side.weighted_fills_sum +=
-            math::checked_float_mul(matching_buy as f64, exchange_rate_f64)?;
-        buy_side.fills_volume = math::checked_add(buy_side.fills_volume, matching_buy)?;
-        if buy_side.min_fill_price == 0.0 || exchange_rate_f64 < buy_side.min_fill_price {
-            buy_side.min_fill_price = exchange_rate_f64;
-        }
-        if buy_side.max_fill_price == 0.0 || exchange_rate_f64 > buy_side.max_fill_price {
-            buy_side.max_fill_price = exchange_rate_f64;
-        }
+        let fill_price = exchange_rate.get_fill_price()?;
+        sell_side.update_fill_stats(matching_sell, fill_price)?;
+        buy_side.update_fill_stats(matching_buy, fill_price)?;
 
         Ok((matching_sell, matching_buy))
     }
@@ -785,6 +1450,7 @@ impl TokenPair {
         settlement_side: MatchingSide,
         outstanding_sell: u64,
         supply_amount: u64,
+        max_proceeds: u64,
         exchange_rate: OraclePrice,
     ) -> Result<(u64, u64)> {
         if outstanding_sell == 0 || supply_amount == 0 {
@@ -799,7 +1465,7 @@ impl TokenPair {
             self.get_token_a_amount(outstanding_sell, exchange_rate)?
         };
 
-        let (matching_sell, matching_buy) = if expected_buy <= supply_amount {
+        let (mut matching_sell, mut matching_buy) = if expected_buy <= supply_amount {
             (outstanding_sell, expected_buy)
         } else {
             (
@@ -811,6 +1477,19 @@ impl TokenPair {
                 supply_amount,
             )
         };
+
+        // exact-output sides don't receive more than their target amount
+        if matching_buy > max_proceeds {
+            matching_buy = max_proceeds;
+            matching_sell = std::cmp::min(
+                matching_sell,
+                if settlement_side == MatchingSide::Sell {
+                    self.get_token_a_amount(matching_buy, exchange_rate)?
+                } else {
+                    self.get_token_b_amount(matching_buy, exchange_rate)?
+                },
+            );
+        }
         if matching_sell == 0 || matching_buy == 0 {
             return Ok((0, 0));
         }
@@ -818,29 +1497,78 @@ impl TokenPair {
         // update balances
         side.source_balance = math::checked_sub(side.source_balance, matching_sell)?;
         side.target_balance = math::checked_add(side.target_balance, matching_buy)?;
+        side.target_pending = side.target_pending.saturating_sub(matching_buy);
 
         // update stats
-        let exchange_rate_f64 = exchange_rate.checked_as_f64()?;
-        side.weighted_fills_sum +=
-            math::checked_float_mul(matching_sell as f64, exchange_rate_f64)?;
-        side.fills_volume = math::checked_add(side.fills_volume, matching_sell)?;
-        if side.min_fill_price == 0.0 || exchange_rate_f64 < side.min_fill_price {
-            side.min_fill_price = exchange_rate_f64;
-        }
-        if side.max_fill_price == 0.0 || exchange_rate_f64 > side.max_fill_price {
-            side.max_fill_price = exchange_rate_f64;
-        }
+        side.update_fill_stats(matching_sell, exchange_rate.get_fill_price()?)?;
 
         Ok((matching_sell, matching_buy))
     }
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
+fn get_sell_side<'a>(pools: &'a mut [&mut Pool], idx: usize) -> &'a mut PoolSide {
+    let num_pools = pools.len();
+    pools[idx % num_pools].get_side_mut(OrderSide::Sell, idx >= num_pools)
+}
 
-    fn get_fixture() -> (TokenPair, Pool) {
-        let mut token_pair = TokenPair::default();
+fn get_buy_side<'a>(pools: &'a mut [&mut Pool], idx: usize) -> &'a mut PoolSide {
+    let num_pools = pools.len();
+    pools[idx % num_pools].get_side_mut(OrderSide::Buy, idx >= num_pools)
+}
+
+fn get_matching_sides<'a>(
+    pools: &'a mut [&mut Pool],
+    sell_idx: usize,
+    buy_idx: usize,
+) -> (&'a mut PoolSide, &'a mut PoolSide) {
+    let num_pools = pools.len();
+    let (sell_pool_idx, sell_output) = (sell_idx % num_pools, sell_idx >= num_pools);
+    let (buy_pool_idx, buy_output) = (buy_idx % num_pools, buy_idx >= num_pools);
+    if sell_pool_idx == buy_pool_idx {
+        let pool = &mut *pools[sell_pool_idx];
+        let sell_side = if sell_output {
+            &mut pool.sell_output_side
+        } else {
+            &mut pool.sell_side
+        };
+        let buy_side = if buy_output {
+            &mut pool.buy_output_side
+        } else {
+            &mut pool.buy_side
+        };
+        (sell_side, buy_side)
+    } else if sell_pool_idx < buy_pool_idx {
+        let (left, right) = pools.split_at_mut(buy_pool_idx);
+        (
+            left[sell_pool_idx].get_side_mut(OrderSide::Sell, sell_output),
+            right[0].get_side_mut(OrderSide::Buy, buy_output),
+        )
+    } else {
+        let (left, right) = pools.split_at_mut(sell_pool_idx);
+        (
+            right[0].get_side_mut(OrderSide::Sell, sell_output),
+            left[buy_pool_idx].get_side_mut(OrderSide::Buy, buy_output),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::state::{
+            multisig::Multisig,
+            order::{Order, OrderSide},
+            pool::{Schedule, ScheduleKind},
+            stop_order::StopOrder,
+            subscription::Subscription,
+            virtual_order::VirtualOrder,
+        },
+        anchor_lang::Discriminator,
+    };
+
+    fn get_fixture() -> (TokenPair, Pool) {
+        let mut token_pair = TokenPair::default();
         token_pair.config_a.decimals = 9;
         token_pair.config_b.decimals = 6;
         token_pair.inception_time = 135;
@@ -954,6 +1682,16 @@ mod test {
             },
             res
         );
+
+        // fill stats are fixed-point with FILL_PRICE_EXPONENT
+        let (fills_volume, weighted_fills_sum) =
+            (pool.buy_side.fills_volume, pool.buy_side.weighted_fills_sum);
+        let (min_fill_price, max_fill_price) =
+            (pool.buy_side.min_fill_price, pool.buy_side.max_fill_price);
+        assert_eq!(2400, fills_volume);
+        assert_eq!(2400 * 30_000_000_000, weighted_fills_sum);
+        assert_eq!(30_000_000_000, min_fill_price);
+        assert_eq!(30_000_000_000, max_fill_price);
     }
 
     #[test]
@@ -1764,24 +2502,1797 @@ mod test {
     }
 
     #[test]
-    fn test_get_token_pair_oracle_price() {
-        let oracle_price1 = OraclePrice {
-            price: 4004,
-            exponent: -10,
+    fn settle_test_held_order() {
+        let (token_pair, mut pool) = get_fixture();
+        let mut order1 = Order {
+            side: OrderSide::Sell,
+            held_balance: 10000,
+            ..Default::default()
+        };
+        let mut order2 = Order {
+            side: OrderSide::Sell,
+            held_balance: 10000,
+            ..Default::default()
         };
+        pool.sell_side.held_balance_total = 20000;
 
-        let oracle_price2 = OraclePrice {
-            price: 100001000,
-            exponent: -8,
+        order1
+            .release(&mut pool.sell_side, &pool.schedule, 300, 0)
+            .unwrap();
+        order2
+            .release(&mut pool.sell_side, &pool.schedule, 300, 0)
+            .unwrap();
+        assert_eq!({ pool.sell_side.source_balance }, 20000);
+        assert_eq!({ pool.sell_side.lp_supply }, 20000);
+        assert_eq!({ pool.sell_side.held_balance_total }, 0);
+        assert!(!order2.is_held());
+
+        // order moved out of the pool is not released for settlement
+        assert_eq!(
+            order2
+                .hold(&mut pool.sell_side, &pool.schedule, 300, 100)
+                .unwrap(),
+            10000
+        );
+        assert!(order2.is_held());
+        assert_eq!({ pool.sell_side.source_balance }, 10000);
+        assert_eq!({ pool.sell_side.lp_supply }, 10000);
+        assert_eq!({ pool.sell_side.held_balance_total }, 10000);
+
+        let res = token_pair
+            .settle_pools(
+                &mut [&mut pool],
+                MatchingSide::Internal,
+                0,
+                0,
+                OraclePrice::new(3000, -2),
+                OraclePrice::new(3000, -2),
+                300,
+            )
+            .unwrap();
+        assert_eq!(res.settlement_side, MatchingSide::Sell);
+        assert_eq!(res.net_amount_required, 10000);
+
+        assert_eq!(
+            order2
+                .release(&mut pool.sell_side, &pool.schedule, 300, 200)
+                .unwrap(),
+            10000
+        );
+        assert_eq!({ pool.sell_side.source_balance }, 20000);
+        assert_eq!({ pool.sell_side.held_balance_total }, 0);
+        assert_eq!(order2.lp_balance, 10000);
+    }
+
+    #[test]
+    fn settle_test_withdraw_proceeds() {
+        let (token_pair, mut pool) = get_fixture();
+        let mut order1 = Order {
+            side: OrderSide::Sell,
+            held_balance: 10000,
+            ..Default::default()
+        };
+        let mut order2 = Order {
+            side: OrderSide::Sell,
+            held_balance: 30000,
+            ..Default::default()
+        };
+        pool.sell_side.held_balance_total = 40000;
+        pool.buy_side.source_balance = 300000;
+        order1
+            .release(&mut pool.sell_side, &pool.schedule, 300, 0)
+            .unwrap();
+        order2
+            .release(&mut pool.sell_side, &pool.schedule, 300, 0)
+            .unwrap();
+        let settle = |pool: &mut Pool, current_time| {
+            token_pair
+                .settle_pools(
+                    &mut [pool],
+                    MatchingSide::Internal,
+                    0,
+                    0,
+                    OraclePrice::new(3000, -2),
+                    OraclePrice::new(3000, -2),
+                    current_time,
+                )
+                .unwrap();
         };
+        settle(&mut pool, 150);
+
+        // one copy of the pool withdraws proceeds mid-way, the other only cancels at the end
+        let mut pool_cancelled = pool;
+        let order1_cancelled = order1.clone();
+        let (withdrawn, held_withdrawn) = order1.withdraw_proceeds(&mut pool.sell_side).unwrap();
+        assert!(withdrawn > 0);
+        assert_eq!(held_withdrawn, 0);
+        assert_eq!(
+            pool.sell_side
+                .get_proceeds(order1.lp_balance, order1.token_debt)
+                .unwrap(),
+            0
+        );
+        // other orders keep their share of the pool
+        assert_eq!(
+            pool.sell_side
+                .get_proceeds(order2.lp_balance, order2.token_debt)
+                .unwrap(),
+            pool_cancelled
+                .sell_side
+                .get_proceeds(order2.lp_balance, order2.token_debt)
+                .unwrap()
+        );
 
-        let pair_price = oracle_price1.checked_div(&oracle_price2).unwrap();
+        settle(&mut pool, 300);
+        settle(&mut pool_cancelled, 300);
+        let cancelled = pool_cancelled
+            .sell_side
+            .get_proceeds(order1_cancelled.lp_balance, order1_cancelled.token_debt)
+            .unwrap();
+        let remaining = pool
+            .sell_side
+            .get_proceeds(order1.lp_balance, order1.token_debt)
+            .unwrap();
+        assert!(remaining > 0);
+        assert_eq!(withdrawn + remaining, cancelled);
         assert_eq!(
-            pair_price,
-            OraclePrice {
-                price: 40039,
-                exponent: -11
-            }
+            pool.sell_side
+                .get_proceeds(order2.lp_balance, order2.token_debt)
+                .unwrap(),
+            pool_cancelled
+                .sell_side
+                .get_proceeds(order2.lp_balance, order2.token_debt)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn settle_test_roll_order() {
+        let (token_pair, mut pool) = get_fixture();
+        let mut order1 = Order {
+            side: OrderSide::Sell,
+            held_balance: 10000,
+            ..Default::default()
+        };
+        let mut order2 = Order {
+            side: OrderSide::Sell,
+            held_balance: 10000,
+            ..Default::default()
+        };
+        pool.sell_side.held_balance_total = 20000;
+        pool.buy_side.source_balance = 300000;
+        order1
+            .release(&mut pool.sell_side, &pool.schedule, 300, 0)
+            .unwrap();
+        order2
+            .release(&mut pool.sell_side, &pool.schedule, 300, 0)
+            .unwrap();
+        token_pair
+            .settle_pools(
+                &mut [&mut pool],
+                MatchingSide::Internal,
+                0,
+                0,
+                OraclePrice::new(3000, -2),
+                OraclePrice::new(3000, -2),
+                150,
+            )
+            .unwrap();
+        let source_left = { pool.sell_side.source_balance };
+        let target_left = { pool.sell_side.target_balance };
+        assert!(source_left > 0 && source_left < 20000);
+        assert!(target_left > 0);
+
+        // rolled order takes its share of the unfilled balance and proceeds
+        order2
+            .hold(&mut pool.sell_side, &pool.schedule, 300, 200)
+            .unwrap();
+        let (roll_amount, proceeds) = order2.take_held(&mut pool.sell_side).unwrap();
+        assert_eq!(roll_amount, source_left / 2);
+        assert_eq!(proceeds, target_left / 2);
+        assert_eq!({ pool.sell_side.source_balance }, source_left - roll_amount);
+        assert_eq!({ pool.sell_side.target_balance }, target_left - proceeds);
+        assert_eq!({ pool.sell_side.held_balance_total }, 0);
+        assert_eq!({ pool.sell_side.held_proceeds_total }, 0);
+        assert!(!order2.is_held());
+        assert_eq!(order2.lp_balance, 0);
+
+        // order held for its conditions stays out of the current pool
+        let (_, mut current_pool) = get_fixture();
+        let mut order3 = Order {
+            side: OrderSide::Sell,
+            limit_price: OraclePrice::new(3050, -2),
+            ..Default::default()
+        };
+        order3
+            .add_held_balance(&mut current_pool.sell_side, roll_amount)
+            .unwrap();
+        assert!(order3.is_held());
+        assert_eq!(order3.held_balance, roll_amount);
+        assert_eq!({ current_pool.sell_side.held_balance_total }, roll_amount);
+        assert_eq!({ current_pool.sell_side.source_balance }, 0);
+        assert_eq!({ current_pool.sell_side.lp_supply }, 0);
+    }
+
+    #[test]
+    fn settle_test_forward_proceeds() {
+        let (token_pair, mut pool) = get_fixture();
+        pool.buy_side.source_balance = 300000;
+        let schedule = pool.schedule;
+        let mut order1 = Order {
+            side: OrderSide::Sell,
+            ..Default::default()
+        };
+        let mut order2 = order1.clone();
+        order1
+            .deposit(&mut pool.sell_side, 10000, &schedule, 300, 0)
+            .unwrap();
+        order2
+            .deposit(&mut pool.sell_side, 10000, &schedule, 300, 0)
+            .unwrap();
+
+        token_pair
+            .settle_pools(
+                &mut [&mut pool],
+                MatchingSide::Internal,
+                0,
+                0,
+                OraclePrice::new(3000, -2),
+                OraclePrice::new(3000, -2),
+                300,
+            )
+            .unwrap();
+        assert_eq!({ pool.sell_side.target_balance }, 600);
+
+        // proceeds are taken once, the other order keeps its share
+        assert_eq!(
+            pool.sell_side
+                .get_proceeds(order1.lp_balance, order1.token_debt)
+                .unwrap(),
+            300
+        );
+        assert_eq!(
+            order1.withdraw_proceeds(&mut pool.sell_side).unwrap(),
+            (300, 0)
+        );
+        assert_eq!(
+            order1.withdraw_proceeds(&mut pool.sell_side).unwrap(),
+            (0, 0)
+        );
+        assert_eq!(
+            pool.sell_side
+                .get_proceeds(order2.lp_balance, order2.token_debt)
+                .unwrap(),
+            300
+        );
+        assert_eq!({ pool.sell_side.target_balance }, 300);
+
+        // forwarded proceeds become the source balance of the next order
+        let (_, mut next_pool) = get_fixture();
+        let mut next_order = Order {
+            side: OrderSide::Buy,
+            ..Default::default()
+        };
+        next_order
+            .deposit(&mut next_pool.buy_side, 300, &schedule, 300, 150)
+            .unwrap();
+        assert_eq!(next_order.lp_balance, 300);
+        assert_eq!(next_order.unsettled_balance, 300);
+        assert_eq!({ next_pool.buy_side.source_balance }, 300);
+
+        // proceeds forwarded into a delayed order wait in its pending start slot
+        let mut delayed_order = Order {
+            side: OrderSide::Buy,
+            start_time: 200,
+            held_balance: 1000,
+            pending_start: true,
+            ..Default::default()
+        };
+        assert!(next_pool
+            .buy_side
+            .add_pending_start(200, 1000, true)
+            .unwrap());
+        next_pool.buy_side.held_balance_total = 1000;
+        assert!(delayed_order
+            .add_pending_balance(&mut next_pool.buy_side, 300)
+            .unwrap());
+        assert_eq!({ next_pool.buy_side.pending_starts[0].balance }, 1300);
+        next_pool
+            .buy_side
+            .activate_pending_starts(&schedule, 300, 200)
+            .unwrap();
+        delayed_order.sync_start(&mut next_pool.buy_side).unwrap();
+        assert!(!delayed_order.pending_start);
+        assert_eq!(delayed_order.unsettled_balance, 1300);
+        assert_eq!({ next_pool.buy_side.held_balance_total }, 0);
+        assert_eq!({ next_pool.buy_side.source_balance }, 1600);
+
+        // only the current pool of a time in force accepts links
+        let slots = [PoolSlot {
+            time_in_force: 300,
+            ..Default::default()
+        }];
+        assert!(TokenPair::is_current_pool(&slots, &next_pool));
+        next_pool.counter = 1;
+        assert!(!TokenPair::is_current_pool(&slots, &next_pool));
+        next_pool.time_in_force = 600;
+        assert!(!TokenPair::is_current_pool(&slots, &next_pool));
+    }
+
+    #[test]
+    fn settle_test_position_tokens() {
+        let (token_pair, mut pool) = get_fixture();
+        pool.buy_side.source_balance = 300000;
+        let schedule = pool.schedule;
+        let settle = |pool: &mut Pool, time: i64| {
+            token_pair
+                .settle_pools(
+                    &mut [pool],
+                    MatchingSide::Internal,
+                    0,
+                    0,
+                    OraclePrice::new(3000, -2),
+                    OraclePrice::new(3000, -2),
+                    time,
+                )
+                .unwrap();
+        };
+        let mut order1 = Order {
+            side: OrderSide::Sell,
+            ..Default::default()
+        };
+        let mut order2 = order1.clone();
+        let mut order3 = order1.clone();
+        order1
+            .deposit(&mut pool.sell_side, 10000, &schedule, 300, 0)
+            .unwrap();
+        settle(&mut pool, 150);
+        order2
+            .deposit(&mut pool.sell_side, 10000, &schedule, 300, 150)
+            .unwrap();
+        assert_eq!(order2.lp_balance, 22497);
+        assert_eq!(order2.token_debt, 374);
+
+        // first position tokens take the debt of the order and count as a trader
+        assert_eq!(
+            order2
+                .tokenize(&mut pool.sell_side, 22497, &schedule, 300, 150)
+                .unwrap(),
+            0
+        );
+        assert_eq!(order2.lp_balance, 0);
+        assert_eq!(order2.token_debt, 0);
+        assert_eq!({ pool.sell_side.position_supply }, 22497);
+        assert_eq!({ pool.sell_side.position_token_debt }, 374);
+        assert_eq!({ pool.sell_side.num_traders }, 1);
+
+        // order with less debt per unit gets its filled tokens released
+        assert_eq!(pool.sell_side.get_proceeds(5000, 0).unwrap(), 83);
+        assert_eq!(
+            order1
+                .tokenize(&mut pool.sell_side, 5000, &schedule, 300, 150)
+                .unwrap(),
+            0
+        );
+        assert_eq!(order1.lp_balance, 5000);
+        assert_eq!(order1.held_proceeds, 83);
+        assert_eq!({ pool.sell_side.held_proceeds_total }, 83);
+        assert_eq!({ pool.sell_side.target_balance }, 83);
+        assert_eq!({ pool.sell_side.position_token_debt }, 457);
+        assert_eq!({ pool.sell_side.num_traders }, 1);
+
+        // order with more debt per unit pays in the difference
+        settle(&mut pool, 225);
+        order3
+            .deposit(&mut pool.sell_side, 10000, &schedule, 300, 225)
+            .unwrap();
+        assert_eq!(order3.token_debt, 1496);
+        assert_eq!(
+            order3
+                .tokenize(&mut pool.sell_side, 59990, &schedule, 300, 225)
+                .unwrap(),
+            499
+        );
+        assert_eq!({ pool.sell_side.position_supply }, 87487);
+        assert_eq!({ pool.sell_side.position_token_debt }, 1454);
+        assert_eq!({ pool.sell_side.token_debt_total }, 1454);
+
+        // orders that are not in the pool or have conditions can't be tokenized
+        let mut order4 = Order {
+            side: OrderSide::Sell,
+            limit_price: OraclePrice::new(3000, -2),
+            lp_balance: 1,
+            ..Default::default()
+        };
+        assert!(order4
+            .tokenize(&mut pool.sell_side, 1, &schedule, 300, 225)
+            .is_err());
+        assert!(order1
+            .tokenize(&mut pool.sell_side, 5001, &schedule, 300, 225)
+            .is_err());
+
+        // positions are redeemed with their share of the debt
+        settle(&mut pool, 300);
+        assert_eq!(
+            pool.sell_side
+                .redeem_position(5000, &schedule, 300, 300)
+                .unwrap(),
+            (0, 65)
+        );
+        assert_eq!(
+            pool.sell_side
+                .redeem_position(82487, &schedule, 300, 300)
+                .unwrap(),
+            (0, 1099)
+        );
+        assert!(pool
+            .sell_side
+            .redeem_position(1, &schedule, 300, 300)
+            .is_err());
+        assert_eq!({ pool.sell_side.position_token_debt }, 0);
+        assert_eq!({ pool.sell_side.token_debt_total }, 0);
+        assert_eq!({ pool.sell_side.num_traders }, 0);
+        assert_eq!(
+            pool.sell_side
+                .get_proceeds(order1.lp_balance, order1.token_debt)
+                .unwrap(),
+            { pool.sell_side.target_balance }
+        );
+    }
+
+    #[test]
+    fn settle_test_limit_order() {
+        let (token_pair, mut pool) = get_fixture();
+        let schedule = pool.schedule;
+        let mut order1 = Order {
+            side: OrderSide::Sell,
+            limit_price: OraclePrice::new(3050, -2),
+            held_balance: 10000,
+            ..Default::default()
+        };
+        let mut order2 = Order {
+            side: OrderSide::Sell,
+            held_balance: 10000,
+            ..Default::default()
+        };
+        pool.sell_side.held_balance_total = 20000;
+        pool.buy_side.source_balance = 300000;
+        order1
+            .release(&mut pool.sell_side, &schedule, 300, 0)
+            .unwrap();
+        order2
+            .release(&mut pool.sell_side, &schedule, 300, 0)
+            .unwrap();
+        assert_eq!({ pool.sell_side.min_price }, 0);
+        assert_eq!({ pool.sell_side.num_bounded_orders }, 0);
+
+        // pair price below the sell limit carries the limited order forward out of the pool
+        let price = OraclePrice::new(3000, -2);
+        assert!(!order1.is_price_acceptable(&price).unwrap());
+        assert_eq!(
+            order1
+                .hold(&mut pool.sell_side, &schedule, 300, 150)
+                .unwrap(),
+            10000
+        );
+        assert_eq!({ pool.sell_side.held_balance_total }, 10000);
+
+        // the unlimited order on the same side keeps filling
+        let res = token_pair
+            .settle_pools(
+                &mut [&mut pool],
+                MatchingSide::Internal,
+                0,
+                0,
+                price,
+                price,
+                150,
+            )
+            .unwrap();
+        assert_eq!(res.total_amount_settled_a, 5556);
+        assert_eq!({ pool.sell_side.source_balance }, 10000 - 5556);
+        assert_eq!({ pool.sell_side.settlement_debt_total }, 0);
+        assert_eq!(
+            pool.sell_side
+                .get_proceeds(order2.lp_balance, order2.token_debt)
+                .unwrap(),
+            { pool.sell_side.target_balance }
+        );
+        assert_eq!(order1.held_balance, 10000);
+        assert_eq!(order1.held_proceeds, 0);
+
+        // limited order rejoins once the price is back and fills with the rest of the side
+        let price = OraclePrice::new(3100, -2);
+        assert!(order1.is_price_acceptable(&price).unwrap());
+        assert_eq!(
+            order1
+                .release(&mut pool.sell_side, &schedule, 300, 200)
+                .unwrap(),
+            10000
+        );
+        let res = token_pair
+            .settle_pools(
+                &mut [&mut pool],
+                MatchingSide::Internal,
+                0,
+                0,
+                price,
+                price,
+                300,
+            )
+            .unwrap();
+        assert_eq!(res.total_amount_settled_a, 20000 - 5556);
+        assert_eq!({ pool.sell_side.source_balance }, 0);
+        assert_eq!({ pool.sell_side.held_balance_total }, 0);
+        assert!(
+            pool.sell_side
+                .get_proceeds(order1.lp_balance, order1.token_debt)
+                .unwrap()
+                > 0
+        );
+    }
+
+    #[test]
+    fn settle_test_price_band() {
+        let (token_pair, mut pool) = get_fixture();
+        let schedule = pool.schedule;
+        let mut order1 = Order {
+            side: OrderSide::Buy,
+            reference_price: OraclePrice::new(3000, -2),
+            max_price_deviation: 50_000_000,
+            held_balance: 300000,
+            ..Default::default()
+        };
+        let mut order2 = Order {
+            side: OrderSide::Buy,
+            held_balance: 300000,
+            ..Default::default()
+        };
+        pool.buy_side.held_balance_total = 600000;
+        pool.sell_side.source_balance = 30_000_000;
+        order1
+            .release(&mut pool.buy_side, &schedule, 300, 0)
+            .unwrap();
+        order2
+            .release(&mut pool.buy_side, &schedule, 300, 0)
+            .unwrap();
+
+        // both sides of the band carry the banded order forward, the rest of the side fills
+        for (price, time) in [(2800, 100), (3200, 150)] {
+            let price = OraclePrice::new(price, -2);
+            assert!(!order1.is_price_acceptable(&price).unwrap());
+            order1
+                .hold(&mut pool.buy_side, &schedule, 300, time)
+                .unwrap();
+            let res = token_pair
+                .settle_pools(
+                    &mut [&mut pool],
+                    MatchingSide::Internal,
+                    0,
+                    0,
+                    price,
+                    price,
+                    time,
+                )
+                .unwrap();
+            assert!(res.total_amount_settled_b > 0);
+            assert_eq!({ pool.buy_side.settlement_debt_total }, 0);
+            assert_eq!(order1.held_balance, 300000);
+        }
+        // the unbanded order is released on its own schedule
+        assert_eq!({ pool.buy_side.source_balance }, 300000 - 166666);
+
+        let price = OraclePrice::new(3100, -2);
+        assert!(order1.is_price_acceptable(&price).unwrap());
+        order1
+            .release(&mut pool.buy_side, &schedule, 300, 150)
+            .unwrap();
+        let res = token_pair
+            .settle_pools(
+                &mut [&mut pool],
+                MatchingSide::Internal,
+                0,
+                0,
+                price,
+                price,
+                225,
+            )
+            .unwrap();
+        assert!(res.total_amount_settled_b > 0);
+        assert!({ pool.buy_side.source_balance } < 300000 - 166666 + 300000);
+    }
+
+    #[test]
+    fn settle_test_delayed_start() {
+        let (token_pair, mut pool) = get_fixture();
+        pool.buy_side.source_balance = 3000000;
+        let mut order1 = Order {
+            side: OrderSide::Sell,
+            ..Default::default()
+        };
+        order1
+            .deposit(&mut pool.sell_side, 10000, &pool.schedule, 300, 0)
+            .unwrap();
+
+        // delayed order waits in a pending start slot
+        let mut order2 = Order {
+            side: OrderSide::Sell,
+            start_time: 150,
+            held_balance: 10000,
+            pending_start: true,
+            ..Default::default()
+        };
+        assert!(pool.sell_side.add_pending_start(150, 10000, true).unwrap());
+        pool.sell_side.held_balance_total = 10000;
+
+        let res = token_pair
+            .settle_pools(
+                &mut [&mut pool],
+                MatchingSide::Internal,
+                0,
+                0,
+                OraclePrice::new(3000, -2),
+                OraclePrice::new(3000, -2),
+                100,
+            )
+            .unwrap();
+        assert_eq!(res.total_amount_settled_a, 3703);
+        assert!(!{ pool.sell_side.pending_starts[0].active });
+        order2.sync_start(&mut pool.sell_side).unwrap();
+        assert!(order2.pending_start);
+
+        // settlement moves the slot to the side and releases its share since the start time
+        let res = token_pair
+            .settle_pools(
+                &mut [&mut pool],
+                MatchingSide::Internal,
+                0,
+                0,
+                OraclePrice::new(3000, -2),
+                OraclePrice::new(3000, -2),
+                200,
+            )
+            .unwrap();
+        assert_eq!(res.total_amount_settled_a, 3704 + 4166);
+        assert_eq!({ pool.sell_side.held_balance_total }, 0);
+        assert!({ pool.sell_side.pending_starts[0].active });
+        assert_eq!(
+            { pool.sell_side.source_balance },
+            20000 - 3703 - 3704 - 4166
+        );
+
+        // the order claims the slot's lp and the slot is freed
+        let slot_lp_amount = pool.sell_side.pending_starts[0].lp_amount;
+        order2.sync_start(&mut pool.sell_side).unwrap();
+        assert!(!order2.pending_start);
+        assert_eq!(order2.held_balance, 0);
+        assert_eq!(order2.lp_balance, slot_lp_amount);
+        assert_eq!(order2.unsettled_balance, 10000);
+        assert_eq!(order2.last_balance_change_time, 150);
+        assert_eq!({ pool.sell_side.pending_starts[0].start_time }, 0);
+        assert_eq!(
+            { pool.sell_side.lp_supply },
+            order1.lp_balance + order2.lp_balance
         );
     }
+
+    #[test]
+    fn settle_test_delayed_top_up() {
+        let (token_pair, mut pool) = get_fixture();
+        pool.buy_side.source_balance = 3000000;
+        let mut order = Order {
+            side: OrderSide::Sell,
+            start_time: 150,
+            held_balance: 10000,
+            pending_start: true,
+            ..Default::default()
+        };
+        assert!(pool.sell_side.add_pending_start(150, 10000, true).unwrap());
+        pool.sell_side.held_balance_total = 10000;
+
+        // top-up of the delayed order goes to its pending start slot
+        assert!(order
+            .add_pending_balance(&mut pool.sell_side, 5000)
+            .unwrap());
+        assert_eq!(order.held_balance, 15000);
+        assert_eq!({ pool.sell_side.held_balance_total }, 15000);
+        assert_eq!({ pool.sell_side.pending_starts[0].balance }, 15000);
+        assert_eq!({ pool.sell_side.pending_starts[0].num_orders }, 1);
+
+        // the slot joins the side with the whole balance and the order claims it
+        token_pair
+            .settle_pools(
+                &mut [&mut pool],
+                MatchingSide::Internal,
+                0,
+                0,
+                OraclePrice::new(3000, -2),
+                OraclePrice::new(3000, -2),
+                200,
+            )
+            .unwrap();
+        assert_eq!({ pool.sell_side.held_balance_total }, 0);
+        assert!(!order
+            .add_pending_balance(&mut pool.sell_side, 5000)
+            .unwrap());
+        assert!(!order.pending_start);
+        assert_eq!(order.held_balance, 0);
+        assert_eq!(order.unsettled_balance, 15000);
+        assert_eq!(order.lp_balance, { pool.sell_side.lp_supply });
+        assert_eq!({ pool.sell_side.pending_starts[0].start_time }, 0);
+
+        // orders that are not delayed are left to the caller
+        let mut order = Order {
+            side: OrderSide::Sell,
+            ..Default::default()
+        };
+        assert!(!order
+            .add_pending_balance(&mut pool.sell_side, 5000)
+            .unwrap());
+        assert_eq!(order.held_balance, 0);
+        assert_eq!({ pool.sell_side.held_balance_total }, 0);
+    }
+
+    #[test]
+    fn test_pending_start_slots() {
+        let (_, mut pool) = get_fixture();
+        let side = &mut pool.sell_side;
+        for time in [110, 120, 130, 140] {
+            assert!(side.add_pending_start(time, 100, true).unwrap());
+        }
+        // orders with the same start time share a slot
+        assert!(side.add_pending_start(120, 100, true).unwrap());
+        assert_eq!({ side.pending_starts[1].num_orders }, 2);
+        assert_eq!({ side.pending_starts[1].balance }, 200);
+        // no free slot for another start time, the order is held instead
+        assert!(!side.add_pending_start(150, 100, true).unwrap());
+
+        // order leaving before the start frees the slot once it is empty
+        let mut order = Order {
+            start_time: 110,
+            held_balance: 100,
+            pending_start: true,
+            ..Default::default()
+        };
+        order.remove_pending_start(side).unwrap();
+        assert!(!order.pending_start);
+        assert_eq!({ side.pending_starts[0].start_time }, 0);
+        assert!(side.add_pending_start(150, 100, true).unwrap());
+        assert_eq!({ side.pending_starts[0].start_time }, 150);
+
+        side.remove_pending_start(120, 100).unwrap();
+        assert_eq!({ side.pending_starts[1].num_orders }, 1);
+        assert_eq!({ side.pending_starts[1].balance }, 100);
+    }
+
+    #[test]
+    fn settle_test_virtual_pool() {
+        let (mut token_pair, _) = get_fixture();
+        token_pair.config_b.decimals = 9;
+
+        let mut virtual_pool = VirtualPool {
+            interval: 100,
+            ..Default::default()
+        };
+        let mut new_order = |side, amount: u64, expiration_time| {
+            let order = VirtualOrder {
+                side,
+                amount,
+                sell_rate: amount as u128 * VirtualPool::RATE_SCALE
+                    / (expiration_time - 100) as u128,
+                start_time: 100,
+                expiration_time,
+                ..Default::default()
+            };
+            virtual_pool.add_order(&order).unwrap();
+            order
+        };
+        let order1 = new_order(OrderSide::Sell, 1000, 300);
+        let order2 = new_order(OrderSide::Sell, 500, 200);
+        let order3 = new_order(OrderSide::Buy, 3000, 400);
+
+        // orders are matched internally while active
+        let mut window_pool = virtual_pool.get_window_pool(150).unwrap();
+        assert_eq!({ window_pool.sell_side.source_balance }, 500);
+        assert_eq!({ window_pool.buy_side.source_balance }, 500);
+        let res = token_pair
+            .settle_pools(
+                &mut [&mut window_pool],
+                MatchingSide::Internal,
+                0,
+                0,
+                OraclePrice::new(1, 0),
+                OraclePrice::new(1, 0),
+                150,
+            )
+            .unwrap();
+        assert_eq!(res.settlement_side, MatchingSide::Internal);
+        virtual_pool.execute(&window_pool, 150).unwrap();
+
+        assert_eq!(
+            virtual_pool.get_order_balances(&order1, &[]).unwrap(),
+            (750, 250)
+        );
+        assert_eq!(
+            virtual_pool.get_order_balances(&order2, &[]).unwrap(),
+            (250, 250)
+        );
+        assert_eq!(
+            virtual_pool.get_order_balances(&order3, &[]).unwrap(),
+            (2500, 500)
+        );
+
+        // order2 leaves at its expiration, unsettled buy amount is carried forward
+        let mut window_pool = virtual_pool.get_window_pool(250).unwrap();
+        assert_eq!({ window_pool.sell_side.source_balance }, 750);
+        assert_eq!({ window_pool.buy_side.source_balance }, 1000);
+        let res = token_pair
+            .settle_pools(
+                &mut [&mut window_pool],
+                MatchingSide::Internal,
+                0,
+                0,
+                OraclePrice::new(1, 0),
+                OraclePrice::new(1, 0),
+                250,
+            )
+            .unwrap();
+        assert_eq!(res.settlement_side, MatchingSide::Buy);
+        assert_eq!(res.net_amount_required, 250);
+        virtual_pool.execute(&window_pool, 250).unwrap();
+
+        assert_eq!(
+            virtual_pool.get_order_balances(&order1, &[]).unwrap(),
+            (250, 750)
+        );
+        assert_eq!(
+            virtual_pool.get_order_balances(&order2, &[]).unwrap(),
+            (0, 500)
+        );
+        assert_eq!(
+            virtual_pool.get_order_balances(&order3, &[]).unwrap(),
+            (1750, 1250)
+        );
+        assert_eq!(virtual_pool.sell_side.sell_rate, order1.sell_rate);
+        assert_eq!({ virtual_pool.sell_side.last_update_time }, 250);
+        assert_eq!({ virtual_pool.buy_side.last_update_time }, 225);
+        assert_eq!({ virtual_pool.last_update_time }, 225);
+        let window_pool = virtual_pool.get_window_pool(250).unwrap();
+        assert_eq!({ window_pool.buy_side.source_balance }, 250);
+        assert_eq!({ window_pool.sell_side.source_balance }, 0);
+
+        assert_eq!(
+            virtual_pool.remove_order(&order2, &mut []).unwrap(),
+            (0, 500)
+        );
+        assert_eq!(virtual_pool.sell_side.source_balance, 250);
+        assert_eq!(virtual_pool.sell_side.target_balance, 750);
+        assert_eq!(virtual_pool.buy_side.source_balance, 1750);
+        assert_eq!(virtual_pool.buy_side.target_balance, 1250);
+    }
+
+    #[test]
+    fn settle_test_exact_output() {
+        let (token_pair, mut pool) = get_fixture();
+        pool.sell_output_side.source_balance = 10000;
+        pool.sell_output_side.target_pending = 150;
+        pool.buy_side.source_balance = 40000;
+
+        // exact-output side only spends the budget required for its target
+        let res = token_pair
+            .settle_pools(
+                &mut [&mut pool],
+                MatchingSide::Internal,
+                0,
+                0,
+                OraclePrice::new(3000, -2),
+                OraclePrice::new(3000, -2),
+                300,
+            )
+            .unwrap();
+
+        assert_eq!(
+            Settlement {
+                net_amount_settled: 0,
+                net_amount_required: 39850,
+                source_amount_received: 0,
+                total_amount_settled_a: 5000,
+                total_amount_settled_b: 150,
+                settlement_side: MatchingSide::Buy,
+            },
+            res
+        );
+        assert_eq!({ pool.sell_output_side.source_balance }, 5000);
+        assert_eq!({ pool.sell_output_side.target_balance }, 150);
+        assert!(pool.sell_output_side.is_target_settled());
+        assert_eq!({ pool.buy_side.target_balance }, 5000);
+
+        // supplied fills at a better price are capped at the target
+        let (token_pair, mut pool) = get_fixture();
+        pool.sell_output_side.source_balance = 10000;
+        pool.sell_output_side.target_pending = 150;
+
+        let res = token_pair
+            .settle_pools(
+                &mut [&mut pool],
+                MatchingSide::Buy,
+                10000,
+                310,
+                OraclePrice::new(3100, -2),
+                OraclePrice::new(3000, -2),
+                300,
+            )
+            .unwrap();
+
+        assert_eq!(
+            Settlement {
+                net_amount_settled: 4838,
+                net_amount_required: 5000,
+                source_amount_received: 150,
+                total_amount_settled_a: 4838,
+                total_amount_settled_b: 0,
+                settlement_side: MatchingSide::Sell,
+            },
+            res
+        );
+        assert_eq!({ pool.sell_output_side.source_balance }, 5162);
+        assert_eq!({ pool.sell_output_side.target_balance }, 150);
+        assert_eq!({ pool.sell_output_side.settlement_debt_total }, 0);
+        assert!(pool.sell_output_side.is_target_settled());
+    }
+
+    #[test]
+    fn settle_test_exact_output_budgets() {
+        let (token_pair, mut pool) = get_fixture();
+        pool.buy_side.source_balance = 40000;
+        let schedule = pool.schedule;
+        let mut order1 = Order {
+            side: OrderSide::Sell,
+            exact_output: true,
+            ..Default::default()
+        };
+        let mut order2 = order1.clone();
+
+        // each order is bounded by the average price of its own budget
+        let limit_price1 = token_pair
+            .get_budget_price(OrderSide::Sell, 10000, 150)
+            .unwrap();
+        let limit_price2 = token_pair
+            .get_budget_price(OrderSide::Sell, 6000, 150)
+            .unwrap();
+        assert_eq!(limit_price1, OraclePrice::new(15_000_000_000, -9));
+        assert_eq!(limit_price2, OraclePrice::new(25_000_000_000, -9));
+        order1
+            .deposit_target(
+                &mut pool.sell_output_side,
+                150,
+                10000,
+                limit_price1,
+                &schedule,
+                300,
+                0,
+            )
+            .unwrap();
+        order2
+            .deposit_target(
+                &mut pool.sell_output_side,
+                150,
+                6000,
+                limit_price2,
+                &schedule,
+                300,
+                0,
+            )
+            .unwrap();
+        assert_eq!({ pool.sell_output_side.source_balance }, 16000);
+        assert_eq!({ pool.sell_output_side.budget_total }, 16000);
+        assert_eq!({ pool.sell_output_side.min_price }, 25_000_000_000);
+        assert_eq!({ pool.sell_output_side.num_bounded_orders }, 2);
+        assert_eq!(order1.lp_balance, order2.lp_balance);
+
+        let res = token_pair
+            .settle_pools(
+                &mut [&mut pool],
+                MatchingSide::Internal,
+                0,
+                0,
+                OraclePrice::new(3000, -2),
+                OraclePrice::new(3000, -2),
+                300,
+            )
+            .unwrap();
+        assert_eq!(res.total_amount_settled_a, 10000);
+        assert_eq!(res.total_amount_settled_b, 300);
+        assert_eq!({ pool.sell_output_side.source_balance }, 6000);
+        assert_eq!({ pool.sell_output_side.target_balance }, 300);
+        assert_eq!(pool.sell_output_side.get_budget_spent(), 10000);
+
+        // spent budget is shared by target, the rest goes back to each order
+        assert_eq!(
+            order1
+                .get_budget_withdrawal(&pool.sell_output_side, order1.lp_balance)
+                .unwrap(),
+            (5000, 10000, 0)
+        );
+        assert_eq!(
+            order2
+                .get_budget_withdrawal(&pool.sell_output_side, order2.lp_balance)
+                .unwrap(),
+            (1000, 6000, 0)
+        );
+
+        // a budget below the pair price pauses the side instead of overspending
+        let (token_pair, mut pool) = get_fixture();
+        pool.buy_side.source_balance = 40000;
+        let mut order3 = Order {
+            side: OrderSide::Sell,
+            exact_output: true,
+            ..Default::default()
+        };
+        let limit_price3 = token_pair
+            .get_budget_price(OrderSide::Sell, 4000, 150)
+            .unwrap();
+        assert_eq!(limit_price3, OraclePrice::new(37_500_000_000, -9));
+        order3
+            .deposit_target(
+                &mut pool.sell_output_side,
+                150,
+                4000,
+                limit_price3,
+                &schedule,
+                300,
+                0,
+            )
+            .unwrap();
+
+        let res = token_pair
+            .settle_pools(
+                &mut [&mut pool],
+                MatchingSide::Internal,
+                0,
+                0,
+                OraclePrice::new(3000, -2),
+                OraclePrice::new(3000, -2),
+                300,
+            )
+            .unwrap();
+        assert_eq!(res.total_amount_settled_a, 0);
+        assert_eq!({ pool.sell_output_side.source_balance }, 4000);
+        assert_eq!({ pool.sell_output_side.settlement_debt_total }, 150);
+
+        let res = token_pair
+            .settle_pools(
+                &mut [&mut pool],
+                MatchingSide::Internal,
+                0,
+                0,
+                OraclePrice::new(4000, -2),
+                OraclePrice::new(4000, -2),
+                300,
+            )
+            .unwrap();
+        assert_eq!(res.total_amount_settled_a, 3750);
+        assert_eq!({ pool.sell_output_side.target_balance }, 150);
+        assert_eq!(
+            order3
+                .get_budget_withdrawal(&pool.sell_output_side, order3.lp_balance)
+                .unwrap(),
+            (250, 4000, 0)
+        );
+    }
+
+    #[test]
+    fn test_schedule_shapes() {
+        let pool_side = PoolSide {
+            source_balance: 8000,
+            ..Default::default()
+        };
+        let mut schedule = Schedule::default();
+        assert_eq!(
+            pool_side.get_unsettled_amount(&schedule, 330, 150).unwrap(),
+            4000
+        );
+
+        schedule.shape = ScheduleShape::new(ScheduleKind::FrontLoaded, [0; 8]);
+        assert_eq!(
+            pool_side.get_unsettled_amount(&schedule, 330, 150).unwrap(),
+            7529
+        );
+
+        schedule.shape = ScheduleShape::new(ScheduleKind::BackLoaded, [0; 8]);
+        assert_eq!(
+            pool_side.get_unsettled_amount(&schedule, 330, 150).unwrap(),
+            470
+        );
+
+        schedule.shape = ScheduleShape::new(ScheduleKind::Custom, [0, 0, 0, 0, 1, 1, 1, 1]);
+        assert_eq!(
+            pool_side.get_unsettled_amount(&schedule, 330, 150).unwrap(),
+            0
+        );
+
+        // nothing is left to release on schedule after the first segment
+        schedule.shape = ScheduleShape::new(ScheduleKind::Custom, [1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            pool_side.get_unsettled_amount(&schedule, 330, 100).unwrap(),
+            8000
+        );
+        assert!(!ScheduleShape::new(ScheduleKind::Custom, [0; 8]).validate());
+    }
+
+    #[test]
+    fn test_pool_slots() {
+        let (mut token_pair, _) = get_fixture();
+        let mut slots = vec![
+            PoolSlot {
+                time_in_force: 300,
+                pool_counter: 2,
+                ..Default::default()
+            },
+            PoolSlot::default(),
+        ];
+        token_pair.set_num_slots(&[], 2).unwrap();
+        assert!(token_pair.validate_slots(&slots));
+        assert!(!token_pair.validate_slots(&slots[..1]));
+
+        // slots follow the header in the account data
+        let mut data = TokenPair::discriminator().to_vec();
+        data.extend_from_slice(bytemuck::bytes_of(&token_pair));
+        data.extend_from_slice(bytemuck::cast_slice(&slots));
+        assert_eq!(data.len(), TokenPair::get_len(2));
+
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let account = AccountInfo::new(
+            &key,
+            false,
+            true,
+            &mut lamports,
+            &mut data,
+            &crate::ID,
+            false,
+            0,
+        );
+        let loader = AccountLoader::<TokenPair>::try_from(&account).unwrap();
+        {
+            let (token_pair, mut loaded) = TokenPair::load_slots_mut(&loader).unwrap();
+            assert_eq!(loaded.len(), 2);
+            assert_eq!({ loaded[0].pool_counter }, 2);
+            loaded[1].time_in_force = 600;
+            assert!(token_pair.validate_slots(&loaded));
+        }
+        assert_eq!(TokenPair::load_pool_counter(&loader, 300).unwrap(), 2);
+        assert_eq!(
+            { TokenPair::load_slots(&loader).unwrap().1[1].time_in_force },
+            600
+        );
+        assert!(TokenPair::load_pool_counter(&loader, 900).is_err());
+
+        // slots that had pools can't be removed, unused ones can
+        slots[1].time_in_force = 600;
+        slots[1].current_pool_present = true;
+        assert!(token_pair.set_num_slots(&slots, 1).is_err());
+        slots[1] = PoolSlot {
+            time_in_force: 600,
+            ..Default::default()
+        };
+        token_pair.set_num_slots(&slots, 1).unwrap();
+        assert_eq!(token_pair.get_num_slots(), 1);
+        assert!(token_pair.validate_slots(&slots[..1]));
+        assert!(token_pair
+            .set_num_slots(&slots, TokenPair::MAX_POOLS + 1)
+            .is_err());
+
+        // duplicate time in force values are rejected
+        slots[1].time_in_force = 300;
+        token_pair.set_num_slots(&[], 2).unwrap();
+        assert!(!token_pair.validate_slots(&slots));
+    }
+
+    #[test]
+    fn test_order_trigger() {
+        let mut stop_order = StopOrder {
+            side: OrderSide::Sell,
+            trigger_price: OraclePrice::new(2500, -2),
+            ..Default::default()
+        };
+        assert!(!stop_order
+            .is_trigger_reached(&OraclePrice::new(3000, -2))
+            .unwrap());
+        assert!(stop_order
+            .is_trigger_reached(&OraclePrice::new(2500, -2))
+            .unwrap());
+        assert!(stop_order
+            .is_trigger_reached(&OraclePrice::new(25, 0))
+            .unwrap());
+
+        stop_order.side = OrderSide::Buy;
+        assert!(stop_order
+            .is_trigger_reached(&OraclePrice::new(3000, -2))
+            .unwrap());
+        assert!(!stop_order
+            .is_trigger_reached(&OraclePrice::new(2000, -2))
+            .unwrap());
+    }
+
+    #[test]
+    fn test_init_current_pool_and_order() {
+        let mut slots = [
+            PoolSlot::default(),
+            PoolSlot {
+                time_in_force: 900,
+                pool_counter: 4,
+                ..Default::default()
+            },
+        ];
+        let token_pair_key = Pubkey::new_unique();
+
+        let mut pool = Pool::default();
+        pool.init_current(&mut slots[1], token_pair_key, 400, 255)
+            .unwrap();
+        assert!(slots[1].current_pool_present);
+        assert!(!slots[0].current_pool_present);
+        assert_eq!({ pool.time_in_force }, 900);
+        assert_eq!({ pool.expiration_time }, 1300);
+        assert_eq!({ pool.counter }, 4);
+        assert_eq!({ pool.schedule.start_time }, 400);
+        assert_eq!({ pool.token_pair }, token_pair_key);
+        assert_eq!(pool.bump, 255);
+
+        let owner = Pubkey::new_unique();
+        let mut order = Order {
+            lp_balance: 100,
+            held_balance: 50,
+            limit_price: OraclePrice::new(2500, -2),
+            ..Default::default()
+        };
+        order.init(owner, 7, OrderSide::Sell, Pubkey::new_unique(), 400, 254);
+        assert_eq!(order.version, Order::VERSION);
+        assert_eq!(order.owner, owner);
+        assert_eq!(order.id, 7);
+        assert_eq!(order.side, OrderSide::Sell);
+        assert_eq!(order.start_time, 400);
+        assert_eq!(order.last_balance_change_time, 400);
+        assert_eq!(order.bump, 254);
+        assert!(order.is_empty());
+        assert_eq!(order.held_balance, 0);
+        assert!(!order.has_price_conditions());
+    }
+
+    #[test]
+    fn test_subscription_orders() {
+        let mut slot = PoolSlot {
+            time_in_force: 300,
+            ..Default::default()
+        };
+        let mut subscription = Subscription {
+            id: 0,
+            side: OrderSide::Sell,
+            time_in_force: 300,
+            amount_per_period: 1000,
+            keeper_bounty: 10,
+            periods_remaining: 3,
+            ..Default::default()
+        };
+        assert_eq!(subscription.get_escrowed_amount().unwrap(), 3030);
+
+        // subscription orders never share an address with orders placed by the owner
+        let order_id = Order::get_subscription_order_id(subscription.id);
+        assert_ne!(Order::get_id_seed(order_id), Order::get_id_seed(0));
+        assert_ne!(
+            Order::get_id_seed(Order::get_subscription_order_id(5)),
+            Order::get_id_seed(5)
+        );
+        assert!(Order::validate_user_id(5).is_ok());
+        assert!(Order::validate_user_id(order_id).is_err());
+
+        // one order per pool period, each period goes to the next current pool
+        let owner = Pubkey::new_unique();
+        for period in 0..3u64 {
+            let mut pool = Pool::default();
+            pool.init_current(&mut slot, Pubkey::new_unique(), 200, 255)
+                .unwrap();
+            assert_eq!({ pool.counter }, period);
+            assert!(subscription.is_due(pool.counter));
+
+            let mut order = Order::default();
+            order.init(
+                owner,
+                order_id,
+                subscription.side,
+                Pubkey::new_unique(),
+                200,
+                254,
+            );
+            order
+                .deposit(
+                    &mut pool.sell_side,
+                    subscription.amount_per_period,
+                    &pool.schedule,
+                    pool.expiration_time,
+                    200,
+                )
+                .unwrap();
+            assert_eq!(order.lp_balance, 1000);
+            assert_eq!({ pool.sell_side.source_balance }, 1000);
+            assert_eq!({ pool.sell_side.lp_supply }, 1000);
+
+            subscription.periods_remaining -= 1;
+            subscription.periods_placed += 1;
+            subscription.last_pool_counter = pool.counter;
+            assert!(!subscription.is_due(pool.counter));
+            assert_eq!(
+                subscription.get_escrowed_amount().unwrap(),
+                (2 - period) * 1010
+            );
+
+            // pool completes and the counter rotates to the next one
+            slot.current_pool_present = false;
+            slot.pool_counter += 1;
+        }
+        assert_eq!(subscription.periods_placed, 3);
+        assert!(!subscription.is_due(slot.pool_counter));
+    }
+
+    #[test]
+    fn test_order_price_band() {
+        let mut order = Order {
+            side: OrderSide::Buy,
+            reference_price: OraclePrice::new(3000, -2),
+            max_price_deviation: 50_000_000,
+            ..Default::default()
+        };
+        assert!(order.has_price_conditions());
+        assert!(order
+            .is_price_acceptable(&OraclePrice::new(3100, -2))
+            .unwrap());
+        assert!(order
+            .is_price_acceptable(&OraclePrice::new(2900, -2))
+            .unwrap());
+        assert!(!order
+            .is_price_acceptable(&OraclePrice::new(3200, -2))
+            .unwrap());
+        assert!(!order
+            .is_price_acceptable(&OraclePrice::new(2800, -2))
+            .unwrap());
+
+        // limit price still applies inside the band
+        order.limit_price = OraclePrice::new(3050, -2);
+        assert!(!order
+            .is_price_acceptable(&OraclePrice::new(3100, -2))
+            .unwrap());
+        assert!(order
+            .is_price_acceptable(&OraclePrice::new(2900, -2))
+            .unwrap());
+        assert_eq!(
+            order.get_price_bounds().unwrap(),
+            (28_500_000_000, 30_500_000_000)
+        );
+
+        // sell limits raise the lower bound
+        order.side = OrderSide::Sell;
+        assert_eq!(
+            order.get_price_bounds().unwrap(),
+            (30_500_000_000, 31_500_000_000)
+        );
+    }
+
+    #[test]
+    fn test_get_token_pair_oracle_price() {
+        let oracle_price1 = OraclePrice {
+            price: 4004,
+            exponent: -10,
+        };
+
+        let oracle_price2 = OraclePrice {
+            price: 100001000,
+            exponent: -8,
+        };
+
+        let pair_price = oracle_price1.checked_div(&oracle_price2).unwrap();
+        assert_eq!(
+            pair_price,
+            OraclePrice {
+                price: 40039,
+                exponent: -11
+            }
+        );
+    }
+
+    #[test]
+    fn test_order_delegate() {
+        let owner = Pubkey::new_unique();
+        let delegate = Pubkey::new_unique();
+        let mut order = Order {
+            owner,
+            ..Default::default()
+        };
+        assert!(order.is_authorized(&owner));
+        assert!(!order.is_authorized(&delegate));
+        // unset delegate doesn't authorize the default key
+        assert!(!order.is_authorized(&Pubkey::default()));
+
+        order.delegate = delegate;
+        assert!(order.is_authorized(&owner));
+        assert!(order.is_authorized(&delegate));
+        assert!(!order.is_authorized(&Pubkey::new_unique()));
+        // delegate manages the order, payouts still go to the owner
+        assert_eq!(order.get_recipient(), owner);
+
+        order.delegate = Pubkey::default();
+        assert!(!order.is_authorized(&delegate));
+    }
+
+    #[test]
+    fn test_order_beneficiary() {
+        let owner = Pubkey::new_unique();
+        let beneficiary = Pubkey::new_unique();
+        let mut order = Order {
+            owner,
+            delegate: Pubkey::new_unique(),
+            ..Default::default()
+        };
+        assert_eq!(order.get_recipient(), owner);
+
+        // beneficiary receives payouts but can't manage the order
+        order.beneficiary = beneficiary;
+        assert_eq!(order.get_recipient(), beneficiary);
+        assert!(!order.is_authorized(&beneficiary));
+
+        order.beneficiary = Pubkey::default();
+        assert_eq!(order.get_recipient(), owner);
+    }
+
+    #[test]
+    fn test_order_id_seed() {
+        let owner = Pubkey::new_unique();
+        let pool = Pubkey::new_unique();
+        let get_order_address = |seeds: &[&[u8]]| {
+            Pubkey::find_program_address(
+                &[&[b"order", owner.as_ref(), pool.as_ref()], seeds].concat(),
+                &crate::ID,
+            )
+            .0
+        };
+
+        // default order keeps the address it had before order ids
+        let legacy_address = get_order_address(&[]);
+        assert_eq!(get_order_address(&[&Order::get_id_seed(0)]), legacy_address);
+
+        // each id gets its own order in the same pool
+        let address1 = get_order_address(&[&Order::get_id_seed(1)]);
+        let address2 = get_order_address(&[&Order::get_id_seed(2)]);
+        assert_ne!(address1, legacy_address);
+        assert_ne!(address2, legacy_address);
+        assert_ne!(address1, address2);
+        assert_eq!(address1, get_order_address(&[&1u64.to_le_bytes()]));
+    }
+
+    #[test]
+    fn test_order_len() {
+        let order = Order::default();
+        assert_eq!(Order::LEN, 8 + order.try_to_vec().unwrap().len());
+    }
+
+    #[test]
+    fn test_zero_copy_layout() {
+        // zero-copy accounts keep the declared field order, clients decode them with the IDL
+        fn offset<T, F>(base: &T, field: *const F) -> usize {
+            field as usize - base as *const T as usize
+        }
+
+        let pool = Pool::default();
+        assert_eq!(offset(&pool, std::ptr::addr_of!(pool.version)), 0);
+        assert_eq!(offset(&pool, std::ptr::addr_of!(pool.status)), 1);
+        assert_eq!(offset(&pool, std::ptr::addr_of!(pool.time_in_force)), 2);
+        assert_eq!(offset(&pool, std::ptr::addr_of!(pool.buy_side)), 46);
+        assert_eq!(
+            offset(&pool, std::ptr::addr_of!(pool.buy_side.pending_starts)),
+            46 + std::mem::size_of::<PoolSide>() - 4 * 41
+        );
+        assert_eq!(
+            offset(&pool, std::ptr::addr_of!(pool.bump)),
+            Pool::LEN - 8 - 1
+        );
+
+        let token_pair = TokenPair::default();
+        assert_eq!(
+            offset(&token_pair, std::ptr::addr_of!(token_pair.version)),
+            0
+        );
+        assert_eq!(
+            offset(&token_pair, std::ptr::addr_of!(token_pair.allow_deposits)),
+            1
+        );
+        assert_eq!(
+            offset(&token_pair, std::ptr::addr_of!(token_pair.fee_numerator)),
+            5
+        );
+        assert_eq!(
+            offset(
+                &token_pair,
+                std::ptr::addr_of!(token_pair.config_a.oracle_type)
+            ),
+            93 + 28
+        );
+        assert_eq!(
+            offset(&token_pair, std::ptr::addr_of!(token_pair.inception_time)),
+            TokenPair::LEN - 8 - 8
+        );
+
+        let multisig = Multisig::default();
+        assert_eq!(offset(&multisig, std::ptr::addr_of!(multisig.version)), 0);
+        assert_eq!(offset(&multisig, std::ptr::addr_of!(multisig.signed)), 207);
+        assert_eq!(
+            offset(&multisig, std::ptr::addr_of!(multisig.bump)),
+            Multisig::LEN - 8 - 1
+        );
+    }
+
+    struct ClockStub;
+
+    impl anchor_lang::solana_program::program_stubs::SyscallStubs for ClockStub {
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe {
+                *(var_addr as *mut Clock) = Clock {
+                    epoch: 1,
+                    ..Clock::default()
+                };
+            }
+            anchor_lang::solana_program::entrypoint::SUCCESS
+        }
+    }
+
+    // Token-2022 mint with 1% fee capped at 50 tokens in the current epoch 1
+    // and 2% fee from epoch 2
+    fn get_fee_mint_data() -> Vec<u8> {
+        use spl_token_2022::extension::{
+            transfer_fee::TransferFee, ExtensionType, StateWithExtensionsMut,
+        };
+
+        let mut data = vec![
+            0u8;
+            ExtensionType::get_account_len::<spl_token_2022::state::Mint>(&[
+                ExtensionType::TransferFeeConfig
+            ])
+        ];
+        let mut state =
+            StateWithExtensionsMut::<spl_token_2022::state::Mint>::unpack_uninitialized(&mut data)
+                .unwrap();
+        let fee_config = state.init_extension::<TransferFeeConfig>(true).unwrap();
+        fee_config.older_transfer_fee = TransferFee {
+            epoch: 0.into(),
+            maximum_fee: 50.into(),
+            transfer_fee_basis_points: 100.into(),
+        };
+        fee_config.newer_transfer_fee = TransferFee {
+            epoch: 2.into(),
+            maximum_fee: 50.into(),
+            transfer_fee_basis_points: 200.into(),
+        };
+        state.base = spl_token_2022::state::Mint {
+            decimals: 6,
+            is_initialized: true,
+            ..Default::default()
+        };
+        state.pack_base();
+        state.init_account_type().unwrap();
+        data
+    }
+
+    // mint data with raw type-length-value extension entries
+    fn get_extension_mint_data(extensions: &[(u16, u16)]) -> Vec<u8> {
+        let mut data = vec![0u8; spl_token_2022::state::Account::LEN];
+        data.push(1); // AccountType::Mint
+        for (extension_type, extension_len) in extensions {
+            data.extend_from_slice(&extension_type.to_le_bytes());
+            data.extend_from_slice(&extension_len.to_le_bytes());
+            data.resize(data.len() + *extension_len as usize, 0);
+        }
+        data
+    }
+
+    fn with_mint<T>(owner: &Pubkey, mut data: Vec<u8>, f: impl FnOnce(&AccountInfo) -> T) -> T {
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mint = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            owner,
+            false,
+            0,
+        );
+        f(&mint)
+    }
+
+    #[test]
+    fn test_transfer_fee() {
+        anchor_lang::solana_program::program_stubs::set_syscall_stubs(Box::new(ClockStub));
+
+        with_mint(&spl_token_2022::ID, get_fee_mint_data(), |mint| {
+            assert_eq!(TokenPair::get_transfer_fee(mint, 1000).unwrap(), 10);
+            assert_eq!(TokenPair::get_transfer_fee(mint, 1050).unwrap(), 11);
+            assert_eq!(TokenPair::get_transfer_fee(mint, 1_000_000).unwrap(), 50);
+            assert_eq!(TokenPair::get_transfer_fee(mint, 0).unwrap(), 0);
+
+            assert_eq!(TokenPair::get_inverse_transfer_fee(mint, 990).unwrap(), 10);
+            assert_eq!(
+                TokenPair::get_inverse_transfer_fee(mint, 1_000_000).unwrap(),
+                50
+            );
+        });
+
+        // legacy mints and Token-2022 mints without the extension have no fee
+        let mut legacy_data = vec![0u8; spl_token_2022::state::Mint::LEN];
+        with_mint(&anchor_spl::token::ID, legacy_data.clone(), |mint| {
+            assert_eq!(TokenPair::get_transfer_fee(mint, 1000).unwrap(), 0);
+            assert_eq!(TokenPair::get_inverse_transfer_fee(mint, 1000).unwrap(), 0);
+        });
+        spl_token_2022::state::Mint::pack(
+            spl_token_2022::state::Mint {
+                decimals: 6,
+                is_initialized: true,
+                ..Default::default()
+            },
+            &mut legacy_data,
+        )
+        .unwrap();
+        with_mint(&spl_token_2022::ID, legacy_data, |mint| {
+            assert_eq!(TokenPair::get_transfer_fee(mint, 1000).unwrap(), 0);
+            assert_eq!(TokenPair::get_inverse_transfer_fee(mint, 1000).unwrap(), 0);
+        });
+    }
+
+    #[test]
+    fn test_transfer_fee_deposit_and_payout() {
+        anchor_lang::solana_program::program_stubs::set_syscall_stubs(Box::new(ClockStub));
+
+        with_mint(&spl_token_2022::ID, get_fee_mint_data(), |mint| {
+            // deposits credit the amount custody receives after the fee
+            for amount in [1u64, 99, 1000, 1234, 4999, 5000, 5001, 1_000_000] {
+                let credited = amount - TokenPair::get_transfer_fee(mint, amount).unwrap();
+                assert!(credited <= amount);
+                assert!(amount - credited <= 50);
+            }
+            assert_eq!(1000 - TokenPair::get_transfer_fee(mint, 1000).unwrap(), 990);
+
+            // payouts grossed up by the inverse fee deliver the full amount
+            for amount in [1u64, 99, 990, 1234, 4950, 4951, 1_000_000] {
+                let gross = amount + TokenPair::get_inverse_transfer_fee(mint, amount).unwrap();
+                assert_eq!(
+                    gross - TokenPair::get_transfer_fee(mint, gross).unwrap(),
+                    amount
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn test_native_tokens() {
+        let (token_pair, _) = get_fixture();
+        let native_mint = anchor_spl::token::spl_token::native_mint::ID;
+        assert!(TokenPair::is_native_mint(&native_mint));
+        assert!(!TokenPair::is_native_mint(&Pubkey::new_unique()));
+
+        // payout accounts are separate per payer
+        let payer = Pubkey::new_unique();
+        let (payout_key, payout_bump) = TokenPair::get_native_payout_address(&payer);
+        assert_eq!(
+            Pubkey::create_program_address(
+                &[b"native_payout", payer.as_ref(), &[payout_bump]],
+                &crate::ID
+            )
+            .unwrap(),
+            payout_key
+        );
+        assert_ne!(
+            TokenPair::get_native_payout_address(&Pubkey::new_unique()).0,
+            payout_key
+        );
+
+        // lamports can't stand in for other mints
+        with_mint(&anchor_spl::token::ID, vec![], |mint| {
+            let err = error!(TwammError::InvalidTokenAccount);
+            assert_eq!(
+                TokenPair::deposit_native_tokens(
+                    mint.clone(),
+                    mint.clone(),
+                    mint.clone(),
+                    mint.clone(),
+                    mint.clone(),
+                    100
+                )
+                .unwrap_err(),
+                err
+            );
+            assert_eq!(
+                token_pair
+                    .withdraw_native_tokens(
+                        mint.clone(),
+                        mint.clone(),
+                        mint.clone(),
+                        mint.clone(),
+                        mint.clone(),
+                        mint.clone(),
+                        mint.clone(),
+                        mint.clone(),
+                        100
+                    )
+                    .unwrap_err(),
+                err
+            );
+        });
+
+        // native payouts must go through the payer's payout account
+        let mut lamports = 0;
+        let mut data = vec![];
+        let mint = AccountInfo::new(
+            &native_mint,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &anchor_spl::token::ID,
+            false,
+            0,
+        );
+        let withdraw = |amount| {
+            token_pair.withdraw_native_tokens(
+                mint.clone(),
+                mint.clone(),
+                mint.clone(),
+                mint.clone(),
+                mint.clone(),
+                mint.clone(),
+                mint.clone(),
+                mint.clone(),
+                amount,
+            )
+        };
+        assert!(withdraw(0).is_ok());
+        assert_eq!(
+            withdraw(100).unwrap_err(),
+            error!(TwammError::InvalidTokenAccount)
+        );
+    }
+
+    #[test]
+    fn test_validate_mint_extensions() {
+        // legacy mints and mints without extensions
+        with_mint(
+            &anchor_spl::token::ID,
+            vec![0u8; spl_token_2022::state::Mint::LEN],
+            |mint| assert!(TokenPair::validate_mint_extensions(mint).is_ok()),
+        );
+        with_mint(
+            &spl_token_2022::ID,
+            vec![0u8; spl_token_2022::state::Mint::LEN],
+            |mint| assert!(TokenPair::validate_mint_extensions(mint).is_ok()),
+        );
+
+        // transfer fee and metadata are allowed
+        with_mint(&spl_token_2022::ID, get_fee_mint_data(), |mint| {
+            assert!(TokenPair::validate_mint_extensions(mint).is_ok())
+        });
+        with_mint(
+            &spl_token_2022::ID,
+            get_extension_mint_data(&[(1, 108), (18, 64), (19, 120)]),
+            |mint| assert!(TokenPair::validate_mint_extensions(mint).is_ok()),
+        );
+
+        // mint close authority, confidential transfers, default account state,
+        // non-transferable, interest bearing, permanent delegate and transfer hook
+        for extension_type in [3u16, 4, 6, 9, 10, 12, 14, 16] {
+            with_mint(
+                &spl_token_2022::ID,
+                get_extension_mint_data(&[(1, 108), (extension_type, 32), (18, 64)]),
+                |mint| {
+                    assert!(TokenPair::validate_mint_extensions(mint).is_err());
+                },
+            );
+        }
+    }
 }
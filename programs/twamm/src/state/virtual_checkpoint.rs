@@ -0,0 +1,15 @@
+use {crate::state::virtual_pool::VirtualBucket, anchor_lang::prelude::*};
+
+/// Keeps a virtual pool bucket after it has been released from the ring,
+/// until all orders that refer to it are cancelled
+#[account]
+#[derive(Default, Debug)]
+pub struct VirtualCheckpoint {
+    pub virtual_pool: Pubkey,
+    pub bucket: VirtualBucket,
+    pub bump: u8,
+}
+
+impl VirtualCheckpoint {
+    pub const LEN: usize = 8 + std::mem::size_of::<VirtualCheckpoint>();
+}
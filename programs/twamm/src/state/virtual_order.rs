@@ -0,0 +1,26 @@
+use {crate::state::order::OrderSide, anchor_lang::prelude::*};
+
+#[account]
+#[derive(Default, Debug)]
+pub struct VirtualOrder {
+    pub owner: Pubkey,
+    pub time: i64,
+    pub side: OrderSide,
+    pub virtual_pool: Pubkey,
+    pub amount: u64,
+    // source tokens released per second, scaled by VirtualPool::RATE_SCALE
+    pub sell_rate: u128,
+    // interval boundaries when the order joins and leaves the pool
+    pub start_time: i64,
+    pub expiration_time: i64,
+    pub bump: u8,
+}
+
+impl VirtualOrder {
+    pub const LEN: usize = 8 + std::mem::size_of::<VirtualOrder>();
+
+    /// Checks if the order has been executed up to the given time
+    pub fn is_complete(&self, last_update_time: i64) -> bool {
+        self.expiration_time <= last_update_time
+    }
+}
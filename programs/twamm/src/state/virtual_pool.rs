@@ -0,0 +1,795 @@
+//! VirtualPool struct tracks aggregate sell rates of orders with arbitrary expirations
+
+use {
+    crate::{
+        error::TwammError,
+        math,
+        state::{
+            order::OrderSide,
+            pool::{Pool, PoolStatus},
+            virtual_order::VirtualOrder,
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Copy, Clone, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct VirtualPoolSide {
+    // aggregate sell rate of active orders, scaled by VirtualPool::RATE_SCALE
+    pub sell_rate: u128,
+    // cumulative amounts of source tokens sold and target tokens received
+    // per unit of sell rate, scaled by VirtualPool::FACTOR_SCALE
+    pub sold_factor: u128,
+    pub earnings_factor: u128,
+    pub source_balance: u64,
+    pub target_balance: u64,
+    pub num_traders: u64,
+    // time up to which orders of the side have been executed
+    pub last_update_time: i64,
+}
+
+#[derive(Copy, Clone, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct VirtualBucketSide {
+    pub starting_rate: u128,
+    pub expiring_rate: u128,
+    // side factors recorded when the bucket time is reached
+    pub sold_factor: u128,
+    pub earnings_factor: u128,
+}
+
+#[derive(Copy, Clone, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct VirtualBucket {
+    pub time: i64,
+    // number of orders that start or expire at the bucket time
+    pub num_orders: u64,
+    pub buy_side: VirtualBucketSide,
+    pub sell_side: VirtualBucketSide,
+}
+
+#[account]
+#[derive(Debug)]
+pub struct VirtualPool {
+    pub token_pair: Pubkey,
+    // order start and expiration times are aligned to this interval
+    pub interval: u32,
+    // time up to which orders of both sides have been executed
+    pub last_update_time: i64,
+    pub buy_side: VirtualPoolSide,
+    pub sell_side: VirtualPoolSide,
+    // ring buffer of rate changes indexed by interval boundaries
+    pub buckets: [VirtualBucket; 64], // VirtualPool::MAX_BUCKETS
+    pub bump: u8,
+}
+
+impl Default for VirtualPool {
+    fn default() -> Self {
+        Self {
+            token_pair: Pubkey::default(),
+            interval: 0,
+            last_update_time: 0,
+            buy_side: VirtualPoolSide::default(),
+            sell_side: VirtualPoolSide::default(),
+            buckets: [VirtualBucket::default(); VirtualPool::MAX_BUCKETS],
+            bump: 0,
+        }
+    }
+}
+
+impl VirtualBucket {
+    pub fn get_side(&self, side: OrderSide) -> &VirtualBucketSide {
+        if side == OrderSide::Buy {
+            &self.buy_side
+        } else {
+            &self.sell_side
+        }
+    }
+
+    pub fn get_side_mut(&mut self, side: OrderSide) -> &mut VirtualBucketSide {
+        if side == OrderSide::Buy {
+            &mut self.buy_side
+        } else {
+            &mut self.sell_side
+        }
+    }
+}
+
+impl VirtualPool {
+    pub const LEN: usize = 8 + std::mem::size_of::<VirtualPool>();
+    pub const MAX_BUCKETS: usize = 64;
+    pub const RATE_SCALE: u128 = 1_000_000_000;
+    pub const FACTOR_SCALE: u128 = 1_000_000_000_000_000_000;
+
+    pub fn get_side(&self, side: OrderSide) -> &VirtualPoolSide {
+        if side == OrderSide::Buy {
+            &self.buy_side
+        } else {
+            &self.sell_side
+        }
+    }
+
+    pub fn get_side_mut(&mut self, side: OrderSide) -> &mut VirtualPoolSide {
+        if side == OrderSide::Buy {
+            &mut self.buy_side
+        } else {
+            &mut self.sell_side
+        }
+    }
+
+    /// Sets the time both sides are executed from
+    pub fn set_last_update_time(&mut self, time: i64) {
+        self.last_update_time = time;
+        self.buy_side.last_update_time = time;
+        self.sell_side.last_update_time = time;
+    }
+
+    /// Checks if the order has been executed up to its expiration time
+    pub fn is_order_complete(&self, order: &VirtualOrder) -> bool {
+        order.is_complete(self.get_side(order.side).last_update_time)
+    }
+
+    /// Returns the last interval boundary at or before the given time
+    pub fn get_boundary(&self, time: i64) -> Result<i64> {
+        let interval = self.interval as i64;
+        math::checked_mul(math::checked_div(time, interval)?, interval)
+    }
+
+    /// Returns the first interval boundary after the given time
+    pub fn get_next_boundary(&self, time: i64) -> Result<i64> {
+        math::checked_add(self.get_boundary(time)?, self.interval as i64)
+    }
+
+    /// Returns the maximum distance between order start and expiration times
+    pub fn get_max_duration(&self) -> Result<i64> {
+        math::checked_mul(self.interval as i64, (VirtualPool::MAX_BUCKETS - 1) as i64)
+    }
+
+    fn get_bucket_index(&self, time: i64) -> Result<usize> {
+        Ok(
+            (math::checked_div(time, self.interval as i64)? as u64
+                % VirtualPool::MAX_BUCKETS as u64) as usize,
+        )
+    }
+
+    /// Returns the index of the bucket assigned to the given boundary time
+    fn find_bucket(&self, time: i64) -> Result<Option<usize>> {
+        let index = self.get_bucket_index(time)?;
+        let bucket = &self.buckets[index];
+        if bucket.time == time && bucket.num_orders > 0 {
+            Ok(Some(index))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the bucket assigned to the given boundary time
+    pub fn get_bucket(&self, time: i64) -> Result<&VirtualBucket> {
+        if let Some(index) = self.find_bucket(time)? {
+            Ok(&self.buckets[index])
+        } else {
+            msg!("Error: Bucket is not assigned to time {}", time);
+            err!(TwammError::InvalidPoolState)
+        }
+    }
+
+    fn get_bucket_mut(&mut self, time: i64) -> Result<&mut VirtualBucket> {
+        if let Some(index) = self.find_bucket(time)? {
+            Ok(&mut self.buckets[index])
+        } else {
+            msg!("Error: Bucket is not assigned to time {}", time);
+            err!(TwammError::InvalidPoolState)
+        }
+    }
+
+    /// Assigns the bucket to the given boundary time and increments the number of orders
+    /// that refer to it. A bucket the pool has advanced past is released for reuse and
+    /// returned, so it can be kept in a checkpoint account for the orders that still refer to it.
+    fn reserve_bucket(&mut self, time: i64) -> Result<Option<VirtualBucket>> {
+        let index = self.get_bucket_index(time)?;
+        let last_update_time = self.last_update_time;
+        let bucket = &mut self.buckets[index];
+        let mut evicted = None;
+        if bucket.time != time {
+            if bucket.num_orders > 0 {
+                if bucket.time > last_update_time {
+                    msg!(
+                        "Error: Bucket is scheduled for {} and can't be reused",
+                        bucket.time
+                    );
+                    return err!(TwammError::InvalidExpirationTime);
+                }
+                evicted = Some(*bucket);
+            }
+            *bucket = VirtualBucket {
+                time,
+                ..VirtualBucket::default()
+            };
+        }
+        bucket.num_orders = math::checked_add(bucket.num_orders, 1)?;
+        Ok(evicted)
+    }
+
+    /// Decrements the number of orders that refer to the bucket, which is either
+    /// in the ring or in one of the given checkpoints
+    fn release_bucket(&mut self, time: i64, checkpoints: &mut [VirtualBucket]) -> Result<()> {
+        let bucket = if self.find_bucket(time)?.is_some() {
+            self.get_bucket_mut(time)?
+        } else {
+            VirtualPool::find_checkpoint_mut(time, checkpoints)?
+        };
+        bucket.num_orders = math::checked_sub(bucket.num_orders, 1)?;
+        Ok(())
+    }
+
+    fn find_checkpoint_mut(
+        time: i64,
+        checkpoints: &mut [VirtualBucket],
+    ) -> Result<&mut VirtualBucket> {
+        if let Some(bucket) = checkpoints
+            .iter_mut()
+            .find(|bucket| bucket.time == time && bucket.num_orders > 0)
+        {
+            Ok(bucket)
+        } else {
+            msg!("Error: Checkpoint for time {} is missing", time);
+            err!(TwammError::InvalidPoolState)
+        }
+    }
+
+    /// Returns the bucket recorded for the given boundary time, either from the ring
+    /// or from one of the given checkpoints
+    fn get_snapshot<'a>(
+        &'a self,
+        time: i64,
+        checkpoints: &'a [VirtualBucket],
+    ) -> Result<&'a VirtualBucket> {
+        if let Some(index) = self.find_bucket(time)? {
+            Ok(&self.buckets[index])
+        } else if let Some(bucket) = checkpoints
+            .iter()
+            .find(|bucket| bucket.time == time && bucket.num_orders > 0)
+        {
+            Ok(bucket)
+        } else {
+            msg!("Error: Checkpoint for time {} is missing", time);
+            err!(TwammError::InvalidPoolState)
+        }
+    }
+
+    /// Schedules a new order to be executed between its start and expiration times.
+    /// Returns buckets released to make room for the order.
+    pub fn add_order(&mut self, order: &VirtualOrder) -> Result<Vec<VirtualBucket>> {
+        if order.start_time <= self.get_side(order.side).last_update_time
+            || order.expiration_time <= order.start_time
+            || math::checked_sub(order.expiration_time, order.start_time)?
+                > self.get_max_duration()?
+        {
+            return err!(TwammError::InvalidExpirationTime);
+        }
+
+        let evicted: Vec<VirtualBucket> = [
+            self.reserve_bucket(order.start_time)?,
+            self.reserve_bucket(order.expiration_time)?,
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        let start_bucket = self
+            .get_bucket_mut(order.start_time)?
+            .get_side_mut(order.side);
+        start_bucket.starting_rate =
+            math::checked_add(start_bucket.starting_rate, order.sell_rate)?;
+
+        let end_bucket = self
+            .get_bucket_mut(order.expiration_time)?
+            .get_side_mut(order.side);
+        end_bucket.expiring_rate = math::checked_add(end_bucket.expiring_rate, order.sell_rate)?;
+
+        let pool_side = self.get_side_mut(order.side);
+        pool_side.source_balance = math::checked_add(pool_side.source_balance, order.amount)?;
+        pool_side.num_traders = math::checked_add(pool_side.num_traders, 1)?;
+
+        Ok(evicted)
+    }
+
+    /// Removes the order from the pool and returns amounts of source and target tokens
+    /// to be paid out. Checkpoints must include buckets of the order that have been
+    /// released from the ring.
+    pub fn remove_order(
+        &mut self,
+        order: &VirtualOrder,
+        checkpoints: &mut [VirtualBucket],
+    ) -> Result<(u64, u64)> {
+        let (source_amount, target_amount) = self.get_order_balances(order, checkpoints)?;
+
+        let last_update_time = self.get_side(order.side).last_update_time;
+        if order.start_time > last_update_time {
+            let start_bucket = self
+                .get_bucket_mut(order.start_time)?
+                .get_side_mut(order.side);
+            start_bucket.starting_rate =
+                math::checked_sub(start_bucket.starting_rate, order.sell_rate)?;
+        } else if !order.is_complete(last_update_time) {
+            let pool_side = self.get_side_mut(order.side);
+            pool_side.sell_rate = math::checked_sub(pool_side.sell_rate, order.sell_rate)?;
+        }
+        if !order.is_complete(last_update_time) {
+            let end_bucket = self
+                .get_bucket_mut(order.expiration_time)?
+                .get_side_mut(order.side);
+            end_bucket.expiring_rate =
+                math::checked_sub(end_bucket.expiring_rate, order.sell_rate)?;
+        }
+
+        self.release_bucket(order.start_time, checkpoints)?;
+        self.release_bucket(order.expiration_time, checkpoints)?;
+
+        let pool_side = self.get_side_mut(order.side);
+        pool_side.source_balance = pool_side.source_balance.saturating_sub(source_amount);
+        pool_side.target_balance = pool_side.target_balance.saturating_sub(target_amount);
+        pool_side.num_traders = math::checked_sub(pool_side.num_traders, 1)?;
+
+        Ok((source_amount, target_amount))
+    }
+
+    /// Returns amounts of unsold source tokens and received target tokens of the order
+    pub fn get_order_balances(
+        &self,
+        order: &VirtualOrder,
+        checkpoints: &[VirtualBucket],
+    ) -> Result<(u64, u64)> {
+        if order.start_time > self.get_side(order.side).last_update_time {
+            return Ok((order.amount, 0));
+        }
+
+        let start = self
+            .get_snapshot(order.start_time, checkpoints)?
+            .get_side(order.side);
+        let (sold_factor, earnings_factor) = if self.is_order_complete(order) {
+            let end = self
+                .get_snapshot(order.expiration_time, checkpoints)?
+                .get_side(order.side);
+            (end.sold_factor, end.earnings_factor)
+        } else {
+            let pool_side = self.get_side(order.side);
+            (pool_side.sold_factor, pool_side.earnings_factor)
+        };
+
+        // sold amount is rounded up and received amount is rounded down to
+        // keep the pool solvent
+        let sold_amount = std::cmp::min(
+            math::checked_as_u64(math::checked_ceil_div(
+                math::checked_mul(
+                    order.sell_rate,
+                    math::checked_sub(sold_factor, start.sold_factor)?,
+                )?,
+                VirtualPool::FACTOR_SCALE,
+            )?)?,
+            order.amount,
+        );
+        let received_amount = math::checked_as_u64(math::checked_div(
+            math::checked_mul(
+                order.sell_rate,
+                math::checked_sub(earnings_factor, start.earnings_factor)?,
+            )?,
+            VirtualPool::FACTOR_SCALE,
+        )?)?;
+
+        Ok((
+            math::checked_sub(order.amount, sold_amount)?,
+            received_amount,
+        ))
+    }
+
+    /// Returns interval boundaries with scheduled rate changes since the last update
+    /// of the side up to the given time
+    fn get_rate_change_times(&self, side: OrderSide, current_time: i64) -> Vec<i64> {
+        let last_update_time = self.get_side(side).last_update_time;
+        let mut times: Vec<i64> = self
+            .buckets
+            .iter()
+            .filter(|bucket| {
+                bucket.num_orders > 0
+                    && bucket.time > last_update_time
+                    && bucket.time <= current_time
+            })
+            .map(|bucket| bucket.time)
+            .collect();
+        times.sort_unstable();
+        if times.last() != Some(&current_time) {
+            times.push(current_time);
+        }
+        times
+    }
+
+    /// Returns amounts of source tokens released by the side since its last update,
+    /// split into windows of constant sell rates
+    fn get_released_amounts(
+        &self,
+        side: OrderSide,
+        current_time: i64,
+    ) -> Result<Vec<(i64, i64, u64)>> {
+        let mut res = vec![];
+        let pool_side = self.get_side(side);
+        if current_time <= pool_side.last_update_time {
+            return Ok(res);
+        }
+
+        let mut window_start = pool_side.last_update_time;
+        let mut sell_rate = pool_side.sell_rate;
+        for window_end in self.get_rate_change_times(side, current_time) {
+            let duration = math::checked_sub(window_end, window_start)? as u128;
+            res.push((
+                window_start,
+                window_end,
+                math::checked_as_u64(math::checked_div(
+                    math::checked_mul(sell_rate, duration)?,
+                    VirtualPool::RATE_SCALE,
+                )?)?,
+            ));
+
+            if let Some(index) = self.find_bucket(window_end)? {
+                let bucket = self.buckets[index].get_side(side);
+                sell_rate = math::checked_sub(
+                    math::checked_add(sell_rate, bucket.starting_rate)?,
+                    bucket.expiring_rate,
+                )?;
+            }
+            window_start = window_end;
+        }
+
+        Ok(res)
+    }
+
+    /// Returns a transient pool holding source tokens released since the last update,
+    /// so they can be settled together with regular pools
+    pub fn get_window_pool(&self, current_time: i64) -> Result<Pool> {
+        let mut pool = Pool {
+            status: PoolStatus::Expired,
+            expiration_time: current_time,
+            token_pair: self.token_pair,
+            ..Pool::default()
+        };
+        for side in [OrderSide::Buy, OrderSide::Sell] {
+            let pool_side = pool.get_side_mut(side, false);
+            for (_, _, released) in self.get_released_amounts(side, current_time)? {
+                pool_side.source_balance = math::checked_add(pool_side.source_balance, released)?;
+            }
+            pool_side.last_balance_change_time = current_time;
+        }
+
+        Ok(pool)
+    }
+
+    /// Distributes results of the window pool settlement between orders and advances
+    /// the pool to the given time
+    pub fn execute(&mut self, window_pool: &Pool, current_time: i64) -> Result<()> {
+        self.execute_side(
+            OrderSide::Buy,
+            window_pool.buy_side.source_balance,
+            window_pool.buy_side.target_balance,
+            current_time,
+        )?;
+        self.execute_side(
+            OrderSide::Sell,
+            window_pool.sell_side.source_balance,
+            window_pool.sell_side.target_balance,
+            current_time,
+        )?;
+        self.last_update_time = std::cmp::min(
+            self.buy_side.last_update_time,
+            self.sell_side.last_update_time,
+        );
+
+        Ok(())
+    }
+
+    /// Settled amounts fill released windows in order and the side is only advanced up
+    /// to the time they cover. Released tokens that haven't been settled are carried
+    /// forward and released again with the next window pool.
+    fn execute_side(
+        &mut self,
+        side: OrderSide,
+        unsettled: u64,
+        received: u64,
+        current_time: i64,
+    ) -> Result<()> {
+        let windows = self.get_released_amounts(side, current_time)?;
+        if windows.is_empty() {
+            return Ok(());
+        }
+
+        let mut released = 0u64;
+        for (_, _, window_released) in windows.iter() {
+            released = math::checked_add(released, *window_released)?;
+        }
+        let sold = math::checked_sub(released, unsettled)?;
+
+        // split received amount between windows pro rata to sold amounts
+        let mut cumulative_sold = 0u64;
+        for (window_start, window_end, window_released) in windows {
+            let prev_cumulative_sold = cumulative_sold;
+            let window_sold =
+                std::cmp::min(window_released, math::checked_sub(sold, cumulative_sold)?);
+            cumulative_sold = math::checked_add(cumulative_sold, window_sold)?;
+
+            VirtualPool::update_factors(
+                self.get_side_mut(side),
+                window_sold,
+                VirtualPool::get_share(received, prev_cumulative_sold, cumulative_sold, sold)?,
+            )?;
+
+            // stop at the time covered by the settled amount, rounded up so
+            // carried amount is never released twice
+            if window_sold < window_released {
+                let duration = math::checked_sub(window_end, window_start)?;
+                let covered = math::checked_as_u64(math::checked_ceil_div(
+                    math::checked_mul(duration as u128, window_sold as u128)?,
+                    window_released as u128,
+                )?)? as i64;
+                if covered < duration {
+                    self.get_side_mut(side).last_update_time =
+                        math::checked_add(window_start, covered)?;
+                    break;
+                }
+            }
+
+            // record factors for orders that start or expire at the window end
+            // and apply scheduled rate changes
+            let pool_side = *self.get_side(side);
+            if let Some(index) = self.find_bucket(window_end)? {
+                let bucket = self.buckets[index].get_side_mut(side);
+                bucket.sold_factor = pool_side.sold_factor;
+                bucket.earnings_factor = pool_side.earnings_factor;
+                let bucket = *bucket;
+
+                self.get_side_mut(side).sell_rate = math::checked_sub(
+                    math::checked_add(pool_side.sell_rate, bucket.starting_rate)?,
+                    bucket.expiring_rate,
+                )?;
+            }
+            self.get_side_mut(side).last_update_time = window_end;
+        }
+
+        let pool_side = self.get_side_mut(side);
+        pool_side.source_balance = pool_side.source_balance.saturating_sub(sold);
+        pool_side.target_balance = math::checked_add(pool_side.target_balance, received)?;
+
+        Ok(())
+    }
+
+    fn get_share(amount: u64, prev_cumulative: u64, cumulative: u64, total: u64) -> Result<u64> {
+        if total == 0 {
+            return Ok(0);
+        }
+        let get_cumulative_share = |cumulative: u64| -> Result<u64> {
+            math::checked_as_u64(math::checked_div(
+                math::checked_mul(amount as u128, cumulative as u128)?,
+                total as u128,
+            )?)
+        };
+        math::checked_sub(
+            get_cumulative_share(cumulative)?,
+            get_cumulative_share(prev_cumulative)?,
+        )
+    }
+
+    fn update_factors(pool_side: &mut VirtualPoolSide, sold: u64, received: u64) -> Result<()> {
+        if pool_side.sell_rate == 0 {
+            return Ok(());
+        }
+        pool_side.sold_factor = math::checked_add(
+            pool_side.sold_factor,
+            math::checked_ceil_div(
+                math::checked_mul(sold as u128, VirtualPool::FACTOR_SCALE)?,
+                pool_side.sell_rate,
+            )?,
+        )?;
+        pool_side.earnings_factor = math::checked_add(
+            pool_side.earnings_factor,
+            math::checked_div(
+                math::checked_mul(received as u128, VirtualPool::FACTOR_SCALE)?,
+                pool_side.sell_rate,
+            )?,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn new_order(
+        side: OrderSide,
+        amount: u64,
+        start_time: i64,
+        expiration_time: i64,
+    ) -> VirtualOrder {
+        VirtualOrder {
+            side,
+            amount,
+            sell_rate: amount as u128 * VirtualPool::RATE_SCALE
+                / (expiration_time - start_time) as u128,
+            start_time,
+            expiration_time,
+            ..Default::default()
+        }
+    }
+
+    // settles the given amount of released sell tokens at 1:2 and advances the pool
+    fn execute_partial(virtual_pool: &mut VirtualPool, current_time: i64, sold: u64) {
+        let mut window_pool = virtual_pool.get_window_pool(current_time).unwrap();
+        window_pool.sell_side.source_balance -= sold;
+        window_pool.sell_side.target_balance = sold * 2;
+        virtual_pool.execute(&window_pool, current_time).unwrap();
+    }
+
+    // settles all released sell tokens at 1:2 and advances the pool
+    fn execute(virtual_pool: &mut VirtualPool, current_time: i64) {
+        let window_pool = virtual_pool.get_window_pool(current_time).unwrap();
+        execute_partial(
+            virtual_pool,
+            current_time,
+            window_pool.sell_side.source_balance,
+        );
+    }
+
+    #[test]
+    fn test_bucket_wrap_around() {
+        let mut virtual_pool = VirtualPool {
+            interval: 100,
+            ..Default::default()
+        };
+
+        let order1 = new_order(OrderSide::Sell, 1000, 100, 200);
+        assert!(virtual_pool.add_order(&order1).unwrap().is_empty());
+        execute(&mut virtual_pool, 300);
+        assert_eq!(
+            virtual_pool.get_order_balances(&order1, &[]).unwrap(),
+            (0, 2000)
+        );
+
+        // scheduled buckets can't be reused by the next ring cycle
+        let scheduled = new_order(OrderSide::Buy, 1000, 400, 500);
+        virtual_pool.add_order(&scheduled).unwrap();
+        assert!(virtual_pool
+            .add_order(&new_order(OrderSide::Buy, 1000, 6800, 6900))
+            .is_err());
+        virtual_pool.remove_order(&scheduled, &mut []).unwrap();
+
+        // buckets of the stale order are released once the pool advances past them
+        execute(&mut virtual_pool, 6450);
+        let order2 = new_order(OrderSide::Sell, 1000, 6500, 6600);
+        let mut checkpoints = virtual_pool.add_order(&order2).unwrap();
+        assert_eq!(checkpoints.len(), 2);
+        assert_eq!({ checkpoints[0].time }, 100);
+        assert_eq!({ checkpoints[1].time }, 200);
+        assert_eq!(virtual_pool.get_bucket(6500).unwrap().num_orders, 1);
+        assert_eq!(virtual_pool.get_bucket(6600).unwrap().num_orders, 1);
+
+        // the stale order needs checkpoints to be cancelled
+        assert!(virtual_pool.get_order_balances(&order1, &[]).is_err());
+        assert!(virtual_pool.remove_order(&order1, &mut []).is_err());
+        assert_eq!(
+            virtual_pool
+                .remove_order(&order1, &mut checkpoints)
+                .unwrap(),
+            (0, 2000)
+        );
+        assert_eq!(checkpoints[0].num_orders, 0);
+        assert_eq!(checkpoints[1].num_orders, 0);
+
+        // the new order is executed from the reused buckets
+        execute(&mut virtual_pool, 6700);
+        assert_eq!(
+            virtual_pool.get_order_balances(&order2, &[]).unwrap(),
+            (0, 2000)
+        );
+        assert_eq!(
+            virtual_pool.remove_order(&order2, &mut []).unwrap(),
+            (0, 2000)
+        );
+        assert_eq!(virtual_pool.sell_side.num_traders, 0);
+    }
+
+    #[test]
+    fn test_stale_bucket_shared_by_orders() {
+        let mut virtual_pool = VirtualPool {
+            interval: 100,
+            ..Default::default()
+        };
+
+        // two orders expire at the same bucket, only one of them is cancelled in time
+        let order1 = new_order(OrderSide::Sell, 1000, 100, 300);
+        let order2 = new_order(OrderSide::Sell, 2000, 200, 300);
+        virtual_pool.add_order(&order1).unwrap();
+        execute(&mut virtual_pool, 150);
+        virtual_pool.add_order(&order2).unwrap();
+        execute(&mut virtual_pool, 400);
+        let balances1 = virtual_pool.get_order_balances(&order1, &[]).unwrap();
+        let balances2 = virtual_pool.get_order_balances(&order2, &[]).unwrap();
+        virtual_pool.remove_order(&order1, &mut []).unwrap();
+
+        // a new order reuses the expiration bucket that order2 still refers to
+        execute(&mut virtual_pool, 6450);
+        let order3 = new_order(OrderSide::Buy, 1000, 6500, 6700);
+        let mut checkpoints = virtual_pool.add_order(&order3).unwrap();
+        assert_eq!(checkpoints.len(), 1);
+        assert_eq!({ checkpoints[0].time }, 300);
+        assert_eq!(checkpoints[0].num_orders, 1);
+
+        // the start bucket of order2 is still in the ring
+        assert_eq!(
+            virtual_pool
+                .get_order_balances(&order2, &checkpoints)
+                .unwrap(),
+            balances2
+        );
+        assert_eq!(
+            virtual_pool
+                .remove_order(&order2, &mut checkpoints)
+                .unwrap(),
+            balances2
+        );
+        assert_eq!(checkpoints[0].num_orders, 0);
+        assert_ne!(balances1, balances2);
+    }
+
+    #[test]
+    fn test_carry_forward_unsettled() {
+        let mut virtual_pool = VirtualPool {
+            interval: 100,
+            ..Default::default()
+        };
+        let order = new_order(OrderSide::Sell, 1000, 100, 200);
+        virtual_pool.add_order(&order).unwrap();
+
+        // the first crank settles a part of the released amount, the side is
+        // only advanced up to the time the settled amount covers
+        assert_eq!(
+            {
+                virtual_pool
+                    .get_window_pool(200)
+                    .unwrap()
+                    .sell_side
+                    .source_balance
+            },
+            1000
+        );
+        execute_partial(&mut virtual_pool, 200, 400);
+        assert_eq!({ virtual_pool.sell_side.last_update_time }, 140);
+        assert_eq!(virtual_pool.sell_side.sell_rate, order.sell_rate);
+        assert!(!virtual_pool.is_order_complete(&order));
+        assert_eq!(
+            virtual_pool.get_order_balances(&order, &[]).unwrap(),
+            (600, 800)
+        );
+
+        // the next crank releases the rest again and settles it
+        assert_eq!(
+            {
+                virtual_pool
+                    .get_window_pool(200)
+                    .unwrap()
+                    .sell_side
+                    .source_balance
+            },
+            600
+        );
+        execute_partial(&mut virtual_pool, 200, 600);
+        assert_eq!({ virtual_pool.sell_side.last_update_time }, 200);
+        assert_eq!(virtual_pool.sell_side.sell_rate, 0);
+        assert!(virtual_pool.is_order_complete(&order));
+        assert_eq!(
+            virtual_pool.get_order_balances(&order, &[]).unwrap(),
+            (0, 2000)
+        );
+        assert_eq!(
+            virtual_pool.remove_order(&order, &mut []).unwrap(),
+            (0, 2000)
+        );
+        assert_eq!(virtual_pool.sell_side.source_balance, 0);
+        assert_eq!(virtual_pool.sell_side.target_balance, 0);
+    }
+}
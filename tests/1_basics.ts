@@ -4,6 +4,17 @@ import { PublicKey, SystemProgram, SYSVAR_RENT_PUBKEY } from "@solana/web3.js";
 import * as spl from "@solana/spl-token";
 import { expect, assert } from "chai";
 
+// returns unused time in force slot state with the linear schedule
+function getSlot(timeInForce: number) {
+  return {
+    timeInForce,
+    scheduleShape: { kind: { linear: {} }, weights: Array(8).fill(0) },
+    poolCounter: new anchor.BN(0),
+    currentPoolPresent: false,
+    futurePoolPresent: false,
+  };
+}
+
 describe("basics", () => {
   let twamm = new TwammTester();
   let crankAuthority = PublicKey.default;
@@ -11,6 +22,8 @@ describe("basics", () => {
   let multisigExpected;
   // expected token pair account state
   let tokenPairExpected;
+  // expected time in force slots that follow the token pair header
+  let slotsExpected;
 
   // setup
   it("setup", async () => {
@@ -50,6 +63,7 @@ describe("basics", () => {
       twamm.multisigKey
     );
     multisigExpected = {
+      version: 1,
       numSigners: 2,
       numSigned: 0,
       minSignatures: 2,
@@ -86,11 +100,11 @@ describe("basics", () => {
         crankRewardTokenB: new anchor.BN(3),
         minSwapAmountTokenA: new anchor.BN(4),
         minSwapAmountTokenB: new anchor.BN(5),
-        maxSwapPriceDiff: 0.1,
-        maxUnsettledAmount: 0.3,
-        minTimeTillExpiration: 0.3,
-        maxOraclePriceErrorTokenA: 123.0,
-        maxOraclePriceErrorTokenB: 456.0,
+        maxSwapPriceDiff: new anchor.BN(100000000),
+        maxUnsettledAmount: new anchor.BN(300000000),
+        minTimeTillExpiration: new anchor.BN(300000000),
+        maxOraclePriceErrorTokenA: new anchor.BN(123000000000),
+        maxOraclePriceErrorTokenB: new anchor.BN(456000000000),
         maxOraclePriceAgeSecTokenA: 8,
         maxOraclePriceAgeSecTokenB: 9,
         oracleTypeTokenA: { none: {} },
@@ -104,6 +118,7 @@ describe("basics", () => {
         admin: twamm.admin1.publicKey,
         multisig: twamm.multisigKey,
         tokenPair: twamm.tokenPairKey,
+        observations: twamm.observationsKey,
         transferAuthority: twamm.authorityKey,
         mintTokenA: twamm.tokenAMint,
         mintTokenB: twamm.tokenBMint,
@@ -140,11 +155,11 @@ describe("basics", () => {
         crankRewardTokenB: new anchor.BN(3),
         minSwapAmountTokenA: new anchor.BN(4),
         minSwapAmountTokenB: new anchor.BN(5),
-        maxSwapPriceDiff: 0.1,
-        maxUnsettledAmount: 0.3,
-        minTimeTillExpiration: 0.3,
-        maxOraclePriceErrorTokenA: 123.0,
-        maxOraclePriceErrorTokenB: 456.0,
+        maxSwapPriceDiff: new anchor.BN(100000000),
+        maxUnsettledAmount: new anchor.BN(300000000),
+        minTimeTillExpiration: new anchor.BN(300000000),
+        maxOraclePriceErrorTokenA: new anchor.BN(123000000000),
+        maxOraclePriceErrorTokenB: new anchor.BN(456000000000),
         maxOraclePriceAgeSecTokenA: 8,
         maxOraclePriceAgeSecTokenB: 9,
         oracleTypeTokenA: { none: {} },
@@ -158,6 +173,7 @@ describe("basics", () => {
         admin: twamm.admin2.publicKey,
         multisig: twamm.multisigKey,
         tokenPair: twamm.tokenPairKey,
+        observations: twamm.observationsKey,
         transferAuthority: twamm.authorityKey,
         mintTokenA: twamm.tokenAMint,
         mintTokenB: twamm.tokenBMint,
@@ -176,6 +192,7 @@ describe("basics", () => {
     );
 
     tokenPairExpected = {
+      version: 1,
       allowDeposits: false,
       allowWithdrawals: false,
       allowCranks: false,
@@ -184,14 +201,14 @@ describe("basics", () => {
       feeDenominator: new anchor.BN(10),
       settleFeeNumerator: new anchor.BN(0),
       settleFeeDenominator: new anchor.BN(1),
-      maxSwapPriceDiff: 0.1,
-      maxUnsettledAmount: 0.3,
-      minTimeTillExpiration: 0.3,
+      maxSwapPriceDiff: new anchor.BN(100000000),
+      maxUnsettledAmount: new anchor.BN(300000000),
+      minTimeTillExpiration: new anchor.BN(300000000),
       crankAuthority: crankAuthority,
       configA: {
         crankReward: new anchor.BN(2),
         minSwapAmount: new anchor.BN(4),
-        maxOraclePriceError: 123.0,
+        maxOraclePriceError: new anchor.BN(123000000000),
         maxOraclePriceAgeSec: 8,
         oracleType: { none: {} },
         oracleAccount: PublicKey.default,
@@ -202,7 +219,7 @@ describe("basics", () => {
       configB: {
         crankReward: new anchor.BN(3),
         minSwapAmount: new anchor.BN(5),
-        maxOraclePriceError: 456.0,
+        maxOraclePriceError: new anchor.BN(456000000000),
         maxOraclePriceAgeSec: 9,
         oracleType: { none: {} },
         oracleAccount: PublicKey.default,
@@ -224,18 +241,19 @@ describe("basics", () => {
         routedVolumeUsd: new anchor.BN(0),
         settledVolumeUsd: new anchor.BN(0),
       },
-      tifs: [0, 2, 3, 0, 0, 0, 0, 0, 0, 10],
-      poolCounters: Array(10).fill(new anchor.BN(0)),
-      currentPoolPresent: Array(10).fill(false),
-      futurePoolPresent: Array(10).fill(false),
+      numSlots: 10,
       tokenPairBump: twamm.tokenPairBump,
       transferAuthorityBump: twamm.authorityBump,
       inceptionTime: new anchor.BN(0),
     };
+    slotsExpected = [0, 2, 3, 0, 0, 0, 0, 0, 0, 10].map((tif) => getSlot(tif));
 
     expect(JSON.stringify(tokenPair)).to.equal(
       JSON.stringify(tokenPairExpected)
     );
+    expect(JSON.stringify(await twamm.getTokenPairSlots())).to.equal(
+      JSON.stringify(slotsExpected)
+    );
   });
 
   it("setCrankAuthority", async () => {
@@ -380,9 +398,9 @@ describe("basics", () => {
       .setLimits({
         minSwapAmountTokenA: new anchor.BN(44),
         minSwapAmountTokenB: new anchor.BN(55),
-        maxSwapPriceDiff: 0.11,
-        maxUnsettledAmount: 0.22,
-        minTimeTillExpiration: 0.33,
+        maxSwapPriceDiff: new anchor.BN(110000000),
+        maxUnsettledAmount: new anchor.BN(220000000),
+        minTimeTillExpiration: new anchor.BN(330000000),
       })
       .accounts({
         admin: twamm.admin2.publicKey,
@@ -395,9 +413,9 @@ describe("basics", () => {
     let tokenPair = await twamm.program.account.tokenPair.fetch(
       twamm.tokenPairKey
     );
-    tokenPairExpected.maxSwapPriceDiff = 0.11;
-    tokenPairExpected.maxUnsettledAmount = 0.22;
-    tokenPairExpected.minTimeTillExpiration = 0.33;
+    tokenPairExpected.maxSwapPriceDiff = new anchor.BN(110000000);
+    tokenPairExpected.maxUnsettledAmount = new anchor.BN(220000000);
+    tokenPairExpected.minTimeTillExpiration = new anchor.BN(330000000);
     tokenPairExpected.configA.minSwapAmount = new anchor.BN(44);
     tokenPairExpected.configB.minSwapAmount = new anchor.BN(55);
     expect(JSON.stringify(tokenPair)).to.equal(
@@ -408,8 +426,8 @@ describe("basics", () => {
   it("setOracleConfig", async () => {
     await twamm.program.methods
       .setOracleConfig({
-        maxOraclePriceErrorTokenA: 123.5,
-        maxOraclePriceErrorTokenB: 456.5,
+        maxOraclePriceErrorTokenA: new anchor.BN(123500000000),
+        maxOraclePriceErrorTokenB: new anchor.BN(456500000000),
         maxOraclePriceAgeSecTokenA: 88,
         maxOraclePriceAgeSecTokenB: 99,
         oracleTypeTokenA: { test: {} },
@@ -428,11 +446,11 @@ describe("basics", () => {
     let tokenPair = await twamm.program.account.tokenPair.fetch(
       twamm.tokenPairKey
     );
-    tokenPairExpected.configA.maxOraclePriceError = 123.5;
+    tokenPairExpected.configA.maxOraclePriceError = new anchor.BN(123500000000);
     tokenPairExpected.configA.maxOraclePriceAgeSec = 88;
     tokenPairExpected.configA.oracleType = { test: {} };
     tokenPairExpected.configA.oracleAccount = twamm.oracleTokenAKey;
-    tokenPairExpected.configB.maxOraclePriceError = 456.5;
+    tokenPairExpected.configB.maxOraclePriceError = new anchor.BN(456500000000);
     tokenPairExpected.configB.maxOraclePriceAgeSec = 99;
     tokenPairExpected.configB.oracleType = { test: {} };
     tokenPairExpected.configB.oracleAccount = twamm.oracleTokenBKey;
@@ -502,10 +520,116 @@ describe("basics", () => {
     let tokenPair = await twamm.program.account.tokenPair.fetch(
       twamm.tokenPairKey
     );
-    tokenPairExpected.tifs = [0, 20, 3, 0, 0, 0, 0, 0, 0, 10];
+    slotsExpected[1].timeInForce = 20;
+    expect(JSON.stringify(tokenPair)).to.equal(
+      JSON.stringify(tokenPairExpected)
+    );
+    expect(JSON.stringify(await twamm.getTokenPairSlots())).to.equal(
+      JSON.stringify(slotsExpected)
+    );
+  });
+
+  it("setSchedule", async () => {
+    try {
+      await twamm.program.methods
+        .setSchedule({
+          timeInForceIndex: 1,
+          scheduleKind: { custom: {} },
+          customWeights: Array(8).fill(0),
+        })
+        .accounts({
+          admin: twamm.admin1.publicKey,
+          multisig: twamm.multisigKey,
+          tokenPair: twamm.tokenPairKey,
+        })
+        .signers([twamm.admin1])
+        .rpc();
+      assert(false, "Set empty custom schedule expected to fail");
+    } catch (_err) {}
+
+    await twamm.program.methods
+      .setSchedule({
+        timeInForceIndex: 1,
+        scheduleKind: { frontLoaded: {} },
+        customWeights: Array(8).fill(0),
+      })
+      .accounts({
+        admin: twamm.admin1.publicKey,
+        multisig: twamm.multisigKey,
+        tokenPair: twamm.tokenPairKey,
+      })
+      .signers([twamm.admin1])
+      .rpc();
+
+    let tokenPair = await twamm.program.account.tokenPair.fetch(
+      twamm.tokenPairKey
+    );
+    slotsExpected[1].scheduleShape = {
+      kind: { frontLoaded: {} },
+      weights: Array(8).fill(0),
+    };
     expect(JSON.stringify(tokenPair)).to.equal(
       JSON.stringify(tokenPairExpected)
     );
+    expect(JSON.stringify(await twamm.getTokenPairSlots())).to.equal(
+      JSON.stringify(slotsExpected)
+    );
+  });
+
+  it("setPoolSlots", async () => {
+    let setPoolSlots = async (numSlots: number) => {
+      await twamm.program.methods
+        .setPoolSlots({ numSlots })
+        .accounts({
+          admin: twamm.admin1.publicKey,
+          multisig: twamm.multisigKey,
+          tokenPair: twamm.tokenPairKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([twamm.admin1])
+        .rpc();
+    };
+    let initialLen = (
+      await twamm.provider.connection.getAccountInfo(twamm.tokenPairKey)
+    ).data.length;
+    let adminBalance = await twamm.getSolBalance(twamm.admin1.publicKey);
+
+    // the account grows by the new slots, the admin pays the rent
+    await setPoolSlots(12);
+    let info = await twamm.provider.connection.getAccountInfo(
+      twamm.tokenPairKey
+    );
+    expect(info.data.length).to.equal(initialLen + 2 * 31);
+    expect(await twamm.getSolBalance(twamm.admin1.publicKey)).to.be.lessThan(
+      adminBalance
+    );
+    slotsExpected.push(getSlot(0), getSlot(0));
+    tokenPairExpected.numSlots = 12;
+    let tokenPair = await twamm.program.account.tokenPair.fetch(
+      twamm.tokenPairKey
+    );
+    expect(JSON.stringify(tokenPair)).to.equal(
+      JSON.stringify(tokenPairExpected)
+    );
+    expect(JSON.stringify(await twamm.getTokenPairSlots())).to.equal(
+      JSON.stringify(slotsExpected)
+    );
+
+    // unused slots are removed and their rent goes back to the admin
+    await setPoolSlots(10);
+    info = await twamm.provider.connection.getAccountInfo(twamm.tokenPairKey);
+    expect(info.data.length).to.equal(initialLen);
+    slotsExpected.splice(10);
+    tokenPairExpected.numSlots = 10;
+    tokenPair = await twamm.program.account.tokenPair.fetch(
+      twamm.tokenPairKey
+    );
+    expect(JSON.stringify(tokenPair)).to.equal(
+      JSON.stringify(tokenPairExpected)
+    );
+    expect(JSON.stringify(await twamm.getTokenPairSlots())).to.equal(
+      JSON.stringify(slotsExpected)
+    );
   });
 
   it("withdrawFees", async () => {
@@ -236,4 +236,83 @@ describe("single_user", () => {
     expect(Number(tokenPair.statsA.feesCollected)).to.equal(0);
     expect(Number(tokenPair.statsB.feesCollected)).to.equal(0);
   });
+
+  it("scenario5", async () => {
+    let side: OrderSide = "sell";
+    let amount = 1000000;
+    let orderId = 1;
+    await twamm.reset(tifs, [1, 10]);
+    await twamm.setOraclePrice(tokenAPrice, tokenBPrice);
+
+    // stop orders escrow tokens out of pools
+    const [ta_balance, tb_balance] = await twamm.getBalances(0);
+    await twamm.placeStopOrder(0, side, tif, amount, 25, orderId);
+    const [ta_balance2, tb_balance2] = await twamm.getBalances(0);
+    expect(ta_balance2).to.equal(ta_balance - amount);
+    expect(tb_balance2).to.equal(tb_balance);
+
+    let err = await twamm.ensureFails(
+      twamm.triggerOrder(0, tif, orderId),
+      "triggerOrder above the stop price should've failed"
+    );
+    expect(err.error.errorCode.code).to.equal("TriggerPriceNotReached");
+
+    // trigger places the order into the pool that is current at the time
+    await twamm.setTime(400);
+    await twamm.setOraclePrice(20, tokenBPrice);
+    await twamm.triggerOrder(0, tif, orderId);
+    await twamm.ensureFails(
+      twamm.program.account.stopOrder.fetch(
+        await twamm.getStopOrderKey(0, orderId)
+      )
+    );
+    let order = await twamm.program.account.order.fetch(
+      await twamm.getOrderKey(0, tif, 0, orderId)
+    );
+    expect(Number(order.lpBalance)).to.equal(amount);
+    expect(order.pool.toBase58()).to.equal(
+      (await twamm.getPoolKey(tif, 0)).toBase58()
+    );
+
+    // cancelled stop orders are refunded in full
+    await twamm.placeStopOrder(0, side, tif, amount, 10, orderId + 1);
+    await twamm.cancelStopOrder(0, orderId + 1);
+    const [ta_balance3, tb_balance3] = await twamm.getBalances(0);
+    expect(ta_balance3).to.equal(ta_balance - amount);
+    expect(tb_balance3).to.equal(tb_balance);
+  });
+
+  it("scenario6", async () => {
+    await twamm.reset(tifs, [0, 10]);
+    const [ta_balance, tb_balance] = await twamm.getBalances(0);
+
+    // default order keeps its address, other ids are separate orders in the same pool
+    await twamm.placeOrder(0, side, tif, amount);
+    await twamm.placeOrder(0, reverseSide, tif, amount, false, 1);
+    await twamm.placeOrder(0, reverseSide, tif, amount, false, 2);
+    let order = await twamm.getOrder(0, tif);
+    expect(Number(order.id)).to.equal(0);
+    expect(Number(order.lpBalance)).to.equal(amount);
+    expect(order.side).to.deep.equal({ buy: {} });
+    for (const orderId of [1, 2]) {
+      order = await twamm.getOrder(0, tif, false, orderId);
+      expect(Number(order.id)).to.equal(orderId);
+      expect(Number(order.lpBalance)).to.equal(amount);
+      expect(order.side).to.deep.equal({ sell: {} });
+    }
+
+    // cancelling one order leaves the others running
+    await twamm.cancelOrder(0, tif, 1e15, false, 1);
+    await twamm.ensureFails(twamm.getOrder(0, tif, false, 1));
+    expect(Number((await twamm.getOrder(0, tif, false, 2)).lpBalance)).to.equal(
+      amount
+    );
+    expect(Number((await twamm.getOrder(0, tif)).lpBalance)).to.equal(amount);
+
+    await twamm.cancelOrder(0, tif, 1e15, false, 2);
+    await twamm.cancelOrder(0, tif, 1e15);
+    const [ta_balance2, tb_balance2] = await twamm.getBalances(0);
+    expect(ta_balance2).to.equal(ta_balance);
+    expect(tb_balance2).to.equal(tb_balance);
+  });
 });
@@ -552,4 +552,97 @@ describe("multi_user", () => {
     expect(ta_balances2[1] - ta_balances[1]).to.equal(-20129629630);
     expect(tb_balances2[1] - tb_balances[1]).to.equal(603888890);
   });
+
+  it("withdraw proceeds", async () => {
+    await twamm.reset(tifs, [0, 10]);
+
+    let ta_balances = [];
+    let tb_balances = [];
+    [ta_balances[0], tb_balances[0]] = await twamm.getBalances(0);
+    [ta_balances[1], tb_balances[1]] = await twamm.getBalances(1);
+
+    // place equal orders
+    await twamm.placeOrder(0, side, tif, 1e9);
+    await twamm.placeOrder(1, side, tif, 1e9);
+
+    // first user claims proceeds mid-way, the order keeps running
+    await twamm.setTime(27);
+    await twamm.settle(reverseSide, settleAmountFull);
+    await twamm.withdrawProceeds(0, tif);
+    let [ta_balance, tb_balance] = await twamm.getBalances(0);
+    expect(ta_balance - ta_balances[0]).to.equal(-1000000000);
+    expect(tb_balance - tb_balances[0]).to.be.greaterThan(0);
+    expect((await twamm.getOrder(0, tif)).lpBalance.toNumber()).to.equal(
+      1000000000
+    );
+
+    // both orders complete
+    await twamm.setTime(tif + 1);
+    await twamm.settle(reverseSide, settleAmountFull);
+
+    // withdraw followed by cancel pays the same total as cancel alone
+    let ta_balances2 = [];
+    let tb_balances2 = [];
+    for (let i = 0; i < 2; ++i) {
+      await twamm.cancelOrder(i, tif, 1e15);
+      [ta_balances2[i], tb_balances2[i]] = await twamm.getBalances(i);
+    }
+    expect(ta_balances2[0] - ta_balances[0]).to.equal(
+      ta_balances2[1] - ta_balances[1]
+    );
+    expect(tb_balances2[0] - tb_balances[0]).to.be.closeTo(
+      tb_balances2[1] - tb_balances[1],
+      1
+    );
+  });
+
+  it("transfer order", async () => {
+    await twamm.reset(tifs, [0, 10]);
+
+    let ta_balances = [];
+    let tb_balances = [];
+    [ta_balances[0], tb_balances[0]] = await twamm.getBalances(0);
+    [ta_balances[1], tb_balances[1]] = await twamm.getBalances(1);
+
+    await twamm.placeOrder(0, side, tif, 1e9);
+    await twamm.setTime(27);
+    await twamm.settle(reverseSide, settleAmountFull);
+    let order = await twamm.getOrder(0, tif);
+    let pool = await twamm.getPool(tif, 0);
+
+    // order moves to the new owner's address with its balances
+    await twamm.transferOrder(0, 1, tif);
+    await twamm.ensureFails(twamm.getOrder(0, tif));
+    let newOrder = await twamm.getOrder(1, tif);
+    expect(newOrder.owner.toBase58()).to.equal(
+      twamm.users[1].publicKey.toBase58()
+    );
+    expect(JSON.stringify(newOrder.side)).to.equal(JSON.stringify(order.side));
+    expect(JSON.stringify(newOrder.pool)).to.equal(JSON.stringify(order.pool));
+    for (const field of [
+      "lpBalance",
+      "tokenDebt",
+      "unsettledBalance",
+      "settlementDebt",
+      "heldBalance",
+      "heldProceeds",
+    ]) {
+      expect(newOrder[field].toString()).to.equal(order[field].toString());
+    }
+    expect(JSON.stringify((await twamm.getPool(tif, 0)).sellSide)).to.equal(
+      JSON.stringify(pool.sellSide)
+    );
+
+    // previous owner has nothing left to cancel, the new owner gets the proceeds
+    await twamm.ensureFails(twamm.cancelOrder(0, tif, 1e15));
+    await twamm.cancelOrder(1, tif, 1e15);
+    let [ta_balance0, tb_balance0] = await twamm.getBalances(0);
+    let [ta_balance1, tb_balance1] = await twamm.getBalances(1);
+    expect(ta_balance0 - ta_balances[0]).to.equal(-1000000000);
+    expect(tb_balance0).to.equal(tb_balances[0]);
+    expect(ta_balance1 - ta_balances[1]).to.equal(
+      Number(pool.sellSide.sourceBalance)
+    );
+    expect(tb_balance1 - tb_balances[1]).to.be.greaterThan(0);
+  });
 });
@@ -1,7 +1,7 @@
 import * as anchor from "@project-serum/anchor";
 import { TwammTester, OrderSide } from "./twamm_tester";
 import { expect, assert } from "chai";
-import { SystemProgram } from "@solana/web3.js";
+import { PublicKey, SystemProgram } from "@solana/web3.js";
 import * as spl from "@solana/spl-token";
 
 describe("fail_tests", () => {
@@ -48,6 +48,50 @@ describe("fail_tests", () => {
       expect(err.error.errorCode.code).to.equal("InvalidPoolAddress");
     }
 
+    try {
+      await twamm.program.methods
+        .placeOrder({
+          side: { sell: {} },
+          timeInForce: tif,
+          amount: new anchor.BN(amount),
+          targetAmount: new anchor.BN(0),
+          limitPrice: { price: new anchor.BN(0), exponent: 0 },
+          maxPriceDeviation: new anchor.BN(0),
+          startTime: new anchor.BN(0),
+          orderId: new anchor.BN(0),
+          autoRoll: false,
+          rollProceeds: false,
+        })
+        .accounts({
+          owner: twamm.users[0].publicKey,
+          userAccountTokenA: null,
+          userAccountTokenB: twamm.tokenBWallets[0],
+          tokenPair: twamm.tokenPairKey,
+          oracleTokenA: twamm.oracleTokenAKey,
+          oracleTokenB: twamm.oracleTokenBKey,
+          mintTokenA: twamm.tokenAMint,
+          mintTokenB: twamm.tokenBMint,
+          custodyTokenA: twamm.tokenACustodyKey,
+          custodyTokenB: twamm.tokenBCustodyKey,
+          order: await twamm.getOrderKey(0, tif, 0),
+          currentPool: await twamm.getPoolKey(tif, 0),
+          targetPool: await twamm.getPoolKey(tif, 0),
+          transferAuthority: null,
+          positionMint: null,
+          userPositionAccount: null,
+          systemProgram: SystemProgram.programId,
+          tokenProgramA: spl.TOKEN_PROGRAM_ID,
+          tokenProgramB: spl.TOKEN_PROGRAM_ID,
+          tokenProgram: null,
+          associatedTokenProgram: null,
+        })
+        .signers([twamm.users[0]])
+        .rpc();
+      assert(false, "placeOrder with lamports for a non-native mint should've failed");
+    } catch (err) {
+      expect(err.error.errorCode.code).to.equal("InvalidTokenAccount");
+    }
+
     let [ta_balance, tb_balance] = await twamm.getBalances(0);
     await twamm.placeOrder(0, "buy", tif, amount);
 
@@ -75,4 +119,217 @@ describe("fail_tests", () => {
     expect(ta_balance).to.equal(ta_balance2);
     expect(tb_balance).to.equal(tb_balance2);
   });
+
+  it("intents", async () => {
+    await twamm.reset(tifs, [0, 10]);
+    await twamm.approveIntents(0, side, amount * 2);
+
+    let err = await twamm.ensureFails(
+      twamm.submitOrderIntent(0, side, tif, amount, 1, 1),
+      "submitOrderIntent signed by another user should've failed"
+    );
+    expect(err.error.errorCode.code).to.equal("InvalidIntentSignature");
+
+    let [ta_balance, tb_balance] = await twamm.getBalances(0);
+    await twamm.submitOrderIntent(0, side, tif, amount, 1);
+    let [ta_balance2, tb_balance2] = await twamm.getBalances(0);
+    expect(ta_balance - ta_balance2).to.equal(amount);
+    expect(tb_balance2).to.equal(tb_balance);
+
+    await twamm.ensureFails(
+      twamm.submitOrderIntent(0, side, tif, amount, 1),
+      "replayed submitOrderIntent should've failed"
+    );
+    let [ta_balance3, tb_balance3] = await twamm.getBalances(0);
+    expect(ta_balance3).to.equal(ta_balance2);
+
+    // the nonce is kept until the intent expires, then its rent goes back to the relayer
+    let nonceKey = await twamm.getIntentNonceKey(0, 1);
+    let intentNonce = await twamm.program.account.intentNonce.fetch(nonceKey);
+    expect(intentNonce.payer.toBase58()).to.equal(
+      twamm.users[3].publicKey.toBase58()
+    );
+    err = await twamm.ensureFails(
+      twamm.closeIntentNonce(0, 1),
+      "closeIntentNonce before the intent expiry should've failed"
+    );
+    expect(err.error.errorCode.code).to.equal("IntentNotExpired");
+
+    await twamm.setTime(intentNonce.expiry.toNumber() + 1);
+    await twamm.ensureFails(
+      twamm.closeIntentNonce(0, 1, 0),
+      "closeIntentNonce to another payer should've failed"
+    );
+    let relayerBalance = await twamm.getSolBalance(twamm.users[3].publicKey);
+    let nonceRent = await twamm.getSolBalance(nonceKey);
+    await twamm.closeIntentNonce(0, 1);
+    expect(await twamm.getSolBalance(twamm.users[3].publicKey)).to.equal(
+      relayerBalance + nonceRent
+    );
+    await twamm.ensureFails(
+      twamm.program.account.intentNonce.fetch(nonceKey),
+      "closed intent nonce account should be gone"
+    );
+  });
+
+  it("delegates", async () => {
+    await twamm.reset(tifs, [0, 10]);
+    await twamm.placeOrder(0, side, tif, amount);
+
+    let err = await twamm.ensureFails(
+      twamm.updateOrder(1, 0, tif, 0, true),
+      "updateOrder by a non-delegate should've failed"
+    );
+    expect(err.error.errorCode.code).to.equal("OrderNotAuthorized");
+
+    // delegate manages the order
+    await twamm.setOrderDelegate(0, tif, twamm.users[1].publicKey);
+    await twamm.updateOrder(1, 0, tif, 0, true);
+    expect((await twamm.getOrder(0, tif)).autoRoll).to.equal(true);
+
+    // but can't hand the order or its payouts to anyone else
+    await twamm.ensureFails(
+      twamm.setOrderDelegate(0, tif, twamm.users[2].publicKey, 1),
+      "setOrderDelegate by the delegate should've failed"
+    );
+    await twamm.ensureFails(
+      twamm.setOrderBeneficiary(0, tif, twamm.users[1].publicKey, 1),
+      "setOrderBeneficiary by the delegate should've failed"
+    );
+    await twamm.ensureFails(
+      twamm.cancelOrderAs(1, 0, 1, tif, amount),
+      "cancelOrder to the delegate's accounts should've failed"
+    );
+
+    // delegate cancels with payouts to the owner
+    let [ta_balance0, tb_balance0] = await twamm.getBalances(0);
+    let [ta_balance1, tb_balance1] = await twamm.getBalances(1);
+    await twamm.cancelOrderAs(1, 0, 0, tif, amount / 2);
+    let [ta_balance0_2, tb_balance0_2] = await twamm.getBalances(0);
+    let [ta_balance1_2, tb_balance1_2] = await twamm.getBalances(1);
+    expect(ta_balance0_2 - ta_balance0).to.equal(amount / 2);
+    expect(tb_balance0_2).to.equal(tb_balance0);
+    expect(ta_balance1_2).to.equal(ta_balance1);
+    expect(tb_balance1_2).to.equal(tb_balance1);
+
+    // removed delegate has no access
+    await twamm.setOrderDelegate(0, tif, PublicKey.default);
+    err = await twamm.ensureFails(
+      twamm.updateOrder(1, 0, tif, 0, false),
+      "updateOrder by a removed delegate should've failed"
+    );
+    expect(err.error.errorCode.code).to.equal("OrderNotAuthorized");
+    await twamm.ensureFails(
+      twamm.cancelOrderAs(1, 0, 0, tif, amount),
+      "cancelOrder by a removed delegate should've failed"
+    );
+
+    await twamm.cancelOrder(0, tif, amount);
+  });
+
+  it("beneficiary", async () => {
+    await twamm.reset(tifs, [0, 10]);
+    await twamm.placeOrder(0, side, tif, amount);
+    await twamm.setOrderBeneficiary(0, tif, twamm.users[2].publicKey);
+    await twamm.setOrderDelegate(0, tif, twamm.users[1].publicKey);
+
+    // delegate cancels only to the beneficiary's token accounts
+    for (const recipientId of [0, 1]) {
+      await twamm.ensureFails(
+        twamm.cancelOrderAs(1, 0, recipientId, tif, amount / 4),
+        "delegate cancelOrder to non-beneficiary accounts should've failed"
+      );
+    }
+    let [ta_balance0, tb_balance0] = await twamm.getBalances(0);
+    let [ta_balance2, tb_balance2] = await twamm.getBalances(2);
+    await twamm.cancelOrderAs(1, 0, 2, tif, amount / 4);
+    let [ta_balance0_2, tb_balance0_2] = await twamm.getBalances(0);
+    let [ta_balance2_2, tb_balance2_2] = await twamm.getBalances(2);
+    expect(ta_balance0_2).to.equal(ta_balance0);
+    expect(tb_balance0_2).to.equal(tb_balance0);
+    expect(ta_balance2_2 - ta_balance2).to.equal(amount / 4);
+    expect(tb_balance2_2).to.equal(tb_balance2);
+
+    // owner can still pick any accounts
+    await twamm.cancelOrderAs(0, 0, 0, tif, amount / 4);
+    [ta_balance0, tb_balance0] = await twamm.getBalances(0);
+    expect(ta_balance0 - ta_balance0_2).to.equal(amount / 4);
+
+    // permissionless cancel of a completed pool pays only the beneficiary
+    await twamm.setTime(tif * 3);
+    for (const recipientId of [0, 3]) {
+      await twamm.ensureFails(
+        twamm.cancelOrderAs(3, 0, recipientId, tif, amount),
+        "permissionless cancelOrder to non-beneficiary accounts should've failed"
+      );
+    }
+    await twamm.cancelOrderAs(3, 0, 2, tif, amount);
+    [ta_balance2, tb_balance2] = await twamm.getBalances(2);
+    expect(ta_balance2 - ta_balance2_2).to.equal(amount / 2);
+    await twamm.ensureFails(twamm.getOrder(0, tif));
+  });
+
+  it("positionTokens", async () => {
+    await twamm.reset(tifs, [0, 10]);
+    await twamm.setTime(10);
+    let positionMint = await twamm.getPositionMintKey(tif, side, 0);
+    let placeOrder = async (startTime: number) => {
+      await twamm.program.methods
+        .placeOrder({
+          side: { sell: {} },
+          timeInForce: tif,
+          amount: new anchor.BN(amount),
+          targetAmount: new anchor.BN(0),
+          limitPrice: { price: new anchor.BN(0), exponent: 0 },
+          maxPriceDeviation: new anchor.BN(0),
+          startTime: new anchor.BN(startTime),
+          orderId: new anchor.BN(0),
+          autoRoll: false,
+          rollProceeds: false,
+        })
+        .accounts({
+          owner: twamm.users[0].publicKey,
+          userAccountTokenA: twamm.tokenAWallets[0],
+          userAccountTokenB: twamm.tokenBWallets[0],
+          tokenPair: twamm.tokenPairKey,
+          oracleTokenA: twamm.oracleTokenAKey,
+          oracleTokenB: twamm.oracleTokenBKey,
+          mintTokenA: twamm.tokenAMint,
+          mintTokenB: twamm.tokenBMint,
+          custodyTokenA: twamm.tokenACustodyKey,
+          custodyTokenB: twamm.tokenBCustodyKey,
+          order: await twamm.getOrderKey(0, tif, 0),
+          currentPool: await twamm.getPoolKey(tif, 0),
+          targetPool: await twamm.getPoolKey(tif, 0),
+          transferAuthority: twamm.authorityKey,
+          positionMint,
+          userPositionAccount: await spl.getAssociatedTokenAddress(
+            positionMint,
+            twamm.users[0].publicKey
+          ),
+          systemProgram: SystemProgram.programId,
+          tokenProgramA: spl.TOKEN_PROGRAM_ID,
+          tokenProgramB: spl.TOKEN_PROGRAM_ID,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+          associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        })
+        .signers([twamm.users[0]])
+        .rpc();
+    };
+
+    // delayed deposits are held until the start time, there is no lp to mint tokens for
+    let [ta_balance, tb_balance] = await twamm.getBalances(0);
+    let err = await twamm.ensureFails(
+      placeOrder(100),
+      "placeOrder with position tokens and no lp credited should've failed"
+    );
+    expect(err.error.errorCode.code).to.equal("OrderNotTokenizable");
+    let [ta_balance2, tb_balance2] = await twamm.getBalances(0);
+    expect(ta_balance2).to.equal(ta_balance);
+    expect(tb_balance2).to.equal(tb_balance);
+
+    await placeOrder(0);
+    [ta_balance2, tb_balance2] = await twamm.getBalances(0);
+    expect(ta_balance - ta_balance2).to.equal(amount);
+  });
 });
@@ -8,6 +8,8 @@ import {
   AccountMeta,
   TransactionSignature,
   SYSVAR_RENT_PUBKEY,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
+  Ed25519Program,
 } from "@solana/web3.js";
 import * as spl from "@solana/spl-token";
 
@@ -42,6 +44,9 @@ export class TwammTester {
   tokenPairKey: PublicKey;
   tokenPairBump: number;
 
+  observationsKey: PublicKey;
+  observationsBump: number;
+
   authorityKey: PublicKey;
   authorityBump: number;
 
@@ -156,6 +161,15 @@ export class TwammTester {
         this.program.programId
       );
 
+    [this.observationsKey, this.observationsBump] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode("observations")),
+          this.tokenPairKey.toBuffer(),
+        ],
+        this.program.programId
+      );
+
     [this.authorityKey, this.authorityBump] =
       await PublicKey.findProgramAddress(
         [Buffer.from(anchor.utils.bytes.utf8.encode("transfer_authority"))],
@@ -370,6 +384,36 @@ export class TwammTester {
     return this.program.account.pool.fetch(await this.getPoolKey(tif, counter));
   };
 
+  // decodes time in force slots that follow the token pair header
+  getTokenPairSlots = async () => {
+    const headerLen = 444;
+    const slotLen = 31;
+    const scheduleKinds = ["linear", "frontLoaded", "backLoaded", "custom"];
+    let info = await this.provider.connection.getAccountInfo(
+      this.tokenPairKey
+    );
+    let slots = [];
+    for (
+      let offset = headerLen;
+      offset + slotLen <= info.data.length;
+      offset += slotLen
+    ) {
+      let data = info.data.subarray(offset, offset + slotLen);
+      let weights = [];
+      for (let i = 0; i < 8; ++i) {
+        weights.push(data.readUInt16LE(5 + i * 2));
+      }
+      slots.push({
+        timeInForce: data.readUInt32LE(0),
+        scheduleShape: { kind: { [scheduleKinds[data[4]]]: {} }, weights },
+        poolCounter: new anchor.BN(data.subarray(21, 29), "le"),
+        currentPoolPresent: data[29] != 0,
+        futurePoolPresent: data[30] != 0,
+      });
+    }
+    return slots;
+  };
+
   printPoolSide = async (
     name: string,
     side: OrderSide,
@@ -398,21 +442,86 @@ export class TwammTester {
     );
   };
 
-  getOrderKey = async (userId: number, tif: number, poolCounter?: number) => {
+  getOrderKey = async (
+    userId: number,
+    tif: number,
+    poolCounter?: number,
+    orderId?: number
+  ) => {
     let [orderKey, orderBump] = await PublicKey.findProgramAddress(
       [
         Buffer.from(anchor.utils.bytes.utf8.encode("order")),
         this.users[userId].publicKey.toBuffer(),
         (await this.getPoolKey(tif, poolCounter)).toBuffer(),
+        orderId
+          ? new anchor.BN(orderId).toArrayLike(Buffer, "le", 8)
+          : Buffer.alloc(0),
       ],
       this.program.programId
     );
     return orderKey;
   };
 
-  getOrder = async (userId: number, tif: number, nextPool?: boolean) => {
+  getStopOrderKey = async (userId: number, orderId: number) => {
+    let [stopOrderKey, stopOrderBump] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from(anchor.utils.bytes.utf8.encode("stop_order")),
+        this.users[userId].publicKey.toBuffer(),
+        this.tokenPairKey.toBuffer(),
+        new anchor.BN(orderId).toArrayLike(Buffer, "le", 8),
+      ],
+      this.program.programId
+    );
+    return stopOrderKey;
+  };
+
+  getIntentAuthorityKey = async (userId: number) => {
+    let [authorityKey, authorityBump] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from(anchor.utils.bytes.utf8.encode("intent_authority")),
+        this.users[userId].publicKey.toBuffer(),
+      ],
+      this.program.programId
+    );
+    return authorityKey;
+  };
+
+  getIntentNonceKey = async (userId: number, nonce: number) => {
+    let [nonceKey, nonceBump] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from(anchor.utils.bytes.utf8.encode("intent_nonce")),
+        this.users[userId].publicKey.toBuffer(),
+        new anchor.BN(nonce).toArrayLike(Buffer, "le", 8),
+      ],
+      this.program.programId
+    );
+    return nonceKey;
+  };
+
+  getPositionMintKey = async (
+    tif: number,
+    side: OrderSide,
+    poolCounter?: number
+  ) => {
+    let [mintKey, mintBump] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from(anchor.utils.bytes.utf8.encode("position_mint")),
+        (await this.getPoolKey(tif, poolCounter)).toBuffer(),
+        Buffer.from([side === "sell" ? 1 : 0]),
+      ],
+      this.program.programId
+    );
+    return mintKey;
+  };
+
+  getOrder = async (
+    userId: number,
+    tif: number,
+    nextPool?: boolean,
+    orderId?: number
+  ) => {
     return await this.program.account.order.fetch(
-      await this.getOrderKey(userId, tif, nextPool ? 1 : 0)
+      await this.getOrderKey(userId, tif, nextPool ? 1 : 0, orderId)
     );
   };
 
@@ -455,11 +564,11 @@ export class TwammTester {
         crankRewardTokenB: new anchor.BN(0),
         minSwapAmountTokenA: new anchor.BN(0),
         minSwapAmountTokenB: new anchor.BN(0),
-        maxSwapPriceDiff: 0.0,
-        maxUnsettledAmount: 0.0,
-        minTimeTillExpiration: 0.3,
-        maxOraclePriceErrorTokenA: 0.0,
-        maxOraclePriceErrorTokenB: 0.0,
+        maxSwapPriceDiff: new anchor.BN(0),
+        maxUnsettledAmount: new anchor.BN(0),
+        minTimeTillExpiration: new anchor.BN(300000000),
+        maxOraclePriceErrorTokenA: new anchor.BN(0),
+        maxOraclePriceErrorTokenB: new anchor.BN(0),
         maxOraclePriceAgeSecTokenA: 9000,
         maxOraclePriceAgeSecTokenB: 9000,
         oracleTypeTokenA: { test: {} },
@@ -473,6 +582,7 @@ export class TwammTester {
         admin: this.admin1.publicKey,
         multisig: this.multisigKey,
         tokenPair: this.tokenPairKey,
+        observations: this.observationsKey,
         transferAuthority: this.authorityKey,
         mintTokenA: this.tokenAMint,
         mintTokenB: this.tokenBMint,
@@ -480,7 +590,8 @@ export class TwammTester {
         custodyTokenB: this.tokenBCustodyKey,
         systemProgram: SystemProgram.programId,
         rent: SYSVAR_RENT_PUBKEY,
-        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        tokenProgramA: spl.TOKEN_PROGRAM_ID,
+        tokenProgramB: spl.TOKEN_PROGRAM_ID,
       })
       .signers([this.admin1])
       .rpc()
@@ -525,10 +636,13 @@ export class TwammTester {
         userAccountTokenA: this.tokenAWallets[userId],
         userAccountTokenB: this.tokenBWallets[userId],
         tokenPair: this.tokenPairKey,
+        mintTokenA: this.tokenAMint,
+        mintTokenB: this.tokenBMint,
         transferAuthority: this.authorityKey,
         custodyTokenA: this.tokenACustodyKey,
         custodyTokenB: this.tokenBCustodyKey,
-        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        tokenProgramA: spl.TOKEN_PROGRAM_ID,
+        tokenProgramB: spl.TOKEN_PROGRAM_ID,
       })
       .signers([this.admin1])
       .rpc()
@@ -555,13 +669,16 @@ export class TwammTester {
         admin: this.admin2.publicKey,
         multisig: this.multisigKey,
         tokenPair: this.tokenPairKey,
+        mintTokenA: this.tokenAMint,
+        mintTokenB: this.tokenBMint,
         transferAuthority: this.authorityKey,
         custodyTokenA: this.tokenACustodyKey,
         custodyTokenB: this.tokenBCustodyKey,
         receiverTokenA: this.tokenAWallets[3],
         receiverTokenB: this.tokenBWallets[3],
         receiverSol: this.users[3].publicKey,
-        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        tokenProgramA: spl.TOKEN_PROGRAM_ID,
+        tokenProgramB: spl.TOKEN_PROGRAM_ID,
       })
       .signers([this.admin2])
       .rpc()
@@ -630,26 +747,49 @@ export class TwammTester {
     side: OrderSide,
     tif: number,
     amount: number,
-    nextPool?: boolean
+    nextPool?: boolean,
+    orderId?: number
   ) => {
     await this.program.methods
       .placeOrder({
         side: side === "sell" ? { sell: {} } : { buy: {} },
         timeInForce: tif,
         amount: new anchor.BN(amount),
+        targetAmount: new anchor.BN(0),
+        limitPrice: { price: new anchor.BN(0), exponent: 0 },
+        maxPriceDeviation: new anchor.BN(0),
+        startTime: new anchor.BN(0),
+        orderId: new anchor.BN(orderId ?? 0),
+        autoRoll: false,
+        rollProceeds: false,
       })
       .accounts({
         owner: this.users[userId].publicKey,
         userAccountTokenA: this.tokenAWallets[userId],
         userAccountTokenB: this.tokenBWallets[userId],
         tokenPair: this.tokenPairKey,
+        oracleTokenA: this.oracleTokenAKey,
+        oracleTokenB: this.oracleTokenBKey,
+        mintTokenA: this.tokenAMint,
+        mintTokenB: this.tokenBMint,
         custodyTokenA: this.tokenACustodyKey,
         custodyTokenB: this.tokenBCustodyKey,
-        order: await this.getOrderKey(userId, tif, nextPool ? 1 : 0),
+        order: await this.getOrderKey(
+          userId,
+          tif,
+          nextPool ? 1 : 0,
+          orderId
+        ),
         currentPool: await this.getPoolKey(tif, 0),
         targetPool: await this.getPoolKey(tif, nextPool ? 1 : 0),
+        transferAuthority: null,
+        positionMint: null,
+        userPositionAccount: null,
         systemProgram: SystemProgram.programId,
-        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        tokenProgramA: spl.TOKEN_PROGRAM_ID,
+        tokenProgramB: spl.TOKEN_PROGRAM_ID,
+        tokenProgram: null,
+        associatedTokenProgram: null,
       })
       .signers([this.users[userId]])
       .rpc()
@@ -665,11 +805,14 @@ export class TwammTester {
     userId: number,
     tif: number,
     lpAmount: number,
-    nextPool?: boolean
+    nextPool?: boolean,
+    orderId?: number
   ) => {
     await this.program.methods
       .cancelOrder({
         lpAmount: new anchor.BN(lpAmount),
+        positionAmount: new anchor.BN(0),
+        unwrapNative: false,
       })
       .accounts({
         payer: this.users[userId].publicKey,
@@ -677,12 +820,402 @@ export class TwammTester {
         userAccountTokenA: this.tokenAWallets[userId],
         userAccountTokenB: this.tokenBWallets[userId],
         tokenPair: this.tokenPairKey,
+        mintTokenA: this.tokenAMint,
+        mintTokenB: this.tokenBMint,
+        transferAuthority: this.authorityKey,
+        custodyTokenA: this.tokenACustodyKey,
+        custodyTokenB: this.tokenBCustodyKey,
+        order: await this.getOrderKey(
+          userId,
+          tif,
+          nextPool ? 1 : 0,
+          orderId
+        ),
+        pool: await this.getPoolKey(tif, nextPool ? 1 : 0),
+        positionMint: null,
+        userPositionAccount: null,
+        nativePayoutAccount: null,
+        systemProgram: SystemProgram.programId,
+        tokenProgramA: spl.TOKEN_PROGRAM_ID,
+        tokenProgramB: spl.TOKEN_PROGRAM_ID,
+        tokenProgram: null,
+      })
+      .signers([this.users[userId]])
+      .rpc()
+      .catch((err) => {
+        if (this.printErrors) {
+          console.error(err);
+        }
+        throw err;
+      });
+  };
+
+  // cancels userId's order with payerId signing and payouts to recipientId's accounts
+  cancelOrderAs = async (
+    payerId: number,
+    userId: number,
+    recipientId: number,
+    tif: number,
+    lpAmount: number
+  ) => {
+    await this.program.methods
+      .cancelOrder({
+        lpAmount: new anchor.BN(lpAmount),
+        positionAmount: new anchor.BN(0),
+        unwrapNative: false,
+      })
+      .accounts({
+        payer: this.users[payerId].publicKey,
+        owner: this.users[userId].publicKey,
+        userAccountTokenA: this.tokenAWallets[recipientId],
+        userAccountTokenB: this.tokenBWallets[recipientId],
+        tokenPair: this.tokenPairKey,
+        mintTokenA: this.tokenAMint,
+        mintTokenB: this.tokenBMint,
         transferAuthority: this.authorityKey,
         custodyTokenA: this.tokenACustodyKey,
         custodyTokenB: this.tokenBCustodyKey,
+        order: await this.getOrderKey(userId, tif, 0),
+        pool: await this.getPoolKey(tif, 0),
+        positionMint: null,
+        userPositionAccount: null,
+        nativePayoutAccount: null,
+        systemProgram: SystemProgram.programId,
+        tokenProgramA: spl.TOKEN_PROGRAM_ID,
+        tokenProgramB: spl.TOKEN_PROGRAM_ID,
+        tokenProgram: null,
+      })
+      .signers([this.users[payerId]])
+      .rpc()
+      .catch((err) => {
+        if (this.printErrors) {
+          console.error(err);
+        }
+        throw err;
+      });
+  };
+
+  setOrderDelegate = async (
+    userId: number,
+    tif: number,
+    delegate: PublicKey,
+    signerId?: number
+  ) => {
+    let signer = this.users[signerId ?? userId];
+    await this.program.methods
+      .setOrderDelegate({ delegate })
+      .accounts({
+        owner: signer.publicKey,
+        tokenPair: this.tokenPairKey,
+        order: await this.getOrderKey(userId, tif, 0),
+        pool: await this.getPoolKey(tif, 0),
+      })
+      .signers([signer])
+      .rpc()
+      .catch((err) => {
+        if (this.printErrors) {
+          console.error(err);
+        }
+        throw err;
+      });
+  };
+
+  setOrderBeneficiary = async (
+    userId: number,
+    tif: number,
+    beneficiary: PublicKey,
+    signerId?: number
+  ) => {
+    let signer = this.users[signerId ?? userId];
+    await this.program.methods
+      .setOrderBeneficiary({ beneficiary })
+      .accounts({
+        owner: signer.publicKey,
+        tokenPair: this.tokenPairKey,
+        order: await this.getOrderKey(userId, tif, 0),
+        pool: await this.getPoolKey(tif, 0),
+      })
+      .signers([signer])
+      .rpc()
+      .catch((err) => {
+        if (this.printErrors) {
+          console.error(err);
+        }
+        throw err;
+      });
+  };
+
+  // updates userId's order with authorityId signing
+  updateOrder = async (
+    authorityId: number,
+    userId: number,
+    tif: number,
+    limitPrice: number,
+    autoRoll: boolean
+  ) => {
+    await this.program.methods
+      .updateOrder({
+        limitPrice: { price: new anchor.BN(limitPrice), exponent: 0 },
+        autoRoll,
+        rollProceeds: false,
+      })
+      .accounts({
+        authority: this.users[authorityId].publicKey,
+        tokenPair: this.tokenPairKey,
+        order: await this.getOrderKey(userId, tif, 0),
+        pool: await this.getPoolKey(tif, 0),
+      })
+      .signers([this.users[authorityId]])
+      .rpc()
+      .catch((err) => {
+        if (this.printErrors) {
+          console.error(err);
+        }
+        throw err;
+      });
+  };
+
+  withdrawProceeds = async (
+    userId: number,
+    tif: number,
+    nextPool?: boolean
+  ) => {
+    await this.program.methods
+      .withdrawProceeds({ unwrapNative: false })
+      .accounts({
+        owner: this.users[userId].publicKey,
+        userAccountTokenA: this.tokenAWallets[userId],
+        userAccountTokenB: this.tokenBWallets[userId],
+        tokenPair: this.tokenPairKey,
+        mintTokenA: this.tokenAMint,
+        mintTokenB: this.tokenBMint,
+        transferAuthority: this.authorityKey,
+        custodyTokenA: this.tokenACustodyKey,
+        custodyTokenB: this.tokenBCustodyKey,
+        order: await this.getOrderKey(userId, tif, nextPool ? 1 : 0),
+        pool: await this.getPoolKey(tif, nextPool ? 1 : 0),
+        nativePayoutAccount: null,
+        systemProgram: SystemProgram.programId,
+        tokenProgramA: spl.TOKEN_PROGRAM_ID,
+        tokenProgramB: spl.TOKEN_PROGRAM_ID,
+      })
+      .signers([this.users[userId]])
+      .rpc()
+      .catch((err) => {
+        if (this.printErrors) {
+          console.error(err);
+        }
+        throw err;
+      });
+  };
+
+  transferOrder = async (
+    userId: number,
+    newUserId: number,
+    tif: number,
+    nextPool?: boolean
+  ) => {
+    await this.program.methods
+      .transferOrder({
+        orderId: new anchor.BN(0),
+      })
+      .accounts({
+        owner: this.users[userId].publicKey,
+        newOwner: this.users[newUserId].publicKey,
+        tokenPair: this.tokenPairKey,
+        transferAuthority: this.authorityKey,
         order: await this.getOrderKey(userId, tif, nextPool ? 1 : 0),
+        newOrder: await this.getOrderKey(newUserId, tif, nextPool ? 1 : 0),
         pool: await this.getPoolKey(tif, nextPool ? 1 : 0),
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([this.users[userId]])
+      .rpc()
+      .catch((err) => {
+        if (this.printErrors) {
+          console.error(err);
+        }
+        throw err;
+      });
+  };
+
+  tokenizeOrder = async (
+    userId: number,
+    tif: number,
+    side: OrderSide,
+    lpAmount: number,
+    nextPool?: boolean
+  ) => {
+    let positionMint = await this.getPositionMintKey(
+      tif,
+      side,
+      nextPool ? 1 : 0
+    );
+    await this.program.methods
+      .tokenizeOrder({
+        lpAmount: new anchor.BN(lpAmount),
+      })
+      .accounts({
+        owner: this.users[userId].publicKey,
+        tokenPair: this.tokenPairKey,
+        transferAuthority: this.authorityKey,
+        order: await this.getOrderKey(userId, tif, nextPool ? 1 : 0),
+        pool: await this.getPoolKey(tif, nextPool ? 1 : 0),
+        positionMint,
+        userPositionAccount: await spl.getAssociatedTokenAddress(
+          positionMint,
+          this.users[userId].publicKey
+        ),
+        userAccount:
+          side === "sell"
+            ? this.tokenBWallets[userId]
+            : this.tokenAWallets[userId],
+        custody:
+          side === "sell" ? this.tokenBCustodyKey : this.tokenACustodyKey,
+        mint: side === "sell" ? this.tokenBMint : this.tokenAMint,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        targetTokenProgram: spl.TOKEN_PROGRAM_ID,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([this.users[userId]])
+      .rpc()
+      .catch((err) => {
+        if (this.printErrors) {
+          console.error(err);
+        }
+        throw err;
+      });
+  };
+
+  redeemPosition = async (
+    userId: number,
+    tif: number,
+    side: OrderSide,
+    lpAmount: number,
+    nextPool?: boolean
+  ) => {
+    let positionMint = await this.getPositionMintKey(
+      tif,
+      side,
+      nextPool ? 1 : 0
+    );
+    await this.program.methods
+      .redeemPosition({
+        side: side === "sell" ? { sell: {} } : { buy: {} },
+        lpAmount: new anchor.BN(lpAmount),
+        unwrapNative: false,
+      })
+      .accounts({
+        owner: this.users[userId].publicKey,
+        userAccountTokenA: this.tokenAWallets[userId],
+        userAccountTokenB: this.tokenBWallets[userId],
+        userPositionAccount: await spl.getAssociatedTokenAddress(
+          positionMint,
+          this.users[userId].publicKey
+        ),
+        tokenPair: this.tokenPairKey,
+        mintTokenA: this.tokenAMint,
+        mintTokenB: this.tokenBMint,
+        transferAuthority: this.authorityKey,
+        custodyTokenA: this.tokenACustodyKey,
+        custodyTokenB: this.tokenBCustodyKey,
+        pool: await this.getPoolKey(tif, nextPool ? 1 : 0),
+        positionMint,
         tokenProgram: spl.TOKEN_PROGRAM_ID,
+        tokenProgramA: spl.TOKEN_PROGRAM_ID,
+        tokenProgramB: spl.TOKEN_PROGRAM_ID,
+      })
+      .signers([this.users[userId]])
+      .rpc()
+      .catch((err) => {
+        if (this.printErrors) {
+          console.error(err);
+        }
+        throw err;
+      });
+  };
+
+  refreshOrder = async (userId: number, tif: number, nextPool?: boolean) => {
+    await this.program.methods
+      .refreshOrder({})
+      .accounts({
+        tokenPair: this.tokenPairKey,
+        oracleTokenA: this.oracleTokenAKey,
+        oracleTokenB: this.oracleTokenBKey,
+        order: await this.getOrderKey(userId, tif, nextPool ? 1 : 0),
+        pool: await this.getPoolKey(tif, nextPool ? 1 : 0),
+      })
+      .rpc()
+      .catch((err) => {
+        if (this.printErrors) {
+          console.error(err);
+        }
+        throw err;
+      });
+  };
+
+  placeStopOrder = async (
+    userId: number,
+    side: OrderSide,
+    tif: number,
+    amount: number,
+    triggerPrice: number,
+    orderId: number
+  ) => {
+    await this.program.methods
+      .placeStopOrder({
+        side: side === "sell" ? { sell: {} } : { buy: {} },
+        timeInForce: tif,
+        amount: new anchor.BN(amount),
+        triggerPrice: { price: new anchor.BN(triggerPrice * 100), exponent: -2 },
+        limitPrice: { price: new anchor.BN(0), exponent: 0 },
+        maxPriceDeviation: new anchor.BN(0),
+        orderId: new anchor.BN(orderId),
+      })
+      .accounts({
+        owner: this.users[userId].publicKey,
+        userAccountTokenA: this.tokenAWallets[userId],
+        userAccountTokenB: this.tokenBWallets[userId],
+        tokenPair: this.tokenPairKey,
+        mintTokenA: this.tokenAMint,
+        mintTokenB: this.tokenBMint,
+        custodyTokenA: this.tokenACustodyKey,
+        custodyTokenB: this.tokenBCustodyKey,
+        stopOrder: await this.getStopOrderKey(userId, orderId),
+        systemProgram: SystemProgram.programId,
+        tokenProgramA: spl.TOKEN_PROGRAM_ID,
+        tokenProgramB: spl.TOKEN_PROGRAM_ID,
+      })
+      .signers([this.users[userId]])
+      .rpc()
+      .catch((err) => {
+        if (this.printErrors) {
+          console.error(err);
+        }
+        throw err;
+      });
+  };
+
+  cancelStopOrder = async (userId: number, orderId: number) => {
+    await this.program.methods
+      .cancelStopOrder({})
+      .accounts({
+        owner: this.users[userId].publicKey,
+        userAccountTokenA: this.tokenAWallets[userId],
+        userAccountTokenB: this.tokenBWallets[userId],
+        tokenPair: this.tokenPairKey,
+        mintTokenA: this.tokenAMint,
+        mintTokenB: this.tokenBMint,
+        transferAuthority: this.authorityKey,
+        custodyTokenA: this.tokenACustodyKey,
+        custodyTokenB: this.tokenBCustodyKey,
+        stopOrder: await this.getStopOrderKey(userId, orderId),
+        nativePayoutAccount: null,
+        systemProgram: SystemProgram.programId,
+        tokenProgramA: spl.TOKEN_PROGRAM_ID,
+        tokenProgramB: spl.TOKEN_PROGRAM_ID,
       })
       .signers([this.users[userId]])
       .rpc()
@@ -694,6 +1227,119 @@ export class TwammTester {
       });
   };
 
+  triggerOrder = async (userId: number, tif: number, orderId: number) => {
+    await this.program.methods
+      .triggerOrder({})
+      .accounts({
+        keeper: this.users[3].publicKey,
+        owner: this.users[userId].publicKey,
+        tokenPair: this.tokenPairKey,
+        oracleTokenA: this.oracleTokenAKey,
+        oracleTokenB: this.oracleTokenBKey,
+        stopOrder: await this.getStopOrderKey(userId, orderId),
+        order: await this.getOrderKey(userId, tif, 0, orderId),
+        currentPool: await this.getPoolKey(tif, 0),
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([this.users[3]])
+      .rpc()
+      .catch((err) => {
+        if (this.printErrors) {
+          console.error(err);
+        }
+        throw err;
+      });
+  };
+
+  approveIntents = async (userId: number, side: OrderSide, amount: number) => {
+    await spl.approve(
+      this.provider.connection,
+      this.users[userId],
+      side === "sell" ? this.tokenAWallets[userId] : this.tokenBWallets[userId],
+      await this.getIntentAuthorityKey(userId),
+      this.users[userId],
+      amount
+    );
+  };
+
+  // signs the intent with signerId's key, the owner's by default, and submits it
+  // with users[3] as a relayer
+  submitOrderIntent = async (
+    userId: number,
+    side: OrderSide,
+    tif: number,
+    amount: number,
+    nonce: number,
+    signerId?: number
+  ) => {
+    let intent = {
+      tokenPair: this.tokenPairKey,
+      side: side === "sell" ? { sell: {} } : { buy: {} },
+      timeInForce: tif,
+      amount: new anchor.BN(amount),
+      expiry: new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+      nonce: new anchor.BN(nonce),
+    };
+    let message = Buffer.concat([
+      this.program.programId.toBuffer(),
+      this.program.coder.types.encode("OrderIntent", intent),
+    ]);
+    let signatureIx = Ed25519Program.createInstructionWithPrivateKey({
+      privateKey: this.users[signerId ?? userId].secretKey,
+      message,
+    });
+
+    await this.program.methods
+      .submitOrderIntent({ intent })
+      .accounts({
+        relayer: this.users[3].publicKey,
+        owner: this.users[userId].publicKey,
+        userAccount:
+          side === "sell"
+            ? this.tokenAWallets[userId]
+            : this.tokenBWallets[userId],
+        tokenPair: this.tokenPairKey,
+        mintTokenA: this.tokenAMint,
+        mintTokenB: this.tokenBMint,
+        intentAuthority: await this.getIntentAuthorityKey(userId),
+        custodyTokenA: this.tokenACustodyKey,
+        custodyTokenB: this.tokenBCustodyKey,
+        intentNonce: await this.getIntentNonceKey(userId, nonce),
+        order: await this.getOrderKey(userId, tif, 0, nonce),
+        currentPool: await this.getPoolKey(tif, 0),
+        instructionsSysvar: SYSVAR_INSTRUCTIONS_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgramA: spl.TOKEN_PROGRAM_ID,
+        tokenProgramB: spl.TOKEN_PROGRAM_ID,
+      })
+      .preInstructions([signatureIx])
+      .signers([this.users[3]])
+      .rpc()
+      .catch((err) => {
+        if (this.printErrors) {
+          console.error(err);
+        }
+        throw err;
+      });
+  };
+
+  closeIntentNonce = async (userId: number, nonce: number, payerId?: number) => {
+    await this.program.methods
+      .closeIntentNonce({})
+      .accounts({
+        payer: this.users[payerId ?? 3].publicKey,
+        tokenPair: this.tokenPairKey,
+        intentNonce: await this.getIntentNonceKey(userId, nonce),
+      })
+      .rpc()
+      .catch((err) => {
+        if (this.printErrors) {
+          console.error(err);
+        }
+        throw err;
+      });
+  };
+
   initPoolMetas = async (tifs: number[]) => {
     this.poolMetas = [];
     for (const tif of tifs) {
@@ -731,12 +1377,16 @@ export class TwammTester {
         userAccountTokenA: this.tokenAWallets[3],
         userAccountTokenB: this.tokenBWallets[3],
         tokenPair: this.tokenPairKey,
+        mintTokenA: this.tokenAMint,
+        mintTokenB: this.tokenBMint,
         transferAuthority: this.authorityKey,
         custodyTokenA: this.tokenACustodyKey,
         custodyTokenB: this.tokenBCustodyKey,
         oracleTokenA: this.oracleTokenAKey,
         oracleTokenB: this.oracleTokenBKey,
-        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        observations: this.observationsKey,
+        tokenProgramA: spl.TOKEN_PROGRAM_ID,
+        tokenProgramB: spl.TOKEN_PROGRAM_ID,
       })
       .remainingAccounts(this.poolMetas)
       .signers([this.users[3]])